@@ -55,6 +55,11 @@ function do_update(details)
 	html += "		<th>Min</th>\n";
 	html += "		<th>Mean</th>\n";
 	html += "		<th>Max</th>\n";
+	html += "		<th>Median</th>\n";
+	html += "		<th>95th</th>\n";
+	html += "		<th>Std Dev</th>\n";
+	html += "		<th>Last</th>\n";
+	html += "		<th>Count</th>\n";
 	html += "	</tr>\n";
 	$.each(details, function (i, detail)
 	{
@@ -64,11 +69,20 @@ function do_update(details)
 		
 		var units = escapeHtml(detail.units.replace("b/s", "bps"));
 		
-		html += "	<tr>\n";
+		// Rows for sample sets which are behaving unusually are highlighted.
+		if (detail.anomaly)
+			html += "	<tr class='anomaly'>\n";
+		else
+			html += "	<tr>\n";
 		html += "		<td>{0}</td>\n".format(escapeHtml(name));
-		html += "		<td>{0} {1}</td>\n".format(detail.min.toFixed(1), units);	// TODO: maybe include a timestamp for these?
-		html += "		<td>{0} {1}</td>\n".format(detail.mean.toFixed(1), units);
-		html += "		<td>{0} {1}</td>\n".format(detail.max.toFixed(1), units);
+		html += "		<td>{0}</td>\n".format(format_stat(detail.min, units));	// TODO: maybe include a timestamp for these?
+		html += "		<td>{0}</td>\n".format(format_stat(detail.mean, units));
+		html += "		<td>{0}</td>\n".format(format_stat(detail.max, units));
+		html += "		<td>{0}</td>\n".format(format_stat(detail.median, units));
+		html += "		<td>{0}</td>\n".format(format_stat(detail.p95, units));
+		html += "		<td>{0}</td>\n".format(format_stat(detail.stddev, units));
+		html += "		<td>{0}</td>\n".format(format_stat(detail.last, units));
+		html += "		<td>{0}</td>\n".format(detail.count);
 		html += "	</tr>\n";
 	});
 	html += "</table>\n";
@@ -76,3 +90,12 @@ function do_update(details)
 	var stats = $('#stats');
 	stats.html(html);
 }
+
+// Statistics are null if there were no samples.
+function format_stat(value, units)
+{
+	if (value === null)
+		return "";
+	else
+		return "{0} {1}".format(value.toFixed(1), units);
+}
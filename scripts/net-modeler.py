@@ -156,7 +156,7 @@ class Poll(object):
 				if not env.options.put:
 					env.logger.info("-" * 60)
 					
-				devices = map(lambda e: Device(e[0], e[1]), env.config['devices'].items())
+				devices = [Device(name, config) for (name, config) in env.config['devices'].items() if 'modeler' in config]	# devices without a modeler are polled by gnos
 				data = {'modeler': 'net', 'entities': [], 'relations': [], 'labels': [], 'gauges': [], 'details': [], 'alerts': [], 'samples': [], 'charts': []}
 				self.__query_devices(data, devices)
 				self.__update_routes(devices)
@@ -429,7 +429,7 @@ class Poll(object):
 			if chart['direction'] == direction:
 				target = 'entities:%s' % chart['admin_ip']
 				name = chart['name']
-				markdown = '![bandwidth](/generated/%s.png#%s)' % (name, self.__num_updates)
+				markdown = '![bandwidth](/chart/%s.png#%s)' % (name, self.__num_updates)
 				add_details(data, target, '%s Bandwidth' % direction.title(), [markdown], opened = 'no', sort_key = 'alpha-' + direction, key = '%s bandwidth' % name)
 			
 	def __add_routing_table(self, data, devices, device):
@@ -956,12 +956,12 @@ class Poll(object):
 				
 				# We always need to add samples so that they stay in sync with one another.
 				if interface.in_octets != None:
-					in_octets = self.__process_sample(device, data, {'key': '%s-%s-in_octets' % (device.admin_ip, name), 'raw': 8*interface.in_octets/1000, 'units': 'kbps'})
+					in_octets = self.__process_sample(device, data, {'key': '%s-%s-in_octets' % (device.admin_ip, name), 'raw': 8*interface.in_octets/1000, 'units': 'kbps', 'entity-id': 'entities:%s' % device.admin_ip, 'limit': interface.speed and interface.speed/1000})
 					in_cell = in_octets['html']
 				else:
 					in_cell = ''
 				if interface.out_octets != None:
-					out_octets = self.__process_sample(device, data, {'key':  '%s-%s-out_octets' % (device.admin_ip, name), 'raw': 8*interface.out_octets/1000, 'units': 'kbps'})
+					out_octets = self.__process_sample(device, data, {'key': '%s-%s-out_octets' % (device.admin_ip, name), 'raw': 8*interface.out_octets/1000, 'units': 'kbps', 'entity-id': 'entities:%s' % device.admin_ip, 'limit': interface.speed and interface.speed/1000})
 					out_cell = out_octets['html']
 				else:
 					out_cell = ''
@@ -1025,7 +1025,11 @@ class Poll(object):
 				value = (table['raw'] - self.__last_sample[table['key']])/elapsed
 		table['value'] = value
 		if self.__num_updates >= 2:
-			data['samples'].append({'name': table['key'], 'value': value, 'units': table['units']})
+			sample = {'name': table['key'], 'value': value, 'units': table['units']}
+			for key in ['entity-id', 'limit']:
+				if table.get(key):
+					sample[key] = table[key]
+			data['samples'].append(sample)
 		
 		# When dynamically adding html content browsers will not reload images that have
 		# been already loaded. To work around this we add a unique fragment identifier
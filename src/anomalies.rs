@@ -0,0 +1,257 @@
+/// Flags samples which deviate from the normal behavior of their sample set.
+///
+/// Static thresholds don't work well for links whose load varies over the day
+/// so each sample set matched by an anomaly spec maintains a baseline using an
+/// exponentially weighted moving mean and variance. If seasonal is set then a
+/// separate baseline is kept for each hour of the day. Samples more than sigmas
+/// standard deviations from the baseline open an alert which is closed once the
+/// samples fall back within clear standard deviations. Baselines aren't
+/// updated while an alert is open so a sustained anomaly stays open instead
+/// of becoming the new normal.
+use std::map::{HashMap};
+
+pub struct AnomalySpec
+{
+	pub samples: ~str,				// glob pattern matched against sample set names
+	pub sigmas: float,				// number of standard deviations at which the alert opens
+	pub clear: float,					// number of standard deviations at which the alert closes
+	pub alpha: float,					// weight given to new samples, e.g. 0.05
+	pub warmup: uint,				// number of samples which must be added to a baseline before it is used
+	pub seasonal: bool,				// if true a baseline is kept for each hour of the day
+	pub level: ~str,					// "error", "warning", or "info"
+}
+
+pub struct Anomalies
+{
+	priv specs: ~[AnomalySpec],
+	priv baselines: HashMap<@~str, @Baseline>,		// "spec index sample name hour" => baseline
+	priv active: HashMap<@~str, bool>,				// "spec index sample name" => true if the spec has an open anomaly
+}
+
+pub fn Anomalies(specs: ~[AnomalySpec]) -> Anomalies
+{
+	Anomalies {specs: specs, baselines: HashMap(), active: HashMap()}
+}
+
+pub impl Anomalies
+{
+	/// Should be called with each new sample. Returns alerts which should be
+	/// opened and (target, id) pairs for alerts which should be closed. Target
+	/// is the subject of the entity the sample is for (if it is empty the sample
+	/// set is still checked but no alerts are returned).
+	fn check(name: &str, target: &str, value: float, time: float) -> (~[model::Alert], ~[(~str, ~str)])
+	{
+		let mut opened = ~[];
+		let mut closed = ~[];
+		if float::is_NaN(value)
+		{
+			return (opened, closed);
+		}
+		
+		for self.specs.eachi |i, spec|
+		{
+			if utils::glob_match(spec.samples, name)
+			{
+				let hour = if spec.seasonal {((time/3600.0) as uint) % 24} else {0};
+				let key = @fmt!("%? %s %?", i, name, hour);
+				let baseline = match self.baselines.find(key)
+				{
+					option::Some(b) => b,
+					option::None =>
+					{
+						let b = @Baseline {mean: 0.0, variance: 0.0, count: 0};
+						self.baselines.insert(key, b);
+						b
+					}
+				};
+				
+				let active_key = @fmt!("%? %s", i, name);
+				let active = self.active.find(active_key).get_default(false);
+				let sigmas = baseline.get_sigmas(spec, value);
+				let id = fmt!("%s anomaly %?", name, i);
+				if !active && sigmas > spec.sigmas
+				{
+					self.active.insert(active_key, true);
+					if target.is_not_empty()
+					{
+						opened.push(model::Alert
+						{
+							target: target.to_owned(),
+							id: id,
+							mesg: fmt!("%s is %.1f which is %.1f standard deviations from its baseline of %.1f.", name, value, sigmas, baseline.mean),
+							resolution: ~"",
+							level: copy spec.level,
+						});
+					}
+				}
+				else if active && sigmas < spec.clear
+				{
+					self.active.insert(active_key, false);
+					if target.is_not_empty()
+					{
+						closed.push((target.to_owned(), id));
+					}
+					baseline.add(spec.alpha, value);
+				}
+				else if !active
+				{
+					baseline.add(spec.alpha, value);
+				}
+			}
+		}
+		
+		(opened, closed)
+	}
+	
+	/// Returns true if the named sample set has an open anomaly (for any spec).
+	fn is_anomalous(name: &str) -> bool
+	{
+		do vec::any(self.specs.mapi(|i, _spec| {i})) |i|
+		{
+			self.active.find(@fmt!("%? %s", *i, name)).get_default(false)
+		}
+	}
+}
+
+// ---- Internal Types --------------------------------------------------------
+priv struct Baseline
+{
+	mut mean: float,
+	mut variance: float,
+	mut count: uint,			// number of samples added to the baseline
+}
+
+priv impl Baseline
+{
+	// Returns the number of standard deviations value is from the mean (or zero
+	// if the baseline isn't ready yet).
+	fn get_sigmas(spec: &AnomalySpec, value: float) -> float
+	{
+		let stddev = float::sqrt(self.variance);
+		if self.count >= spec.warmup && stddev > 0.0
+		{
+			float::abs(value - self.mean)/stddev
+		}
+		else
+		{
+			0.0
+		}
+	}
+	
+	fn add(alpha: float, value: float)
+	{
+		if self.count == 0
+		{
+			self.mean = value;
+		}
+		else
+		{
+			let delta = value - self.mean;
+			self.mean += alpha*delta;
+			self.variance = (1.0 - alpha)*(self.variance + alpha*delta*delta);
+		}
+		self.count += 1;
+	}
+}
+
+#[test]
+fn test_anomalies()
+{
+	let spec = AnomalySpec {samples: ~"*-in_octets", sigmas: 3.0, clear: 2.0, alpha: 0.1, warmup: 10, seasonal: false, level: ~"info"};
+	let anomalies = Anomalies(~[spec]);
+	
+	// the baseline alternates between 90 and 110
+	for uint::range(0, 50) |i|
+	{
+		let value = if i % 2 == 0 {90.0} else {110.0};
+		let (opened, closed) = anomalies.check("eth0-in_octets", "entities:a", value, i as float);
+		assert opened.is_empty() && closed.is_empty();
+	}
+	assert !anomalies.is_anomalous("eth0-in_octets");
+	
+	// a big spike opens an alert
+	let (opened, _closed) = anomalies.check("eth0-in_octets", "entities:a", 500.0, 50.0);
+	assert opened.len() == 1;
+	assert opened[0].id == ~"eth0-in_octets anomaly 0";
+	assert anomalies.is_anomalous("eth0-in_octets");
+	
+	// a sustained anomaly doesn't become the baseline
+	for uint::range(0, 50) |i|
+	{
+		let (opened, closed) = anomalies.check("eth0-in_octets", "entities:a", 500.0, 100.0 + i as float);
+		assert opened.is_empty() && closed.is_empty();
+	}
+	assert anomalies.is_anomalous("eth0-in_octets");
+	
+	// which closes once we're back to normal
+	let (opened, closed) = anomalies.check("eth0-in_octets", "entities:a", 100.0, 151.0);
+	assert opened.is_empty();
+	assert closed == ~[(~"entities:a", ~"eth0-in_octets anomaly 0")];
+	assert !anomalies.is_anomalous("eth0-in_octets");
+	
+	// samples that don't match are ignored
+	let (opened, _closed) = anomalies.check("eth0-out_octets", "entities:a", 1.0e9, 152.0);
+	assert opened.is_empty();
+}
+
+#[test]
+fn test_warmup()
+{
+	let spec = AnomalySpec {samples: ~"cpu", sigmas: 3.0, clear: 2.0, alpha: 0.5, warmup: 5, seasonal: true, level: ~"warning"};
+	let anomalies = Anomalies(~[spec]);
+	
+	anomalies.check("cpu", "entities:a", 1.0, 0.0);
+	anomalies.check("cpu", "entities:a", 2.0, 10.0);
+	let (opened, _closed) = anomalies.check("cpu", "entities:a", 100.0, 20.0);
+	assert opened.is_empty();
+	
+	// seasonal baselines are per hour so this uses a new baseline
+	let (opened, _closed) = anomalies.check("cpu", "entities:a", 100.0, 3600.0);
+	assert opened.is_empty();
+}
+
+#[test]
+fn test_seasonal()
+{
+	let spec = AnomalySpec {samples: ~"cpu", sigmas: 3.0, clear: 2.0, alpha: 0.1, warmup: 10, seasonal: true, level: ~"warning"};
+	let anomalies = Anomalies(~[spec]);
+	
+	// cpu is about 10 between midnight and 1am and about 100 between 1am and 2am
+	for uint::range(0, 40) |i|
+	{
+		let delta = if i % 2 == 0 {-1.0} else {1.0};
+		anomalies.check("cpu", "entities:a", 10.0 + delta, 60.0*(i as float));
+		anomalies.check("cpu", "entities:a", 100.0 + 10.0*delta, 3600.0 + 60.0*(i as float));
+	}
+	
+	// 100 is normal between 1am and 2am on the next day
+	let (opened, _closed) = anomalies.check("cpu", "entities:a", 100.0, 86400.0 + 3600.0 + 3000.0);
+	assert opened.is_empty();
+	
+	// but not between midnight and 1am
+	let (opened, _closed) = anomalies.check("cpu", "entities:a", 100.0, 2.0*86400.0 + 3000.0);
+	assert opened.len() == 1;
+}
+
+#[test]
+fn test_multiple_specs()
+{
+	let wide = AnomalySpec {samples: ~"cpu", sigmas: 6.0, clear: 2.0, alpha: 0.1, warmup: 10, seasonal: false, level: ~"error"};
+	let narrow = AnomalySpec {samples: ~"c*", sigmas: 3.0, clear: 2.0, alpha: 0.1, warmup: 10, seasonal: false, level: ~"warning"};
+	let anomalies = Anomalies(~[wide, narrow]);
+	
+	for uint::range(0, 50) |i|
+	{
+		anomalies.check("cpu", "entities:a", if i % 2 == 0 {9.0} else {11.0}, i as float);
+	}
+	
+	// only the narrow spec opens (with its own id)
+	let (opened, _closed) = anomalies.check("cpu", "entities:a", 15.0, 50.0);
+	assert opened.len() == 1;
+	assert opened[0].id == ~"cpu anomaly 1";
+	
+	// a bigger deviation opens the wide spec without closing the narrow one
+	let (opened, closed) = anomalies.check("cpu", "entities:a", 100.0, 51.0);
+	assert opened.len() == 1 && closed.is_empty();
+	assert opened[0].id == ~"cpu anomaly 0";
+}
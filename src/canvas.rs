@@ -0,0 +1,249 @@
+/// Simple RGB raster used to render charts as PNGs.
+use chart::{Color, Anchor, Start, Middle, End, Surface, char_width};
+
+pub struct Canvas
+{
+	pub width: uint,
+	pub height: uint,
+	priv mut pixels: ~[u8],			// RGB triplets in row order
+}
+
+/// Canvases start out white.
+pub fn Canvas(width: uint, height: uint) -> Canvas
+{
+	Canvas {width: width, height: height, pixels: vec::from_elem(3*width*height, 255u8)}
+}
+
+pub impl Canvas
+{
+	fn to_png() -> ~[u8]
+	{
+		png::encode_rgb(self.width, self.height, self.pixels)
+	}
+	
+	pure fn get_pixel(x: uint, y: uint) -> Color
+	{
+		let i = 3*(y*self.width + x);
+		Color {r: self.pixels[i], g: self.pixels[i + 1], b: self.pixels[i + 2]}
+	}
+	
+	// Pixels outside the canvas are silently ignored.
+	priv fn set_pixel(x: int, y: int, color: Color)
+	{
+		if x >= 0 && y >= 0 && (x as uint) < self.width && (y as uint) < self.height
+		{
+			let i = 3*((y as uint)*self.width + (x as uint));
+			self.pixels[i] = color.r;
+			self.pixels[i + 1] = color.g;
+			self.pixels[i + 2] = color.b;
+		}
+	}
+}
+
+pub impl Canvas : Surface
+{
+	fn fill_rect(x: int, y: int, width: int, height: int, color: Color)
+	{
+		for int::range(y, y + height) |row|
+		{
+			for int::range(x, x + width) |col|
+			{
+				self.set_pixel(col, row, color);
+			}
+		}
+	}
+	
+	// Bresenham's algorithm. Thick lines are drawn by stamping a square at each point.
+	fn line(x0: int, y0: int, x1: int, y1: int, color: Color, thickness: uint)
+	{
+		let offset = ((thickness as int) - 1)/2;
+		let dx = int::abs(x1 - x0);
+		let dy = -int::abs(y1 - y0);
+		let sx = if x0 < x1 {1} else {-1};
+		let sy = if y0 < y1 {1} else {-1};
+		
+		let mut x = x0;
+		let mut y = y0;
+		let mut err = dx + dy;
+		loop
+		{
+			self.fill_rect(x - offset, y - offset, thickness as int, thickness as int, color);
+			if x == x1 && y == y1
+			{
+				break;
+			}
+			
+			let e2 = 2*err;
+			if e2 >= dy
+			{
+				err += dy;
+				x += sx;
+			}
+			if e2 <= dx
+			{
+				err += dx;
+				y += sy;
+			}
+		}
+	}
+	
+	fn text(x: int, y: int, text: &str, color: Color, anchor: Anchor)
+	{
+		let width = (str::char_len(text) as int)*char_width;
+		let mut left = match anchor
+		{
+			Start => x,
+			Middle => x - width/2,
+			End => x - width,
+		};
+		
+		for str::each_char(text) |ch|
+		{
+			let code = ch as uint;
+			let code = if code >= 32 && code < 127 {code} else {'?' as uint};
+			let glyph = vec::view(font, 5*(code - 32), 5*(code - 32) + 5);
+			for glyph.eachi |col, bits|
+			{
+				for uint::range(0, 7) |row|
+				{
+					if (*bits >> (row as u8)) & 1u8 != 0
+					{
+						self.set_pixel(left + (col as int), y + (row as int), color);
+					}
+				}
+			}
+			left += char_width;
+		}
+	}
+}
+
+// ---- Internal Types --------------------------------------------------------
+
+// Classic 5x7 font for the printable ASCII characters. Each glyph is five
+// columns with the least significant bit at the top.
+priv const font: [u8 * 475] = [
+	0x00, 0x00, 0x00, 0x00, 0x00,		// space
+	0x00, 0x00, 0x5F, 0x00, 0x00,		// !
+	0x00, 0x07, 0x00, 0x07, 0x00,		// "
+	0x14, 0x7F, 0x14, 0x7F, 0x14,		// #
+	0x24, 0x2A, 0x7F, 0x2A, 0x12,		// $
+	0x23, 0x13, 0x08, 0x64, 0x62,		// %
+	0x36, 0x49, 0x55, 0x22, 0x50,		// &
+	0x00, 0x05, 0x03, 0x00, 0x00,		// '
+	0x00, 0x1C, 0x22, 0x41, 0x00,		// (
+	0x00, 0x41, 0x22, 0x1C, 0x00,		// )
+	0x08, 0x2A, 0x1C, 0x2A, 0x08,		// *
+	0x08, 0x08, 0x3E, 0x08, 0x08,		// +
+	0x00, 0x50, 0x30, 0x00, 0x00,		// ,
+	0x08, 0x08, 0x08, 0x08, 0x08,		// -
+	0x00, 0x60, 0x60, 0x00, 0x00,		// .
+	0x20, 0x10, 0x08, 0x04, 0x02,		// /
+	0x3E, 0x51, 0x49, 0x45, 0x3E,		// 0
+	0x00, 0x42, 0x7F, 0x40, 0x00,		// 1
+	0x42, 0x61, 0x51, 0x49, 0x46,		// 2
+	0x21, 0x41, 0x45, 0x4B, 0x31,		// 3
+	0x18, 0x14, 0x12, 0x7F, 0x10,		// 4
+	0x27, 0x45, 0x45, 0x45, 0x39,		// 5
+	0x3C, 0x4A, 0x49, 0x49, 0x30,		// 6
+	0x01, 0x71, 0x09, 0x05, 0x03,		// 7
+	0x36, 0x49, 0x49, 0x49, 0x36,		// 8
+	0x06, 0x49, 0x49, 0x29, 0x1E,		// 9
+	0x00, 0x36, 0x36, 0x00, 0x00,		// :
+	0x00, 0x56, 0x36, 0x00, 0x00,		// ;
+	0x00, 0x08, 0x14, 0x22, 0x41,		// <
+	0x14, 0x14, 0x14, 0x14, 0x14,		// =
+	0x41, 0x22, 0x14, 0x08, 0x00,		// >
+	0x02, 0x01, 0x51, 0x09, 0x06,		// ?
+	0x32, 0x49, 0x79, 0x41, 0x3E,		// @
+	0x7E, 0x11, 0x11, 0x11, 0x7E,		// A
+	0x7F, 0x49, 0x49, 0x49, 0x36,		// B
+	0x3E, 0x41, 0x41, 0x41, 0x22,		// C
+	0x7F, 0x41, 0x41, 0x22, 0x1C,		// D
+	0x7F, 0x49, 0x49, 0x49, 0x41,		// E
+	0x7F, 0x09, 0x09, 0x01, 0x01,		// F
+	0x3E, 0x41, 0x41, 0x51, 0x32,		// G
+	0x7F, 0x08, 0x08, 0x08, 0x7F,		// H
+	0x00, 0x41, 0x7F, 0x41, 0x00,		// I
+	0x20, 0x40, 0x41, 0x3F, 0x01,		// J
+	0x7F, 0x08, 0x14, 0x22, 0x41,		// K
+	0x7F, 0x40, 0x40, 0x40, 0x40,		// L
+	0x7F, 0x02, 0x04, 0x02, 0x7F,		// M
+	0x7F, 0x04, 0x08, 0x10, 0x7F,		// N
+	0x3E, 0x41, 0x41, 0x41, 0x3E,		// O
+	0x7F, 0x09, 0x09, 0x09, 0x06,		// P
+	0x3E, 0x41, 0x51, 0x21, 0x5E,		// Q
+	0x7F, 0x09, 0x19, 0x29, 0x46,		// R
+	0x46, 0x49, 0x49, 0x49, 0x31,		// S
+	0x01, 0x01, 0x7F, 0x01, 0x01,		// T
+	0x3F, 0x40, 0x40, 0x40, 0x3F,		// U
+	0x1F, 0x20, 0x40, 0x20, 0x1F,		// V
+	0x7F, 0x20, 0x18, 0x20, 0x7F,		// W
+	0x63, 0x14, 0x08, 0x14, 0x63,		// X
+	0x03, 0x04, 0x78, 0x04, 0x03,		// Y
+	0x61, 0x51, 0x49, 0x45, 0x43,		// Z
+	0x00, 0x00, 0x7F, 0x41, 0x41,		// [
+	0x02, 0x04, 0x08, 0x10, 0x20,		// backslash
+	0x41, 0x41, 0x7F, 0x00, 0x00,		// ]
+	0x04, 0x02, 0x01, 0x02, 0x04,		// ^
+	0x40, 0x40, 0x40, 0x40, 0x40,		// _
+	0x00, 0x01, 0x02, 0x04, 0x00,		// `
+	0x20, 0x54, 0x54, 0x54, 0x78,		// a
+	0x7F, 0x48, 0x44, 0x44, 0x38,		// b
+	0x38, 0x44, 0x44, 0x44, 0x20,		// c
+	0x38, 0x44, 0x44, 0x48, 0x7F,		// d
+	0x38, 0x54, 0x54, 0x54, 0x18,		// e
+	0x08, 0x7E, 0x09, 0x01, 0x02,		// f
+	0x08, 0x14, 0x54, 0x54, 0x3C,		// g
+	0x7F, 0x08, 0x04, 0x04, 0x78,		// h
+	0x00, 0x44, 0x7D, 0x40, 0x00,		// i
+	0x20, 0x40, 0x44, 0x3D, 0x00,		// j
+	0x00, 0x7F, 0x10, 0x28, 0x44,		// k
+	0x00, 0x41, 0x7F, 0x40, 0x00,		// l
+	0x7C, 0x04, 0x18, 0x04, 0x78,		// m
+	0x7C, 0x08, 0x04, 0x04, 0x78,		// n
+	0x38, 0x44, 0x44, 0x44, 0x38,		// o
+	0x7C, 0x14, 0x14, 0x14, 0x08,		// p
+	0x08, 0x14, 0x14, 0x18, 0x7C,		// q
+	0x7C, 0x08, 0x04, 0x04, 0x08,		// r
+	0x48, 0x54, 0x54, 0x54, 0x20,		// s
+	0x04, 0x3F, 0x44, 0x40, 0x20,		// t
+	0x3C, 0x40, 0x40, 0x20, 0x7C,		// u
+	0x1C, 0x20, 0x40, 0x20, 0x1C,		// v
+	0x3C, 0x40, 0x30, 0x40, 0x3C,		// w
+	0x44, 0x28, 0x10, 0x28, 0x44,		// x
+	0x0C, 0x50, 0x50, 0x50, 0x3C,		// y
+	0x44, 0x64, 0x54, 0x4C, 0x44,		// z
+	0x00, 0x08, 0x36, 0x41, 0x00,		// {
+	0x00, 0x00, 0x7F, 0x00, 0x00,		// |
+	0x00, 0x41, 0x36, 0x08, 0x00,		// }
+	0x08, 0x04, 0x08, 0x10, 0x08,		// ~
+];
+
+#[test]
+fn test_canvas()
+{
+	let black = Color {r: 0, g: 0, b: 0};
+	let white = Color {r: 255, g: 255, b: 255};
+	let canvas = Canvas(10, 10);
+	assert canvas.get_pixel(0, 0) == white;
+	
+	canvas.line(0, 0, 9, 9, black, 1);
+	assert canvas.get_pixel(0, 0) == black;
+	assert canvas.get_pixel(5, 5) == black;
+	assert canvas.get_pixel(9, 9) == black;
+	assert canvas.get_pixel(9, 0) == white;
+	
+	// clipped
+	canvas.fill_rect(-5, -5, 100, 1, black);
+	
+	// the top of an I is a horizontal bar
+	canvas.text(0, 2, "I", black, Start);
+	assert canvas.get_pixel(1, 2) == black;
+	assert canvas.get_pixel(0, 2) == white;
+	
+	// widths are measured in chars (non-ascii chars are drawn as ?)
+	let canvas = Canvas(20, 10);
+	canvas.text(20, 0, "\u00e9", black, End);
+	assert canvas.get_pixel(14, 1) == black;
+	assert canvas.get_pixel(8, 1) == white;
+}
@@ -0,0 +1,395 @@
+/// Renders sample sets as charts and sparklines.
+///
+/// Drawing is done using the Surface trait which is implemented by both
+/// canvas::Canvas (saved as a PNG) and svg::Svg so the two outputs share the
+/// same layout code. Layout uses the metrics of the canvas 5x7 bitmap font.
+use core::io::{WriterUtil};
+use Path = path::Path;
+use Canvas = canvas::Canvas;
+use Svg = svg::Svg;
+use RingBuffer = ring_buffer::RingBuffer;
+use samples::{Chart};
+use runits::generated::*;
+use runits::units::*;
+
+pub struct Color
+{
+	pub r: u8,
+	pub g: u8,
+	pub b: u8,
+}
+
+/// Horizontal alignment of text relative to its x coordinate.
+pub enum Anchor
+{
+	Start,
+	Middle,
+	End,
+}
+
+pub trait Surface
+{
+	fn fill_rect(x: int, y: int, width: int, height: int, color: Color);
+	fn line(x0: int, y0: int, x1: int, y1: int, color: Color, thickness: uint);
+	fn text(x: int, y: int, text: &str, color: Color, anchor: Anchor);	// y is the top of the text
+}
+
+pub const char_width: int = 6;			// glyphs are 5 pixels wide with one pixel of spacing
+pub const char_height: int = 7;
+
+pub fn chart_to_png(chart: &Chart, times: &RingBuffer<float>, samples: &[RingBuffer<float>], width: uint, height: uint) -> ~[u8]
+{
+	let canvas = Canvas(width, height);
+	draw_chart(&canvas, width as int, height as int, chart, times, samples);
+	canvas.to_png()
+}
+
+pub fn chart_to_svg(chart: &Chart, times: &RingBuffer<float>, samples: &[RingBuffer<float>], width: uint, height: uint) -> ~str
+{
+	let svg = Svg(width, height);
+	draw_chart(&svg, width as int, height as int, chart, times, samples);
+	svg.to_str()
+}
+
+/// Writes a small chart of the samples without axes to path (a PNG) and an
+/// SVG version alongside it. The shaded area is the inter-quartile range.
+pub fn write_sparkline(path: &Path, samples: &RingBuffer<float>, units: &str, scratch: &Path) -> option::Option<~str>
+{
+	let (width, height) = (150, 50);
+	
+	let canvas = Canvas(width, height);
+	draw_sparkline(&canvas, width as int, height as int, samples, units);
+	
+	let svg = Svg(width, height);
+	draw_sparkline(&svg, width as int, height as int, samples, units);
+	
+	match write_file(path, canvas.to_png(), scratch)
+	{
+		option::None => write_file(&path.with_filetype("svg"), str::to_bytes(svg.to_str()), scratch),
+		err => err,
+	}
+}
+
+/// Draws a chart with color-coded lines for each sample set. Times and samples
+/// should be the same length.
+pub fn draw_chart<S: Surface>(surface: &S, width: int, height: int, chart: &Chart, times: &RingBuffer<float>, samples: &[RingBuffer<float>])
+{
+	let (left, right, top, bottom) = (70, 20, 40, 40);
+	let plot_width = width - left - right;
+	let plot_height = height - top - bottom;
+	
+	surface.fill_rect(0, 0, width, height, white);
+	surface.text(width/2, 12, chart.title, black, Middle);
+	surface.text(left, top - char_height - 8, chart.y_label, black, Start);
+	
+	let first_time = if times.is_not_empty() {times[0]} else {0.0};
+	let last_time = if times.is_not_empty() {times.last()} else {0.0};
+	let span = if last_time > first_time {last_time - first_time} else {1.0};
+	let (min_value, max_value) = get_extent(samples);
+	
+	let x = |time: float| {left + (((time - first_time)/span)*(plot_width as float)) as int};
+	let y = |value: float| {top + plot_height - (((value - min_value)/(max_value - min_value))*(plot_height as float)) as int};
+	
+	// Grid lines and tick labels. Times are shown relative to the most recent sample.
+	let (scale, x_units) = get_time_interval(span);
+	for int::range(0, 5) |i|
+	{
+		let value = min_value + (max_value - min_value)*(i as float)/4.0;
+		surface.line(left, y(value), left + plot_width, y(value), grid, 1);
+		surface.text(left - 6, y(value) - char_height/2, format_value(value), black, End);
+		
+		let time = first_time + span*(i as float)/4.0;
+		surface.line(x(time), top, x(time), top + plot_height, grid, 1);
+		surface.text(x(time), top + plot_height + 6, format_value((time - last_time)*scale), black, Middle);
+	}
+	surface.text(left + plot_width/2, height - char_height - 8, fmt!("Time (%s)", x_units), black, Middle);
+	
+	for samples.eachi |i, buffer|
+	{
+		let color = palette[i % palette.len()];
+		draw_line(surface, times, buffer, x, y, color, 2);
+	}
+	draw_frame(surface, left, top, plot_width, plot_height);
+	
+	// Legend goes in the upper left of the plot.
+	for chart.legends.eachi |i, legend|
+	{
+		let row = top + 8 + (i as int)*(char_height + 6);
+		surface.fill_rect(left + 8, row, char_height, char_height, palette[i % palette.len()]);
+		surface.text(left + 8 + char_height + 4, row, *legend, black, Start);
+	}
+}
+
+/// Draws a line for the samples without axes. The inter-quartile range is
+/// shaded, the min and max samples are labeled, and the units (if any) are
+/// drawn in the upper left.
+pub fn draw_sparkline<S: Surface>(surface: &S, width: int, height: int, samples: &RingBuffer<float>, units: &str)
+{
+	surface.fill_rect(0, 0, width, height, white);
+	
+	let values = do iter::filter_to_vec(samples) |x| {!float::is_NaN(*x)};
+	if values.len() < 2
+	{
+		return;
+	}
+	let sorted = std::sort::merge_sort(values, |x, y| {*x <= *y});
+	let (min_value, max_value) = get_extent(~[copy *samples]);
+	
+	// Leave room on the right for the labels and at the top for the units.
+	let label_width = (uint::max(str::char_len(format_value(sorted[0])), str::char_len(format_value(sorted.last()))) as int)*char_width + 4;
+	let units_height = if units.is_not_empty() {char_height + 2} else {0};
+	let (left, right, top, bottom) = (4, label_width, char_height/2 + 2 + units_height, char_height/2 + 2);
+	let plot_width = width - left - right;
+	let plot_height = height - top - bottom;
+	
+	if units.is_not_empty()
+	{
+		surface.text(left, 1, units, grey, Start);
+	}
+	
+	let count = samples.len();
+	let x = |index: float| {left + ((index/((count - 1) as float))*(plot_width as float)) as int};
+	let y = |value: float| {top + plot_height - (((value - min_value)/(max_value - min_value))*(plot_height as float)) as int};
+	
+	let q1 = utils::get_percentile(sorted, 25.0);
+	let q3 = utils::get_percentile(sorted, 75.0);
+	surface.fill_rect(left, y(q3), plot_width, y(q1) - y(q3) + 1, cornsilk);
+	
+	let indexes = RingBuffer(count);
+	for uint::range(0, count) |i| {indexes.push(i as float);}
+	draw_line(surface, &indexes, samples, x, y, black, 1);
+	
+	// Label the (last) min and max samples.
+	let mut min_index = 0;
+	let mut max_index = 0;
+	for uint::range(0, count) |i|
+	{
+		if !float::is_NaN(samples[i])
+		{
+			if float::is_NaN(samples[min_index]) || samples[i] <= samples[min_index] {min_index = i}
+			if float::is_NaN(samples[max_index]) || samples[i] >= samples[max_index] {max_index = i}
+		}
+	}
+	for [(min_index, min_color), (max_index, max_color)].each |entry|
+	{
+		let (index, color) = *entry;
+		let (px, py) = (x(index as float), y(samples[index]));
+		surface.fill_rect(px - 1, py - 1, 3, 3, color);
+		surface.text(left + plot_width + 4, int::min(int::max(py - char_height/2, 0), height - char_height), format_value(samples[index]), color, Start);
+	}
+}
+
+// The poll interval can vary by quite a bit so using seconds for the x axis
+// will often be hard to interpret. So we use this function to choose a time
+// unit that should be suitable for the times in use. Returns the scale factor
+// used to convert seconds into the new units along with the units.
+pub fn get_time_interval(max_time: float) -> (float, ~str)
+{
+	let x = from_units(max_time, Second).normalize_time();
+	(x.value/max_time, x.units.to_str())
+}
+
+/// Returns an error if the file could not be written. The file is written to a
+/// private temporary directory within scratch and then renamed so that clients
+/// (and other jobs writing the same file) never see a partially written file.
+/// Scratch has to be on the same file system as path for the rename to work and
+/// should not be served (see Options::scratch_dir).
+pub fn write_file(path: &Path, bytes: &[u8], scratch: &Path) -> option::Option<~str>
+{
+	if !os::path_is_dir(scratch)
+	{
+		os::make_dir(scratch, 7*8*8);
+	}
+	match std::tempfile::mkdtemp(scratch, "tmp-")
+	{
+		option::Some(ref temp_dir) =>
+		{
+			let temp_path = temp_dir.push(path.filename().get());
+			let err = match io::file_writer(&temp_path, ~[io::Create, io::Truncate])
+			{
+				result::Ok(writer) =>
+				{
+					writer.write(bytes);
+					if os::rename_file(&temp_path, path)
+					{
+						option::None
+					}
+					else
+					{
+						os::remove_file(&temp_path);
+						option::Some(fmt!("Failed to rename %s to %s", temp_path.to_str(), path.to_str()))
+					}
+				}
+				result::Err(ref err) =>
+				{
+					option::Some(fmt!("Failed to create %s: %s", temp_path.to_str(), *err))
+				}
+			};
+			os::remove_dir(temp_dir);
+			err
+		}
+		option::None =>
+		{
+			option::Some(fmt!("Failed to create a temporary directory in %s", scratch.to_str()))
+		}
+	}
+}
+
+pub impl Color : ToStr
+{
+	pure fn to_str() -> ~str
+	{
+		fmt!("#%02x%02x%02x", self.r as uint, self.g as uint, self.b as uint)
+	}
+}
+
+pub impl Color : cmp::Eq
+{
+	pure fn eq(other: &Color) -> bool
+	{
+		self.r == other.r && self.g == other.g && self.b == other.b
+	}
+	
+	pure fn ne(other: &Color) -> bool
+	{
+		!self.eq(other)
+	}
+}
+
+priv const white: Color = Color {r: 255, g: 255, b: 255};
+priv const black: Color = Color {r: 0, g: 0, b: 0};
+priv const grid: Color = Color {r: 220, g: 220, b: 220};
+priv const grey: Color = Color {r: 100, g: 100, b: 100};
+priv const cornsilk: Color = Color {r: 255, g: 248, b: 220};
+priv const min_color: Color = Color {r: 55, g: 126, b: 184};
+priv const max_color: Color = Color {r: 228, g: 26, b: 28};
+
+// This is ColorBrewer's Set1 palette (see http://colorbrewer2.org) which has
+// colors that are both pleasing to the eye and visually distinct.
+priv const palette: [Color * 9] = [
+	Color {r: 228, g: 26, b: 28},
+	Color {r: 55, g: 126, b: 184},
+	Color {r: 77, g: 175, b: 74},
+	Color {r: 152, g: 78, b: 163},
+	Color {r: 255, g: 127, b: 0},
+	Color {r: 255, g: 255, b: 51},
+	Color {r: 166, g: 86, b: 40},
+	Color {r: 247, g: 129, b: 191},
+	Color {r: 153, g: 153, b: 153},
+];
+
+// ---- Internal Functions ----------------------------------------------------
+
+// Counter resets and missing samples are recorded as NaN so the line has a gap there.
+priv fn draw_line<S: Surface>(surface: &S, xs: &RingBuffer<float>, ys: &RingBuffer<float>, x: fn (float) -> int, y: fn (float) -> int, color: Color, thickness: uint)
+{
+	let mut last = option::None;
+	for uint::range(0, uint::min(xs.len(), ys.len())) |i|
+	{
+		if float::is_NaN(ys[i])
+		{
+			last = option::None;
+		}
+		else
+		{
+			let point = (x(xs[i]), y(ys[i]));
+			match last
+			{
+				option::Some((x0, y0)) => surface.line(x0, y0, point.first(), point.second(), color, thickness),
+				option::None => surface.line(point.first(), point.second(), point.first(), point.second(), color, thickness),
+			}
+			last = option::Some(point);
+		}
+	}
+}
+
+priv fn draw_frame<S: Surface>(surface: &S, left: int, top: int, width: int, height: int)
+{
+	surface.line(left, top, left + width, top, black, 1);
+	surface.line(left + width, top, left + width, top + height, black, 1);
+	surface.line(left + width, top + height, left, top + height, black, 1);
+	surface.line(left, top + height, left, top, black, 1);
+}
+
+// Returns the range of the y axis: this always includes zero (so negative
+// samples are within the plot) and is never empty.
+priv fn get_extent(samples: &[RingBuffer<float>]) -> (float, float)
+{
+	let mut min_value = 0.0;
+	let mut max_value = 0.0;
+	for samples.each |buffer|
+	{
+		for buffer.each |x|
+		{
+			if *x < min_value {min_value = *x}		// false for NaN
+			if *x > max_value {max_value = *x}
+		}
+	}
+	if max_value > min_value {(min_value, max_value)} else {(min_value, min_value + 1.0)}
+}
+
+priv fn format_value(value: float) -> ~str
+{
+	let magnitude = float::abs(value);
+	if magnitude >= 100.0 || magnitude == 0.0
+	{
+		fmt!("%.0f", value)
+	}
+	else if magnitude >= 10.0
+	{
+		fmt!("%.1f", value)
+	}
+	else
+	{
+		fmt!("%.2f", value)
+	}
+}
+
+#[test]
+fn test_draw_chart()
+{
+	let times = RingBuffer(3);
+	let eth0 = RingBuffer(3);
+	let eth1 = RingBuffer(3);
+	for [(0.0, 1.0, 5.0), (60.0, float::NaN, 10.0), (120.0, 3.0, 0.0)].each |row|
+	{
+		let (t, a, b) = *row;
+		times.push(t);
+		eth0.push(a);
+		eth1.push(b);
+	}
+	let chart = Chart {name: ~"eth", sample_sets: ~[~"eth0", ~"eth1"], legends: ~[~"eth0", ~"eth1"], range: 0.0, title: ~"Title", y_label: ~"kbps"};
+	
+	let svg = Svg(800, 500);
+	draw_chart(&svg, 800, 500, &chart, &times, ~[eth0, eth1]);
+	let text = svg.to_str();
+	assert text.contains(">Title</text>");
+	assert text.contains(">eth1</text>");
+	assert text.contains(">Time (");
+	assert text.contains("stroke=\"#e41a1c\"");
+	
+	let canvas = Canvas(150, 50);
+	draw_sparkline(&canvas, 150, 50, &eth1, "");
+	assert canvas.get_pixel(0, 0) == white;
+	
+	let svg = Svg(150, 50);
+	draw_sparkline(&svg, 150, 50, &eth1, "kbps");
+	assert svg.to_str().contains(">kbps</text>");
+}
+
+#[test]
+fn test_negative_samples()
+{
+	let samples = RingBuffer(3);
+	for [-10.0, 5.0, float::NaN].each |x| {samples.push(*x);}
+	assert get_extent(~[copy samples]) == (-10.0, 5.0);
+	assert get_extent(~[RingBuffer(0)]) == (0.0, 1.0);
+	
+	// the line stays within the plot (which is above the bottom margin and left of the labels)
+	let canvas = Canvas(150, 50);
+	draw_sparkline(&canvas, 150, 50, &samples, "");
+	for uint::range(0, 120) |x|
+	{
+		assert canvas.get_pixel(x, 49) == white;
+	}
+}
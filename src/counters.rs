@@ -0,0 +1,134 @@
+/// Converts raw monotonic counters (e.g. SNMP ifInOctets) into per second rates.
+///
+/// Counters can wrap (especially 32-bit counters on fast links) and they are
+/// reset to zero when the agent restarts. Wraps are corrected for but resets
+/// (and the first sample for a counter) result in a NaN rate so that charts show
+/// a gap instead of a huge spike. A decrease is only treated as a wrap if the
+/// resulting rate is possible (e.g. doesn't exceed the interface speed).
+use std::map::{HashMap};
+
+/// How AddSample values should be interpreted.
+pub enum SampleKind
+{
+	Gauge,					// value is used as is
+	Counter32(float),		// value is a 32-bit counter, the rate is multiplied by the scale
+	Counter64(float),		// value is a 64-bit counter, the rate is multiplied by the scale
+}
+
+pub struct Counters
+{
+	priv last: HashMap<@~str, (float, float)>,		// sample name => (counter value, time)
+}
+
+pub fn Counters() -> Counters
+{
+	Counters {last: HashMap()}
+}
+
+pub impl Counters
+{
+	/// Returns the value to record for the sample: gauges are returned as is,
+	/// counters are converted into a rate (or NaN if a rate cannot be computed).
+	/// Limit is the max scaled rate (e.g. the interface speed) or NaN if unknown.
+	fn get_value(name: &str, value: float, kind: SampleKind, time: float, limit: float) -> float
+	{
+		match kind
+		{
+			Gauge =>
+			{
+				value
+			}
+			Counter32(scale) =>
+			{
+				self.get_rate(name, value, time, 4294967296.0, limit/scale)*scale
+			}
+			Counter64(scale) =>
+			{
+				self.get_rate(name, value, time, 18446744073709551616.0, limit/scale)*scale
+			}
+		}
+	}
+	
+	priv fn get_rate(name: &str, value: float, time: float, modulus: float, max_rate: float) -> float
+	{
+		let key = @name.to_owned();
+		let rate = match self.last.find(key)
+		{
+			option::Some((last_value, last_time)) => compute_rate(last_value, last_time, value, time, modulus, max_rate),
+			option::None => float::NaN,
+		};
+		self.last.insert(key, (value, time));
+		rate
+	}
+}
+
+/// Returns the per second rate between two counter readings, correcting
+/// for a single wrap. NaN is returned if the counter was reset. Max_rate is
+/// the largest possible (unscaled) rate or NaN if it isn't known.
+pub pure fn compute_rate(last_value: float, last_time: float, value: float, time: float, modulus: float, max_rate: float) -> float
+{
+	let elapsed = time - last_time;
+	if elapsed <= 0.0
+	{
+		return float::NaN;
+	}
+	
+	let delta =
+		if value >= last_value
+		{
+			value - last_value
+		}
+		else
+		{
+			// Either the counter wrapped or the agent restarted. A wrap should be
+			// a relatively small increment past the modulus. If the increment is
+			// implausibly large, or faster than the link can go, then it's much
+			// more likely that the counter was reset to zero (a counter which was
+			// high when the agent restarted can look like a modest wrap).
+			let wrapped = modulus - last_value + value;
+			let too_fast = !float::is_NaN(max_rate) && wrapped/elapsed > max_rate;
+			if wrapped < modulus/2.0 && !too_fast {wrapped} else {float::NaN}
+		};
+	delta/elapsed
+}
+
+#[test]
+fn test_compute_rate()
+{
+	// normal
+	assert compute_rate(100.0, 10.0, 300.0, 20.0, 4294967296.0, float::NaN) == 20.0;
+	
+	// 32-bit wrap
+	assert compute_rate(4294967196.0, 10.0, 100.0, 20.0, 4294967296.0, float::NaN) == 20.0;
+	assert compute_rate(4294967196.0, 10.0, 100.0, 20.0, 4294967296.0, 1000.0) == 20.0;
+	
+	// reset
+	assert float::is_NaN(compute_rate(1000000000.0, 10.0, 50.0, 20.0, 4294967296.0, float::NaN));
+	assert float::is_NaN(compute_rate(1000000000.0, 10.0, 50.0, 20.0, 18446744073709551616.0, float::NaN));
+	
+	// reset from a high value (this would be a 27 MB/s wrap which is too fast for a 100 Mbps link)
+	assert float::is_NaN(compute_rate(4026531840.0, 10.0, 5.0, 20.0, 4294967296.0, 100.0e6/8.0));
+	
+	// bogus times
+	assert float::is_NaN(compute_rate(100.0, 10.0, 300.0, 10.0, 4294967296.0, float::NaN));
+}
+
+#[test]
+fn test_counters()
+{
+	let counters = Counters();
+	assert counters.get_value("cpu", 0.5, Gauge, 10.0, float::NaN) == 0.5;
+	
+	// first sample is a gap
+	assert float::is_NaN(counters.get_value("eth0", 1000.0, Counter32(8.0), 10.0, float::NaN));
+	assert counters.get_value("eth0", 2000.0, Counter32(8.0), 20.0, float::NaN) == 800.0;
+	assert counters.get_value("eth0", 2000.0, Counter32(8.0), 30.0, float::NaN) == 0.0;
+	
+	// counters are tracked by name
+	assert float::is_NaN(counters.get_value("eth1", 2000.0, Counter64(1.0), 30.0, float::NaN));
+	
+	// agent restart (the limit is in scaled units, here kbps)
+	counters.get_value("eth2", 4026531840.0, Counter32(8.0/1000.0), 10.0, 100000.0);
+	assert float::is_NaN(counters.get_value("eth2", 5.0, Counter32(8.0/1000.0), 20.0, 100000.0));
+	assert float::abs(counters.get_value("eth2", 1255.0, Counter32(8.0/1000.0), 30.0, 100000.0) - 1.0) < 0.001;
+}
@@ -0,0 +1,284 @@
+/// Cron-like expressions used to schedule jobs (see task_runner::Scheduler).
+///
+/// Expressions have five space separated fields: minute (0-59), hour (0-23),
+/// day of month (1-31), month (1-12), and day of week (0-6 where 0 is Sunday).
+/// Each field may be *, a number, a range (1-5), a step (*/15 or 0-30/10), or
+/// a comma separated list of these. Like cron if both day of month and day of
+/// week are restricted then a day matches if either field matches. Times are
+/// local times.
+use std::time::{Tm, Timespec};
+
+pub struct Cron
+{
+	priv text: ~str,
+	priv minutes: ~[bool],
+	priv hours: ~[bool],
+	priv days: ~[bool],				// indexed by day of month - 1
+	priv months: ~[bool],			// indexed by month - 1
+	priv weekdays: ~[bool],
+	priv any_day: bool,				// true if the day of month field is *
+	priv any_weekday: bool,
+}
+
+pub fn parse_cron(text: &str) -> result::Result<Cron, ~str>
+{
+	let fields = str::words(text);
+	if fields.len() != 5
+	{
+		return result::Err(fmt!("cron expression '%s' should have five fields", text));
+	}
+	
+	let mut sets = ~[];
+	for [(0u, 59u), (0u, 23u), (1u, 31u), (1u, 12u), (0u, 6u)].eachi |i, bounds|
+	{
+		let (min, max) = *bounds;
+		match parse_field(fields[i], min, max)
+		{
+			result::Ok(move set) => sets.push(set),
+			result::Err(ref err) => return result::Err(fmt!("bad cron expression '%s': %s", text, *err)),
+		}
+	}
+	
+	result::Ok(Cron {
+		text: text.to_owned(),
+		minutes: copy sets[0],
+		hours: copy sets[1],
+		days: copy sets[2],
+		months: copy sets[3],
+		weekdays: copy sets[4],
+		any_day: fields[2] == ~"*",
+		any_weekday: fields[4] == ~"*",
+	})
+}
+
+pub impl Cron
+{
+	pure fn matches(tm: &Tm) -> bool
+	{
+		self.minutes[tm.tm_min as uint] && self.hours[tm.tm_hour as uint] && self.matches_day(tm)
+	}
+	
+	/// Returns the first time (in seconds since the epoch) after time which
+	/// matches. Returns None if nothing matches within a year (e.g. for "0 0 30 2 *").
+	/// Like cron times which are skipped when daylight savings time starts are
+	/// not matched and times which are repeated when it ends are matched once.
+	fn next_after(time: float) -> option::Option<float>
+	{
+		self.next_after_in(time, local_tm)
+	}
+}
+
+pub impl Cron : ToStr
+{
+	pure fn to_str() -> ~str
+	{
+		copy self.text
+	}
+}
+
+// ---- Internal Functions ----------------------------------------------------
+priv impl Cron
+{
+	// To_tm converts seconds since the epoch into a local time (this is a
+	// parameter so that the daylight savings time handling can be tested).
+	fn next_after_in(time: float, to_tm: fn (float) -> Tm) -> option::Option<float>
+	{
+		let mut t = float::floor(time/60.0)*60.0 + 60.0;
+		let limit = t + 366.0*24.0*60.0*60.0;
+		while t < limit
+		{
+			let tm = to_tm(t);
+			if !self.matches_day(&tm)
+			{
+				t = start_of_next_day(t, &tm, to_tm);
+			}
+			else if !self.hours[tm.tm_hour as uint]
+			{
+				t += ((60 - tm.tm_min)*60) as float;						// skip to the next hour
+			}
+			else if !self.minutes[tm.tm_min as uint] || is_repeated(t, &tm, to_tm)
+			{
+				t += 60.0;
+			}
+			else
+			{
+				return option::Some(t);
+			}
+		}
+		option::None
+	}
+	
+	pure fn matches_day(tm: &Tm) -> bool
+	{
+		let day = (tm.tm_mday - 1) as uint;
+		let weekday = tm.tm_wday as uint;
+		if !self.months[tm.tm_mon as uint]
+		{
+			false
+		}
+		else if self.any_day || self.any_weekday
+		{
+			self.days[day] && self.weekdays[weekday]
+		}
+		else
+		{
+			self.days[day] || self.weekdays[weekday]
+		}
+	}
+}
+
+priv fn local_tm(time: float) -> Tm
+{
+	std::time::at(Timespec::new(time as i64, 0))
+}
+
+// Days aren't always 24 hours long (they are 23 or 25 hours when daylight savings
+// time starts or ends) so the estimate of the next midnight is corrected using
+// the local time it maps to.
+priv fn start_of_next_day(time: float, tm: &Tm, to_tm: fn (float) -> Tm) -> float
+{
+	let next = time + (((23 - tm.tm_hour)*60 + 60 - tm.tm_min)*60) as float;
+	let next_tm = to_tm(next);
+	let corrected = next - ((next_tm.tm_hour*60 + next_tm.tm_min)*60) as float;
+	if next_tm.tm_mday != tm.tm_mday && corrected > time {corrected} else {next}
+}
+
+// Returns true if the local time also occurred an hour earlier (i.e. the clocks
+// went back).
+priv fn is_repeated(time: float, tm: &Tm, to_tm: fn (float) -> Tm) -> bool
+{
+	let earlier = to_tm(time - 60.0*60.0);
+	earlier.tm_mday == tm.tm_mday && earlier.tm_hour == tm.tm_hour && earlier.tm_min == tm.tm_min
+}
+
+// Returns a vector with an entry for each value in [min, max] which is true if
+// the field includes that value.
+priv fn parse_field(field: &str, min: uint, max: uint) -> result::Result<~[bool], ~str>
+{
+	let mut set = vec::from_elem(max - min + 1, false);
+	for str::split_char(field, ',').each |item|
+	{
+		let (range, step, has_step) = match str::find_char(*item, '/')
+		{
+			option::Some(i) => (item.slice(0, i), uint::from_str(item.slice(i + 1, item.len())), true),
+			option::None => (copy *item, option::Some(1), false),
+		};
+		
+		let bounds =
+			if range == ~"*"
+			{
+				option::Some((min, max))
+			}
+			else
+			{
+				match str::find_char(range, '-')
+				{
+					option::Some(i) =>
+					{
+						match (uint::from_str(range.slice(0, i)), uint::from_str(range.slice(i + 1, range.len())))
+						{
+							(option::Some(lower), option::Some(upper)) => option::Some((lower, upper)),
+							_ => option::None,
+						}
+					}
+					option::None =>
+					{
+						// Like cron 5/10 means 5, 15, 25, etc.
+						uint::from_str(range).map(|lower| {(*lower, if has_step {max} else {*lower})})
+					}
+				}
+			};
+		
+		match (bounds, step)
+		{
+			(option::Some((lower, upper)), option::Some(step)) if lower >= min && upper <= max && lower <= upper && step > 0 =>
+			{
+				let mut value = lower;
+				while value <= upper
+				{
+					set[value - min] = true;
+					value += step;
+				}
+			}
+			_ =>
+			{
+				return result::Err(fmt!("'%s' should be *, a number, or a range within [%?, %?] optionally followed by /step", *item, min, max));
+			}
+		}
+	}
+	result::Ok(set)
+}
+
+#[test]
+fn test_parse_cron()
+{
+	assert parse_cron("* * * * *").is_ok();
+	assert parse_cron("*/15 0-6,22,23 1 */3 1-5").is_ok();
+	
+	assert parse_cron("").is_err();
+	assert parse_cron("* * * *").is_err();
+	assert parse_cron("60 * * * *").is_err();
+	assert parse_cron("* * 0 * *").is_err();
+	assert parse_cron("5-1 * * * *").is_err();
+	assert parse_cron("*/0 * * * *").is_err();
+	assert parse_cron("x * * * *").is_err();
+	
+	let cron = parse_cron("5/20 * * * *").get();
+	assert cron.minutes.position_elem(&true) == option::Some(5);
+	assert cron.minutes.filtered(|b| {*b}).len() == 3;
+	
+	let cron = parse_cron("*/15 0-6,22 * * *").get();
+	assert cron.minutes.filtered(|b| {*b}).len() == 4;
+	assert cron.hours.filtered(|b| {*b}).len() == 8;
+	assert cron.to_str() == ~"*/15 0-6,22 * * *";
+}
+
+#[test]
+fn test_next_after()
+{
+	let now = 1357059600.0;		// 2013-01-01 17:00 UTC
+	
+	let cron = parse_cron("* * * * *").get();
+	let next = cron.next_after(now).get();
+	assert next > now && next <= now + 60.0;
+	
+	let cron = parse_cron("30 2 * * *").get();
+	let next = cron.next_after(now).get();
+	let tm = std::time::at(Timespec::new(next as i64, 0));
+	assert tm.tm_min == 30 && tm.tm_hour == 2;
+	assert next > now && next <= now + 25.0*60.0*60.0;
+	
+	let cron = parse_cron("0 0 * * 0").get();
+	let next = cron.next_after(now).get();
+	let tm = std::time::at(Timespec::new(next as i64, 0));
+	assert tm.tm_wday == 0 && tm.tm_hour == 0 && tm.tm_min == 0;
+	assert cron.next_after(next).get() > next;
+	
+	let cron = parse_cron("0 0 30 2 *").get();
+	assert cron.next_after(now).is_none();
+}
+
+#[test]
+fn test_daylight_savings()
+{
+	// Like US Eastern time in 2013: clocks went forward at 2am on March 10 and
+	// back at 2am on November 3.
+	fn eastern_tm(time: float) -> Tm
+	{
+		let offset = if time >= 1362898800.0 && time < 1383458400.0 {-4.0} else {-5.0};
+		std::time::at_utc(Timespec::new((time + offset*60.0*60.0) as i64, 0))
+	}
+	
+	// March 10 was a Sunday with only 23 hours
+	let cron = parse_cron("30 0 * * 1").get();
+	assert cron.next_after_in(1362892200.0, eastern_tm) == option::Some(1362976200.0);
+	
+	// November 3 was a Sunday with 25 hours
+	let cron = parse_cron("0 0 * * 1").get();
+	assert cron.next_after_in(1383453000.0, eastern_tm) == option::Some(1383541200.0);
+	
+	// 1:30 happened twice on November 3
+	let cron = parse_cron("30 1 * * *").get();
+	assert cron.next_after_in(1383453000.0, eastern_tm) == option::Some(1383456600.0);
+	assert cron.next_after_in(1383456600.0, eastern_tm) == option::Some(1383546600.0);
+}
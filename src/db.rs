@@ -3,19 +3,20 @@
 //! Used to test the client-side code.
 use model::*;
 use rrdf::*;
+use task_runner::*;
 
 // TODO: In the future this should be replaced with a turtle file
 // and --db should take a path to it (and maybe others).
-pub fn setup(state_chan: oldcomm::Chan<model::Msg>, poll_rate: u16) 
+pub fn setup(state_chan: oldcomm::Chan<model::Msg>, scheduler: Scheduler, poll_rate: u16) 
 {
-	oldcomm::send(state_chan, model::UpdateMsg(~"primary", |store, _data| {add_got(store, state_chan, poll_rate); true}, ~""));
+	oldcomm::send(state_chan, model::UpdateMsg(~"primary", |store, _data| {add_got(store, state_chan, scheduler, poll_rate); true}, ~""));
 	add_alerts(state_chan);
 }
 
 // This is designed to test live updating of views. What we do is degrade the loyalty (to the crown) of
 // winterfell and knight's landing. The color and level settings of the layalty gauges are adjusted
-// based on the current loyalty value.
-priv fn update_got(state_chan: oldcomm::Chan<model::Msg>, winterfell_loyalty_subject: ~str, winterfell_loyalty_value: f64, kings_landing_loyalty_subject: ~str, kings_landing_loyalty_value: f64, poll_rate: u16)
+// based on the current loyalty value. This is run by the scheduler every poll.
+priv fn update_got(state_chan: oldcomm::Chan<model::Msg>, winterfell_loyalty_subject: &str, kings_landing_loyalty_subject: &str)
 {
 	fn degrade_loyalty(value: f64, delta: f64) -> f64
 	{
@@ -49,37 +50,38 @@ priv fn update_got(state_chan: oldcomm::Chan<model::Msg>, winterfell_loyalty_sub
 		}
 	}
 	
-	libc::funcs::posix88::unistd::sleep(poll_rate as core::libc::types::os::arch::c95::c_uint);
-	
-	let winterfell_loyalty_value = degrade_loyalty(winterfell_loyalty_value, 0.2f64);
-	let kings_landing_loyalty_value = degrade_loyalty(kings_landing_loyalty_value, 0.1f64);
-	error!("winterfell_loyalty_value = %?", winterfell_loyalty_value);
-	error!("kings_landing_loyalty_value = %?", kings_landing_loyalty_value);
+	fn update_gauge(store: &Store, subject: ~str, delta: f64)
+	{
+		let value = match store.find_object(copy subject, ~"gnos:gauge")
+		{
+			option::Some(@FloatValue(value)) => degrade_loyalty(value, delta),
+			_ => 1.0f64,
+		};
+		error!("%s = %?", subject, value);
+		
+		store.replace_triple(~[], {subject: copy subject, predicate: ~"gnos:gauge", object: @FloatValue(value)});
+		
+		let (style, level) = gauge_state(value);
+		store.replace_triple(~[], {subject: copy subject, predicate: ~"gnos:level", object: @IntValue(level)});
+		store.replace_triple(~[], {subject: copy subject, predicate: ~"gnos:style", object: @StringValue(style, ~"")});
+	}
 	
+	let winterfell_loyalty_subject = winterfell_loyalty_subject.to_owned();
+	let kings_landing_loyalty_subject = kings_landing_loyalty_subject.to_owned();
 	oldcomm::send(state_chan, model::UpdateMsg(~"primary",
 		|store, _data, copy winterfell_loyalty_subject, copy kings_landing_loyalty_subject|
 		{
-			store.replace_triple(~[], {subject: copy winterfell_loyalty_subject, predicate: ~"gnos:gauge", object: @FloatValue(winterfell_loyalty_value)});
-			store.replace_triple(~[], {subject: copy kings_landing_loyalty_subject, predicate: ~"gnos:gauge", object: @FloatValue(kings_landing_loyalty_value)});
-			
-			let (style, level) = gauge_state(winterfell_loyalty_value);
-			store.replace_triple(~[], {subject: copy winterfell_loyalty_subject, predicate: ~"gnos:level", object: @IntValue(level)});
-			store.replace_triple(~[], {subject: copy winterfell_loyalty_subject, predicate: ~"gnos:style", object: @StringValue(style, ~"")});
-			
-			let (style, level) = gauge_state(kings_landing_loyalty_value);
-			store.replace_triple(~[], {subject: copy kings_landing_loyalty_subject, predicate: ~"gnos:level", object: @IntValue(level)});
-			store.replace_triple(~[], {subject: copy kings_landing_loyalty_subject, predicate: ~"gnos:style", object: @StringValue(style, ~"")});
+			update_gauge(store, copy winterfell_loyalty_subject, 0.2f64);
+			update_gauge(store, copy kings_landing_loyalty_subject, 0.1f64);
 			true
 		}, ~""));
-		
-	update_got(state_chan, winterfell_loyalty_subject, winterfell_loyalty_value, kings_landing_loyalty_subject, kings_landing_loyalty_value, poll_rate);
 }
 
-priv fn add_got(store: &Store, state_chan: oldcomm::Chan<model::Msg>, poll_rate: u16)
+priv fn add_got(store: &Store, state_chan: oldcomm::Chan<model::Msg>, scheduler: Scheduler, poll_rate: u16)
 {
 	add_globals(store, poll_rate);
 	add_entities(store);
-	add_infos(store, state_chan, poll_rate);
+	add_infos(store, state_chan, scheduler, poll_rate);
 	add_relations(store);
 	add_details(store);
 }
@@ -237,7 +239,7 @@ priv fn add_entities(store: &Store)
 	]);
 }
 
-priv fn add_infos(store: &Store, state_chan: oldcomm::Chan<model::Msg>, poll_rate: u16)
+priv fn add_infos(store: &Store, state_chan: oldcomm::Chan<model::Msg>, scheduler: Scheduler, poll_rate: u16)
 {
 	// wall labels
 	store.add(get_blank_name(store, ~"wall-label"), ~[
@@ -328,8 +330,13 @@ priv fn add_infos(store: &Store, state_chan: oldcomm::Chan<model::Msg>, poll_rat
 		(~"gnos:style",		@StringValue(~"gauge-bar-color:lime", ~"")),
 	]);
 	
-	// update_got calls libc sleep so it needs its own thread
-	do task::spawn_sched(task::SingleThreaded) |copy winterfell_loyalty_subject, copy kings_landing_loyalty_subject| {update_got(state_chan, copy winterfell_loyalty_subject, winterfell_loyalty_value, copy kings_landing_loyalty_subject, kings_landing_loyalty_value, poll_rate);}
+	let make: fn~ () -> Job = |copy winterfell_loyalty_subject, copy kings_landing_loyalty_subject|
+	{
+		let (winterfell_loyalty_subject, kings_landing_loyalty_subject) = (copy winterfell_loyalty_subject, copy kings_landing_loyalty_subject);
+		let action: JobFn = |move winterfell_loyalty_subject, move kings_landing_loyalty_subject| {update_got(state_chan, winterfell_loyalty_subject, kings_landing_loyalty_subject); option::None};
+		Job {name: ~"update got", action: action, policy: IgnoreFailures, timeout: option::None}
+	};
+	scheduler.add(Schedule {name: ~"update got", when: Every(poll_rate as float), jitter: 0.0, make: make});
 }
 
 priv fn add_alerts(state_chan: oldcomm::Chan<model::Msg>) -> bool
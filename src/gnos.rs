@@ -10,38 +10,12 @@ use rwebserve::{Config, Request, Response, ResponseHandler, OpenSse, linear_map_
 
 priv fn copy_scripts(root: &Path, user: &str, host: &str) -> option::Option<~str>
 {
-	let dir = core::os::make_absolute(root).pop();		// gnos/html => /gnos
-	let dir = dir.push(~"scripts");						// /gnos => /gnos/scripts
-	let files = utils::list_dir_path(&dir, ~[~".json", ~".py"]);
+	let files = utils::list_dir_path(&modeler::get_scripts_dir(root), ~[~".json", ~".py"]);
 	
 	utils::scp_files(files, user, host)
 }
 
-priv fn modeler_exited(script: &str, err: option::Option<~str>, state_chan: oldcomm::Chan<model::Msg>)
-{
-	let mesg =
-		if err.is_some()
-		{
-			fmt!("%s exited with stderr: %s\n", script, err.get())
-		}
-		else
-		{
-			fmt!("%s exited with no stderr", script)
-		};
-	
-	let lines = mesg.split_char('\n');
-	for lines.each |line| {error!("%s", *line)};
-	
-	let alert = model::Alert {target: ~"gnos:container", id: fmt!("%s exited", script), level: ~"error", mesg: mesg, resolution: ~"Restart gnos."};	// TODO: probably should have a button somewhere to restart the script (would have to close the alert)
-	oldcomm::send(state_chan, model::UpdateMsg(~"primary", |store, _err| {model::open_alert(store, &alert)}, ~""));
-}
-
-priv fn run_modeler(user: &str, host: &str, script: &str, network_file: &str, ip: &str, port: u16) -> option::Option<~str>
-{
-	utils::run_remote_command(user, host, fmt!("python %s --ip=%s --port=%? -v %s", script, ip, port, network_file))
-}
-
-priv fn setup(options: &options::Options, state_chan: oldcomm::Chan<model::Msg>) -> ~[ExitFn]
+priv fn setup(options: &options::Options, ctx: &modeler::ModelerContext, modelers: modeler::Modelers, logs: syslog::SyslogLogs) -> ~[ExitFn]
 {
 	let mut cleanup = ~[];
 	
@@ -51,35 +25,59 @@ priv fn setup(options: &options::Options, state_chan: oldcomm::Chan<model::Msg>)
 		os::make_dir(&path, 7*8*8 + 7*8 + 7);
 	}
 	
-	let root = copy options.root;
-	let client = copy options.client_ip;
-	let action: task_runner::JobFn = |copy client| copy_scripts(&root, env!("GNOS_USER"), client);
-	task_runner::run_blocking(Job {action: action, policy: task_runner::ShutdownOnFailure}, ~[]);
+	if options::uses_ssh(options)
+	{
+		let root = copy options.root;
+		let client = copy options.client_ip;
+		let action: task_runner::JobFn = |copy client| copy_scripts(&root, env!("GNOS_USER"), client);
+		task_runner::run_blocking(ctx.registry, Job {name: ~"copy scripts", action: action, policy: task_runner::ShutdownOnFailure, timeout: option::Some(60.0)}, ~[]);
+	}
 	
-	let network_file = copy(options.network_file);
-	let mut modelers = ~[];
+	// Scripts model all of the devices which use them so they are only started once.
+	let mut started = ~[];
 	for options.devices.each |device|
 	{
-		if !vec::contains(modelers, &@copy device.modeler)
+		let key = match device.modeler_type {options::Builtin => copy device.name, _ => copy device.modeler};
+		if !started.contains(&key)
 		{
-			let client = copy options.client_ip;
-			let network_file = copy network_file;
-			let modeler = copy device.modeler;
-			let ip = copy options.bind_ip;
-			let port = copy options.bind_port;
-			let action: task_runner::JobFn = |copy client, copy modeler| run_modeler(env!("GNOS_USER"), client, modeler, network_file, ip, port);
-			let script = Job {action: action, policy: task_runner::NotifyOnExit(|err, copy modeler| {modeler_exited(modeler, err, state_chan)})};
-			modelers.push(@copy modeler);
-			
-			let exit: task_runner::ExitFn = || {utils::run_remote_command(env!("GNOS_USER"), client, fmt!("pgrep -f %s | xargs --no-run-if-empty kill -9", modeler));};
-			task_runner::sequence(~[script], ~[copy exit]);
-			cleanup.push(exit);
+			cleanup.push(modeler::start_modeler(device, ctx, modelers));
+			started.push(key);
 		}
 	}
+	cleanup.push(modeler::wait_for_modelers(ctx));
+	
+	if options.trap_port != 0
+	{
+		cleanup.push(traps::start_trap_listener(options, ctx.state_chan));
+	}
+	
+	if options.syslog_port != 0
+	{
+		cleanup.push(syslog::start_syslog_listener(options, logs, ctx.state_chan));
+	}
+	
+	if options.netflow_port != 0
+	{
+		cleanup.push(netflow::start_netflow_collector(ctx));
+	}
+	
+	if options.sflow_port != 0
+	{
+		cleanup.push(sflow::start_sflow_collector(ctx));
+	}
 	
 	cleanup
 }
 
+// Ensure that samples from the last poll make it to disk.
+priv fn flush_samples(samples_chan: oldcomm::Chan<samples::Msg>)
+{
+	let port = oldcomm::Port();
+	let chan = oldcomm::Chan(&port);
+	oldcomm::send(samples_chan, samples::FlushMsg(chan));
+	oldcomm::recv(port);
+}
+
 priv fn get_shutdown(cleanup: ~[ExitFn]) -> !
 {
 	info!("received shutdown request");
@@ -116,29 +114,42 @@ priv fn static_view(options: &options::Options, config: &Config, request: &Reque
 	response
 }
 
+// Max number of charts (e.g. sparklines) to render at once in the background.
+priv const max_renders: uint = 2;
+
 fn main()
 {
 	info!("starting up gnos");
-	if env!("GNOS_USER").is_empty()
+	let mut options = options::parse_command_line(os::args());
+	options::validate(&options);
+	
+	if !options.db && options::uses_ssh(&options) && env!("GNOS_USER").is_empty()
 	{
 		error!("GNOS_USER must be set to the name of a user able to ssh into the network json client.");
 		libc::exit(1)
 	}
 	
-	let mut options = options::parse_command_line(os::args());
-	options::validate(&options);
-	
 	let state_chan = do utils::spawn_moded_listener(task::ThreadPerCore) |port, copy options| {model::manage_state(port, options.bind_ip, options.bind_port)};
-	let samples_chan = do utils::spawn_moded_listener(task::ThreadPerCore) |port| {samples::manage_samples(port)};
-	let cleanup = if !options.db
+	let samples_chan = do utils::spawn_moded_listener(task::ThreadPerCore) |port, copy options| {samples::manage_samples(port, copy options.samples_dir, state_chan, copy options.thresholds, copy options.anomalies)};
+	let registry = JobRegistry();
+	let render_queue = JobQueue(registry, max_renders);
+	let scheduler = Scheduler(registry);
+	let logs = syslog::SyslogLogs(&options, state_chan, samples_chan, render_queue);
+	let runs = tools::ToolRuns(&options.artifacts_dir);
+	let modelers = modeler::Modelers();
+	scheduler.add(samples::compact_schedule(&options.samples_dir));
+	let mut cleanup = if !options.db
 		{
-			setup(&options, state_chan)
+			let ctx = modeler::ModelerContext {options: copy options, state_chan: state_chan, samples_chan: samples_chan, render_queue: render_queue, registry: registry, scheduler: scheduler};
+			setup(&options, &ctx, modelers, logs)
 		}
 		else
 		{
-			db::setup(state_chan, options.poll_rate);
+			db::setup(state_chan, scheduler, options.poll_rate);
 			~[]
 		};
+	cleanup.push(|| {render_queue.shutdown(10.0)});
+	cleanup.push(|| {flush_samples(samples_chan)});
 	
 	let options1 = copy options;
 	oldcomm::send(state_chan, model::UpdateMsg(~"globals", |store, _err| {update_globals(store, &options1)}, ~""));
@@ -149,14 +160,28 @@ fn main()
 	let subject_v: ResponseHandler = |_config, request, response, copy options| {get_subject::get_subject(&options, request, response)};
 	let details_v: ResponseHandler = |_config, request, response, copy options| {get_details::get_details(&options, request, response)};
 	let home_v: ResponseHandler = |_config, _request, response, copy options| {get_home::get_home(&options, response)};
-	let modeler_p: ResponseHandler = |_config, request, response, copy options| {put_json::put_json(&options, state_chan, samples_chan, request, response)};
+	let jobs_v: ResponseHandler = |_config, _request, response| {get_jobs::get_jobs(registry, modelers, response)};
+	let job_p: ResponseHandler = |_config, request, response, copy options| {post_job::post_job(&options, registry, request, response)};
+	let modeler_p: ResponseHandler = |_config, request, response, copy options| {put_json::put_json(&options, state_chan, samples_chan, render_queue, request, response)};
 	let query_store_v: ResponseHandler = |_config, request, response, copy options| {get_query_store::get_query_store(&options, request, response)};
+	let chart_v: ResponseHandler = |config, request, response, copy options| {get_chart::get_chart(&options, samples_chan, render_queue, config, request, response)};
+	let samples_v: ResponseHandler = |_config, request, response| {get_samples::get_samples(samples_chan, request, response)};
+	let sample_sets_v: ResponseHandler = |_config, request, response| {get_samples::get_sample_sets(samples_chan, request, response)};
+	let schedules_v: ResponseHandler = |_config, _request, response| {get_schedules::get_schedules(scheduler, response)};
+	let schedule_p: ResponseHandler = |_config, request, response, copy options| {post_schedule::post_schedule(&options, scheduler, request, response)};
 	let bail_v: ResponseHandler = |_config, _request, _response| {get_shutdown(copy cleanup)};
 	let static_v: ResponseHandler = |config, request, response, copy options| {static_view(&options, config, request, response)};
+	let syslog_v: ResponseHandler = |_config, request, response| {get_syslog::get_syslog(logs, request, response)};
 	let test_v: ResponseHandler = |_config, request, response| {get_test::get_test(request, response)};
+	let tools_v: ResponseHandler = |_config, _request, response, copy options| {get_tools::get_tools(&options, response)};
+	let tool_p: ResponseHandler = |_config, request, response, copy options| {post_tool::post_tool(&options, registry, runs, request, response)};
+	let tool_artifact_v: ResponseHandler = |_config, request, response, copy options| {get_tool_artifact::get_tool_artifact(&options, request, response)};
 	
 	let query_s: OpenSse = |_config, request, push| {sse_query::sse_query(state_chan, request, push)};
 	let samples_s: OpenSse = |_config, request, push| {sse_samples::sse_query(samples_chan, request, push)};
+	let series_s: OpenSse = |_config, request, push| {sse_series::sse_query(samples_chan, request, push)};
+	let jobs_s: OpenSse = |_config, request, push| {sse_jobs::sse_query(registry, modelers, request, push)};
+	let tool_s: OpenSse = |_config, request, push, copy options| {sse_tool::sse_query(&options, runs, request, push)};
 	
 	let config = Config
 	{
@@ -169,27 +194,49 @@ fn main()
 		resources_root: copy options.root,
 		routes: ~[
 			Route(~"home", ~"GET", ~"/"),
+			Route(~"chart", ~"GET", ~"/chart/{name}"),
 			Route(~"details", ~"GET", ~"/details/{name}/*subject"),
+			Route(~"jobs", ~"GET", ~"/jobs"),
+			Route(~"job", ~"POST", ~"/jobs/{action}"),
 			Route(~"shutdown", ~"GET", ~"/shutdown"),		// TODO: enable this via debug cfg (or maybe via a command line option)
 			Route(~"models", ~"GET", ~"/models"),
 			Route(~"query_store", ~"GET", ~"/query-store"),
+			Route(~"samples", ~"GET", ~"/samples/{name}"),
+			Route(~"sample_sets", ~"GET", ~"/sample-sets"),
+			Route(~"schedules", ~"GET", ~"/schedules"),
+			Route(~"schedule", ~"POST", ~"/schedules/{action}"),
 			Route(~"subject", ~"GET", ~"/subject/{name}/*subject"),
+			Route(~"syslog", ~"GET", ~"/syslog/{ip}"),
 			Route(~"test", ~"GET", ~"/test"),
+			Route(~"tools", ~"GET", ~"/tools"),
+			Route(~"tool", ~"POST", ~"/tools/{tool}"),
+			Route(~"tool_artifact", ~"GET", ~"/tool-artifacts/{name}"),
 			Route(~"modeler", ~"GET", ~"/modeler"),
 			Route(~"modeler", ~"PUT", ~"/modeler"),
 		],
 		views: linear_map_from_vector(~[
 			(~"home",  home_v),
+			(~"chart",  chart_v),
 			(~"details",  details_v),
+			(~"jobs",  jobs_v),
+			(~"job",  job_p),
 			(~"shutdown",  bail_v),
 			(~"models",  models_v),
 			(~"query_store",  query_store_v),
+			(~"samples",  samples_v),
+			(~"sample_sets",  sample_sets_v),
+			(~"schedules",  schedules_v),
+			(~"schedule",  schedule_p),
 			(~"subject",  subject_v),
+			(~"syslog",  syslog_v),
 			(~"modeler",  modeler_p),
 			(~"test",  test_v),
+			(~"tools",  tools_v),
+			(~"tool",  tool_p),
+			(~"tool_artifact",  tool_artifact_v),
 		]),
 		static_handler: static_v,
-		sse: linear_map_from_vector(~[(~"/query", query_s), (~"/samples", samples_s)]),
+		sse: linear_map_from_vector(~[(~"/query", query_s), (~"/samples", samples_s), (~"/series", series_s), (~"/job-events", jobs_s), (~"/tool-events", tool_s)]),
 		settings: linear_map_from_vector(~[(~"debug",  ~"true")]),		// TODO: make this a command-line option
 		..rwebserve::initialize_config()
 	};
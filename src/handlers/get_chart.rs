@@ -0,0 +1,187 @@
+/// Renders charts registered by the modelers on demand:
+///
+/// * **/chart/{name}.png?width=&height=&range=** Returns a PNG for the chart.
+///
+/// * **/chart/{name}.svg?width=&height=&range=** Returns an SVG for the chart.
+///
+/// Width and height are in pixels and default to 800x500. They are rounded up
+/// to one of a few sizes (see sizes) so that clients can't fill the cache with
+/// charts that differ by a pixel. Range is the time span to chart in seconds
+/// and defaults to the range the modeler registered the chart with. Charts are
+/// rendered using the render queue and are cached in html/generated/charts
+/// until new samples are added to the chart's sample sets (the least recently
+/// used charts are deleted once there are more than samples::max_cached_charts).
+use oldcomm::{Chan, Port};
+use samples::{Msg, GetChart, ChartRendered, UnknownChart, CachedChart, RenderChart, FailedChart};
+use server = rwebserve;
+use RingBuffer = ring_buffer::RingBuffer;
+use task_runner::{Job, JobFn, JobQueue};
+
+pub fn get_chart(options: &options::Options, samples_chan: Chan<Msg>, render_queue: JobQueue, config: &server::Config, request: &server::Request, response: server::Response) -> server::Response
+{
+	let file = request.matches.get(@~"name");
+	let (name, format) =
+		if file.ends_with(".png")
+		{
+			(file.slice(0, file.len() - 4), ~"png")
+		}
+		else if file.ends_with(".svg")
+		{
+			(file.slice(0, file.len() - 4), ~"svg")
+		}
+		else
+		{
+			return error_response(response, ~"404 Not Found", fmt!("Charts should end with .png or .svg: %s", file));
+		};
+	
+	let width = get_uint(request, ~"width", 800);
+	let height = get_uint(request, ~"height", 500);
+	let range = match request.params.find(@~"range")
+	{
+		option::Some(ref text) => float::from_str(*text),
+		option::None => option::Some(0.0),
+	};
+	if width.is_none() || height.is_none() || range.is_none()
+	{
+		return error_response(response, ~"400 Bad Request", ~"width, height, and range should be numbers");
+	}
+	let (width, height, range) = (get_size(width.get()), get_size(height.get()), range.get());
+	if !(range >= 0.0) || float::is_infinite(range)
+	{
+		return error_response(response, ~"400 Bad Request", ~"range should be a non-negative number");
+	}
+	
+	// The range is printed with enough digits that different ranges get different keys.
+	let key = fmt!("%s-%?x%?-%s.%s", name, width, height, float::to_str(range, 17), format);
+	let port = Port();
+	let chan = Chan(&port);
+	oldcomm::send(samples_chan, GetChart(copy name, copy key, range, chan));
+	match oldcomm::recv(port)
+	{
+		UnknownChart =>
+		{
+			return error_response(response, ~"404 Not Found", fmt!("Unknown chart: %s", name));
+		}
+		CachedChart =>
+		{
+		}
+		FailedChart(ref err) =>
+		{
+			return error_response(response, ~"500 Internal Server Error", copy *err);
+		}
+		RenderChart(move chart, move times, move buffers, generation) =>
+		{
+			let dir = os::make_absolute(&options.root).push("generated").push("charts");
+			if !os::path_is_dir(&dir)
+			{
+				os::make_dir(&dir, 7*8*8 + 5*8 + 5);
+			}
+			let path = dir.push(copy key);
+			
+			match render_chart(render_queue, &path, &options.scratch_dir, chart, times, buffers, width, height, format == ~"png")
+			{
+				option::None =>
+				{
+					oldcomm::send(samples_chan, ChartRendered(copy key, path, generation, option::None));
+				}
+				option::Some(err) =>
+				{
+					error!("%s", err);
+					oldcomm::send(samples_chan, ChartRendered(copy key, path, generation, option::Some(copy err)));
+					return error_response(response, ~"500 Internal Server Error", err);
+				}
+			}
+		}
+	}
+	
+	// Let rwebserve serve up the file.
+	let request = server::Request {path: fmt!("/generated/charts/%s", key), ..copy *request};
+	let mut response = server::configuration::static_view(config, &request, response);
+	response.headers.insert(~"Cache-Control", fmt!("max-age=%?", options.poll_rate - 1));
+	if format == ~"svg"
+	{
+		response.headers.insert(~"Content-Type", ~"image/svg+xml");
+	}
+	response
+}
+
+// ---- Internal Functions ----------------------------------------------------
+
+// Sizes (in pixels) charts are rendered at. These are small enough that clients
+// can't make us allocate huge images.
+priv const sizes: [uint * 7] = [200, 300, 400, 500, 800, 1200, 1600];
+
+// Seconds to wait for the render queue to render a chart.
+priv const render_timeout: float = 60.0;
+
+// Returns the smallest allowed size that is at least size (or the largest size).
+priv fn get_size(size: uint) -> uint
+{
+	match vec::find(sizes, |s| {*s >= size})
+	{
+		option::Some(s) => s,
+		option::None => sizes[sizes.len() - 1],
+	}
+}
+
+// Renders the chart to path using the render queue (so that chart requests
+// can't swamp the machine) and waits for it to finish.
+priv fn render_chart(render_queue: JobQueue, path: &Path, scratch: &Path, chart: samples::Chart, times: RingBuffer<float>, buffers: ~[RingBuffer<float>], width: uint, height: uint, png: bool) -> option::Option<~str>
+{
+	let port = Port();
+	let chan = Chan(&port);
+	
+	let name = fmt!("%s chart", chart.name);
+	let file = path.to_str();
+	let (path, scratch) = (copy *path, copy *scratch);
+	let action: JobFn = |move path, move scratch, move chart, move times, move buffers|
+	{
+		let bytes =
+			if png
+			{
+				chart::chart_to_png(&chart, &times, buffers, width, height)
+			}
+			else
+			{
+				str::to_bytes(chart::chart_to_svg(&chart, &times, buffers, width, height))
+			};
+		chart::write_file(&path, bytes, &scratch)
+	};
+	let notify: fn~ (option::Option<~str>) = |err| {oldcomm::send(chan, err)};
+	
+	// GetChart ensures that there is only one render per key at a time so
+	// this job never replaces an older job.
+	render_queue.enqueue(copy file, Job {name: name, action: action, policy: task_runner::NotifyOnExit(notify), timeout: option::Some(render_timeout)});
+	match std::timer::recv_timeout(uv::global_loop::get(), (1000.0*render_timeout) as uint + 1000, port)
+	{
+		option::Some(move err) => err,
+		option::None => option::Some(fmt!("%s did not finish", file)),
+	}
+}
+
+priv fn get_uint(request: &server::Request, key: ~str, default: uint) -> option::Option<uint>
+{
+	match request.params.find(@key)
+	{
+		option::Some(ref text) => uint::from_str(*text),
+		option::None => option::Some(default),
+	}
+}
+
+priv fn error_response(response: server::Response, status: ~str, mesg: ~str) -> server::Response
+{
+	let mut response = response;
+	response.status = status;
+	response.headers.insert(~"Content-Type", ~"text/plain");
+	server::Response {body: rwebserve::configuration::StringBody(@mesg), ..response}
+}
+
+#[test]
+fn test_get_size()
+{
+	assert get_size(0) == 200;
+	assert get_size(200) == 200;
+	assert get_size(201) == 300;
+	assert get_size(800) == 800;
+	assert get_size(100000) == 1600;
+}
@@ -0,0 +1,52 @@
+/// Returns what task_runner jobs are doing:
+///
+/// * **/jobs** Returns a json list of objects of the form:
+/// {"name": "sparkline", "state": "failed", "started": 1357600000.5,
+/// "finished": 1357600002.0, "runs": 3, "error": "stderr", "restartable": true,
+/// "health": null}
+/// where started and finished are seconds since the epoch (or null) and health
+/// is "starting", "healthy", "unhealthy", or "stopped" for modeler jobs (and
+/// null for other jobs).
+///
+/// See post_job for cancelling and restarting jobs and sse_jobs (/job-events)
+/// for a stream of updates.
+use server = rwebserve;
+use modeler::{Health, Modelers};
+use task_runner::{JobRegistry, JobInfo};
+
+pub fn get_jobs(registry: JobRegistry, modelers: Modelers, response: server::Response) -> server::Response
+{
+	let body = jobs_to_json(registry.list(), modelers.health()).to_str();
+	
+	let mut response = response;
+	response.headers.insert(~"Content-Type", ~"application/json");
+	response.headers.insert(~"Cache-Control", ~"no-cache");
+	server::Response {body: rwebserve::configuration::StringBody(@body), ..response}
+}
+
+pub fn jobs_to_json(jobs: &[JobInfo], health: &[(~str, Health)]) -> std::json::Json
+{
+	fn time_to_json(time: float) -> std::json::Json
+	{
+		if float::is_NaN(time) {std::json::Null} else {std::json::Number(time)}
+	}
+	
+	std::json::List(
+		do vec::map(jobs) |job|
+		{
+			let mut map = ~send_map::linear::LinearMap();
+			map.insert(~"name", std::json::String(copy job.name));
+			map.insert(~"state", std::json::String(job.state.to_str()));
+			map.insert(~"started", time_to_json(job.started));
+			map.insert(~"finished", time_to_json(job.finished));
+			map.insert(~"runs", std::json::Number(job.runs as float));
+			map.insert(~"error", std::json::String(copy job.error));
+			map.insert(~"restartable", std::json::Boolean(job.restartable));
+			map.insert(~"health", match health.find(|h| {let (ref name, _) = *h; *name == job.name})
+			{
+				option::Some((_, ref h)) => std::json::String(h.to_str()),
+				option::None => std::json::Null,
+			});
+			std::json::Object(map)
+		})
+}
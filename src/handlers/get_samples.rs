@@ -0,0 +1,288 @@
+/// Returns raw sample data so that it can be used by tools other than gnos
+/// (e.g. notebooks or other dashboards). Two forms are supported:
+///
+/// * **/samples/{name}?from=&to=&step=&format=** Returns the samples for a
+/// single sample set.
+///
+/// * **/sample-sets?names=a,b&from=&to=&step=&format=** Like the above except
+/// that multiple sample sets are returned.
+///
+/// Json is an object mapping each sample set name to a list of [time, value]
+/// lists (or null if the sample set is unknown). Csv has one time column so
+/// the sample sets must have the same owner (e.g. come from the same modeler).
+///
+/// From and to are seconds since the epoch (negative values are relative to the
+/// current time). From defaults to an hour ago and to defaults to now. If step is
+/// present then the samples are averaged into buckets of step seconds. Format
+/// may be json (the default) or csv.
+use oldcomm::{Chan, Port};
+use samples::{Msg, GetSampleSets};
+use server = rwebserve;
+use RingBuffer = ring_buffer::RingBuffer;
+
+pub fn get_samples(samples_chan: Chan<Msg>, request: &server::Request, response: server::Response) -> server::Response
+{
+	let name = request.matches.get(@~"name");
+	get_sample_data(samples_chan, request, response, ~[name])
+}
+
+pub fn get_sample_sets(samples_chan: Chan<Msg>, request: &server::Request, response: server::Response) -> server::Response
+{
+	match request.params.find(@~"names")
+	{
+		option::Some(ref names) =>
+		{
+			let names = do str::split_char(*names, ',').filter |n| {n.is_not_empty()};
+			get_sample_data(samples_chan, request, response, names)
+		}
+		option::None =>
+		{
+			bad_request(response, ~"names parameter is missing")
+		}
+	}
+}
+
+// ---- Internal Functions ----------------------------------------------------
+priv fn get_sample_data(samples_chan: Chan<Msg>, request: &server::Request, response: server::Response, names: ~[~str]) -> server::Response
+{
+	if names.is_empty()
+	{
+		return bad_request(response, ~"no sample set names were specified");
+	}
+	
+	let now = utils::imprecise_time_s();
+	let from = get_time(request, ~"from", now - 60.0*60.0, now);
+	let to = get_time(request, ~"to", now, now);
+	let step = get_float(request, ~"step", 0.0);
+	if from.is_none() || to.is_none() || step.is_none()
+	{
+		return bad_request(response, ~"from, to, and step should be numbers");
+	}
+	let (from, to, step) = (from.get(), to.get(), step.get());
+	
+	let port = Port();
+	let chan = Chan(&port);
+	oldcomm::send(samples_chan, GetSampleSets(copy names, now - from, tiers::Average, chan));
+	let series = oldcomm::recv(port);
+	if series.all(|s| {s.is_none()})
+	{
+		let mut response = response;
+		response.status = ~"404 Not Found";
+		response.headers.insert(~"Content-Type", ~"text/plain");
+		return server::Response {body: rwebserve::configuration::StringBody(@fmt!("Unknown sample set: %s", str::connect(names, ", "))), ..response};
+	}
+	
+	let series = do series.map |s|
+		{
+			match *s
+			{
+				option::Some((ref times, ref values)) => option::Some(get_rows(times, ~[copy *values], from, to, step)),
+				option::None => option::None,
+			}
+		};
+	let format = match request.params.find(@~"format") {option::Some(ref f) => copy *f, option::None => ~"json"};
+	if format == ~"csv"
+	{
+		match rows_to_csv(names, series)
+		{
+			result::Ok(text) =>
+			{
+				let mut response = response;
+				response.headers.insert(~"Content-Type", ~"text/csv");
+				server::Response {body: rwebserve::configuration::StringBody(@text), ..response}
+			}
+			result::Err(err) =>
+			{
+				bad_request(response, err)
+			}
+		}
+	}
+	else if format == ~"json"
+	{
+		let mut response = response;
+		response.headers.insert(~"Content-Type", ~"application/json");
+		server::Response {body: rwebserve::configuration::StringBody(@series_to_json(names, series).to_str()), ..response}
+	}
+	else
+	{
+		bad_request(response, fmt!("format should be json or csv, not %s", format))
+	}
+}
+
+// Returns rows of the form (time, values) within [from, to].
+priv fn get_rows(times: &RingBuffer<float>, buffers: &[RingBuffer<float>], from: float, to: float, step: float) -> ~[(float, ~[float])]
+{
+	let mut rows = ~[];
+	for uint::range(0, times.len()) |i|
+	{
+		let time = times[i];
+		if time >= from && time <= to
+		{
+			rows.push((time, do buffers.map |b| {b[i]}));
+		}
+	}
+	
+	if step > 0.0 {average_rows(rows, step)} else {rows}
+}
+
+// Averages the rows into buckets of step seconds (NaNs are ignored). The times
+// of the new rows are the start of each bucket.
+priv fn average_rows(rows: &[(float, ~[float])], step: float) -> ~[(float, ~[float])]
+{
+	let mut result = ~[];
+	let mut i = 0;
+	while i < rows.len()
+	{
+		let bucket = float::floor(rows[i].first()/step)*step;
+		let num_values = rows[i].second().len();
+		let mut sums = vec::from_elem(num_values, 0.0);
+		let mut counts = vec::from_elem(num_values, 0u);
+		while i < rows.len() && rows[i].first() < bucket + step
+		{
+			for rows[i].second().eachi |j, value|
+			{
+				if !float::is_NaN(*value)
+				{
+					sums[j] += *value;
+					counts[j] += 1;
+				}
+			}
+			i += 1;
+		}
+		
+		let values = do vec::from_fn(num_values) |j| {if counts[j] > 0 {sums[j]/(counts[j] as float)} else {float::NaN}};
+		result.push((bucket, values));
+	}
+	result
+}
+
+// Returns an object mapping each sample set name to a list of [time, value]
+// lists (or null for unknown sample sets).
+priv fn series_to_json(names: &[~str], series: &[option::Option<~[(float, ~[float])]>]) -> std::json::Json
+{
+	fn value_to_json(value: float) -> std::json::Json
+	{
+		if float::is_NaN(value) {std::json::Null} else {std::json::Number(value)}
+	}
+	
+	let mut map = ~send_map::linear::LinearMap();
+	for names.eachi |j, name|
+	{
+		let value = match series[j]
+			{
+				option::Some(ref rows) =>
+				{
+					std::json::List(
+						do rows.map |row|
+						{
+							std::json::List(~[std::json::Number(row.first()), value_to_json(row.second()[0])])
+						})
+				}
+				option::None =>
+				{
+					std::json::Null
+				}
+			};
+		map.insert(copy *name, value);
+	}
+	std::json::Object(map)
+}
+
+// All of the known sample sets must have the same times. Columns for unknown
+// sample sets are empty.
+priv fn rows_to_csv(names: &[~str], series: &[option::Option<~[(float, ~[float])]>]) -> result::Result<~str, ~str>
+{
+	let known = do vec::filter_map(series) |s| {copy *s};
+	let times = do known[0].map |row| {row.first()};
+	if known.any(|rows| {rows.map(|row| {row.first()}) != times})
+	{
+		return result::Err(~"csv requires sample sets with the same times (i.e. the same owner)");
+	}
+	
+	let mut text = ~"time," + str::connect(names, ",") + "\n";
+	for times.eachi |i, time|
+	{
+		let values = do series.map |s|
+			{
+				match *s
+				{
+					option::Some(ref rows) if !float::is_NaN(rows[i].second()[0]) => rows[i].second()[0].to_str(),
+					_ => ~"",
+				}
+			};
+		text += fmt!("%s,%s\n", time.to_str(), str::connect(values, ","));
+	}
+	result::Ok(text)
+}
+
+priv fn get_time(request: &server::Request, key: ~str, default: float, now: float) -> option::Option<float>
+{
+	match get_float(request, key, default)
+	{
+		option::Some(value) if value < 0.0 => option::Some(now + value),
+		x => x,
+	}
+}
+
+priv fn get_float(request: &server::Request, key: ~str, default: float) -> option::Option<float>
+{
+	match request.params.find(@key)
+	{
+		option::Some(ref text) => float::from_str(*text),
+		option::None => option::Some(default),
+	}
+}
+
+priv fn bad_request(response: server::Response, mesg: ~str) -> server::Response
+{
+	let mut response = response;
+	response.status = ~"400 Bad Request";
+	response.headers.insert(~"Content-Type", ~"text/plain");
+	server::Response {body: rwebserve::configuration::StringBody(@mesg), ..response}
+}
+
+#[test]
+fn test_average_rows()
+{
+	let rows = ~[(10.0, ~[1.0, 2.0]), (20.0, ~[3.0, float::NaN]), (35.0, ~[5.0, 6.0])];
+	let result = average_rows(rows, 30.0);
+	assert result.len() == 2;
+	assert result[0].first() == 0.0;
+	assert result[0].second() == ~[2.0, 2.0];
+	assert result[1].first() == 30.0;
+	assert result[1].second() == ~[5.0, 6.0];
+}
+
+#[test]
+fn test_series_to_json()
+{
+	use std::json::{List, Number, Null, Object};
+	
+	let series = ~[option::Some(~[(10.0, ~[1.0]), (20.0, ~[float::NaN])]), option::None];
+	match series_to_json(~[~"eth0", ~"lo"], series)
+	{
+		Object(ref map) =>
+		{
+			assert *map.get(&~"eth0") == List(~[List(~[Number(10.0), Number(1.0)]), List(~[Number(20.0), Null])]);
+			assert *map.get(&~"lo") == Null;
+		}
+		_ => fail ~"expected an object",
+	}
+	
+	// a single sample set is still an object
+	match series_to_json(~[~"eth0"], ~[option::Some(~[(10.0, ~[1.0])])])
+	{
+		Object(ref map) => assert map.len() == 1,
+		_ => fail ~"expected an object",
+	}
+}
+
+#[test]
+fn test_rows_to_csv()
+{
+	let series = ~[option::Some(~[(10.0, ~[1.0]), (20.0, ~[float::NaN])]), option::None];
+	assert rows_to_csv(~[~"eth0", ~"lo"], series) == result::Ok(fmt!("time,eth0,lo\n%s,%s,\n%s,,\n", 10.0.to_str(), 1.0.to_str(), 20.0.to_str()));
+	
+	let series = ~[option::Some(~[(10.0, ~[1.0])]), option::Some(~[(15.0, ~[1.0])])];
+	assert rows_to_csv(~[~"a", ~"b"], series).is_err();
+}
@@ -0,0 +1,43 @@
+/// Returns the jobs which the task_runner Scheduler runs:
+///
+/// * **/schedules** Returns a json list of objects of the form:
+/// {"name": "compact samples", "when": "cron 0 3 * * *", "next_run": 1357700400.0,
+/// "last_run": 1357614000.0, "runs": 1, "skipped": 0, "running": false, "paused": false}
+/// where next_run and last_run are seconds since the epoch (or null).
+///
+/// See post_schedule for pausing and resuming schedules.
+use server = rwebserve;
+use task_runner::{Scheduler, ScheduleInfo};
+
+pub fn get_schedules(scheduler: Scheduler, response: server::Response) -> server::Response
+{
+	let body = schedules_to_json(scheduler.list()).to_str();
+	
+	let mut response = response;
+	response.headers.insert(~"Content-Type", ~"application/json");
+	response.headers.insert(~"Cache-Control", ~"no-cache");
+	server::Response {body: rwebserve::configuration::StringBody(@body), ..response}
+}
+
+pub fn schedules_to_json(schedules: &[ScheduleInfo]) -> std::json::Json
+{
+	fn time_to_json(time: float) -> std::json::Json
+	{
+		if float::is_NaN(time) {std::json::Null} else {std::json::Number(time)}
+	}
+	
+	std::json::List(
+		do vec::map(schedules) |s|
+		{
+			let mut map = ~send_map::linear::LinearMap();
+			map.insert(~"name", std::json::String(copy s.name));
+			map.insert(~"when", std::json::String(copy s.when));
+			map.insert(~"next_run", time_to_json(s.next_run));
+			map.insert(~"last_run", time_to_json(s.last_run));
+			map.insert(~"runs", std::json::Number(s.runs as float));
+			map.insert(~"skipped", std::json::Number(s.skipped as float));
+			map.insert(~"running", std::json::Boolean(s.running));
+			map.insert(~"paused", std::json::Boolean(s.paused));
+			std::json::Object(map)
+		})
+}
@@ -0,0 +1,71 @@
+/// Returns syslog messages received from a device:
+///
+/// * **/syslog/{ip}?search=REGEX&limit=N** Returns a json list of objects of
+/// the form:
+/// {"received": 1357600000.5, "facility": "daemon", "severity": "err",
+/// "timestamp": "Mar  1 09:02:03", "host": "rtr-4", "app": "ospfd", "mesg": "..."}
+/// with the newest messages first. search is optional and filters the
+/// messages to those whose app or mesg matches (see regex.rs). Searches which
+/// take too long to match return 400. limit defaults to 100.
+///
+/// See syslog.rs for how messages are received.
+use server = rwebserve;
+use syslog::{SyslogLogs, Entry};
+
+pub fn get_syslog(logs: SyslogLogs, request: &server::Request, response: server::Response) -> server::Response
+{
+	let ip = request.matches.get(@~"ip");
+	let pattern = match request.params.find(@~"search")
+		{
+			option::Some(ref search) if search.is_not_empty() =>
+			{
+				match regex::Regex(*search)
+				{
+					result::Ok(re) => option::Some(re),
+					result::Err(ref err) => return utils::text_response(response, ~"400 Bad Request", fmt!("Bad search: %s", *err)),
+				}
+			}
+			_ => option::None,
+		};
+	let limit = match request.params.find(@~"limit")
+		{
+			option::Some(ref limit) =>
+			{
+				match uint::from_str(*limit)
+				{
+					option::Some(n) => n,
+					option::None => return utils::text_response(response, ~"400 Bad Request", fmt!("Bad limit: %s", *limit)),
+				}
+			}
+			option::None => 100,
+		};
+	
+	let body = match logs.search(ip, pattern, limit)
+		{
+			result::Ok(ref entries) => entries_to_json(*entries).to_str(),
+			result::Err(ref err) => return utils::text_response(response, ~"400 Bad Request", fmt!("Bad search: %s", *err)),
+		};
+	
+	let mut response = response;
+	response.headers.insert(~"Content-Type", ~"application/json");
+	response.headers.insert(~"Cache-Control", ~"no-cache");
+	server::Response {body: rwebserve::configuration::StringBody(@body), ..response}
+}
+
+// ---- Internal Functions ----------------------------------------------------
+priv fn entries_to_json(entries: &[Entry]) -> std::json::Json
+{
+	std::json::List(
+		do vec::map(entries) |entry|
+		{
+			let mut map = ~send_map::linear::LinearMap();
+			map.insert(~"received", std::json::Number(entry.received));
+			map.insert(~"facility", std::json::String(syslog::facility_to_str(entry.facility)));
+			map.insert(~"severity", std::json::String(syslog::severity_to_str(entry.severity)));
+			map.insert(~"timestamp", std::json::String(copy entry.timestamp));
+			map.insert(~"host", std::json::String(copy entry.host));
+			map.insert(~"app", std::json::String(copy entry.app));
+			map.insert(~"mesg", std::json::String(copy entry.mesg));
+			std::json::Object(map)
+		})
+}
@@ -0,0 +1,31 @@
+/// Returns the files written by tools (see tools.rs):
+///
+/// * **/tool-artifacts/{name}** Returns the artifact as an attachment. Names
+/// are the ones in the artifact urls pushed by sse_tool.
+///
+/// Like the other tool requests these are only allowed when gnos is started
+/// with --admin.
+use server = rwebserve;
+
+pub fn get_tool_artifact(options: &options::Options, request: &server::Request, response: server::Response) -> server::Response
+{
+	if !options.admin
+	{
+		return utils::text_response(response, ~"403 Forbidden", ~"Tool artifacts require --admin");
+	}
+	
+	let name = request.matches.get(@~"name");
+	if !tools::is_artifact_name(name) || !os::path_exists(&options.artifacts_dir.push(copy name))
+	{
+		return utils::text_response(response, ~"404 Not Found", fmt!("Unknown artifact: %s", name));
+	}
+	
+	// Let rwebserve serve up the file.
+	let config = server::Config {resources_root: copy options.artifacts_dir, ..server::initialize_config()};
+	let request = server::Request {path: ~"/" + name, ..copy *request};
+	let mut response = server::configuration::static_view(&config, &request, response);
+	response.headers.insert(~"Content-Type", ~"application/octet-stream");
+	response.headers.insert(~"Content-Disposition", fmt!("attachment; filename=\"%s\"", name));
+	response.headers.insert(~"Cache-Control", ~"no-cache");
+	response
+}
@@ -0,0 +1,27 @@
+/// Returns the tools which can be run against entities:
+///
+/// * **/tools** Returns a json list of objects of the form:
+/// {"name": "tcpdump", "description": "Captures...", "artifact": "pcap"}
+/// where description is html and artifact is empty if the tool's only output
+/// is its stdout.
+///
+/// See post_tool for running tools.
+use server = rwebserve;
+
+pub fn get_tools(options: &options::Options, response: server::Response) -> server::Response
+{
+	let body = std::json::List(
+		do tools::get_tools(options).map |tool|
+		{
+			let mut map = ~send_map::linear::LinearMap();
+			map.insert(~"name", std::json::String(copy tool.name));
+			map.insert(~"description", std::json::String(copy tool.description));
+			map.insert(~"artifact", std::json::String(copy tool.artifact));
+			std::json::Object(map)
+		}).to_str();
+	
+	let mut response = response;
+	response.headers.insert(~"Content-Type", ~"application/json");
+	response.headers.insert(~"Cache-Control", ~"no-cache");
+	server::Response {body: rwebserve::configuration::StringBody(@body), ..response}
+}
@@ -0,0 +1,53 @@
+/// Admin actions for task_runner jobs:
+///
+/// * **POST /jobs/cancel?name=** Kills the job's processes and keeps it from
+/// being restarted.
+///
+/// * **POST /jobs/restart?name=** Runs the job again (cancelling it first if it
+/// is running). Returns 409 if the job can't be restarted (see the restartable
+/// field returned by get_jobs).
+///
+/// These are only allowed when gnos is started with --admin.
+use server = rwebserve;
+use task_runner::JobRegistry;
+
+pub fn post_job(options: &options::Options, registry: JobRegistry, request: &server::Request, response: server::Response) -> server::Response
+{
+	if !options.admin
+	{
+		return utils::text_response(response, ~"403 Forbidden", ~"Job actions require --admin");
+	}
+	
+	let name = match request.params.find(@~"name")
+	{
+		option::Some(ref name) if name.is_not_empty() => copy *name,
+		_ => return utils::text_response(response, ~"400 Bad Request", ~"name parameter is missing"),
+	};
+	
+	let action = request.matches.get(@~"action");
+	let job = match registry.list().find(|job| {job.name == name})
+	{
+		option::Some(job) => job,
+		option::None => return utils::text_response(response, ~"404 Not Found", fmt!("Unknown job: %s", name)),
+	};
+	
+	if action == ~"cancel"
+	{
+		registry.cancel(name);
+	}
+	else if action == ~"restart" && !job.restartable
+	{
+		return utils::text_response(response, ~"409 Conflict", fmt!("%s can't be restarted", name));
+	}
+	else if action == ~"restart"
+	{
+		registry.restart(name);
+	}
+	else
+	{
+		return utils::text_response(response, ~"404 Not Found", fmt!("Unknown job action: %s", action));
+	}
+	
+	info!("%s job %s", action, name);
+	utils::text_response(response, ~"200 OK", fmt!("%s %s", action, name))
+}
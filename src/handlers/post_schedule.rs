@@ -0,0 +1,46 @@
+/// Admin actions for task_runner schedules:
+///
+/// * **POST /schedules/pause?name=** Keeps the job from running until it is
+/// resumed (a run which is already in progress is not affected).
+///
+/// * **POST /schedules/resume?name=** Starts running the job again.
+///
+/// These are only allowed when gnos is started with --admin.
+use server = rwebserve;
+use task_runner::Scheduler;
+
+pub fn post_schedule(options: &options::Options, scheduler: Scheduler, request: &server::Request, response: server::Response) -> server::Response
+{
+	if !options.admin
+	{
+		return utils::text_response(response, ~"403 Forbidden", ~"Schedule actions require --admin");
+	}
+	
+	let name = match request.params.find(@~"name")
+	{
+		option::Some(ref name) if name.is_not_empty() => copy *name,
+		_ => return utils::text_response(response, ~"400 Bad Request", ~"name parameter is missing"),
+	};
+	
+	let action = request.matches.get(@~"action");
+	if !scheduler.list().any(|s| {s.name == name})
+	{
+		return utils::text_response(response, ~"404 Not Found", fmt!("Unknown schedule: %s", name));
+	}
+	
+	if action == ~"pause"
+	{
+		scheduler.pause(name);
+	}
+	else if action == ~"resume"
+	{
+		scheduler.resume(name);
+	}
+	else
+	{
+		return utils::text_response(response, ~"404 Not Found", fmt!("Unknown schedule action: %s", action));
+	}
+	
+	info!("%s schedule %s", action, name);
+	utils::text_response(response, ~"200 OK", fmt!("%s %s", action, name))
+}
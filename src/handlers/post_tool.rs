@@ -0,0 +1,57 @@
+/// Runs a tool (see tools.rs) against an entity:
+///
+/// * **POST /tools/{tool}?target=ENTITY** Target is the subject of a device's
+/// entity (e.g. entities:10.0.0.1). Returns json of the form:
+/// {"id": 3, "command": "ping -c 10 10.0.0.1", "events": "/tool-events?id=3"}
+/// where events is the Server Sent Events stream for the tool's output (see
+/// sse_tool).
+///
+/// These are only allowed when gnos is started with --admin.
+use server = rwebserve;
+use task_runner::JobRegistry;
+use tools::ToolRuns;
+
+pub fn post_tool(options: &options::Options, registry: JobRegistry, runs: ToolRuns, request: &server::Request, response: server::Response) -> server::Response
+{
+	if !options.admin
+	{
+		return utils::text_response(response, ~"403 Forbidden", ~"Tools require --admin");
+	}
+	
+	let name = request.matches.get(@~"tool");
+	let tool = match tools::get_tools(options).find(|t| {t.name == name})
+		{
+			option::Some(tool) => tool,
+			option::None => return utils::text_response(response, ~"404 Not Found", fmt!("Unknown tool: %s", name)),
+		};
+	
+	let ip = match request.params.find(@~"target")
+		{
+			option::Some(ref target) if !target.starts_with("entities:") =>
+			{
+				return utils::text_response(response, ~"400 Bad Request", ~"target should be an entity subject (e.g. entities:10.0.0.1)");
+			}
+			option::Some(ref target) =>
+			{
+				match tools::get_target_ip(options.devices, *target)
+				{
+					option::Some(ip) => ip,
+					option::None => return utils::text_response(response, ~"404 Not Found", fmt!("Couldn't find an ip address for %s", *target)),
+				}
+			}
+			_ => return utils::text_response(response, ~"400 Bad Request", ~"target parameter is missing"),
+		};
+	
+	let (id, command) = runs.start(options, registry, &tool, ip);
+	
+	let mut map = ~send_map::linear::LinearMap();
+	map.insert(~"id", std::json::Number(id as float));
+	map.insert(~"command", std::json::String(command));
+	map.insert(~"events", std::json::String(fmt!("/tool-events?id=%?", id)));
+	let body = std::json::Object(map).to_str();
+	
+	let mut response = response;
+	response.headers.insert(~"Content-Type", ~"application/json");
+	response.headers.insert(~"Cache-Control", ~"no-cache");
+	server::Response {body: rwebserve::configuration::StringBody(@body), ..response}
+}
@@ -1,7 +1,5 @@
 /// This is the code that handles PUTs from the modeler scripts. It parses the
 /// incoming json, converts it into triplets, and updates the model.
-use core::path::{GenericPath};
-use core::io::{WriterUtil, ReaderUtil};
 use std::json::{Json};
 use json = std::json;
 use std::map::*;
@@ -11,15 +9,17 @@ use rrdf::*;
 use task_runner::*;
 use oldcomm::{Chan, Port};
 use server = rwebserve;
-use mustache::{Context, Template};
+use Path = path::Path;
+use Snapshot = timed_buffer::Snapshot;
 
 pub type SamplesChan = Chan<samples::Msg>;
 
 // This is equivalent to an hours worth of data at a fast poll rate (20s). Slower poll rates (which
-// are expected to be more likely) will retain correspondingly longer time spans.
+// are expected to be more likely) will retain correspondingly longer time spans. Older samples
+// are retained at lower resolutions (see samples::get_tiers).
 pub const samples_capacity: uint = 180;
 
-pub fn put_json(options: &Options, state_chan: Chan<Msg>, samples_chan: SamplesChan, request: &server::Request, response: server::Response) -> server::Response
+pub fn put_json(options: &Options, state_chan: Chan<Msg>, samples_chan: SamplesChan, render_queue: JobQueue, request: &server::Request, response: server::Response) -> server::Response
 {
 	// Unfortunately we don't send an error back to the modeler if the json was invalid.
 	// Of course that shouldn't happen...
@@ -27,43 +27,27 @@ pub fn put_json(options: &Options, state_chan: Chan<Msg>, samples_chan: SamplesC
 	info!("-------- got %? bytes from %s --------", request.body.len(), addr);
 	
 	let options = copy *options;
-	oldcomm::send(state_chan, UpdateMsg(~"primary", |s, d, move options| {handle_update(&options, addr, s, d, samples_chan)}, copy request.body));
+	oldcomm::send(state_chan, UpdateMsg(~"primary", |s, d, move options| {handle_update(&options, addr, s, d, samples_chan, render_queue)}, copy request.body));
 	
 	server::Response {body: rwebserve::configuration::StringBody(@~""), ..response}
 }
 
-priv fn handle_update(options: &Options, remote_addr: &str, store: &Store, body: &str, samples_chan: SamplesChan) -> bool
+/// Used by modelers running within gnos (see modeler.rs). Data has the same
+/// format as the json PUT by the modeler scripts.
+pub fn put_update(options: &Options, state_chan: Chan<Msg>, samples_chan: SamplesChan, render_queue: JobQueue, source: &str, data: Json)
+{
+	let options = copy *options;
+	let source = source.to_owned();
+	oldcomm::send(state_chan, UpdateMsg(~"primary", |s, _d, move options, move source, move data| {apply_update(&options, source, s, &data, samples_chan, render_queue); true}, ~""));
+}
+
+priv fn handle_update(options: &Options, remote_addr: &str, store: &Store, body: &str, samples_chan: SamplesChan, render_queue: JobQueue) -> bool
 {
 	match json::from_str(body)
 	{
 		result::Ok(ref data) =>
 		{
-			match *data
-			{
-				json::Object(ref d) =>
-				{
-					store.replace_triple(~[], {subject: ~"gnos:map", predicate: ~"gnos:last_update", object: @DateTimeValue(std::time::now())});
-					store.replace_triple(~[], {subject: ~"gnos:map", predicate: ~"gnos:poll_interval", object: @IntValue(options.poll_rate as i64)});
-					
-					let mut modeler = option::None;
-					if d.contains_key(&~"modeler")
-					{
-						modeler = prune_modeler(store, d.get_ref(&~"modeler"));
-					}
-					do optional_list(data, ~"entities") |list| {add_entities(store, &modeler, list);};
-					do optional_list(data, ~"labels") |list| {add_labels(store, &modeler, list);};
-					do optional_list(data, ~"gauges") |list| {add_gauges(store, &modeler, list);};
-					do optional_list(data, ~"details") |list| {add_details(store, &modeler, list);};
-					do optional_list(data, ~"relations") |list| {add_relations(store, &modeler, list);};
-					do optional_list(data, ~"alerts") |list| {add_alerts(store, list);};
-					do optional_list(data, ~"samples") |list| {add_samples(options, samples_chan, list);};
-					do optional_list(data, ~"charts") |list| {add_charts(options, samples_chan, list);};
-				}
-				_ =>
-				{
-					error!("Data from %s was expected to be a dict but is a %?", remote_addr, data);	// TODO: probably want to add errors to store
-				}
-			}
+			apply_update(options, remote_addr, store, data, samples_chan, render_queue);
 		}
 		result::Err(err) =>
 		{
@@ -76,6 +60,40 @@ priv fn handle_update(options: &Options, remote_addr: &str, store: &Store, body:
 	true
 }
 
+priv fn apply_update(options: &Options, source: &str, store: &Store, data: &Json, samples_chan: SamplesChan, render_queue: JobQueue)
+{
+	match *data
+	{
+		json::Object(ref d) =>
+		{
+			store.replace_triple(~[], {subject: ~"gnos:map", predicate: ~"gnos:last_update", object: @DateTimeValue(std::time::now())});
+			store.replace_triple(~[], {subject: ~"gnos:map", predicate: ~"gnos:poll_interval", object: @IntValue(options.poll_rate as i64)});
+			
+			let mut modeler = option::None;
+			if d.contains_key(&~"modeler")
+			{
+				modeler = prune_modeler(store, d.get_ref(&~"modeler"));
+			}
+			do optional_list(data, ~"entities") |list| {add_entities(store, &modeler, list);};
+			do optional_list(data, ~"labels") |list| {add_labels(store, &modeler, list);};
+			do optional_list(data, ~"gauges") |list| {add_gauges(store, &modeler, list);};
+			do optional_list(data, ~"details") |list| {add_details(store, &modeler, list);};
+			do optional_list(data, ~"relations") |list| {add_relations(store, &modeler, list);};
+			do optional_list(data, ~"alerts") |list| {add_alerts(store, list);};
+			
+			// Each source polls on its own schedule so each gets its own sample set
+			// (otherwise every update would pad the other sources' series).
+			let owner = match modeler {option::Some(StringValue(ref name, _)) => copy *name, _ => source.to_owned()};
+			do optional_list(data, ~"samples") |list| {add_samples(options, owner, samples_chan, render_queue, list);};
+			do optional_list(data, ~"charts") |list| {add_charts(samples_chan, list);};
+		}
+		_ =>
+		{
+			error!("Data from %s was expected to be a dict but is a %?", source, data);	// TODO: probably want to add errors to store
+		}
+	}
+}
+
 priv fn add_entities(store: &Store, modeler: &Option<Object>, list: &json::List)
 {
 	fn add_entity(store: &Store, modeler: &Option<Object>, object: &Json)
@@ -232,9 +250,21 @@ priv fn add_relations(store: &Store, modeler: &Option<Object>, list: &json::List
 		let right = get_str(object, ~"right-entity-id");
 		debug!("adding relation for %s -> %s", left, right);
 		
+		// Styles set by other parts of gnos (e.g. netflow line widths) win.
+		let mut style = ~"";
+		do optional_str(object, ~"style") |value| {style = value};
+		for model::get_relation_overrides(store, left, right).each |entry|
+		{
+			let (name, value) = copy *entry;
+			style = model::merge_style(style, name, value);
+		}
+		
 		entries.push((~"gnos:left",		@IriValue(left)));
 		entries.push((~"gnos:right",	@IriValue(right)));
-		do optional_str(object, ~"style")		|value| {entries.push((~"gnos:style", @StringValue(value, ~"")))};
+		if style.is_not_empty()
+		{
+			entries.push((~"gnos:style", @StringValue(style, ~"")));
+		}
 		do optional_str(object, ~"predicate")	|value| {entries.push((~"gnos:predicate", @StringValue(value, ~"")))};
 		
 		add_labels(store, modeler, object, &mut entries, target, ~"left");
@@ -283,131 +313,111 @@ priv fn add_alerts(store: &Store, list: &json::List)
 	}
 }
 
-priv fn add_samples(options: &Options, samples_chan: SamplesChan, list: &json::List)
+priv fn add_samples(options: &Options, owner: &str, samples_chan: SamplesChan, render_queue: JobQueue, list: &json::List)
 {
-	let path = get_sparkline_script(options);
-	let context = mustache::Context(~".", ~"");
-	let template = context.compile_file(path.to_str());
-	
-	let mut script = ~"";
+	// All the samples in a PUT are from the same poll so they share a time (this
+	// is what allows samples::SampleSet to keep them aligned).
+	let time = utils::imprecise_time_s();
 	for list.each |sample|
 	{
 		let name = get_str(sample, ~"name");
 		let value = get_float(sample, ~"value");
 		let units = get_str(sample, ~"units");
+		let kind = get_sample_kind(sample);
 		debug!("adding %s sample, value = %? %s", name, value, units);
 		
-		samples_chan.send(samples::AddSample(~"snmp", copy name, value, samples_capacity));
-		script += build_sparkline(options, samples_chan, name, units, template);
-	}
-	
-	if script.is_not_empty()
-	{
-		run_r_script(script);
+		// Entity and limit are optional and are used by threshold alerts.
+		samples_chan.send(samples::AddSample(samples::Sample
+		{
+			owner: owner.to_owned(),
+			name: copy name,
+			value: value,
+			kind: kind,
+			time: time,
+			capacity: samples_capacity,
+			entity: if has_value(sample, ~"entity-id") {get_str(sample, ~"entity-id")} else {~""},
+			limit: if has_value(sample, ~"limit") {get_float(sample, ~"limit")} else {float::NaN},
+			units: copy units,
+		}));
+		match get_sparkline(options, samples_chan, name)
+		{
+			option::Some((path, samples)) => write_sparkline(render_queue, name, path, samples, copy units, copy options.scratch_dir),
+			option::None => {}
+		}
 	}
 }
 
-priv fn add_charts(options: &Options, samples_chan: SamplesChan, list: &json::List)
+// Charts are registered with the samples task and rendered when a client
+// asks for them (see get_chart).
+priv fn add_charts(samples_chan: SamplesChan, list: &json::List)
 {
-	let mut charts = ~[];
-	
-	let root = os::make_absolute(&options.root);
-	let root = root.push("generated");
 	for list.each |chart|
 	{
-		let path = root.push(fmt!("%s.png", get_str(chart, ~"name")));
-		charts.push(samples::Chart 
+		let chart = samples::Chart 
 		{
-			path: path.to_str(),
+			name: get_str(chart, ~"name"),
 			sample_sets: get_strs(chart, ~"samples"),
 			legends: get_strs(chart, ~"legends"),
-			interval: options.poll_rate as float,
+			range: if has_value(chart, ~"range") {get_float(chart, ~"range")} else {0.0},
 			title: get_str(chart, ~"title"),
 			y_label: get_str(chart, ~"y_label"),
-		});
-		debug!("adding charts for %?", charts.last().sample_sets);
+		};
+		debug!("registering chart for %?", chart.sample_sets);
+		samples_chan.send(samples::RegisterChart(chart));
 	}
-	
-	if charts.is_not_empty()
+}
+
+// Samples may be gauges (the default) or raw counters which the samples
+// task will convert into rates, e.g. {"type": "counter32", "scale": 0.008}
+// will convert octet counts into kbps.
+priv fn get_sample_kind(sample: &Json) -> counters::SampleKind
+{
+	let scale = if has_value(sample, ~"scale") {get_float(sample, ~"scale")} else {1.0};
+	let kind = if has_value(sample, ~"type") {get_str(sample, ~"type")} else {~"gauge"};
+	match kind
 	{
-		// We always create these charts. That's a bit wasteful because they don't appear on the main page.
-		// However building an URL that encodes all the info neccesary to create them would be rather
-		// awful. TODO: I guess samples could store a Chart struct and then use that to dynamically create
-		// the charts.
-		samples::create_charts(~"snmp-modeler", charts, samples_chan);
+		~"gauge" => counters::Gauge,
+		~"counter32" => counters::Counter32(scale),
+		~"counter64" => counters::Counter64(scale),
+		_ =>
+		{
+			error!("Bad sample type: %s", kind);
+			counters::Gauge
+		}
 	}
 }
 
-// Creates an R script which when run will produce a sparkline chart for the named sample set.
-priv fn build_sparkline(options: &Options, samples_chan: SamplesChan, name: &str, units: ~str, template: Template) -> ~str
+// Returns the path to the sparkline chart for the named sample set along with
+// the samples to chart.
+priv fn get_sparkline(options: &Options, samples_chan: SamplesChan, name: &str) -> option::Option<(Path, Snapshot<float>)>
 {
 	let port = Port();
 	let chan = Chan(&port);
 	samples_chan.send(samples::GetSampleSet(name.to_owned(), chan));
-	let (buffer, _num_adds) = port.recv();
+	let (samples, _num_adds) = port.recv();
 	
-	if (buffer.len() > 1)
+	if (samples.len() > 1)
 	{
 		let mut path = os::make_absolute(&options.root);
 		path = path.push("generated");
 		path = path.push(fmt!("%s.png", name));
-		
-		let context = HashMap();
-		context.insert(@~"samples", mustache::Str(@str::connect(do iter::map_to_vec(&buffer) |s| {s.to_str()}, ", ")));
-		context.insert(@~"file", mustache::Str(@path.to_str()));
-		context.insert(@~"width", mustache::Str(@~"150"));
-		context.insert(@~"height", mustache::Str(@~"50"));
-		context.insert(@~"label", mustache::Str(@units));
-		
-		template.render_data(mustache::Map(context))
+		option::Some((path, samples))
 	}
 	else
 	{
-		~""
+		option::None
 	}
 }
 
-priv fn run_r_script(script: &str)
+// Sparklines are keyed by sample name so if the renderer falls behind stale
+// sparklines are dropped instead of piling up. Failed renders aren't restarted
+// because the next poll will render a newer sparkline anyway (failures show up
+// in /jobs).
+priv fn write_sparkline(render_queue: JobQueue, name: &str, path: Path, samples: Snapshot<float>, units: ~str, scratch: Path)
 {
-	fn get_output(label: &str, reader: io::Reader) -> ~str
-	{
-		let text = str::from_bytes(reader.read_whole_stream());
-		if text.is_not_empty() {fmt!("%s:\n%s\n", label, text)} else {~""}
-	}
-	
-	let script = ~"library(YaleToolkit)\n\n" + script;
-	let action: JobFn =
-		||
-		{
-			let path = GenericPath::from_str("/tmp/gnos-sparkline.R");		// TODO use a better path once rust has a better tmp file function
-			match io::file_writer(&path, ~[io::Create, io::Truncate])
-			{
-				result::Ok(writer) =>
-				{
-					writer.write_str(script);
-					
-					let program = run::start_program("Rscript", [path.to_str()]);
-					let result = program.finish();
-					if result != 0
-					{
-						let mut err = fmt!("Rscript %s returned %?\n", path.to_str(), result);
-						err += get_output("stdout", program.output());
-						err += get_output("stderr", program.err());
-						option::Some(err)
-					}
-					else
-					{
-						option::None
-					}
-				}
-				result::Err(ref err) =>
-				{
-					option::Some(fmt!("Failed to create %s: %s", path.to_str(), *err))
-				}
-			}
-		};
-	let cleanup: ExitFn = || {};
-	run(Job {action: action, policy: IgnoreFailures}, ~[cleanup]);
+	let action: JobFn = |move path, move samples, move units, move scratch| {chart::write_sparkline(&path, &samples.get_values(), units, &scratch)};
+	let key = fmt!("%s sparkline", name);
+	render_queue.enqueue(copy key, Job {name: key, action: action, policy: IgnoreFailures, timeout: option::None});
 }
 
 priv fn prune_modeler(store: &Store, value: &Json) -> Option<Object>
@@ -685,11 +695,3 @@ priv fn optional_list(value: &Json, key: ~str, callback: fn (value: &json::List)
 		}
 	}
 }
-
-priv fn get_sparkline_script(options: &Options) -> Path
-{
-	let path = options.root.pop();				// gnos
-	let path = path.push(~"scripts");
-	let path = path.push(~"sparkline.R");		// gnos/scripts/sparkline.R
-	path
-}
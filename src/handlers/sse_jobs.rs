@@ -0,0 +1,44 @@
+/// Uses Server Sent Events to push the state of task_runner jobs.
+use oldcomm::{Chan, Port};
+use server = rwebserve;
+use modeler::Modelers;
+use task_runner::JobRegistry;
+use handlers::get_jobs::jobs_to_json;
+
+/// Used by client code to track jobs. Data is the same json list returned by
+/// GET /jobs and is sent whenever a job changes state.
+pub fn sse_query(registry: JobRegistry, modelers: Modelers, _request: &server::Request, push: server::PushChan) -> server::ControlChan
+{
+	do utils::spawn_moded_listener(task::ThreadPerCore) |control_port: server::ControlPort|
+	{
+		info!("starting jobs stream");
+		let notify_port = Port();
+		let notify_chan = Chan(&notify_port);
+		
+		let key = fmt!("jobs %?", ptr::addr_of(&notify_port));
+		registry.register(copy key, notify_chan);
+		
+		loop
+		{
+			match oldcomm::select2(notify_port, control_port)
+			{
+				either::Left(ref jobs) =>
+				{
+					oldcomm::send(push, fmt!("retry: 5000\ndata: %s\n\n", jobs_to_json(*jobs, modelers.health()).to_str()));
+				}
+				either::Right(server::RefreshEvent) =>
+				{
+					// Registering sends the jobs again.
+					registry.deregister(copy key);
+					registry.register(copy key, notify_chan);
+				}
+				either::Right(server::CloseEvent) =>
+				{
+					info!("shutting down jobs stream");
+					registry.deregister(key);
+					break;
+				}
+			}
+		}
+	}
+}
@@ -7,7 +7,9 @@ use runits::units::*;
 use runits::generated::*;
 
 // Sends a list of json objects where each object is of the form: 
-// {"sample_name": "eth1", "min": 1.0, "mean": 1.0, "max": 1.0, "units": "kbps"}.
+// {"sample_name": "eth1", "min": 1.0, "mean": 1.0, "max": 1.0, "median": 1.0, "p95": 1.0,
+// "stddev": 0.0, "last": 1.0, "count": 1, "units": "kbps", "anomaly": false}. Statistics
+// are null if the sample set has no samples.
 pub fn sse_query(samples_chan: Chan<Msg>, request: &server::Request, push: server::PushChan) -> server::ControlChan
 {
 	let owner = copy request.params.get(@~"owner");
@@ -57,7 +59,12 @@ priv fn details_to_json(details: &[Detail]) -> std::json::Json
 
 priv fn detail_to_json(detail: &Detail) -> std::json::Json
 {
-	let value = from_units(detail.max, Kilo*Bit/Second);
+	fn stat_to_json(value: float, scale: float) -> std::json::Json
+	{
+		if float::is_NaN(value) {std::json::Null} else {std::json::Number(value*scale)}
+	}
+	
+	let value = from_units(if float::is_NaN(detail.max) {1.0} else {detail.max}, Kilo*Bit/Second);
 	let value = value.normalize_si();
 	
 	let unit = from_units(1.0, Kilo*Bit/Second);
@@ -65,10 +72,16 @@ priv fn detail_to_json(detail: &Detail) -> std::json::Json
 	
 	let mut map = ~send_map::linear::LinearMap();
 	map.insert(~"sample_name", std::json::String(copy detail.sample_name));
-	map.insert(~"min", std::json::Number(detail.min*unit.value));
-	map.insert(~"mean", std::json::Number(detail.mean*unit.value));
-	map.insert(~"max", std::json::Number(detail.max*unit.value));
+	map.insert(~"min", stat_to_json(detail.min, unit.value));
+	map.insert(~"mean", stat_to_json(detail.mean, unit.value));
+	map.insert(~"max", stat_to_json(detail.max, unit.value));
+	map.insert(~"median", stat_to_json(detail.median, unit.value));
+	map.insert(~"p95", stat_to_json(detail.p95, unit.value));
+	map.insert(~"stddev", stat_to_json(detail.stddev, unit.value));
+	map.insert(~"last", stat_to_json(detail.last, unit.value));
+	map.insert(~"count", std::json::Number(detail.count as float));
 	map.insert(~"units", std::json::String(value.units.to_str()));
+	map.insert(~"anomaly", std::json::Boolean(detail.anomaly));
 	
 	std::json::Object(map)
 }
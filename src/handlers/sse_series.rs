@@ -0,0 +1,94 @@
+/// Uses Server Sent Events to push sample points so that clients can render
+/// (and zoom) charts themselves.
+use oldcomm::{Chan, Port};
+use samples::{Msg, RegisterSeries, DeregisterMsg, Series};
+use server = rwebserve;
+
+/// Used by client code to stream the points for one or more sample sets, e.g.
+/// /series?names=a,b. Data is a list of json objects of the form:
+/// {"name": "eth1", "units": "kbps", "points": [[time, value], ...]} where time
+/// is seconds since the epoch and value is null if the sample was NaN. Note
+/// that sample sets are zero filled so if a sample set was not sampled at a
+/// time (e.g. it was added after the others) its value will be 0.
+///
+/// The first event is a "reset" event which contains all the retained points.
+/// After that a normal message event is sent for each new point (so a poll
+/// which adds samples for several of the names results in several events each
+/// with a single point). Reset events are also sent when the stream is
+/// refreshed so clients should replace their points when they get one.
+pub fn sse_query(samples_chan: Chan<Msg>, request: &server::Request, push: server::PushChan) -> server::ControlChan
+{
+	let names = match request.params.find(@~"names")
+	{
+		option::Some(ref names) => do str::split_char(*names, ',').filter |n| {n.is_not_empty()},
+		option::None => ~[],
+	};
+	
+	do utils::spawn_moded_listener(task::ThreadPerCore) |control_port: server::ControlPort, copy names|
+	{
+		info!("starting %? series stream", names);
+		let notify_port = Port();
+		let notify_chan = Chan(&notify_port);
+		
+		let key = fmt!("series %?", ptr::addr_of(&notify_port));
+		if names.is_empty()
+		{
+			oldcomm::send(push, ~"retry: 5000\ndata: \"names parameter is missing\"\n\n");
+		}
+		else
+		{
+			oldcomm::send(samples_chan, RegisterSeries(copy key, copy names, notify_chan));
+		}
+		
+		loop
+		{
+			match oldcomm::select2(notify_port, control_port)
+			{
+				either::Left((true, ref series)) =>
+				{
+					oldcomm::send(push, fmt!("retry: 5000\nevent: reset\ndata: %s\n\n", series_to_json(*series).to_str()));
+				}
+				either::Left((false, ref series)) =>
+				{
+					oldcomm::send(push, fmt!("retry: 5000\ndata: %s\n\n", series_to_json(*series).to_str()));
+				}
+				either::Right(server::RefreshEvent) =>
+				{
+					// Re-registering will send all the points again.
+					if names.is_not_empty()
+					{
+						oldcomm::send(samples_chan, DeregisterMsg(copy key));
+						oldcomm::send(samples_chan, RegisterSeries(copy key, copy names, notify_chan));
+					}
+				}
+				either::Right(server::CloseEvent) =>
+				{
+					info!("shutting down series stream");
+					oldcomm::send(samples_chan, DeregisterMsg(key));
+					break;
+				}
+			}
+		}
+	}
+}
+
+priv fn series_to_json(series: &[Series]) -> std::json::Json
+{
+	std::json::List(
+		do vec::map(series) |s|
+		{
+			let mut points = ~[];
+			for s.points.each |entry|
+			{
+				let (time, value) = *entry;
+				let value = if float::is_NaN(value) {std::json::Null} else {std::json::Number(value)};
+				points.push(std::json::List(~[std::json::Number(time), value]));
+			}
+			
+			let mut map = ~send_map::linear::LinearMap();
+			map.insert(~"name", std::json::String(copy s.name));
+			map.insert(~"units", std::json::String(copy s.units));
+			map.insert(~"points", std::json::List(points));
+			std::json::Object(map)
+		})
+}
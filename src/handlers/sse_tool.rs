@@ -0,0 +1,55 @@
+/// Uses Server Sent Events to push the output of a tool run (see post_tool).
+use oldcomm::{Chan, Port};
+use server = rwebserve;
+use tools::ToolRuns;
+
+/// Request is of the form /tool-events?id=3. Data is a json object for each
+/// line the tool writes followed by an object once the tool exits (see
+/// ToolRuns::register). Like post_tool this requires --admin.
+pub fn sse_query(options: &options::Options, runs: ToolRuns, request: &server::Request, push: server::PushChan) -> server::ControlChan
+{
+	let admin = options.admin;
+	let id = match request.params.find(@~"id")
+		{
+			option::Some(ref id) => uint::from_str(*id).get_default(0),
+			option::None => 0,
+		};
+	
+	do utils::spawn_moded_listener(task::ThreadPerCore) |control_port: server::ControlPort|
+	{
+		info!("starting tool %? stream", id);
+		let notify_port = Port();
+		let notify_chan = Chan(&notify_port);
+		
+		let key = fmt!("tool %?", ptr::addr_of(&notify_port));
+		if !admin
+		{
+			oldcomm::send(push, ~"data: {\"done\": true, \"error\": \"Tools require --admin\", \"artifact\": null}\n\n");
+		}
+		else if !runs.register(id, copy key, notify_chan)
+		{
+			oldcomm::send(push, fmt!("data: {\"done\": true, \"error\": \"Unknown tool run: %?\", \"artifact\": null}\n\n", id));
+		}
+		
+		loop
+		{
+			match oldcomm::select2(notify_port, control_port)
+			{
+				either::Left(ref event) =>
+				{
+					oldcomm::send(push, fmt!("data: %s\n\n", *event));
+				}
+				either::Right(server::RefreshEvent) =>
+				{
+					// Lines are only sent once.
+				}
+				either::Right(server::CloseEvent) =>
+				{
+					info!("shutting down tool %? stream", id);
+					runs.deregister(id, key);
+					break;
+				}
+			}
+		}
+	}
+}
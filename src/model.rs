@@ -315,6 +315,167 @@ pub fn close_alert(store: &Store, target: &str, id: &str) -> bool
 	}
 }
 
+/// Helper used to set a style (e.g. "line-width") on the relations between
+/// two entities. Other styles on the relations are retained. Returns true if a
+/// relation was found.
+pub fn set_relation_style(store: &Store, left: &str, right: &str, name: &str, value: &str) -> bool
+{
+	let mut changed = false;
+	for [(left, right), (right, left)].each |entry|
+	{
+		let (lhs, rhs) = *entry;
+		let expr = fmt!("
+		%s
+		SELECT
+			?subject ?style
+		WHERE
+		{
+			?subject gnos:left %s .
+			?subject gnos:right %s .
+			OPTIONAL
+			{
+				?subject gnos:style ?style
+			}
+		}", get_prefixes(store), lhs, rhs);
+		
+		match eval_query(store, expr)
+		{
+			result::Ok(ref solution) =>
+			{
+				let subject_index = solution.bindings.position_elem(&~"subject").get();
+				let style_index = solution.bindings.position_elem(&~"style").get();
+				for solution.rows.each |row|
+				{
+					let old = if row[style_index].is_unbound() {~""} else {row[style_index].as_str()};
+					store.replace_triple(~[], {subject: (*row[subject_index]).to_str(), predicate: ~"gnos:style", object: @StringValue(merge_style(old, name, value), ~"")});
+					changed = true;
+				}
+			}
+			result::Err(ref err) =>
+			{
+				error!("set_relation_style> %s", *err);
+				error!("set_relation_style> %s", expr);
+			}
+		}
+	}
+	changed
+}
+
+/// Like set_relation_style except that the style is also applied to relations
+/// added later on. Modelers replace their relations each time they update so
+/// this is needed for styles set by other parts of gnos (e.g. netflow). See
+/// get_relation_overrides.
+pub fn set_relation_override(store: &Store, left: &str, right: &str, name: &str, value: &str) -> bool
+{
+	let expr = fmt!("
+	%s
+	SELECT
+		?subject
+	WHERE
+	{
+		?subject gnos:override-left %s .
+		?subject gnos:override-right %s .
+		?subject gnos:override-name \"%s\" .
+	}", get_prefixes(store), left, right, name);
+	
+	match eval_query(store, expr)
+	{
+		result::Ok(ref solution) if solution.rows.is_not_empty() =>
+		{
+			store.replace_triple(~[], {subject: (*solution.rows[0][0]).to_str(), predicate: ~"gnos:override-value", object: @StringValue(value.to_owned(), ~"")});
+		}
+		result::Ok(_) =>
+		{
+			let subject = get_blank_name(store, ~"override");
+			store.add(subject, ~[
+				(~"gnos:override-left", @IriValue(left.to_owned())),
+				(~"gnos:override-right", @IriValue(right.to_owned())),
+				(~"gnos:override-name", @StringValue(name.to_owned(), ~"")),
+				(~"gnos:override-value", @StringValue(value.to_owned(), ~"")),
+			]);
+		}
+		result::Err(ref err) =>
+		{
+			error!("set_relation_override> %s", *err);
+			error!("set_relation_override> %s", expr);
+		}
+	}
+	set_relation_style(store, left, right, name, value)
+}
+
+/// Returns the (name, value) styles set by set_relation_override for relations
+/// between left and right (in either direction).
+pub fn get_relation_overrides(store: &Store, left: &str, right: &str) -> ~[(~str, ~str)]
+{
+	let mut overrides = ~[];
+	for [(left, right), (right, left)].each |entry|
+	{
+		let (lhs, rhs) = *entry;
+		let expr = fmt!("
+		%s
+		SELECT
+			?name ?value
+		WHERE
+		{
+			?subject gnos:override-left %s .
+			?subject gnos:override-right %s .
+			?subject gnos:override-name ?name .
+			?subject gnos:override-value ?value .
+		}", get_prefixes(store), lhs, rhs);
+		
+		match eval_query(store, expr)
+		{
+			result::Ok(ref solution) =>
+			{
+				let name_index = solution.bindings.position_elem(&~"name").get();
+				let value_index = solution.bindings.position_elem(&~"value").get();
+				for solution.rows.each |row|
+				{
+					overrides.push((row[name_index].as_str(), row[value_index].as_str()));
+				}
+			}
+			result::Err(ref err) =>
+			{
+				error!("get_relation_overrides> %s", *err);
+				error!("get_relation_overrides> %s", expr);
+			}
+		}
+	}
+	overrides
+}
+
+/// Returns a space separated list of name:value styles with name set to value.
+pub fn merge_style(style: &str, name: &str, value: &str) -> ~str
+{
+	let mut styles = str::words(style).filtered(|s| {!s.starts_with(name + ":")});
+	styles.push(fmt!("%s:%s", name, value));
+	str::connect(styles, " ")
+}
+
+/// Helper used to record restarts of task_runner jobs in the globals store (so
+/// that they can be shown in the UI).
+pub fn record_restart(store: &Store, job: &str, event: &task_runner::RestartEvent)
+{
+	let subject = ~"gnos:job-" + job;
+	let (err, gave_up, delta) = match *event
+	{
+		task_runner::Restarting(_, ref err) => (copy *err, false, 1),
+		task_runner::GaveUp(_, ref err) => (copy *err, true, 0),
+	};
+	
+	// Jobs may be run more than once (e.g. chart jobs) so the restart count is a total.
+	let total = match store.find_object(copy subject, ~"gnos:restarts")
+	{
+		option::Some(@IntValue(value)) => value + delta,
+		_ => delta,
+	};
+	
+	store.replace_triple(~[], {subject: copy subject, predicate: ~"gnos:job", object: @StringValue(job.to_owned(), ~"")});
+	store.replace_triple(~[], {subject: copy subject, predicate: ~"gnos:restarts", object: @IntValue(total)});
+	store.replace_triple(~[], {subject: copy subject, predicate: ~"gnos:last_error", object: @StringValue(err, ~"")});
+	store.replace_triple(~[], {subject: copy subject, predicate: ~"gnos:gave_up", object: @BoolValue(gave_up)});
+}
+
 pub fn eval_query(store: &Store, expr: &str) -> result::Result<Solution, ~str>
 {
 	match compile(expr)
@@ -0,0 +1,422 @@
+/// Modelers produce the updates used to build the model (see put_json for the
+/// format of the updates). Devices pick the modeler implementation using the
+/// optional type field in the network json file:
+///
+/// * ssh - the script is copied to the network json client and run there using
+/// ssh. The script PUTs its updates to /modeler. This is the default.
+/// * local - like ssh except that the script is run on this machine as a child
+/// process. This is the default if the client is "local".
+/// * builtin - the modeler is written in rust and runs within gnos. Updates are
+/// produced every poll. The modeler field names the builtin: currently only snmp
+/// (see snmp_poller.rs) which is also used for devices with an snmp type and no
+/// modeler.
+use Path = path::Path;
+use io::WriterUtil;
+use std::json::Json;
+use options::{Options, Device, Ssh, Local, Builtin};
+use task_runner::*;
+
+pub enum Health
+{
+	Starting,								// the modeler has not run yet
+	Healthy,
+	Unhealthy(~str),						// the modeler failed but will be retried
+	Stopped(~str),							// the modeler was stopped or failed too often
+}
+
+pub impl Health : ToStr
+{
+	pure fn to_str() -> ~str
+	{
+		match self
+		{
+			Starting => ~"starting",
+			Healthy => ~"healthy",
+			Unhealthy(_) => ~"unhealthy",
+			Stopped(_) => ~"stopped",
+		}
+	}
+}
+
+pub trait Modeler
+{
+	/// Used in logs and as the job name.
+	pure fn name() -> ~str;
+	
+	/// Starts the modeler running in the background.
+	fn start();
+	
+	/// Stops the modeler. Called when gnos shuts down. This should not block
+	/// (see wait_for_modelers).
+	fn stop();
+	
+	fn health() -> Health;
+	
+	/// Returns the modeler's current update or None for modelers which PUT
+	/// their updates themselves.
+	fn produce_update() -> option::Option<result::Result<Json, ~str>>;
+}
+
+/// Builtin modelers implement this to produce their updates.
+pub trait Producer
+{
+	fn produce_update() -> result::Result<Json, ~str>;
+}
+
+/// Everything modelers need to run and to apply their updates.
+pub struct ModelerContext
+{
+	pub options: Options,
+	pub state_chan: oldcomm::Chan<model::Msg>,
+	pub samples_chan: oldcomm::Chan<samples::Msg>,
+	pub render_queue: JobQueue,
+	pub registry: JobRegistry,
+	pub scheduler: Scheduler,
+}
+
+/// Handle to the task which tracks the modelers which have been started so
+/// that their health can be reported (see get_jobs).
+pub struct Modelers
+{
+	priv chan: oldcomm::Chan<ModelersMsg>,
+}
+
+pub fn Modelers() -> Modelers
+{
+	let chan = do utils::spawn_moded_listener(task::ThreadPerCore) |port| {manage_modelers(port)};
+	Modelers {chan: chan}
+}
+
+pub impl Modelers
+{
+	/// Returns the name (which is also the job name) and health of each modeler.
+	fn health() -> ~[(~str, Health)]
+	{
+		let port = oldcomm::Port();
+		let chan = oldcomm::Chan(&port);
+		oldcomm::send(self.chan, HealthMsg(chan));
+		oldcomm::recv(port)
+	}
+}
+
+/// Starts the modeler used by the device. Returns a function which stops the
+/// modeler.
+pub fn start_modeler(device: &Device, ctx: &ModelerContext, modelers: Modelers) -> ExitFn
+{
+	match device.modeler_type
+	{
+		Ssh => start(SshModeler(device.modeler, ctx), modelers),
+		Local => start(LocalModeler(device.modeler, ctx), modelers),
+		Builtin =>
+		{
+			match device.modeler
+			{
+				~"snmp" => start(BuiltinModeler(device.name, snmp_poller::SnmpPoller(device), ctx), modelers),
+				_ =>
+				{
+					io::stderr().write_line(fmt!("%s is not a builtin modeler.", device.modeler));
+					libc::exit(1)
+				}
+			}
+		}
+	}
+}
+
+/// Returns a function which waits for the modelers stopped by the functions
+/// start_modeler returns to exit.
+pub fn wait_for_modelers(ctx: &ModelerContext) -> ExitFn
+{
+	let registry = ctx.registry;
+	|| {registry.wait_for_stopped(script_grace + 1.0)}
+}
+
+/// Returns the directory containing the modeler scripts.
+pub fn get_scripts_dir(root: &Path) -> Path
+{
+	let dir = core::os::make_absolute(root).pop();		// gnos/html => /gnos
+	dir.push(~"scripts")								// /gnos => /gnos/scripts
+}
+
+/// Runs a python script on the network json client using ssh.
+pub struct SshModeler
+{
+	priv script: ~str,
+	priv ctx: ModelerContext,
+	priv mut handle: option::Option<JobHandle>,
+}
+
+pub fn SshModeler(script: &str, ctx: &ModelerContext) -> SshModeler
+{
+	SshModeler {script: script.to_owned(), ctx: copy *ctx, handle: option::None}
+}
+
+pub impl SshModeler : Modeler
+{
+	pure fn name() -> ~str
+	{
+		copy self.script
+	}
+	
+	fn start()
+	{
+		let client = copy self.ctx.options.client_ip;
+		let script = copy self.script;
+		let network_file = copy self.ctx.options.network_file;
+		let ip = copy self.ctx.options.bind_ip;
+		let port = self.ctx.options.bind_port;
+		let action: JobFn = |move client, move script, move network_file, move ip| run_ssh_script(env!("GNOS_USER"), client, script, network_file, ip, port);
+		self.handle = option::Some(start_script(&self.ctx, self.script, action));
+	}
+	
+	fn stop()
+	{
+		// Cancel the job first so that it isn't restarted when we kill the modeler.
+		if self.handle.is_some()
+		{
+			self.handle.get().cancel();
+		}
+		utils::run_remote_command(env!("GNOS_USER"), self.ctx.options.client_ip, fmt!("pgrep -f %s | xargs --no-run-if-empty kill -9", self.script));
+	}
+	
+	fn health() -> Health
+	{
+		get_health(self.ctx.registry, self.script)
+	}
+	
+	fn produce_update() -> option::Option<result::Result<Json, ~str>>
+	{
+		option::None
+	}
+}
+
+/// Runs a python script from the scripts directory as a child process.
+pub struct LocalModeler
+{
+	priv script: ~str,
+	priv ctx: ModelerContext,
+	priv mut handle: option::Option<JobHandle>,
+}
+
+pub fn LocalModeler(script: &str, ctx: &ModelerContext) -> LocalModeler
+{
+	LocalModeler {script: script.to_owned(), ctx: copy *ctx, handle: option::None}
+}
+
+pub impl LocalModeler : Modeler
+{
+	pure fn name() -> ~str
+	{
+		copy self.script
+	}
+	
+	fn start()
+	{
+		let dir = get_scripts_dir(&self.ctx.options.root);
+		let script = copy self.script;
+		let network_file = copy self.ctx.options.network_file;
+		let ip = copy self.ctx.options.bind_ip;
+		let port = self.ctx.options.bind_port;
+		let action: JobFn = |move dir, move script, move network_file, move ip| run_local_script(&dir, script, network_file, ip, port);
+		self.handle = option::Some(start_script(&self.ctx, self.script, action));
+	}
+	
+	fn stop()
+	{
+		// Local modelers are our children so we can simply terminate them (this
+		// doesn't wait for them to exit so that all the modelers are signalled at once).
+		if self.handle.is_some()
+		{
+			self.handle.get().stop(script_grace);
+		}
+	}
+	
+	fn health() -> Health
+	{
+		get_health(self.ctx.registry, self.script)
+	}
+	
+	fn produce_update() -> option::Option<result::Result<Json, ~str>>
+	{
+		option::None
+	}
+}
+
+/// Runs a producer within gnos every poll.
+pub struct BuiltinModeler<P: Producer Copy Owned>
+{
+	priv name: ~str,
+	priv producer: P,
+	priv ctx: ModelerContext,
+}
+
+pub fn BuiltinModeler<P: Producer Copy Owned>(name: &str, producer: P, ctx: &ModelerContext) -> BuiltinModeler<P>
+{
+	BuiltinModeler {name: name.to_owned(), producer: producer, ctx: copy *ctx}
+}
+
+pub impl<P: Producer Copy Owned> BuiltinModeler<P> : Modeler
+{
+	pure fn name() -> ~str
+	{
+		copy self.name
+	}
+	
+	fn start()
+	{
+		let name = copy self.name;
+		let producer = copy self.producer;
+		let ctx = copy self.ctx;
+		let make: fn~ () -> Job = |move name, move producer, move ctx|
+		{
+			let (job_name, name, producer, ctx) = (copy name, copy name, copy producer, copy ctx);
+			let timeout = option::Some(ctx.options.poll_rate as float);
+			let action: JobFn = |move name, move producer, move ctx| {run_producer(&ctx, name, &producer)};
+			Job {name: job_name, action: action, policy: IgnoreFailures, timeout: timeout}
+		};
+		self.ctx.scheduler.add(Schedule {name: copy self.name, when: Every(self.ctx.options.poll_rate as float), jitter: 0.0, make: make});
+	}
+	
+	fn stop()
+	{
+		self.ctx.scheduler.pause(self.name);
+		self.ctx.registry.cancel(self.name);
+	}
+	
+	fn health() -> Health
+	{
+		get_health(self.ctx.registry, self.name)
+	}
+	
+	fn produce_update() -> option::Option<result::Result<Json, ~str>>
+	{
+		option::Some(self.producer.produce_update())
+	}
+}
+
+// ---- Internal Types --------------------------------------------------------
+priv enum ModelersMsg
+{
+	AddModelerMsg(~str, fn~ () -> Health),				// name + function returning the modeler's health
+	HealthMsg(oldcomm::Chan<~[(~str, Health)]>),
+}
+
+// ---- Internal Functions ----------------------------------------------------
+
+// Seconds local scripts have to exit after SIGTERM before they are killed.
+priv const script_grace: float = 5.0;
+
+priv fn start<M: Modeler Copy Owned>(modeler: M, modelers: Modelers) -> ExitFn
+{
+	info!("starting %s modeler", modeler.name());
+	modeler.start();
+	oldcomm::send(modelers.chan, AddModelerMsg(modeler.name(), |copy modeler| {modeler.health()}));
+	|move modeler| {modeler.stop()}
+}
+
+priv fn manage_modelers(port: oldcomm::Port<ModelersMsg>)
+{
+	let mut modelers = ~[];
+	loop
+	{
+		match oldcomm::recv(port)
+		{
+			AddModelerMsg(move name, move health) =>
+			{
+				modelers.push((name, health));
+			}
+			HealthMsg(reply) =>
+			{
+				oldcomm::send(reply, do modelers.map |m| {let (ref name, ref health) = *m; (copy *name, (*health)())});
+			}
+		}
+	}
+}
+
+// Scripts are supposed to run forever so they are restarted if they exit.
+priv fn start_script(ctx: &ModelerContext, script: &str, action: JobFn) -> JobHandle
+{
+	let state_chan = ctx.state_chan;
+	let name = script.to_owned();
+	let policy = RestartPolicy {max_restarts: 5, window: 10.0*60.0, notify: |event, copy name| {script_exited(name, event, state_chan)}};
+	let job = Job {name: script.to_owned(), action: action, policy: RestartWithBackoff(policy), timeout: option::None};
+	task_runner::sequence(ctx.registry, ~[job], ~[])
+}
+
+// If scripts keep exiting we give up and open an alert.
+priv fn script_exited(script: &str, event: &RestartEvent, state_chan: oldcomm::Chan<model::Msg>)
+{
+	let name = script.to_owned();
+	let event = copy *event;
+	oldcomm::send(state_chan, model::UpdateMsg(~"globals", |store, _err, copy name, copy event| {model::record_restart(store, name, &event); true}, ~""));
+	
+	let (mesg, gave_up) =
+		match event
+		{
+			Restarting(count, ref err) => (fmt!("%s exited (restart %?): %s", script, count, *err), false),
+			GaveUp(count, ref err) => (fmt!("%s exited after %? restarts: %s", script, count, *err), true),
+		};
+	
+	let lines = mesg.split_char('\n');
+	for lines.each |line| {error!("%s", *line)};
+	
+	if gave_up
+	{
+		let alert = model::Alert {target: ~"gnos:container", id: fmt!("%s exited", script), level: ~"error", mesg: mesg, resolution: ~"Restart gnos."};	// TODO: probably should have a button somewhere to restart the script (would have to close the alert)
+		oldcomm::send(state_chan, model::UpdateMsg(~"primary", |store, _err| {model::open_alert(store, &alert)}, ~""));
+	}
+}
+
+priv fn run_ssh_script(user: &str, host: &str, script: &str, network_file: &str, ip: &str, port: u16) -> option::Option<~str>
+{
+	match utils::run_remote_command(user, host, fmt!("python %s --ip=%s --port=%? -v %s", script, ip, port, network_file))
+	{
+		option::None => option::Some(~"exited with no stderr"),
+		err => err,
+	}
+}
+
+priv fn run_local_script(dir: &Path, script: &str, network_file: &str, ip: &str, port: u16) -> option::Option<~str>
+{
+	match utils::run_local_command("python", ~[script.to_owned(), fmt!("--ip=%s", ip), fmt!("--port=%?", port), ~"-v", network_file.to_owned()], dir)
+	{
+		option::None => option::Some(~"exited with no stderr"),
+		err => err,
+	}
+}
+
+priv fn run_producer<P: Producer>(ctx: &ModelerContext, name: &str, producer: &P) -> option::Option<~str>
+{
+	match producer.produce_update()
+	{
+		result::Ok(move data) =>
+		{
+			handlers::put_json::put_update(&ctx.options, ctx.state_chan, ctx.samples_chan, ctx.render_queue, name, data);
+			option::None
+		}
+		result::Err(move err) =>
+		{
+			option::Some(err)
+		}
+	}
+}
+
+priv fn get_health(registry: JobRegistry, name: &str) -> Health
+{
+	match registry.list().find(|job| {job.name == name.to_owned()})
+	{
+		option::Some(ref job) =>
+		{
+			match job.state
+			{
+				JobQueued => Starting,
+				JobRunning | JobSucceeded => Healthy,
+				JobRestarting | JobFailed | JobTimedOut => Unhealthy(copy job.error),
+				JobCancelled | JobGaveUp => Stopped(copy job.error),
+			}
+		}
+		option::None =>
+		{
+			Starting
+		}
+	}
+}
@@ -0,0 +1,766 @@
+/// Collects NetFlow v5, NetFlow v9, and IPFIX flows from the devices in the
+/// network json file.
+///
+/// The collector is enabled by adding a netflow-port (typically 2055 or 4739)
+/// to the network json file. Flows are attributed to devices using the
+/// exporter's address and are aggregated per exporter and interface. Every
+/// poll the collector:
+/// * adds in and out kbps samples for each interface that has seen flows,
+/// * sets line-width on the relations between the exporter and the devices
+/// its flows were routed to (log scaled so that busy links are drawn with
+/// fatter lines and overriding the widths modelers use),
+/// * publishes Flows details for the exporter with the interface rates and
+/// the top talkers.
+///
+/// Sampled flows are scaled using the v5 sampling interval. Templates are
+/// tracked per exporter and observation domain. Options templates are parsed so
+/// that their records can be skipped but are otherwise ignored.
+use std::json::{Json};
+use std::map::*;
+use modeler::{ModelerContext};
+use options::{Device};
+use task_runner::{ExitFn};
+
+/// A flow record (only the fields gnos uses are retained).
+pub struct Flow
+{
+	pub src: ~str,				// ip address
+	pub dst: ~str,
+	pub next_hop: ~str,			// empty if not exported
+	pub src_port: uint,
+	pub dst_port: uint,
+	pub protocol: uint,			// e.g. 6 for tcp
+	pub bytes: float,
+	pub packets: float,
+	pub input: uint,			// ifIndex (0 if not exported)
+	pub output: uint,
+}
+
+/// Templates from NetFlow v9 and IPFIX exporters keyed by exporter, domain, and
+/// template id.
+pub type Templates = HashMap<~str, Template>;
+
+pub struct Template
+{
+	pub fields: ~[(uint, uint)],	// (field type, length) where a length of 65535 means variable length (IPFIX only)
+	pub options: bool,				// records from options templates are skipped
+}
+
+/// Decodes a v5, v9, or IPFIX packet. New templates are added to templates.
+pub fn decode_packet(data: &[u8], exporter: &str, templates: Templates) -> result::Result<~[Flow], ~str>
+{
+	if data.len() < 4
+	{
+		return result::Err(fmt!("packet from %s is too short", exporter));
+	}
+	
+	match get_uint(data, 0, 2)
+	{
+		5 => decode_v5(data),
+		9 => decode_v9(data, exporter, templates),
+		10 => decode_ipfix(data, exporter, templates),
+		version => result::Err(fmt!("packet from %s has unsupported version %?", exporter, version)),
+	}
+}
+
+/// Converts a rate into a line width: 1 for idle links, 4 for 1 Mbps, and 12
+/// (the max) for roughly 100 Gbps.
+pub pure fn link_width(kbps: float) -> float
+{
+	let width = 1.0 + 1.5*float::log10(1.0 + kbps/10.0);
+	float::floor(10.0*(if width < 12.0 {width} else {12.0}) + 0.5)/10.0
+}
+
+/// Spawns a task to collect flows on options.netflow_port. Returns a
+/// function which stops the collector.
+pub fn start_netflow_collector(ctx: &ModelerContext) -> ExitFn
+{
+	let ctx = copy *ctx;
+	let stop_chan = do utils::spawn_moded_listener(task::SingleThreaded) |stop, move ctx|
+	{
+		match snmp::UdpSocket(ctx.options.netflow_port)
+		{
+			result::Ok(socket) =>
+			{
+				info!("listening for flows on port %?", ctx.options.netflow_port);
+				let collector = Collector {templates: HashMap(), exporters: HashMap(), publishes: 0};
+				let interval = if ctx.options.poll_rate > 0 {ctx.options.poll_rate as float} else {1.0};
+				let mut last_publish = utils::imprecise_time_s();
+				while !stop.peek()
+				{
+					match socket.recv_from(poll_interval)
+					{
+						option::Some((ref data, ref ip, _port)) => collector.add_packet(&ctx, *data, *ip),
+						option::None => {}
+					}
+					
+					let now = utils::imprecise_time_s();
+					if now - last_publish >= interval
+					{
+						collector.publish(&ctx, now - last_publish);
+						last_publish = now;
+					}
+				}
+				socket.close();
+			}
+			result::Err(ref err) =>
+			{
+				let alert = model::Alert {target: ~"gnos:container", id: ~"netflow collector", level: ~"error", mesg: fmt!("Couldn't listen for flows: %s.", *err), resolution: ~"Use a different netflow-port or run gnos with permission to bind to the port."};
+				oldcomm::send(ctx.state_chan, model::UpdateMsg(~"primary", |store, _err| {model::open_alert(store, &alert)}, ~""));
+			}
+		}
+	};
+	|| {oldcomm::send(stop_chan, ())}
+}
+
+// ---- Internal Types --------------------------------------------------------
+priv struct Collector
+{
+	templates: Templates,
+	exporters: HashMap<~str, @Exporter>,	// exporter ip => Exporter
+	mut publishes: uint,
+}
+
+// Byte counts since the last publish.
+priv struct Exporter
+{
+	device: Device,
+	interfaces: HashMap<uint, (float, float)>,	// ifIndex => (in bytes, out bytes)
+	peers: HashMap<~str, float>,					// managed ip of the device flows were routed to => bytes
+	mut talkers: HashMap<~str, Talker>,			// conversation => Talker
+}
+
+priv struct Talker
+{
+	src: ~str,
+	dst: ~str,
+	protocol: uint,
+	bytes: float,
+	packets: float,
+}
+
+priv impl Collector
+{
+	// Packets from unknown devices are dropped before they are decoded so that
+	// they can't fill templates up.
+	fn add_packet(ctx: &ModelerContext, data: &[u8], ip: &str)
+	{
+		match self.get_exporter(ctx, ip)
+		{
+			option::Some(exporter) =>
+			{
+				match decode_packet(data, ip, self.templates)
+				{
+					result::Ok(ref flows) =>
+					{
+						for flows.each |flow|
+						{
+							exporter.add_flow(ctx, flow);
+						}
+					}
+					result::Err(ref err) =>
+					{
+						info!("%s", *err);
+					}
+				}
+			}
+			option::None =>
+			{
+				debug!("ignoring flows from unknown device %s", ip);
+			}
+		}
+	}
+	
+	fn get_exporter(ctx: &ModelerContext, ip: &str) -> option::Option<@Exporter>
+	{
+		match self.exporters.find(ip.to_owned())
+		{
+			option::Some(exporter) => option::Some(exporter),
+			option::None =>
+			{
+				do ctx.options.devices.find(|d| {d.managed_ip == ip.to_owned()}).map |device|
+				{
+					let exporter = @Exporter {device: copy *device, interfaces: HashMap(), peers: HashMap(), talkers: HashMap()};
+					self.exporters.insert(ip.to_owned(), exporter);
+					exporter
+				}
+			}
+		}
+	}
+	
+	fn publish(ctx: &ModelerContext, elapsed: float)
+	{
+		self.publishes += 1;
+		for self.exporters.each_value |exporter|
+		{
+			let update = exporter.get_update(elapsed, self.publishes);
+			handlers::put_json::put_update(&ctx.options, ctx.state_chan, ctx.samples_chan, ctx.render_queue, ~"netflow " + exporter.device.managed_ip, update);
+			
+			// Links which were busy stay in the table so that they shrink back down
+			// once they go idle.
+			let mut widths = ~[];
+			for exporter.peers.each |peer, bytes|
+			{
+				widths.push((peer, link_width(8.0*bytes/1000.0/elapsed)));
+			}
+			for widths.each |entry|
+			{
+				let (peer, _width) = copy *entry;
+				exporter.peers.insert(peer, 0.0);
+			}
+			if widths.is_not_empty()
+			{
+				let left = ~"entities:" + exporter.device.managed_ip;
+				oldcomm::send(ctx.state_chan, model::UpdateMsg(~"primary", |store, _err, move left, copy widths|
+				{
+					let mut changed = false;
+					for widths.each |entry|
+					{
+						let (peer, width) = copy *entry;
+						changed = model::set_relation_override(store, left, ~"entities:" + peer, "line-width", fmt!("%?", width)) || changed;
+					}
+					changed
+				}, ~""));
+			}
+		}
+	}
+}
+
+priv impl Exporter
+{
+	fn add_flow(ctx: &ModelerContext, flow: &Flow)
+	{
+		if flow.input != 0
+		{
+			let (in_bytes, out_bytes) = self.interfaces.find(flow.input).get_default((0.0, 0.0));
+			self.interfaces.insert(flow.input, (in_bytes + flow.bytes, out_bytes));
+		}
+		if flow.output != 0
+		{
+			let (in_bytes, out_bytes) = self.interfaces.find(flow.output).get_default((0.0, 0.0));
+			self.interfaces.insert(flow.output, (in_bytes, out_bytes + flow.bytes));
+		}
+		
+		// Flows are routed to the next hop or, if the next hop isn't exported, to
+		// the destination.
+		let peer = if flow.next_hop.is_not_empty() && flow.next_hop != ~"0.0.0.0" {copy flow.next_hop} else {copy flow.dst};
+		if peer != self.device.managed_ip && ctx.options.devices.any(|d| {d.managed_ip == peer})
+		{
+			let bytes = self.peers.find(copy peer).get_default(0.0);
+			self.peers.insert(peer, bytes + flow.bytes);
+		}
+		
+		let src = if flow.src_port != 0 {fmt!("%s:%?", flow.src, flow.src_port)} else {copy flow.src};
+		let dst = if flow.dst_port != 0 {fmt!("%s:%?", flow.dst, flow.dst_port)} else {copy flow.dst};
+		let key = fmt!("%s %s %?", src, dst, flow.protocol);
+		let talker = match self.talkers.find(copy key)
+			{
+				option::Some(ref talker) => Talker {bytes: talker.bytes + flow.bytes, packets: talker.packets + flow.packets, ..copy *talker},
+				option::None => Talker {src: src, dst: dst, protocol: flow.protocol, bytes: flow.bytes, packets: flow.packets},
+			};
+		self.talkers.insert(key, talker);
+	}
+	
+	// Interfaces which have seen flows always get samples so that they stay in
+	// sync with one another.
+	fn get_update(elapsed: float, publishes: uint) -> Json
+	{
+		let ip = copy self.device.managed_ip;
+		let target = ~"entities:" + ip;
+		let to_kbps = |bytes: float| {8.0*bytes/1000.0/elapsed};
+		
+		let mut samples = ~[];
+		let mut rows = ~[];
+		let mut indexes = ~[];
+		for self.interfaces.each_key |index|
+		{
+			indexes.push(index);
+		}
+		let indexes = std::sort::merge_sort(indexes, |a, b| {*a <= *b});
+		for indexes.each |index|
+		{
+			let (in_bytes, out_bytes) = self.interfaces.get(*index);
+			let mut row = ~[fmt!("if%?", *index)];
+			for [(~"in", in_bytes), (~"out", out_bytes)].each |entry|
+			{
+				let (direction, bytes) = copy *entry;
+				let name = fmt!("%s-if%?-%s_flow_kbps", ip, *index, direction);
+				samples.push(utils::to_object(~[
+					(~"name", std::json::String(copy name)),
+					(~"value", std::json::Number(to_kbps(bytes))),
+					(~"units", std::json::String(~"kbps")),
+					(~"entity-id", std::json::String(copy target)),
+				]));
+				row.push(fmt!("%.1f", to_kbps(bytes)));
+				row.push(if publishes >= 2 {fmt!("<img src = '/generated/%s.png#%?' alt = '%s'>", name, publishes, name)} else {~""});
+			}
+			rows.push(row);
+			self.interfaces.insert(*index, (0.0, 0.0));
+		}
+		
+		let mut talkers = ~[];
+		for self.talkers.each_value |talker|
+		{
+			talkers.push(talker);
+		}
+		let talkers = std::sort::merge_sort(talkers, |a, b| {a.bytes >= b.bytes});
+		let talker_rows = do vec::view(talkers, 0, uint::min(talkers.len(), max_talkers)).map |talker|
+			{
+				~[copy talker.src, copy talker.dst, protocol_to_str(talker.protocol), fmt!("%?", talker.packets as uint), fmt!("%?", talker.bytes as uint), fmt!("%.1f", to_kbps(talker.bytes))]
+			};
+		self.talkers = HashMap();
+		
+		let mut details = ~[];
+		if rows.is_not_empty()
+		{
+			details.push(utils::to_object(~[
+				(~"style", std::json::String(~"html")),
+				(~"header", utils::strs_to_json(~[~"Interface", ~"In (kbps)", ~"", ~"Out (kbps)", ~""])),
+				(~"rows", utils::rows_to_json(rows)),
+			]));
+		}
+		if talker_rows.is_not_empty()
+		{
+			details.push(utils::to_object(~[
+				(~"style", std::json::String(~"plain")),
+				(~"header", utils::strs_to_json(~[~"Source", ~"Destination", ~"Protocol", ~"Packets", ~"Bytes", ~"Rate (kbps)"])),
+				(~"rows", utils::rows_to_json(talker_rows)),
+			]));
+		}
+		else
+		{
+			details.push(std::json::String(~"No flows were received during the last poll."));
+		}
+		details.push(std::json::String(fmt!("*Top talkers are the %? conversations with the most traffic during the last poll.*", max_talkers)));
+		
+		utils::to_object(~[
+			(~"modeler", std::json::String(~"netflow " + ip)),
+			(~"samples", std::json::List(samples)),
+			(~"details", std::json::List(~[utils::to_object(~[
+				(~"entity-id", std::json::String(copy target)),
+				(~"label", std::json::String(~"Flows")),
+				(~"details", std::json::String(std::json::List(details).to_str())),
+				(~"open", std::json::String(~"no")),
+				(~"sort-key", std::json::String(~"gamma")),
+				(~"id", std::json::String(~"flows")),
+			])])),
+		])
+	}
+}
+
+// Number of conversations shown in the top talkers table.
+priv const max_talkers: uint = 10;
+
+// How often (in ms) the collector checks to see if it should stop.
+priv const poll_interval: uint = 1000;
+
+// ---- Internal Functions ----------------------------------------------------
+priv fn decode_v5(data: &[u8]) -> result::Result<~[Flow], ~str>
+{
+	let count = get_uint(data, 2, 2) as uint;
+	if data.len() < 24 + 48*count
+	{
+		return result::Err(fmt!("v5 packet has %? bytes but %? records", data.len(), count));
+	}
+	
+	let sampling = get_uint(data, 22, 2) & 0x3FFF;
+	let scale = if sampling > 1 {sampling as float} else {1.0};
+	result::Ok(do vec::from_fn(count) |i|
+	{
+		let offset = 24 + 48*i;
+		Flow {
+			src: ipv4_to_str(data, offset),
+			dst: ipv4_to_str(data, offset + 4),
+			next_hop: ipv4_to_str(data, offset + 8),
+			input: get_uint(data, offset + 12, 2) as uint,
+			output: get_uint(data, offset + 14, 2) as uint,
+			packets: scale*(get_uint(data, offset + 16, 4) as float),
+			bytes: scale*(get_uint(data, offset + 20, 4) as float),
+			src_port: get_uint(data, offset + 32, 2) as uint,
+			dst_port: get_uint(data, offset + 34, 2) as uint,
+			protocol: data[offset + 38] as uint,
+		}
+	})
+}
+
+// The header is followed by flowsets: 0 is a template, 1 is an options template,
+// and 256 and up are data.
+priv fn decode_v9(data: &[u8], exporter: &str, templates: Templates) -> result::Result<~[Flow], ~str>
+{
+	if data.len() < 20
+	{
+		return result::Err(fmt!("v9 packet from %s is too short", exporter));
+	}
+	
+	let domain = fmt!("%s/%?", exporter, get_uint(data, 16, 4));
+	decode_sets(data, 20, data.len(), domain, 0, 1, templates)
+}
+
+// Like v9 except that 2 is a template and 3 is an options template.
+priv fn decode_ipfix(data: &[u8], exporter: &str, templates: Templates) -> result::Result<~[Flow], ~str>
+{
+	if data.len() < 16
+	{
+		return result::Err(fmt!("IPFIX packet from %s is too short", exporter));
+	}
+	
+	let len = get_uint(data, 2, 2) as uint;
+	if len < 16 || len > data.len()
+	{
+		return result::Err(fmt!("IPFIX packet from %s has a bad length", exporter));
+	}
+	
+	let domain = fmt!("%s/%?", exporter, get_uint(data, 12, 4));
+	decode_sets(data, 16, len, domain, 2, 3, templates)
+}
+
+priv fn decode_sets(data: &[u8], offset: uint, len: uint, domain: &str, template_set: uint, options_set: uint, templates: Templates) -> result::Result<~[Flow], ~str>
+{
+	let ipfix = template_set == 2;
+	let mut flows = ~[];
+	let mut offset = offset;
+	while offset + 4 <= len
+	{
+		let id = get_uint(data, offset, 2) as uint;
+		let set_len = get_uint(data, offset + 2, 2) as uint;
+		if set_len < 4 || offset + set_len > len
+		{
+			return result::Err(fmt!("%s has a flowset with a bad length", domain));
+		}
+		
+		let body = vec::view(data, offset + 4, offset + set_len);
+		if id == template_set
+		{
+			add_templates(body, domain, false, ipfix, templates);
+		}
+		else if id == options_set
+		{
+			add_templates(body, domain, true, ipfix, templates);
+		}
+		else if id >= 256
+		{
+			match templates.find(fmt!("%s/%?", domain, id))
+			{
+				option::Some(ref template) =>
+				{
+					if !template.options
+					{
+						vec::push_all(&mut flows, decode_records(body, template));
+					}
+				}
+				option::None =>
+				{
+					debug!("%s has no template for flowset %?", domain, id);	// common until the exporter sends its templates
+				}
+			}
+		}
+		offset += set_len;
+	}
+	result::Ok(flows)
+}
+
+priv fn add_templates(body: &[u8], domain: &str, options: bool, ipfix: bool, templates: Templates)
+{
+	let mut offset = 0;
+	while offset + (if options {6} else {4}) <= body.len()
+	{
+		// v9 options templates have scope and option lengths (in bytes) instead
+		// of a field count. IPFIX options templates have a scope field count
+		// after the field count.
+		let id = get_uint(body, offset, 2) as uint;
+		let count =
+			if options && !ipfix
+			{
+				(get_uint(body, offset + 2, 2) + get_uint(body, offset + 4, 2)) as uint/4
+			}
+			else
+			{
+				get_uint(body, offset + 2, 2) as uint
+			};
+		offset += if options {6} else {4};
+		if id < 256
+		{
+			return;					// padding
+		}
+		
+		let mut fields = ~[];
+		while fields.len() < count && offset + 4 <= body.len()
+		{
+			let kind = get_uint(body, offset, 2) as uint;
+			let len = get_uint(body, offset + 2, 2) as uint;
+			offset += 4;
+			if ipfix && kind & 0x8000 != 0
+			{
+				offset += 4;		// enterprise number (the field is skipped by using a kind nobody else uses)
+				fields.push((0x8000, len));
+			}
+			else
+			{
+				fields.push((kind, len));
+			}
+		}
+		if fields.len() < count || offset > body.len()
+		{
+			return;					// padding or a truncated template
+		}
+		templates.insert(fmt!("%s/%?", domain, id), Template {fields: fields, options: options});
+	}
+}
+
+priv fn decode_records(body: &[u8], template: &Template) -> ~[Flow]
+{
+	let mut flows = ~[];
+	let mut offset = 0;
+	loop
+	{
+		let start = offset;
+		let mut flow = Flow {src: ~"", dst: ~"", next_hop: ~"", src_port: 0, dst_port: 0, protocol: 0, bytes: 0.0, packets: 0.0, input: 0, output: 0};
+		let mut out_bytes = 0.0;
+		let mut out_packets = 0.0;
+		let mut complete = true;
+		for template.fields.each |field|
+		{
+			let (kind, len) = *field;
+			let mut len = len;
+			if len == 65535
+			{
+				// IPFIX variable length encoding
+				if offset >= body.len() {complete = false; break;}
+				len = body[offset] as uint;
+				offset += 1;
+				if len == 255
+				{
+					if offset + 2 > body.len() {complete = false; break;}
+					len = get_uint(body, offset, 2) as uint;
+					offset += 2;
+				}
+			}
+			if offset + len > body.len()
+			{
+				complete = false;
+				break;
+			}
+			
+			// Field types are from RFC 3954 (IPFIX uses the same numbers).
+			let value = if len <= 8 {get_uint(body, offset, len)} else {0};
+			match kind
+			{
+				1 => flow.bytes = value as float,				// IN_BYTES (octetDeltaCount)
+				2 => flow.packets = value as float,				// IN_PKTS (packetDeltaCount)
+				4 => flow.protocol = value as uint,				// PROTOCOL
+				7 => flow.src_port = value as uint,				// L4_SRC_PORT
+				8 if len == 4 => flow.src = ipv4_to_str(body, offset),			// IPV4_SRC_ADDR
+				10 => flow.input = value as uint,				// INPUT_SNMP
+				11 => flow.dst_port = value as uint,			// L4_DST_PORT
+				12 if len == 4 => flow.dst = ipv4_to_str(body, offset),			// IPV4_DST_ADDR
+				14 => flow.output = value as uint,				// OUTPUT_SNMP
+				15 if len == 4 => flow.next_hop = ipv4_to_str(body, offset),		// IPV4_NEXT_HOP
+				23 => out_bytes = value as float,				// OUT_BYTES
+				24 => out_packets = value as float,				// OUT_PKTS
+				27 if len == 16 => flow.src = ipv6_to_str(body, offset),		// IPV6_SRC_ADDR
+				28 if len == 16 => flow.dst = ipv6_to_str(body, offset),		// IPV6_DST_ADDR
+				62 if len == 16 => flow.next_hop = ipv6_to_str(body, offset),	// IPV6_NEXT_HOP
+				_ => {}
+			}
+			offset += len;
+		}
+		
+		// Records are followed by padding which is shorter than a record.
+		if !complete || offset == start
+		{
+			break;
+		}
+		if flow.bytes == 0.0 && flow.packets == 0.0
+		{
+			flow.bytes = out_bytes;
+			flow.packets = out_packets;
+		}
+		flows.push(flow);
+	}
+	flows
+}
+
+priv pure fn get_uint(data: &[u8], offset: uint, len: uint) -> u64
+{
+	let mut value = 0u64;
+	for uint::range(offset, offset + len) |i|
+	{
+		value = (value << 8) | data[i] as u64;
+	}
+	value
+}
+
+priv pure fn ipv4_to_str(data: &[u8], offset: uint) -> ~str
+{
+	fmt!("%?.%?.%?.%?", data[offset] as uint, data[offset + 1] as uint, data[offset + 2] as uint, data[offset + 3] as uint)
+}
+
+priv fn ipv6_to_str(data: &[u8], offset: uint) -> ~str
+{
+	let groups = do vec::from_fn(8) |i| {fmt!("%x", get_uint(data, offset + 2*i, 2) as uint)};
+	str::connect(groups, ":")
+}
+
+priv fn protocol_to_str(protocol: uint) -> ~str
+{
+	match protocol
+	{
+		1 => ~"icmp",
+		6 => ~"tcp",
+		17 => ~"udp",
+		47 => ~"gre",
+		50 => ~"esp",
+		58 => ~"icmpv6",
+		89 => ~"ospf",
+		132 => ~"sctp",
+		_ => fmt!("%?", protocol),
+	}
+}
+
+#[cfg(test)]
+fn push_uint(data: &mut ~[u8], value: uint, len: uint)
+{
+	for uint::range(0, len) |i|
+	{
+		data.push(((value >> (8*(len - i - 1))) & 0xFF) as u8);
+	}
+}
+
+#[test]
+fn test_v5()
+{
+	let mut data = ~[];
+	push_uint(&mut data, 5, 2);				// version
+	push_uint(&mut data, 1, 2);				// count
+	push_uint(&mut data, 0, 16);			// uptime, secs, nsecs, sequence
+	push_uint(&mut data, 0, 2);				// engine type and id
+	push_uint(&mut data, 0x4000 | 10, 2);	// sampling interval
+	
+	vec::push_all(&mut data, ~[10, 0, 0, 1, 10, 0, 1, 5, 10, 0, 0, 2]);
+	push_uint(&mut data, 3, 2);				// input
+	push_uint(&mut data, 4, 2);				// output
+	push_uint(&mut data, 7, 4);				// packets
+	push_uint(&mut data, 1500, 4);			// bytes
+	push_uint(&mut data, 0, 8);				// first and last
+	push_uint(&mut data, 5000, 2);			// src port
+	push_uint(&mut data, 80, 2);			// dst port
+	vec::push_all(&mut data, ~[0, 0x18, 6, 0]);		// pad, tcp flags, protocol, tos
+	push_uint(&mut data, 0, 8);				// as, masks, pad
+	
+	let flows = decode_packet(data, "10.0.0.1", HashMap()).get();
+	assert flows.len() == 1;
+	assert flows[0].src == ~"10.0.0.1";
+	assert flows[0].dst == ~"10.0.1.5";
+	assert flows[0].next_hop == ~"10.0.0.2";
+	assert flows[0].input == 3;
+	assert flows[0].output == 4;
+	assert flows[0].packets == 70.0;
+	assert flows[0].bytes == 15000.0;
+	assert flows[0].src_port == 5000;
+	assert flows[0].dst_port == 80;
+	assert flows[0].protocol == 6;
+	
+	assert decode_packet(vec::slice(data, 0, data.len() - 1), "10.0.0.1", HashMap()).is_err();
+}
+
+#[test]
+fn test_v9()
+{
+	let templates = HashMap();
+	let mut data = ~[];
+	push_uint(&mut data, 9, 2);				// version
+	push_uint(&mut data, 2, 2);				// count
+	push_uint(&mut data, 0, 12);			// uptime, secs, sequence
+	push_uint(&mut data, 42, 4);			// source id
+	
+	push_uint(&mut data, 0, 2);				// template flowset
+	push_uint(&mut data, 4 + 4 + 4*5, 2);
+	push_uint(&mut data, 256, 2);			// template id
+	push_uint(&mut data, 5, 2);				// field count
+	for [(8u, 4u), (12, 4), (1, 4), (2, 4), (10, 2)].each |field|
+	{
+		let (kind, len) = *field;
+		push_uint(&mut data, kind, 2);
+		push_uint(&mut data, len, 2);
+	}
+	
+	push_uint(&mut data, 256, 2);			// data flowset
+	push_uint(&mut data, 4 + 2*18 + 2, 2);
+	for [(1u, 1000u), (2, 2000)].each |record|
+	{
+		let (host, bytes) = *record;
+		vec::push_all(&mut data, ~[192, 168, 0, host as u8, 10, 0, 0, 9]);
+		push_uint(&mut data, bytes, 4);
+		push_uint(&mut data, 2, 4);
+		push_uint(&mut data, 7, 2);
+	}
+	push_uint(&mut data, 0, 2);				// padding
+	
+	let flows = decode_packet(data, "10.0.0.1", templates).get();
+	assert flows.len() == 2;
+	assert flows[0].src == ~"192.168.0.1";
+	assert flows[0].dst == ~"10.0.0.9";
+	assert flows[0].bytes == 1000.0;
+	assert flows[1].src == ~"192.168.0.2";
+	assert flows[1].bytes == 2000.0;
+	assert flows[1].packets == 2.0;
+	assert flows[1].input == 7;
+	assert templates.contains_key(~"10.0.0.1/42/256");
+	assert decode_packet(data, "10.0.0.2", templates).get().len() == 2;
+	
+	// Data from a different exporter can't use the template.
+	assert decode_packet(vec::slice(data, 0, 20) + vec::slice(data, 48, data.len()), "10.0.0.3", templates).get().is_empty();
+}
+
+#[test]
+fn test_ipfix()
+{
+	let templates = HashMap();
+	let mut data = ~[];
+	push_uint(&mut data, 10, 2);			// version
+	push_uint(&mut data, 0, 2);				// length (set below)
+	push_uint(&mut data, 0, 8);				// export time, sequence
+	push_uint(&mut data, 1, 4);				// observation domain
+	
+	push_uint(&mut data, 2, 2);				// template set
+	push_uint(&mut data, 4 + 4 + 4*4 + 4, 2);
+	push_uint(&mut data, 300, 2);			// template id
+	push_uint(&mut data, 4, 2);				// field count
+	push_uint(&mut data, 27, 2);			// sourceIPv6Address
+	push_uint(&mut data, 16, 2);
+	push_uint(&mut data, 0x8000 | 100, 2);	// enterprise field
+	push_uint(&mut data, 65535, 2);
+	push_uint(&mut data, 9, 4);
+	push_uint(&mut data, 1, 2);				// octetDeltaCount
+	push_uint(&mut data, 8, 2);
+	push_uint(&mut data, 4, 2);				// protocolIdentifier
+	push_uint(&mut data, 1, 2);
+	
+	push_uint(&mut data, 300, 2);			// data set
+	push_uint(&mut data, 4 + 16 + 4 + 8 + 1, 2);
+	push_uint(&mut data, 0x20010DB8, 4);
+	push_uint(&mut data, 0, 10);
+	push_uint(&mut data, 1, 2);
+	vec::push_all(&mut data, ~[3, 1, 2, 3]);			// variable length field
+	push_uint(&mut data, 123456, 8);
+	data.push(17);
+	
+	let len = data.len();
+	data[2] = (len >> 8) as u8;
+	data[3] = (len & 0xFF) as u8;
+	
+	let flows = decode_packet(data, "10.0.0.1", templates).get();
+	assert flows.len() == 1;
+	assert flows[0].src == ~"2001:db8:0:0:0:0:0:1";
+	assert flows[0].bytes == 123456.0;
+	assert flows[0].protocol == 17;
+}
+
+#[test]
+fn test_link_width()
+{
+	assert link_width(0.0) == 1.0;
+	assert link_width(1000.0) < link_width(10000.0);
+	assert link_width(1000.0) > 3.0;
+	assert link_width(1.0e12) == 12.0;
+}
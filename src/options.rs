@@ -3,12 +3,28 @@ use core::path::{GenericPath};
 use io::WriterUtil;
 use Path = path::Path;
 use std::getopts::*;
+use thresholds::{Threshold, Above, Below};
+use syslog::{SyslogRule};
+use tools::{Tool};
+use traps::{TrapRule};
+use anomalies::{AnomalySpec};
+
+/// How a device's modeler is run (see modeler.rs).
+pub enum ModelerType
+{
+	Ssh,
+	Local,
+	Builtin,
+}
 
 pub struct Device
 {
 	pub name: ~str,
 	pub managed_ip: ~str,
-	pub modeler: ~str,
+	pub modeler: ~str,					// script name or the name of a builtin modeler
+	pub modeler_type: ModelerType,		// from the optional type field (defaults to local if client is "local" and ssh otherwise)
+	pub snmp_security: snmp::Security,	// used by the builtin snmp modeler (from the optional authentication field)
+	pub snmp_port: u16,					// from the optional snmp-port field (defaults to 161)
 }
 
 /// Various options derived from the command line and the network.json file.
@@ -22,12 +38,27 @@ pub struct Options
 	pub browse: ~str,
 	pub bind_ip: ~str,
 	pub bind_port: u16,
+	pub samples_dir: Path,	// samples are persisted here
+	pub scratch_dir: Path,	// temporary files are written here (this is outside root so they are never served)
+	pub artifacts_dir: Path,	// tool artifacts are written here (outside root so that only get_tool_artifact serves them)
 	
 	// these are from the network.json file
 	pub network_name: ~str,
-	pub client_ip: ~str,
+	pub client_ip: ~str,		// "local" to run the modelers on this machine by default (see Device)
 	pub poll_rate: u16,
 	pub devices: ~[Device],
+	pub thresholds: ~[Threshold],
+	pub anomalies: ~[AnomalySpec],
+	pub trap_port: u16,				// from the optional trap-port field (0 if traps aren't listened for)
+	pub trap_rules: ~[TrapRule],	// from the optional traps field (these are checked before the default rules)
+	pub trap_proxies: ~[~str],		// from the optional trap-proxies field (ips allowed to forward traps for other devices)
+	pub syslog_port: u16,			// from the optional syslog-port field (0 if syslog messages aren't listened for)
+	pub syslog_tcp: bool,			// from the optional syslog-tcp field (syslog messages are always received over udp)
+	pub syslog_rules: ~[SyslogRule],
+	pub netflow_port: u16,			// from the optional netflow-port field (0 if flows aren't collected)
+	pub sflow_port: u16,			// from the optional sflow-port field (0 if sFlow isn't collected)
+	pub sflow_proxies: ~[~str],		// from the optional sflow-proxies field (ips allowed to send datagrams for other agents)
+	pub tools: ~[Tool],			// from the optional tools field (these replace default tools with the same name)
 }
 
 // str constants aren't supported yet.
@@ -56,6 +87,7 @@ pub fn parse_command_line(args: ~[~str]) -> Options
 		optflag(~"help"),
 		optopt(~"bind"),
 		optopt(~"browse"),		// TODO: not sure we always want to have this, maybe debug only?
+		optopt(~"samples"),
 		optflag(~"version")
 	];
 	let matched = match getopts(vec::tail(args), opts)
@@ -77,23 +109,47 @@ pub fn parse_command_line(args: ~[~str]) -> Options
 	let path: path::Path = GenericPath::from_str(matched.free[0]);
 	let network = load_network_file(&path);
 	
+	let root: path::Path = GenericPath::from_str(opt_str(&matched, ~"root"));
+	let samples_dir = if opt_present(&matched, ~"samples") {GenericPath::from_str(opt_str(&matched, ~"samples"))} else {os::make_absolute(&root).pop().push(~"samples")};
+	
 	Options
 	{
-		root: GenericPath::from_str(opt_str(&matched, ~"root")),
+		root: root,
 		admin: opt_present(&matched, ~"admin"),
 		network_file: path.filename().get(),
 		db: opt_present(&matched, ~"db"),
 		browse: if opt_present(&matched, ~"browse") {opt_str(&matched, ~"browse")} else {~""},
 		bind_ip: if opt_present(&matched, ~"bind") {endpoint_to_ip(opt_str(&matched, ~"bind"))} else {~"127.0.0.1"},
 		bind_port: if opt_present(&matched, ~"bind") {endpoint_to_port(opt_str(&matched, ~"bind"))} else {8080},
+		samples_dir: samples_dir,
+		scratch_dir: os::make_absolute(&root).pop().push(~"scratch"),
+		artifacts_dir: os::make_absolute(&root).pop().push(~"artifacts"),
 		
 		network_name: copy network.network,
 		client_ip: copy network.client,
 		poll_rate: network.poll_rate,
 		devices: copy network.devices,
+		thresholds: copy network.thresholds,
+		anomalies: copy network.anomalies,
+		trap_port: network.trap_port,
+		trap_rules: copy network.trap_rules,
+		trap_proxies: copy network.trap_proxies,
+		syslog_port: network.syslog_port,
+		syslog_tcp: network.syslog_tcp,
+		syslog_rules: copy network.syslog_rules,
+		netflow_port: network.netflow_port,
+		sflow_port: network.sflow_port,
+		sflow_proxies: copy network.sflow_proxies,
+		tools: copy network.tools,
 	}
 }
 
+/// Returns true if any of the devices use ssh to run their modeler.
+pub pure fn uses_ssh(options: &Options) -> bool
+{
+	options.devices.any(|d| {match d.modeler_type {Ssh => true, _ => false}})
+}
+
 pub fn validate(options: &Options)
 {
 	if !os::path_is_dir(&options.root)
@@ -115,10 +171,11 @@ priv fn print_usage()
 	io::println(~"--db        use a hard-coded database instead of modeler scripts");
 	io::println(~"-h, --help  prints this message and exits");
 	io::println(~"--root=DIR  path to the directory containing html files");
+	io::println(~"--samples=DIR  directory used to persist samples [ROOT/../samples]");
 	io::println(~"--version   prints the gnos version number and exits");
 }
 
-priv fn load_network_file(path: &Path) -> {network: ~str, client: ~str, poll_rate: u16, devices: ~[Device]}
+priv fn load_network_file(path: &Path) -> {network: ~str, client: ~str, poll_rate: u16, devices: ~[Device], thresholds: ~[Threshold], anomalies: ~[AnomalySpec], trap_port: u16, trap_rules: ~[TrapRule], trap_proxies: ~[~str], syslog_port: u16, syslog_tcp: bool, syslog_rules: ~[SyslogRule], netflow_port: u16, sflow_port: u16, sflow_proxies: ~[~str], tools: ~[Tool]}
 {
 	match io::file_reader(path)
 	{
@@ -132,7 +189,19 @@ priv fn load_network_file(path: &Path) -> {network: ~str, client: ~str, poll_rat
 						network: get_network_str(path, *data, &~"network"),
 						client: get_network_str(path, *data, &~"client"),
 						poll_rate: get_network_u16(path, *data, &~"poll-rate"),
-						devices: get_network_devices(path, *data, &~"devices"),
+						devices: get_network_devices(path, *data, &~"devices", get_network_str(path, *data, &~"client") == ~"local"),
+						thresholds: get_network_thresholds(path, *data, &~"thresholds"),
+						anomalies: get_network_anomalies(path, *data, &~"anomalies"),
+						trap_port: if data.contains_key(&~"trap-port") {get_network_u16(path, *data, &~"trap-port")} else {0},
+						trap_rules: get_network_trap_rules(path, *data, &~"traps"),
+						trap_proxies: get_network_strs(path, *data, &~"trap-proxies"),
+						syslog_port: if data.contains_key(&~"syslog-port") {get_network_u16(path, *data, &~"syslog-port")} else {0},
+						syslog_tcp: if data.contains_key(&~"syslog-tcp") {get_network_bool(path, *data, &~"syslog-tcp")} else {false},
+						syslog_rules: get_network_syslog_rules(path, *data, &~"syslog-rules"),
+						netflow_port: if data.contains_key(&~"netflow-port") {get_network_u16(path, *data, &~"netflow-port")} else {0},
+						sflow_port: if data.contains_key(&~"sflow-port") {get_network_u16(path, *data, &~"sflow-port")} else {0},
+						sflow_proxies: get_network_strs(path, *data, &~"sflow-proxies"),
+						tools: get_network_tools(path, *data, &~"tools"),
 					}
 				}
 				result::Ok(ref x) =>
@@ -155,7 +224,7 @@ priv fn load_network_file(path: &Path) -> {network: ~str, client: ~str, poll_rat
 	}
 }
 
-priv fn get_network_devices(path: &Path, data: &send_map::linear::LinearMap<~str, std::json::Json>, key: &~str) -> ~[Device]
+priv fn get_network_devices(path: &Path, data: &send_map::linear::LinearMap<~str, std::json::Json>, key: &~str, local: bool) -> ~[Device]
 {
 	match data.find(key)
 	{
@@ -165,7 +234,7 @@ priv fn get_network_devices(path: &Path, data: &send_map::linear::LinearMap<~str
 			for value.each
 			|key, value|
 			{
-				vec::push(&mut devices, get_network_device(path, *key, value));
+				vec::push(&mut devices, get_network_device(path, *key, value, local));
 			}
 			devices
 		}
@@ -182,16 +251,38 @@ priv fn get_network_devices(path: &Path, data: &send_map::linear::LinearMap<~str
 	}
 }
 
-priv fn get_network_device(path: &Path, name: &str, value: &std::json::Json) -> Device
+priv fn get_network_device(path: &Path, name: &str, value: &std::json::Json, local: bool) -> Device
 {
 	match *value
 	{
 		std::json::Object(ref value) =>
 		{
+			let kind = if value.contains_key(&~"type") {get_network_str(path, *value, &~"type")} else {~""};
+			let default = if local {Local} else {Ssh};
+			let (modeler_type, modeler) = match kind
+			{
+				~"ssh" => (Ssh, get_network_str(path, *value, &~"modeler")),
+				~"local" => (Local, get_network_str(path, *value, &~"modeler")),
+				~"builtin" => (Builtin, get_network_str(path, *value, &~"modeler")),
+				
+				// snmp and linux_ssh are the device types used by net-modeler.py. Snmp
+				// devices which don't name a modeler script use the builtin snmp modeler.
+				~"snmp" if !value.contains_key(&~"modeler") => (Builtin, ~"snmp"),
+				~"" | ~"snmp" | ~"linux_ssh" => (default, get_network_str(path, *value, &~"modeler")),
+				_ =>
+				{
+					io::stderr().write_line(fmt!("In '%s' %s type should be ssh, local, builtin, snmp, or linux_ssh but was %s.", path.to_str(), name, kind));
+					libc::exit(1)
+				}
+			};
+			let authentication = if value.contains_key(&~"authentication") {get_network_str(path, *value, &~"authentication")} else {~"-v2c -c public"};
 			Device {
 				name: name.to_owned(),
 				managed_ip: get_network_str(path, *value, &~"ip"),
-				modeler: get_network_str(path, *value, &~"modeler"),
+				modeler: copy modeler,
+				modeler_type: modeler_type,
+				snmp_security: if modeler == ~"snmp" {get_snmp_security(path, name, authentication)} else {snmp::Community(~"")},
+				snmp_port: if value.contains_key(&~"snmp-port") {get_network_u16(path, *value, &~"snmp-port")} else {161},
 			}
 		}
 		ref x =>
@@ -202,6 +293,378 @@ priv fn get_network_device(path: &Path, name: &str, value: &std::json::Json) ->
 	}
 }
 
+// Authentication uses net-snmp options, e.g. "-v2c -c public" or
+// "-v3 -l authNoPriv -u gnos -a SHA -A password".
+priv fn get_snmp_security(path: &Path, name: &str, authentication: &str) -> snmp::Security
+{
+	fn bail(path: &Path, name: &str, mesg: &str) -> !
+	{
+		io::stderr().write_line(fmt!("In '%s' %s %s.", path.to_str(), name, mesg));
+		libc::exit(1)
+	}
+	
+	let args = str::words(authentication);
+	let mut version = ~"2c";
+	let mut community = ~"public";
+	let mut level = ~"noAuthNoPriv";
+	let mut user = ~"";
+	let mut protocol = ~"MD5";
+	let mut password = ~"";
+	let mut i = 0;
+	while i < args.len()
+	{
+		// Values may be attached to their flag, e.g. -v2c.
+		let (flag, value) =
+			if args[i].len() > 2 && args[i].starts_with("-")
+			{
+				(args[i].slice(0, 2), args[i].slice(2, args[i].len()))
+			}
+			else if i + 1 < args.len()
+			{
+				i += 1;
+				(copy args[i - 1], copy args[i])
+			}
+			else
+			{
+				(copy args[i], ~"")
+			};
+		match flag
+		{
+			~"-v" => version = value,
+			~"-c" => community = value,
+			~"-l" => level = value,
+			~"-u" => user = value,
+			~"-a" => protocol = value,
+			~"-A" => password = value,
+			~"-x" | ~"-X" => bail(path, name, "uses SNMPv3 privacy but the builtin snmp modeler only supports noAuthNoPriv and authNoPriv"),
+			_ => {}
+		}
+		i += 1;
+	}
+	
+	match version
+	{
+		~"2c" => snmp::Community(community),
+		~"3" =>
+		{
+			if user.is_empty()
+			{
+				bail(path, name, "uses SNMPv3 but has no user name (-u)");
+			}
+			let auth = match str::to_lower(level)
+			{
+				~"noauthnopriv" | ~"noauth" => snmp::NoAuth,
+				~"authnopriv" | ~"auth" =>
+				{
+					match str::to_upper(protocol)
+					{
+						~"MD5" => snmp::AuthMd5,
+						~"SHA" => snmp::AuthSha,
+						_ => bail(path, name, fmt!("authentication protocol (-a) should be MD5 or SHA but was %s", protocol)),
+					}
+				}
+				~"authpriv" | ~"priv" => bail(path, name, "uses SNMPv3 privacy but the builtin snmp modeler only supports noAuthNoPriv and authNoPriv"),
+				_ => bail(path, name, fmt!("security level (-l) should be noAuthNoPriv or authNoPriv but was %s", level)),
+			};
+			match auth
+			{
+				snmp::NoAuth => password = ~"",
+				_ if password.len() < 8 => bail(path, name, "SNMPv3 password (-A) must be at least 8 characters"),
+				_ => {}
+			}
+			snmp::Usm(snmp::UsmUser {name: user, auth: auth, password: password})
+		}
+		_ => bail(path, name, fmt!("uses SNMP version %s but the builtin snmp modeler only supports 2c and 3", version)),
+	}
+}
+
+// Thresholds are optional.
+priv fn get_network_thresholds(path: &Path, data: &send_map::linear::LinearMap<~str, std::json::Json>, key: &~str) -> ~[Threshold]
+{
+	match data.find(key)
+	{
+		option::Some(std::json::List(ref value)) =>
+		{
+			do value.map |v| {get_network_threshold(path, v)}
+		}
+		option::Some(ref x) =>
+		{
+			io::stderr().write_line(fmt!("In '%s' %s was expected to be a json::list but was %?.", path.to_str(), *key, x));
+			libc::exit(1)
+		}
+		option::None =>
+		{
+			~[]
+		}
+	}
+}
+
+priv fn get_network_threshold(path: &Path, value: &std::json::Json) -> Threshold
+{
+	match *value
+	{
+		std::json::Object(ref value) =>
+		{
+			let (comparison, limit) =
+				if value.contains_key(&~"above")
+				{
+					(Above, get_network_float(path, *value, &~"above"))
+				}
+				else if value.contains_key(&~"below")
+				{
+					(Below, get_network_float(path, *value, &~"below"))
+				}
+				else
+				{
+					io::stderr().write_line(fmt!("In '%s' thresholds should have an above or below entry.", path.to_str()));
+					libc::exit(1)
+				};
+			
+			// By default alerts are closed once the value is 10% back from the threshold.
+			let clear = match comparison
+				{
+					Above => 0.9*limit,
+					Below => 1.1*limit,
+				};
+			
+			if value.contains_key(&~"polls") && value.contains_key(&~"window")
+			{
+				io::stderr().write_line(fmt!("In '%s' thresholds may have polls or a window but not both.", path.to_str()));
+				libc::exit(1)
+			}
+			
+			Threshold {
+				samples: get_network_str(path, *value, &~"samples"),
+				comparison: comparison,
+				value: limit,
+				clear: if value.contains_key(&~"clear") {get_network_float(path, *value, &~"clear")} else {clear},
+				percent: if value.contains_key(&~"percent") {get_network_bool(path, *value, &~"percent")} else {false},
+				polls: if value.contains_key(&~"polls") {get_network_u16(path, *value, &~"polls") as uint} else {1},
+				window: if value.contains_key(&~"window") {get_network_float(path, *value, &~"window")} else {0.0},
+				level: if value.contains_key(&~"level") {get_network_str(path, *value, &~"level")} else {~"warning"},
+				mesg: if value.contains_key(&~"mesg") {get_network_str(path, *value, &~"mesg")} else {~""},
+				resolution: if value.contains_key(&~"resolution") {get_network_str(path, *value, &~"resolution")} else {~""},
+			}
+		}
+		ref x =>
+		{
+			io::stderr().write_line(fmt!("In '%s' thresholds were expected to be json::dicts but one was %?.", path.to_str(), x));
+			libc::exit(1)
+		}
+	}
+}
+
+// Anomalies are optional.
+priv fn get_network_anomalies(path: &Path, data: &send_map::linear::LinearMap<~str, std::json::Json>, key: &~str) -> ~[AnomalySpec]
+{
+	match data.find(key)
+	{
+		option::Some(std::json::List(ref value)) =>
+		{
+			do value.map |v| {get_network_anomaly(path, v)}
+		}
+		option::Some(ref x) =>
+		{
+			io::stderr().write_line(fmt!("In '%s' %s was expected to be a json::list but was %?.", path.to_str(), *key, x));
+			libc::exit(1)
+		}
+		option::None =>
+		{
+			~[]
+		}
+	}
+}
+
+priv fn get_network_anomaly(path: &Path, value: &std::json::Json) -> AnomalySpec
+{
+	match *value
+	{
+		std::json::Object(ref value) =>
+		{
+			let sigmas = if value.contains_key(&~"sigmas") {get_network_float(path, *value, &~"sigmas")} else {3.0};
+			let alpha = if value.contains_key(&~"alpha") {get_network_float(path, *value, &~"alpha")} else {0.05};
+			if alpha <= 0.0 || alpha > 1.0
+			{
+				io::stderr().write_line(fmt!("In '%s' anomaly alpha should be in (0.0, 1.0] but was %?.", path.to_str(), alpha));
+				libc::exit(1);
+			}
+			
+			AnomalySpec {
+				samples: get_network_str(path, *value, &~"samples"),
+				sigmas: sigmas,
+				clear: if value.contains_key(&~"clear") {get_network_float(path, *value, &~"clear")} else {if sigmas > 1.0 {sigmas - 1.0} else {0.0}},
+				alpha: alpha,
+				warmup: if value.contains_key(&~"warmup") {get_network_u16(path, *value, &~"warmup") as uint} else {30},
+				seasonal: if value.contains_key(&~"seasonal") {get_network_bool(path, *value, &~"seasonal")} else {false},
+				level: if value.contains_key(&~"level") {get_network_str(path, *value, &~"level")} else {~"info"},
+			}
+		}
+		ref x =>
+		{
+			io::stderr().write_line(fmt!("In '%s' anomalies were expected to be json::dicts but one was %?.", path.to_str(), x));
+			libc::exit(1)
+		}
+	}
+}
+
+// Trap rules are optional.
+priv fn get_network_trap_rules(path: &Path, data: &send_map::linear::LinearMap<~str, std::json::Json>, key: &~str) -> ~[TrapRule]
+{
+	match data.find(key)
+	{
+		option::Some(std::json::List(ref value)) =>
+		{
+			do value.map |v| {get_network_trap_rule(path, v)}
+		}
+		option::Some(ref x) =>
+		{
+			io::stderr().write_line(fmt!("In '%s' %s was expected to be a json::list but was %?.", path.to_str(), *key, x));
+			libc::exit(1)
+		}
+		option::None =>
+		{
+			~[]
+		}
+	}
+}
+
+priv fn get_network_trap_rule(path: &Path, value: &std::json::Json) -> TrapRule
+{
+	match *value
+	{
+		std::json::Object(ref value) =>
+		{
+			let name = get_network_str(path, *value, &~"trap");
+			let trap = match traps::parse_trap(name)
+				{
+					option::Some(oid) => oid,
+					option::None =>
+					{
+						io::stderr().write_line(fmt!("In '%s' trap '%s' is not a generic trap name or a dotted oid.", path.to_str(), name));
+						libc::exit(1)
+					}
+				};
+			
+			TrapRule {
+				trap: trap,
+				open: if value.contains_key(&~"open") {get_network_bool(path, *value, &~"open")} else {true},
+				alert: get_network_str(path, *value, &~"alert"),
+				level: if value.contains_key(&~"level") {get_network_str(path, *value, &~"level")} else {~"warning"},
+				mesg: if value.contains_key(&~"mesg") {get_network_str(path, *value, &~"mesg")} else {~""},
+				resolution: if value.contains_key(&~"resolution") {get_network_str(path, *value, &~"resolution")} else {~""},
+			}
+		}
+		ref x =>
+		{
+			io::stderr().write_line(fmt!("In '%s' traps were expected to be json::dicts but one was %?.", path.to_str(), x));
+			libc::exit(1)
+		}
+	}
+}
+
+// Syslog rules are optional.
+priv fn get_network_syslog_rules(path: &Path, data: &send_map::linear::LinearMap<~str, std::json::Json>, key: &~str) -> ~[SyslogRule]
+{
+	match data.find(key)
+	{
+		option::Some(std::json::List(ref value)) =>
+		{
+			do value.map |v| {get_network_syslog_rule(path, v)}
+		}
+		option::Some(ref x) =>
+		{
+			io::stderr().write_line(fmt!("In '%s' %s was expected to be a json::list but was %?.", path.to_str(), *key, x));
+			libc::exit(1)
+		}
+		option::None =>
+		{
+			~[]
+		}
+	}
+}
+
+priv fn get_network_syslog_rule(path: &Path, value: &std::json::Json) -> SyslogRule
+{
+	match *value
+	{
+		std::json::Object(ref value) =>
+		{
+			let pattern = match regex::Regex(get_network_str(path, *value, &~"pattern"))
+				{
+					result::Ok(re) => re,
+					result::Err(ref err) =>
+					{
+						io::stderr().write_line(fmt!("In '%s' syslog-rules has a bad pattern: %s.", path.to_str(), *err));
+						libc::exit(1)
+					}
+				};
+			
+			SyslogRule {
+				pattern: pattern,
+				open: if value.contains_key(&~"open") {get_network_bool(path, *value, &~"open")} else {true},
+				alert: get_network_str(path, *value, &~"alert"),
+				level: if value.contains_key(&~"level") {get_network_str(path, *value, &~"level")} else {~"warning"},
+				mesg: if value.contains_key(&~"mesg") {get_network_str(path, *value, &~"mesg")} else {~"{mesg}"},
+				resolution: if value.contains_key(&~"resolution") {get_network_str(path, *value, &~"resolution")} else {~""},
+			}
+		}
+		ref x =>
+		{
+			io::stderr().write_line(fmt!("In '%s' syslog-rules were expected to be json::dicts but one was %?.", path.to_str(), x));
+			libc::exit(1)
+		}
+	}
+}
+
+// Tools are optional.
+priv fn get_network_tools(path: &Path, data: &send_map::linear::LinearMap<~str, std::json::Json>, key: &~str) -> ~[Tool]
+{
+	match data.find(key)
+	{
+		option::Some(std::json::List(ref value)) =>
+		{
+			do value.map |v| {get_network_tool(path, v)}
+		}
+		option::Some(ref x) =>
+		{
+			io::stderr().write_line(fmt!("In '%s' %s was expected to be a json::list but was %?.", path.to_str(), *key, x));
+			libc::exit(1)
+		}
+		option::None =>
+		{
+			~[]
+		}
+	}
+}
+
+priv fn get_network_tool(path: &Path, value: &std::json::Json) -> Tool
+{
+	match *value
+	{
+		std::json::Object(ref value) =>
+		{
+			let tool = Tool {
+				name: get_network_str(path, *value, &~"name"),
+				command: get_network_str(path, *value, &~"command"),
+				description: if value.contains_key(&~"description") {get_network_str(path, *value, &~"description")} else {~""},
+				artifact: if value.contains_key(&~"artifact") {get_network_str(path, *value, &~"artifact")} else {~""},
+				timeout: if value.contains_key(&~"timeout") {get_network_float(path, *value, &~"timeout")} else {60.0},
+			};
+			if tool.artifact.is_not_empty() && !tool.command.contains("{artifact}")
+			{
+				io::stderr().write_line(fmt!("In '%s' tool %s has an artifact but its command doesn't use {artifact}.", path.to_str(), tool.name));
+				libc::exit(1)
+			}
+			tool
+		}
+		ref x =>
+		{
+			io::stderr().write_line(fmt!("In '%s' tools were expected to be json::dicts but one was %?.", path.to_str(), x));
+			libc::exit(1)
+		}
+	}
+}
+
 priv fn get_network_str(path: &Path, data: &send_map::linear::LinearMap<~str, std::json::Json>, key: &~str) -> ~str
 {
 	match data.find(key)
@@ -223,6 +686,38 @@ priv fn get_network_str(path: &Path, data: &send_map::linear::LinearMap<~str, st
 	}
 }
 
+// String lists are optional.
+priv fn get_network_strs(path: &Path, data: &send_map::linear::LinearMap<~str, std::json::Json>, key: &~str) -> ~[~str]
+{
+	match data.find(key)
+	{
+		option::Some(std::json::List(ref value)) =>
+		{
+			do value.map |v|
+			{
+				match *v
+				{
+					std::json::String(ref s) => s.to_owned(),
+					ref x =>
+					{
+						io::stderr().write_line(fmt!("In '%s' %s was expected to be a list of json::strings but contained %?.", path.to_str(), *key, x));
+						libc::exit(1)
+					}
+				}
+			}
+		}
+		option::Some(ref x) =>
+		{
+			io::stderr().write_line(fmt!("In '%s' %s was expected to be a json::list but was %?.", path.to_str(), *key, x));
+			libc::exit(1)
+		}
+		option::None =>
+		{
+			~[]
+		}
+	}
+}
+
 priv fn get_network_u16(path: &Path, data: &send_map::linear::LinearMap<~str, std::json::Json>, key: &~str) -> u16
 {
 	match data.find(key)
@@ -254,6 +749,27 @@ priv fn get_network_u16(path: &Path, data: &send_map::linear::LinearMap<~str, st
 	}
 }
 
+priv fn get_network_bool(path: &Path, data: &send_map::linear::LinearMap<~str, std::json::Json>, key: &~str) -> bool
+{
+	match data.find(key)
+	{
+		option::Some(std::json::Boolean(value)) =>
+		{
+			value
+		}
+		option::Some(ref x) =>
+		{
+			io::stderr().write_line(fmt!("In '%s' %s was expected to be a json::bool but was %?.", path.to_str(), *key, x));
+			libc::exit(1)
+		}
+		option::None =>
+		{
+			io::stderr().write_line(fmt!("Expected to find %s in '%s'.", *key, path.to_str()));
+			libc::exit(1)
+		}
+	}
+}
+
 priv fn get_network_float(path: &Path, data: &send_map::linear::LinearMap<~str, std::json::Json>, key: &~str) -> float
 {
 	match data.find(key)
@@ -0,0 +1,120 @@
+/// Minimal PNG encoder used for charts and sparklines.
+///
+/// Images are written as 8-bit RGB with no filtering. The zlib stream uses
+/// stored (uncompressed) deflate blocks: the charts are small and mostly white
+/// so the files are larger than they could be but this keeps the encoder tiny
+/// and avoids a dependency on libpng or zlib.
+
+/// Pixels should contain width*height RGB triplets (in row order).
+pub fn encode_rgb(width: uint, height: uint, pixels: &[u8]) -> ~[u8]
+{
+	assert pixels.len() == 3*width*height;
+	
+	let mut png = ~[0x89u8, 'P' as u8, 'N' as u8, 'G' as u8, 0x0Du8, 0x0Au8, 0x1Au8, 0x0Au8];
+	
+	let mut header = ~[];
+	utils::push_u32(&mut header, width as u32);
+	utils::push_u32(&mut header, height as u32);
+	header.push_all([8u8, 2u8, 0u8, 0u8, 0u8]);		// bit depth, color type (RGB), compression, filter, interlace
+	push_chunk(&mut png, "IHDR", header);
+	
+	// Each scan line starts with a filter type byte (0 is None).
+	let row_size = 3*width;
+	let mut raw = vec::with_capacity((row_size + 1)*height);
+	for uint::range(0, height) |y|
+	{
+		raw.push(0u8);
+		raw.push_all(vec::view(pixels, y*row_size, (y + 1)*row_size));
+	}
+	push_chunk(&mut png, "IDAT", zlib_stored(raw));
+	
+	push_chunk(&mut png, "IEND", ~[]);
+	png
+}
+
+/// CRC used by PNG chunks (the same CRC-32 used by zip and ethernet).
+pub pure fn crc32(bytes: &[u8]) -> u32
+{
+	let mut crc = 0xFFFFFFFFu32;
+	for bytes.each |b|
+	{
+		crc ^= *b as u32;
+		for 8.times
+		{
+			crc = if crc & 1 != 0 {0xEDB88320u32 ^ (crc >> 1)} else {crc >> 1};
+		}
+	}
+	crc ^ 0xFFFFFFFFu32
+}
+
+/// Checksum used at the end of zlib streams.
+pub pure fn adler32(bytes: &[u8]) -> u32
+{
+	let mut a = 1u32;
+	let mut b = 0u32;
+	for bytes.each |x|
+	{
+		a = (a + *x as u32) % 65521;
+		b = (b + a) % 65521;
+	}
+	(b << 16) | a
+}
+
+// ---- Internal Functions ----------------------------------------------------
+priv fn zlib_stored(data: &[u8]) -> ~[u8]
+{
+	let max_block = 65535;
+	
+	let mut stream = ~[0x78u8, 0x01u8];		// deflate with a 32K window, no preset dictionary
+	let mut offset = 0;
+	loop
+	{
+		let len = uint::min(max_block, data.len() - offset);
+		let last = offset + len == data.len();
+		stream.push(if last {1u8} else {0u8});		// BFINAL and BTYPE = 00 (stored)
+		stream.push((len & 0xFF) as u8);
+		stream.push((len >> 8) as u8);
+		stream.push((!len & 0xFF) as u8);
+		stream.push(((!len >> 8) & 0xFF) as u8);
+		stream.push_all(vec::view(data, offset, offset + len));
+		offset += len;
+		
+		if last
+		{
+			break;
+		}
+	}
+	
+	utils::push_u32(&mut stream, adler32(data));
+	stream
+}
+
+priv fn push_chunk(png: &mut ~[u8], kind: &str, data: &[u8])
+{
+	utils::push_u32(png, data.len() as u32);
+	
+	let mut body = str::to_bytes(kind);
+	body.push_all(data);
+	png.push_all(body);
+	utils::push_u32(png, crc32(body));
+}
+
+#[test]
+fn test_checksums()
+{
+	assert crc32(str::to_bytes("123456789")) == 0xCBF43926u32;
+	assert adler32(str::to_bytes("Wikipedia")) == 0x11E60398u32;
+}
+
+#[test]
+fn test_encode()
+{
+	let png = encode_rgb(2, 1, [255u8, 0u8, 0u8, 0u8, 0u8, 255u8]);
+	assert vec::view(png, 0, 8) == [0x89u8, 0x50u8, 0x4Eu8, 0x47u8, 0x0Du8, 0x0Au8, 0x1Au8, 0x0Au8];
+	assert vec::view(png, 12, 16) == str::to_bytes("IHDR");
+	assert vec::view(png, png.len() - 8, png.len() - 4) == str::to_bytes("IEND");
+	
+	// IDAT data is a zlib header, a stored block header, one scan line of 7 bytes, and the adler
+	let idat_len = (png[33] as uint << 24) | (png[34] as uint << 16) | (png[35] as uint << 8) | png[36] as uint;
+	assert idat_len == 2 + 5 + 7 + 4;
+}
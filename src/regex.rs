@@ -0,0 +1,485 @@
+/// Small backtracking regular expression matcher used for things like the
+/// syslog alert rules. Supports literals, ., character classes (e.g. [a-z],
+/// [^0-9], \d, \w, \s and their negations), the ^ and $ anchors, groups with
+/// alternation, and the *, +, ?, {n}, {n,}, and {n,m} quantifiers. Patterns
+/// starting with (?i) are case insensitive. Captures and back references are
+/// not supported.
+///
+/// Patterns like (a*)*b can take exponential time to fail so patterns from
+/// untrusted sources should use is_match_within.
+pub struct Regex
+{
+	priv root: Item,
+	priv ignore_case: bool,
+}
+
+/// Returns an error if the pattern is malformed.
+pub fn Regex(pattern: &str) -> result::Result<Regex, ~str>
+{
+	let ignore_case = pattern.starts_with("(?i)");
+	let parser = Parser {chars: str::chars(if ignore_case {str::slice(pattern, 4, pattern.len())} else {pattern.to_owned()}), index: 0, error: ~""};
+	let alternatives = parser.alternatives(0);
+	if parser.error.is_not_empty()
+	{
+		result::Err(fmt!("%s at %? in '%s'", parser.error, parser.index, pattern))
+	}
+	else
+	{
+		result::Ok(Regex {root: Item {node: Group(alternatives), min: 1, max: 1}, ignore_case: ignore_case})
+	}
+}
+
+pub impl Regex
+{
+	/// Returns true if the pattern matches any part of text.
+	fn is_match(text: &str) -> bool
+	{
+		self.is_match_within(text, uint::max_value).get()
+	}
+	
+	/// Like is_match except that None is returned if matching takes more than
+	/// max_steps steps.
+	fn is_match_within(text: &str, max_steps: uint) -> option::Option<bool>
+	{
+		let chars = str::chars(text);
+		let budget = Budget {steps: max_steps};
+		for uint::range(0, chars.len() + 1) |start|
+		{
+			if match_items(&self, &budget, ~[copy self.root], chars, start, |_end| {true})
+			{
+				return option::Some(true);
+			}
+			if budget.steps == 0
+			{
+				return option::None;
+			}
+		}
+		option::Some(false)
+	}
+}
+
+// ---- Internal Types --------------------------------------------------------
+priv enum Node
+{
+	Literal(char),
+	AnyChar,
+	Class(~[(char, char)], bool),		// inclusive ranges + true if negated
+	StartAnchor,
+	EndAnchor,
+	Group(~[~[Item]]),					// alternatives
+}
+
+priv struct Item
+{
+	node: Node,
+	min: uint,
+	max: uint,							// uint::max_value if unbounded
+}
+
+// Number of nodes which may still be matched. Once this hits zero all matches
+// fail.
+priv struct Budget
+{
+	mut steps: uint,
+}
+
+// Errors are sticky: once one happens the remainder of the pattern is skipped.
+priv struct Parser
+{
+	chars: ~[char],
+	mut index: uint,
+	mut error: ~str,
+}
+
+priv impl Parser
+{
+	fn at_end() -> bool
+	{
+		self.index >= self.chars.len() || self.error.is_not_empty()
+	}
+	
+	fn next() -> char
+	{
+		let ch = self.chars[self.index];
+		self.index += 1;
+		ch
+	}
+	
+	fn fail(mesg: &str)
+	{
+		if self.error.is_empty()
+		{
+			self.error = mesg.to_owned();
+		}
+	}
+	
+	fn alternatives(depth: uint) -> ~[~[Item]]
+	{
+		let mut result = ~[];
+		let mut items = ~[];
+		while !self.at_end() && self.chars[self.index] != ')'
+		{
+			if self.chars[self.index] == '|'
+			{
+				self.index += 1;
+				result.push(move items);
+				items = ~[];
+			}
+			else
+			{
+				let node = self.atom(depth);
+				let (min, max) = self.quantifier();
+				items.push(Item {node: node, min: min, max: max});
+			}
+		}
+		result.push(move items);
+		
+		if depth == 0 && !self.at_end()
+		{
+			self.fail("unbalanced )");
+		}
+		result
+	}
+	
+	fn atom(depth: uint) -> Node
+	{
+		match self.next()
+		{
+			'.' => AnyChar,
+			'^' => StartAnchor,
+			'$' => EndAnchor,
+			'\\' => self.escape(),
+			'[' => self.class(),
+			'(' =>
+			{
+				let alternatives = self.alternatives(depth + 1);
+				if self.at_end()
+				{
+					self.fail("missing )");
+				}
+				else
+				{
+					self.index += 1;
+				}
+				Group(alternatives)
+			}
+			'*' | '+' | '?' | '{' =>
+			{
+				self.fail("quantifier without an operand");
+				AnyChar
+			}
+			ch => Literal(ch),
+		}
+	}
+	
+	fn escape() -> Node
+	{
+		if self.at_end()
+		{
+			self.fail("trailing \\");
+			return AnyChar;
+		}
+		
+		match self.next()
+		{
+			'd' => Class(digit_ranges(), false),
+			'D' => Class(digit_ranges(), true),
+			'w' => Class(word_ranges(), false),
+			'W' => Class(word_ranges(), true),
+			's' => Class(space_ranges(), false),
+			'S' => Class(space_ranges(), true),
+			't' => Literal('\t'),
+			'n' => Literal('\n'),
+			'r' => Literal('\r'),
+			ch => Literal(ch),
+		}
+	}
+	
+	fn class() -> Node
+	{
+		let mut ranges = ~[];
+		let negated = !self.at_end() && self.chars[self.index] == '^';
+		if negated
+		{
+			self.index += 1;
+		}
+		
+		let mut first = true;
+		while !self.at_end() && (first || self.chars[self.index] != ']')
+		{
+			first = false;
+			let lower = self.next();
+			if lower == '\\' && !self.at_end()
+			{
+				match self.escape()
+				{
+					Class(ref escaped, false) => vec::push_all(&mut ranges, *escaped),
+					Literal(ch) => ranges.push((ch, ch)),
+					_ => self.fail("negated classes can't be used within []"),
+				}
+			}
+			else if self.index + 1 < self.chars.len() && self.chars[self.index] == '-' && self.chars[self.index + 1] != ']'
+			{
+				self.index += 1;
+				let upper = self.next();
+				if upper < lower
+				{
+					self.fail("bad range");
+				}
+				ranges.push((lower, upper));
+			}
+			else
+			{
+				ranges.push((lower, lower));
+			}
+		}
+		
+		if self.at_end()
+		{
+			self.fail("missing ]");
+		}
+		else
+		{
+			self.index += 1;
+		}
+		Class(ranges, negated)
+	}
+	
+	fn quantifier() -> (uint, uint)
+	{
+		if self.at_end()
+		{
+			return (1, 1);
+		}
+		
+		let result = match self.chars[self.index]
+			{
+				'*' => {self.index += 1; (0, uint::max_value)}
+				'+' => {self.index += 1; (1, uint::max_value)}
+				'?' => {self.index += 1; (0, 1)}
+				'{' => {self.index += 1; self.repeats()}
+				_ => return (1, 1),
+			};
+		
+		// We only care about whether there is a match so lazy quantifiers are the
+		// same as greedy ones.
+		if !self.at_end() && self.chars[self.index] == '?'
+		{
+			self.index += 1;
+		}
+		result
+	}
+	
+	// Parses the part of {n}, {n,}, or {n,m} after the brace.
+	fn repeats() -> (uint, uint)
+	{
+		let min = self.number();
+		let max =
+			if !self.at_end() && self.chars[self.index] == ','
+			{
+				self.index += 1;
+				if !self.at_end() && self.chars[self.index] == '}' {uint::max_value} else {self.number()}
+			}
+			else
+			{
+				min
+			};
+		
+		if self.at_end() || self.chars[self.index] != '}'
+		{
+			self.fail("expected }");
+		}
+		else
+		{
+			self.index += 1;
+		}
+		if max < min
+		{
+			self.fail("bad repeat count");
+		}
+		(min, max)
+	}
+	
+	fn number() -> uint
+	{
+		let start = self.index;
+		let mut value = 0;
+		while !self.at_end() && char::is_digit(self.chars[self.index])
+		{
+			value = 10*value + (self.next() as uint - '0' as uint);
+		}
+		if self.index == start
+		{
+			self.fail("expected a number");
+		}
+		value
+	}
+}
+
+// ---- Internal Functions ----------------------------------------------------
+priv fn digit_ranges() -> ~[(char, char)]
+{
+	~[('0', '9')]
+}
+
+priv fn word_ranges() -> ~[(char, char)]
+{
+	~[('a', 'z'), ('A', 'Z'), ('0', '9'), ('_', '_')]
+}
+
+priv fn space_ranges() -> ~[(char, char)]
+{
+	~[(' ', ' '), ('\t', '\t'), ('\n', '\n'), ('\r', '\r'), ('\x0B', '\x0C')]
+}
+
+// Calls k with the end of the match for each way items can match text
+// starting at pos. Returns true as soon as k does.
+priv fn match_items(re: &Regex, budget: &Budget, items: &[Item], text: &[char], pos: uint, k: fn (uint) -> bool) -> bool
+{
+	if items.is_empty()
+	{
+		k(pos)
+	}
+	else
+	{
+		match_repeat(re, budget, items, 0, text, pos, k)
+	}
+}
+
+// Greedily matches items[0] count or more times and then the rest of items.
+priv fn match_repeat(re: &Regex, budget: &Budget, items: &[Item], count: uint, text: &[char], pos: uint, k: fn (uint) -> bool) -> bool
+{
+	let item = &items[0];
+	let rest = vec::view(items, 1, items.len());
+	
+	// Matches which don't consume anything can repeat forever so we move on to
+	// the rest of the items when that happens.
+	if count < item.max && match_node(re, budget, &item.node, text, pos, |end| {if end == pos {match_items(re, budget, rest, text, end, k)} else {match_repeat(re, budget, items, count + 1, text, end, k)}})
+	{
+		true
+	}
+	else
+	{
+		count >= item.min && match_items(re, budget, rest, text, pos, k)
+	}
+}
+
+priv fn match_node(re: &Regex, budget: &Budget, node: &Node, text: &[char], pos: uint, k: fn (uint) -> bool) -> bool
+{
+	if budget.steps == 0
+	{
+		return false;
+	}
+	budget.steps -= 1;
+	
+	match *node
+	{
+		Literal(ch) => pos < text.len() && same_char(re, text[pos], ch) && k(pos + 1),
+		AnyChar => pos < text.len() && k(pos + 1),
+		Class(ref ranges, negated) => pos < text.len() && in_class(re, *ranges, text[pos]) != negated && k(pos + 1),
+		StartAnchor => pos == 0 && k(pos),
+		EndAnchor => pos == text.len() && k(pos),
+		Group(ref alternatives) => alternatives.any(|items| {match_items(re, budget, *items, text, pos, k)}),
+	}
+}
+
+priv fn same_char(re: &Regex, lhs: char, rhs: char) -> bool
+{
+	if re.ignore_case {to_lower(lhs) == to_lower(rhs)} else {lhs == rhs}
+}
+
+priv fn in_class(re: &Regex, ranges: &[(char, char)], ch: char) -> bool
+{
+	let in_ranges = |c: char| {ranges.any(|r| {let (lower, upper) = *r; c >= lower && c <= upper})};
+	in_ranges(ch) || (re.ignore_case && (in_ranges(to_lower(ch)) || in_ranges(to_upper(ch))))
+}
+
+priv pure fn to_lower(ch: char) -> char
+{
+	if ch >= 'A' && ch <= 'Z' {(ch as u32 + 32) as char} else {ch}
+}
+
+priv pure fn to_upper(ch: char) -> char
+{
+	if ch >= 'a' && ch <= 'z' {(ch as u32 - 32) as char} else {ch}
+}
+
+#[cfg(test)]
+fn matches(pattern: &str, text: &str) -> bool
+{
+	Regex(pattern).get().is_match(text)
+}
+
+#[test]
+fn test_literals()
+{
+	assert matches("neighbor", "OSPF neighbor down");
+	assert !matches("neighbour", "OSPF neighbor down");
+	assert matches("", "anything");
+	assert matches("a.c", "xabcx");
+	assert matches("a\\.c", "a.c");
+	assert !matches("a\\.c", "abc");
+	assert matches("(?i)ospf NEIGHBOR", "OSPF neighbor down");
+}
+
+#[test]
+fn test_anchors()
+{
+	assert matches("^OSPF", "OSPF neighbor down");
+	assert !matches("^neighbor", "OSPF neighbor down");
+	assert matches("down$", "OSPF neighbor down");
+	assert !matches("neighbor$", "OSPF neighbor down");
+	assert matches("^$", "");
+}
+
+#[test]
+fn test_classes()
+{
+	assert matches("[0-9]+\\.[0-9]+", "from 10.1 to");
+	assert matches("^[^ ]+ neighbor", "OSPF neighbor down");
+	assert !matches("^[^O]", "OSPF");
+	assert matches("\\d{1,3}(\\.\\d{1,3}){3}", "Nbr 10.0.0.12 from FULL");
+	assert !matches("\\d{1,3}(\\.\\d{1,3}){3}", "Nbr 10.0.12 from FULL");
+	assert matches("\\w+\\s\\S", "eth0 x");
+	assert matches("[-a]", "-");
+	assert matches("[]]", "]");
+}
+
+#[test]
+fn test_quantifiers()
+{
+	assert matches("^ab*c$", "ac");
+	assert matches("^ab*c$", "abbbc");
+	assert !matches("^ab+c$", "ac");
+	assert matches("^ab?c$", "abc");
+	assert !matches("^ab?c$", "abbc");
+	assert matches("^a{2}$", "aa");
+	assert !matches("^a{2}$", "aaa");
+	assert matches("^a{2,}$", "aaaa");
+	assert matches("^(a|)+$", "aa");
+	assert matches("^(a*)*b$", "aaab");
+	assert matches("OSPF.*neighbor.*(down|dead)", "OSPF: neighbor 10.0.0.2 is dead");
+	assert !matches("OSPF.*neighbor.*(down|dead)", "OSPF: neighbor 10.0.0.2 is up");
+	assert matches("(?i)OSPF.*neighbor.*down", "%OSPF-5-ADJCHG: Process 1, Nbr 10.0.0.2 on Gi0/1 from FULL to DOWN, Neighbor Down: Dead timer expired");
+}
+
+#[test]
+fn test_budget()
+{
+	let re = Regex("^(a*)*b$").get();
+	assert re.is_match_within("aaab", 1000) == option::Some(true);
+	assert re.is_match_within("aaac", 1000) == option::Some(false);
+	assert re.is_match_within("aaaaaaaaaaaaaaaaaaaaaaaaaaaaac", 10000).is_none();
+}
+
+#[test]
+fn test_errors()
+{
+	assert Regex("a(b").is_err();
+	assert Regex("a)b").is_err();
+	assert Regex("[ab").is_err();
+	assert Regex("*a").is_err();
+	assert Regex("a{3,1}").is_err();
+	assert Regex("a\\").is_err();
+	assert Regex("[z-a]").is_err();
+}
@@ -1,20 +1,21 @@
 /// Fixed size buffer: when it is at capacity pushs drop the oldest element.
-pub struct RingBuffer
+/// See timed_buffer::TimedBuffer for a buffer that can be queried by time.
+pub struct RingBuffer<T: Copy>
 {
-	priv mut buffer: ~[float],
+	priv mut buffer: ~[T],
 	priv capacity: uint,			// number of elements the buffer is able to hold (can't guarantee that vec capacity is exactly what we set it to)
 	priv mut size: uint,			// number of elements with legit values in the buffer
 	priv mut next: uint,			// index at which new elements land
 }
 
-pub fn RingBuffer(capacity: uint) -> RingBuffer
+pub fn RingBuffer<T: Copy>(capacity: uint) -> RingBuffer<T>
 {
 	let ring = RingBuffer {buffer: ~[], capacity: capacity, size: 0, next: 0};
 	vec::reserve(&mut ring.buffer, capacity);
 	ring
 }
 
-pub impl RingBuffer
+pub impl<T: Copy> RingBuffer<T>
 {
 	pure fn len() -> uint
 	{
@@ -36,6 +37,13 @@ pub impl RingBuffer
 		self.size
 	}
 	
+	/// Returns the most recently pushed element.
+	pure fn last() -> T
+	{
+		assert self.size > 0;
+		self[self.size - 1]
+	}
+	
 	fn clear()
 	{
 		vec::truncate(&mut self.buffer, 0);
@@ -43,7 +51,7 @@ pub impl RingBuffer
 		self.next = 0;
 	}
 	
-	fn push(element: float)
+	fn push(element: T)
 	{
 		assert self.capacity > 0;
 		
@@ -58,11 +66,20 @@ pub impl RingBuffer
 		}
 		self.next = (self.next + 1) % self.capacity;
 	}
+	
+	/// Replaces the most recently pushed element.
+	fn set_last(element: T)
+	{
+		assert self.size > 0;
+		
+		let index = (self.next + self.capacity - 1) % self.capacity;
+		self.buffer[index] = element;
+	}
 }
 
-pub impl RingBuffer : ops::Index<uint, float>
+pub impl<T: Copy> RingBuffer<T> : ops::Index<uint, T>
 {
-	pure fn index(&self, index: uint) -> float
+	pure fn index(&self, index: uint) -> T
 	{
 		assert index < self.size;
 		
@@ -77,9 +94,9 @@ pub impl RingBuffer : ops::Index<uint, float>
 	}
 }
 
-pub impl RingBuffer : BaseIter<float>
+pub impl<T: Copy> RingBuffer<T> : BaseIter<T>
 {
-	pure fn each(&self, blk: fn(v: &float) -> bool)
+	pure fn each(&self, blk: fn(v: &T) -> bool)
 	{
 		let mut i = 0;
 		while i < self.size
@@ -98,7 +115,7 @@ pub impl RingBuffer : BaseIter<float>
 	}
 }
 
-pub impl RingBuffer : ToStr
+pub impl<T: Copy> RingBuffer<T> : ToStr
 {
 	pure fn to_str() -> ~str
 	{
@@ -110,11 +127,11 @@ pub impl RingBuffer : ToStr
 fn test_ring_buffer()
 {
 	// size 0
-	let buffer = RingBuffer(0);
+	let buffer: RingBuffer<float> = RingBuffer(0);
 	assert buffer.len() == 0;
 	
 	// size 1
-	let buffer = RingBuffer(1);
+	let buffer: RingBuffer<float> = RingBuffer(1);
 	assert buffer.len() == 0;
 	
 	buffer.push(2.0);
@@ -170,4 +187,17 @@ fn test_ring_buffer()
 	assert buffer.len() == 2;
 	assert buffer[0] == 2.0;
 	assert buffer[1] == 3.0;
+	
+	// last
+	buffer.set_last(7.0);
+	assert buffer.len() == 2;
+	assert buffer.last() == 7.0;
+	
+	buffer.push(8.0);
+	buffer.push(9.0);
+	buffer.push(10.0);
+	buffer.set_last(11.0);
+	assert buffer.len() == 4;
+	assert buffer[0] == 7.0;
+	assert buffer[3] == 11.0;
 }
@@ -0,0 +1,386 @@
+/// Persists samples so that charts and sparklines survive server restarts.
+///
+/// Samples are appended to segment files which each cover a day. To keep the
+/// segments compact sample names are stored once in an index file and each
+/// record in a segment is just an id, time, and value. Retention is enforced by
+/// deleting whole segments which means that the segment being appended to is
+/// never touched by compact.
+///
+/// The directory layout looks like:
+/// samples/index.txt				lines of the form "id	owner	capacity	name"
+/// samples/segment-1356998400.dat	records are big endian (u32 id, f64 time, f64 value)
+use core::path::{GenericPath};
+use core::io::{WriterUtil};
+use std::map::{HashMap};
+use Path = path::Path;
+
+pub struct SampleLog
+{
+	priv dir: Path,
+	priv ids: HashMap<@~str, uint>,			// owner + tab + sample name => id
+	priv mut next_id: uint,
+	priv mut pending: ~[u8],					// records which have not yet been written
+	priv mut pending_time: float,				// time of the pending records
+	priv mut segment_start: float,			// start time of the segment being appended to
+}
+
+// Segments cover a day.
+pub const segment_span: float = 86400.0;
+
+priv const record_size: uint = 20;
+
+/// Creates the log directory if neccesary.
+pub fn SampleLog(dir: &Path) -> SampleLog
+{
+	if !os::path_is_dir(dir)
+	{
+		if !os::make_dir(dir, 7*8*8 + 5*8 + 5)
+		{
+			error!("Failed to create %s", dir.to_str());
+		}
+	}
+	
+	SampleLog {dir: copy *dir, ids: HashMap(), next_id: 0, pending: ~[], pending_time: float::NaN, segment_start: float::NaN}
+}
+
+pub impl SampleLog
+{
+	/// Calls f with each persisted sample (in time order). Returns the number of samples that were loaded.
+	fn load(f: fn (owner: &str, name: &str, value: float, time: float, capacity: uint)) -> uint
+	{
+		let series = self.load_index();
+		
+		let mut count = 0;
+		for get_segments(&self.dir).each |entry|
+		{
+			let (_start, path) = copy *entry;
+			match io::read_whole_file(&path)
+			{
+				result::Ok(ref bytes) =>
+				{
+					if bytes.len() % record_size != 0
+					{
+						// Probably the server was killed in the middle of a write. If we
+						// don't drop the partial record then the records appended after it
+						// will be misaligned.
+						error!("%s has a partial record", path.to_str());
+						truncate_segment(&path, *bytes, bytes.len() - bytes.len() % record_size);
+					}
+					
+					let mut i = 0;
+					while i + record_size <= bytes.len()
+					{
+						let id = get_u32(*bytes, i) as uint;
+						let time = get_f64(*bytes, i + 4);
+						let value = get_f64(*bytes, i + 12);
+						match series.find(id)
+						{
+							option::Some((owner, name, capacity)) =>
+							{
+								f(*owner, *name, value, time, capacity);
+								count += 1;
+							}
+							option::None =>
+							{
+								error!("%s has an unknown id: %?", path.to_str(), id);
+							}
+						}
+						i += record_size;
+					}
+				}
+				result::Err(ref err) =>
+				{
+					error!("Failed to read %s: %s", path.to_str(), *err);
+				}
+			}
+		}
+		count
+	}
+	
+	/// Queues up a sample to be written. Samples are written out in batches
+	/// (one batch per poll).
+	fn add(owner: &str, name: &str, value: float, time: float, capacity: uint)
+	{
+		if !float::is_NaN(self.pending_time) && time != self.pending_time
+		{
+			self.flush();
+		}
+		
+		let id = match self.ids.find(@get_key(owner, name))
+		{
+			option::Some(id) => id,
+			option::None => self.add_name(owner, name, capacity),
+		};
+		
+		utils::push_u32(&mut self.pending, id as u32);
+		push_f64(&mut self.pending, time);
+		push_f64(&mut self.pending, value);
+		self.pending_time = time;
+	}
+	
+	/// Writes any queued samples to the current segment.
+	fn flush()
+	{
+		if self.pending.is_empty()
+		{
+			return;
+		}
+		
+		let start = float::floor(self.pending_time/segment_span)*segment_span;
+		if float::is_NaN(self.segment_start) || start > self.segment_start
+		{
+			self.segment_start = start;
+		}
+		
+		let path = self.dir.push(fmt!("segment-%?.dat", self.segment_start as i64));
+		match io::file_writer(&path, ~[io::Create, io::Append])
+		{
+			result::Ok(writer) =>
+			{
+				writer.write(self.pending);
+			}
+			result::Err(ref err) =>
+			{
+				error!("Failed to open %s: %s", path.to_str(), *err);
+			}
+		}
+		
+		self.pending = ~[];
+		self.pending_time = float::NaN;
+	}
+	
+	// ---- Internal Methods --------------------------------------------------
+	priv fn add_name(owner: &str, name: &str, capacity: uint) -> uint
+	{
+		let id = self.next_id;
+		self.next_id += 1;
+		self.ids.insert(@get_key(owner, name), id);
+		
+		let path = self.dir.push(~"index.txt");
+		match io::file_writer(&path, ~[io::Create, io::Append])
+		{
+			result::Ok(writer) =>
+			{
+				writer.write_str(fmt!("%?\t%s\t%?\t%s\n", id, owner, capacity, name));
+			}
+			result::Err(ref err) =>
+			{
+				error!("Failed to open %s: %s", path.to_str(), *err);
+			}
+		}
+		id
+	}
+	
+	priv fn load_index() -> HashMap<uint, (@~str, @~str, uint)>
+	{
+		let series = HashMap();
+		
+		let path = self.dir.push(~"index.txt");
+		if os::path_exists(&path)
+		{
+			match io::read_whole_file_str(&path)
+			{
+				result::Ok(ref text) =>
+				{
+					for str::each_line(*text) |line|
+					{
+						let parts = str::splitn_char(line, '\t', 3);
+						if parts.len() == 4
+						{
+							match (uint::from_str(parts[0]), uint::from_str(parts[2]))
+							{
+								(option::Some(id), option::Some(capacity)) =>
+								{
+									series.insert(id, (@copy parts[1], @copy parts[3], capacity));
+									self.ids.insert(@get_key(parts[1], parts[3]), id);
+									self.next_id = uint::max(self.next_id, id + 1);
+								}
+								_ =>
+								{
+									error!("Bad line in %s: '%s'", path.to_str(), line);
+								}
+							}
+						}
+						else if line.is_not_empty()
+						{
+							error!("Bad line in %s: '%s'", path.to_str(), line);
+						}
+					}
+				}
+				result::Err(ref err) =>
+				{
+					error!("Failed to read %s: %s", path.to_str(), *err);
+				}
+			}
+		}
+		
+		series
+	}
+}
+
+/// Removes segments which contain only samples older than retention seconds.
+///
+/// Note that this is designed to be run from a task_runner job.
+pub fn compact(dir: &Path, retention: float) -> option::Option<~str>
+{
+	let cutoff = utils::imprecise_time_s() - retention;
+	
+	let mut errors = ~[];
+	for get_segments(dir).each |entry|
+	{
+		let (start, path) = copy *entry;
+		if start + segment_span < cutoff
+		{
+			info!("removing %s", path.to_str());
+			if !os::remove_file(&path)
+			{
+				errors.push(fmt!("Failed to remove %s", path.to_str()));
+			}
+		}
+	}
+	
+	if errors.is_empty() {option::None} else {option::Some(str::connect(errors, "\n"))}
+}
+
+// ---- Internal Functions ----------------------------------------------------
+
+// The same sample name may be used by different owners.
+priv pure fn get_key(owner: &str, name: &str) -> ~str
+{
+	fmt!("%s\t%s", owner, name)
+}
+
+// Returns the start time and path of each segment sorted by start time.
+priv fn get_segments(dir: &Path) -> ~[(float, Path)]
+{
+	let mut segments = ~[];
+	if os::path_is_dir(dir)
+	{
+		for os::list_dir(dir).each |file|
+		{
+			if file.starts_with("segment-") && file.ends_with(".dat")
+			{
+				let digits = file.slice("segment-".len(), file.len() - ".dat".len());
+				match i64::from_str(digits)
+				{
+					option::Some(start) => segments.push((start as float, dir.push(copy *file))),
+					option::None => error!("Bad segment name: %s", *file),
+				}
+			}
+		}
+	}
+	
+	std::sort::merge_sort(segments, |x, y| {x.first() <= y.first()})
+}
+
+priv fn truncate_segment(path: &Path, bytes: &[u8], len: uint)
+{
+	match io::file_writer(path, ~[io::Create, io::Truncate])
+	{
+		result::Ok(writer) =>
+		{
+			writer.write(vec::view(bytes, 0, len));
+		}
+		result::Err(ref err) =>
+		{
+			error!("Failed to truncate %s: %s", path.to_str(), *err);
+		}
+	}
+}
+
+priv fn push_f64(bytes: &mut ~[u8], value: float)
+{
+	let bits: u64 = unsafe {cast::transmute(value as f64)};
+	utils::push_u64(bytes, bits);
+}
+
+priv fn get_u32(bytes: &[u8], offset: uint) -> u32
+{
+	let mut value = 0u32;
+	for uint::range(0, 4) |i|
+	{
+		value = (value << 8) | (bytes[offset + i] as u32);
+	}
+	value
+}
+
+priv fn get_f64(bytes: &[u8], offset: uint) -> float
+{
+	let mut bits = 0u64;
+	for uint::range(0, 8) |i|
+	{
+		bits = (bits << 8) | (bytes[offset + i] as u64);
+	}
+	let value: f64 = unsafe {cast::transmute(bits)};
+	value as float
+}
+
+#[test]
+fn test_round_trip()
+{
+	let dir = std::tempfile::mkdtemp(&os::tmpdir(), "gnos-samples").get();
+	
+	let log = SampleLog(&dir);
+	log.add("snmp", "eth0", 1.5, 100.0, 10);
+	log.add("snmp", "eth1", -2.0, 100.0, 10);
+	log.add("snmp", "eth0", 3.0, 120.0, 10);
+	log.add("other", "lo", 0.0, 2.0*segment_span, 5);
+	log.flush();
+	
+	let log = SampleLog(&dir);
+	let mut samples = ~[];
+	let count = do log.load |owner, name, value, time, capacity|
+	{
+		samples.push((owner.to_owned(), name.to_owned(), value, time, capacity));
+	};
+	assert count == 4;
+	assert samples[0] == (~"snmp", ~"eth0", 1.5, 100.0, 10);
+	assert samples[1] == (~"snmp", ~"eth1", -2.0, 100.0, 10);
+	assert samples[2] == (~"snmp", ~"eth0", 3.0, 120.0, 10);
+	assert samples[3] == (~"other", ~"lo", 0.0, 2.0*segment_span, 5);
+	
+	// ids should be preserved across loads
+	log.add("snmp", "eth1", 4.0, 140.0, 10);
+	log.flush();
+	let mut names = ~[];
+	do SampleLog(&dir).load |_owner, name, _value, _time, _capacity| {names.push(name.to_owned());};
+	assert names == ~[~"eth0", ~"eth1", ~"eth0", ~"eth1", ~"lo"];
+	
+	// the first segment is old enough to be compacted
+	assert compact(&dir, utils::imprecise_time_s() - 2.0*segment_span).is_none();
+	assert get_segments(&dir).len() == 1;
+	
+	for os::list_dir_path(&dir).each |path| {os::remove_file(*path);}
+	os::remove_dir(&dir);
+}
+
+#[test]
+fn test_partial_record()
+{
+	let dir = std::tempfile::mkdtemp(&os::tmpdir(), "gnos-samples").get();
+	
+	let log = SampleLog(&dir);
+	log.add("snmp", "eth0", 1.5, 100.0, 10);
+	log.flush();
+	
+	// simulate a crash in the middle of a write
+	let (_start, path) = copy get_segments(&dir)[0];
+	match io::file_writer(&path, ~[io::Append])
+	{
+		result::Ok(writer) => writer.write(~[0u8, 0u8, 0u8, 0u8, 1u8, 2u8, 3u8]),
+		result::Err(ref err) => fail copy *err,
+	}
+	
+	let log = SampleLog(&dir);
+	let count = do log.load |_owner, _name, _value, _time, _capacity| {};
+	assert count == 1;
+	log.add("snmp", "eth0", 3.0, 120.0, 10);
+	log.flush();
+	
+	let mut values = ~[];
+	do SampleLog(&dir).load |_owner, _name, value, time, _capacity| {values.push((value, time));};
+	assert values == ~[(1.5, 100.0), (3.0, 120.0)];
+	
+	for os::list_dir_path(&dir).each |path| {os::remove_file(*path);}
+	os::remove_dir(&dir);
+}
@@ -0,0 +1,378 @@
+/// Manages multiple sample series as a unit so that they remain aligned.
+///
+/// Samples are currently used for things like interface stats. Unfortunately
+/// tap interfaces are managed by applications and may come and go (for example
+/// after a reboot the normal interfaces can come up before the tap interfaces).
+/// So SampleSet keeps one row per poll: when a series first appears it is
+/// prepended with fill values and when a series is missing from a poll it is
+/// padded with a fill value. This guarantees that every series in the set has
+/// the same length and that the i-th element of each series was sampled at
+/// the i-th timestamp.
+///
+/// The set also maintains consolidated tiers so that longer time spans can be
+/// charted (see tiers.rs) and statistics for each series which are updated as
+/// samples are added and dropped (fill values are not included in the
+/// statistics).
+use std::map::{HashMap};
+use RingBuffer = ring_buffer::RingBuffer;
+use timed_buffer::{TimedBuffer, Snapshot, WindowStats};
+use tiers::{Consolidation, Tier, TierSpec};
+
+pub struct SampleSet
+{
+	priv times: @RingBuffer<float>,					// time each row was added (seconds since the epoch)
+	priv series: HashMap<@~str, @Series>,			// sample name => values
+	priv capacity: uint,								// max number of rows to retain
+	priv fill: float,									// value used for missing samples (typically 0.0 or NaN)
+	priv tiers: ~[@Tier],								// ordered from finest to coarsest step
+}
+
+pub fn SampleSet(capacity: uint, fill: float, tiers: &[TierSpec]) -> SampleSet
+{
+	let tiers = do tiers.map |spec| {@Tier(*spec, fill)};
+	SampleSet {times: @RingBuffer(capacity), series: HashMap(), capacity: capacity, fill: fill, tiers: tiers}
+}
+
+pub impl SampleSet
+{
+	/// Returns the number of rows in the set (all series have this length).
+	pure fn len() -> uint
+	{
+		self.times.len()
+	}
+	
+	pure fn is_empty() -> bool
+	{
+		self.times.is_empty()
+	}
+	
+	fn contains(name: &str) -> bool
+	{
+		self.series.contains_key(@name.to_owned())
+	}
+	
+	/// Adds a sample taken at time. All samples from a single poll should use
+	/// the same time: a new row is started whenever time changes.
+	fn add(name: &str, value: float, time: float)
+	{
+		if self.times.is_empty() || time > self.times.last()
+		{
+			if self.times.is_not_empty()
+			{
+				self.consolidate();
+			}
+			
+			self.times.push(time);
+			for self.series.each_value |series|
+			{
+				series.pad(time, self.fill);
+			}
+		}
+		else if time < self.times.last()
+		{
+			error!("Ignoring %s sample: its time (%?) is older than the current row (%?)", name, time, self.times.last());
+			return;
+		}
+		
+		match self.series.find(@name.to_owned())
+		{
+			option::Some(series) =>
+			{
+				series.set_last(value);
+			}
+			option::None =>
+			{
+				let series = @self.new_series();
+				series.set_last(value);
+				self.series.insert(@name.to_owned(), series);
+			}
+		}
+	}
+	
+	/// Returns a copy of the time at which each row was added.
+	fn get_times() -> RingBuffer<float>
+	{
+		copy *self.times
+	}
+	
+	/// Returns a copy of the named series. If the series does not exist then
+	/// a series of fill values is returned.
+	fn get(name: &str) -> RingBuffer<float>
+	{
+		match self.series.find(@name.to_owned())
+		{
+			option::Some(series) => series.buffer.snapshot().get_values(),
+			option::None => self.new_series().buffer.snapshot().get_values(),
+		}
+	}
+	
+	/// Returns the samples for the named series with times >= time. These
+	/// share most of their storage with the set so they are cheap to send to
+	/// other tasks.
+	fn since(name: &str, time: float) -> Snapshot<float>
+	{
+		match self.series.find(@name.to_owned())
+		{
+			option::Some(series) => series.buffer.since(time),
+			option::None => Snapshot(),
+		}
+	}
+	
+	/// Like get except that multiple series are returned along with their
+	/// times. The finest resolution which covers range seconds is used
+	/// (kind is ignored if the raw samples are used) and only the rows within
+	/// range seconds of the newest row are returned (all rows are returned if
+	/// range is 0.0).
+	fn get_range(names: &[~str], range: float, kind: Consolidation) -> (RingBuffer<float>, ~[RingBuffer<float>])
+	{
+		let from = if range > 0.0 && self.times.is_not_empty() {self.times.last() - range} else {float::neg_infinity};
+		match self.find_tier(range)
+		{
+			option::Some(tier) =>
+			{
+				// Times are the start of each step so steps which overlap the range start before from.
+				trim_rows(tier.get_times(), do names.map |name| {tier.get(*name, kind)}, from - tier.spec.step)
+			}
+			option::None =>
+			{
+				trim_rows(self.get_times(), do names.map |name| {self.get(*name)}, from)
+			}
+		}
+	}
+	
+	/// Calls f with each sample name and the statistics for its series.
+	fn each(f: fn (name: &str, stats: &WindowStats) -> bool)
+	{
+		for self.series.each |name, series|
+		{
+			if !f(*name, &series.stats)
+			{
+				break;
+			}
+		}
+	}
+	
+	// Returns None if the raw samples cover range.
+	priv fn find_tier(range: float) -> Option<@Tier>
+	{
+		// If the raw buffers haven't wrapped yet then they have everything.
+		if self.tiers.is_empty() || self.times.len() < self.capacity
+		{
+			return option::None;
+		}
+		if range <= self.times.last() - self.times[0]
+		{
+			return option::None;
+		}
+		
+		for self.tiers.each |tier|
+		{
+			if range <= tier.span()
+			{
+				return option::Some(*tier);
+			}
+		}
+		option::Some(self.tiers.last())
+	}
+	
+	// Called when a row is complete to add it to the tiers.
+	priv fn consolidate()
+	{
+		if self.tiers.is_not_empty()
+		{
+			let mut values = ~[];
+			for self.series.each |name, series|
+			{
+				values.push((name, series.buffer.last().second()));
+			}
+			
+			let time = self.times.last();
+			for self.tiers.each |tier|
+			{
+				tier.add(time, values);
+			}
+		}
+	}
+	
+	priv fn new_series() -> Series
+	{
+		let series = Series {buffer: TimedBuffer(self.capacity), samples: RingBuffer(self.capacity), stats: WindowStats()};
+		for self.times.each |time|
+		{
+			series.pad(*time, self.fill);
+		}
+		series
+	}
+}
+
+// ---- Internal Types --------------------------------------------------------
+priv struct Series
+{
+	buffer: TimedBuffer<float>,
+	samples: RingBuffer<float>,		// the values stats is computed over (NaN for rows which were padded)
+	stats: WindowStats,
+}
+
+priv impl Series
+{
+	// Rows start out padded and the padding is replaced if a sample arrives.
+	fn pad(time: float, fill: float)
+	{
+		match self.buffer.push(time, fill)
+		{
+			option::Some(_) => self.stats.remove(self.samples[0]),
+			option::None => {}
+		}
+		self.samples.push(float::NaN);
+	}
+	
+	fn set_last(value: float)
+	{
+		self.buffer.set_last(value);
+		self.stats.remove(self.samples.last());
+		self.samples.set_last(value);
+		self.stats.add(value);
+	}
+}
+
+// ---- Internal Functions ----------------------------------------------------
+
+// Drops the rows older than from.
+priv fn trim_rows(times: RingBuffer<float>, buffers: ~[RingBuffer<float>], from: float) -> (RingBuffer<float>, ~[RingBuffer<float>])
+{
+	let mut first = 0;
+	while first < times.len() && times[first] < from
+	{
+		first += 1;
+	}
+	if first == 0
+	{
+		return (times, buffers);
+	}
+	
+	let keep = |buffer: &RingBuffer<float>|
+	{
+		let result = RingBuffer(buffer.len() - first);
+		for uint::range(first, buffer.len()) |i| {result.push(buffer[i]);}
+		result
+	};
+	(keep(&times), do buffers.map |b| {keep(b)})
+}
+
+#[test]
+fn test_aligned_series()
+{
+	let set = SampleSet(3, 0.0, ~[]);
+	assert set.is_empty();
+	
+	// eth0 starts out alone
+	set.add("eth0", 1.0, 10.0);
+	assert set.len() == 1;
+	
+	// tap0 shows up later and is prepended with zeros
+	set.add("eth0", 2.0, 20.0);
+	set.add("tap0", 5.0, 20.0);
+	assert set.len() == 2;
+	
+	let tap = set.get("tap0");
+	assert tap.len() == 2;
+	assert tap[0] == 0.0;
+	assert tap[1] == 5.0;
+	
+	// eth0 misses a poll and is padded
+	set.add("tap0", 6.0, 30.0);
+	let eth = set.get("eth0");
+	assert eth.len() == 3;
+	assert eth[0] == 1.0;
+	assert eth[1] == 2.0;
+	assert eth[2] == 0.0;
+	
+	// old rows are dropped from every series together
+	set.add("eth0", 3.0, 40.0);
+	let times = set.get_times();
+	let eth = set.get("eth0");
+	let tap = set.get("tap0");
+	assert times.len() == 3;
+	assert times[0] == 20.0;
+	assert eth[0] == 2.0 && eth[2] == 3.0;
+	assert tap[0] == 5.0 && tap[2] == 0.0;
+	
+	// unknown series are all fill
+	let missing = set.get("lo");
+	assert missing.len() == 3;
+	assert missing[1] == 0.0;
+	
+	// stats track the samples in the set (padding is skipped)
+	for set.each |name, stats|
+	{
+		if name == "eth0"
+		{
+			assert stats.count() == 2;
+			assert stats.min() == 2.0;
+			assert stats.max() == 3.0;
+			assert stats.mean() == 2.5;
+		}
+		else if name == "tap0"
+		{
+			assert stats.count() == 2;
+			assert stats.min() == 5.0;
+			assert stats.last() == 6.0;
+		}
+	}
+	
+	let recent = set.since("eth0", 30.0);
+	assert recent.len() == 2;
+	assert recent.get(0) == (30.0, 0.0);
+	assert recent.get(1) == (40.0, 3.0);
+}
+
+#[test]
+fn test_nan_fill()
+{
+	let set = SampleSet(4, float::NaN, ~[]);
+	set.add("a", 1.0, 1.0);
+	set.add("b", 2.0, 2.0);
+	
+	let a = set.get("a");
+	let b = set.get("b");
+	assert a[0] == 1.0;
+	assert float::is_NaN(a[1]);
+	assert float::is_NaN(b[0]);
+	assert b[1] == 2.0;
+	
+	// stale samples are ignored
+	set.add("a", 9.0, 1.5);
+	assert set.len() == 2;
+	assert float::is_NaN(set.get("a")[1]);
+}
+
+#[test]
+fn test_tier_selection()
+{
+	let set = SampleSet(3, 0.0, ~[TierSpec {step: 100.0, capacity: 10}, TierSpec {step: 1000.0, capacity: 10}]);
+	for uint::range(0, 30) |i|
+	{
+		set.add("eth0", i as float, 50.0*(i as float));
+	}
+	
+	// raw samples cover 100s (but only the last 60s are returned)
+	let (times, buffers) = set.get_range(~[~"eth0"], 60.0, tiers::Average);
+	assert times.len() == 2;
+	assert times[0] == 1400.0;
+	assert buffers[0][1] == 29.0;
+	
+	let (times, _) = set.get_range(~[~"eth0"], 0.0, tiers::Average);
+	assert times.len() == 3;
+	
+	// first tier covers 1000s (the steps overlapping the last 500s are returned)
+	let (times, buffers) = set.get_range(~[~"eth0"], 500.0, tiers::Average);
+	assert times.len() == 5;
+	assert times[0] == 900.0;
+	assert buffers[0][0] == 18.5;
+	
+	// second tier covers 10000s
+	let (times, buffers) = set.get_range(~[~"eth0"], 5000.0, tiers::Maximum);
+	assert times.len() == 1;
+	assert times[0] == 0.0;
+	assert buffers[0][0] == 19.0;
+}
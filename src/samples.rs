@@ -1,86 +1,275 @@
 /// Functions and types used to manage a task responsible for managing sample data.
-use core::path::{GenericPath};
+use Path = path::Path;
 use oldcomm::{Chan, Port};
-use core::io::{WriterUtil, ReaderUtil};
 use std::map::{HashMap};
 use RingBuffer = ring_buffer::RingBuffer;
-use runits::generated::*;
-use runits::units::*;
+use timed_buffer::{Snapshot, WindowStats};
+use SampleSet = sample_set::SampleSet;
+use SampleLog = sample_log::SampleLog;
+use counters::{Counters, SampleKind};
+use tiers::{Consolidation, TierSpec};
+use thresholds::{Threshold, Thresholds};
+use anomalies::{AnomalySpec, Anomalies};
 use task_runner::*;
 
 pub enum Msg
 {
-	AddSample(~str, ~str, float, uint),					// owner + sample set name + sample value + number of samples to retain
-	GetSampleSet(~str, Chan<(RingBuffer, uint)>),	// sample set name + channel which receives a copy of the buffer and num (global) adds
-	GetSampleSets(~[~str], Chan<~[RingBuffer]>),	// sample set names + channel which receives a copy of the buffers
+	AddSample(Sample),
+	GetSampleSet(~str, Chan<(Snapshot<float>, uint)>),	// sample set name + channel which receives the samples and num (global) adds
+	GetSampleSets(~[~str], float, Consolidation, Chan<~[option::Option<(RingBuffer<float>, RingBuffer<float>)>]>),	// sample set names + time range (in secs) + consolidation + channel which receives a copy of the times and values for each name (None for unknown names)
 	
 	RegisterMsg(~str, ~str, Chan<~[Detail]>),		// key + owner + channel to receive updates
-	DeregisterMsg(~str),								// key
+	RegisterSeries(~str, ~[~str], Chan<(bool, ~[Series])>),	// key + sample set names + channel to receive sample points (the bool is true if the points replace all earlier points)
+	DeregisterMsg(~str),								// key (from RegisterMsg or RegisterSeries)
+	
+	RegisterChart(Chart),								// charts are rendered on demand (see get_chart)
+	GetChart(~str, ~str, float, Chan<ChartReply>),	// chart name + cache key + time range (in secs, 0.0 to use the chart's range) + channel to receive the reply
+	ChartRendered(~str, Path, uint, option::Option<~str>),	// cache key + path of the rendered file + generation from RenderChart + error
 	
 	SyncMsg,
+	FlushMsg(Chan<bool>),							// writes pending samples to disk, sends true when finished
 	ExitMsg,
 }
 
+pub enum ChartReply
+{
+	UnknownChart,
+	CachedChart,										// the chart was rendered after the last sample was added to its sample sets
+	RenderChart(Chart, RingBuffer<float>, ~[RingBuffer<float>], uint),	// chart + times + samples + generation (the caller must send ChartRendered)
+	FailedChart(~str),								// another client's render of the chart failed
+}
+
+// Number of rendered charts kept in html/generated/charts (the least recently
+// used charts are deleted once there are more than this).
+pub const max_cached_charts: uint = 200;
+
+// Persisted samples are discarded after they fall out of the coarsest tier (see get_tiers).
+pub const sample_retention: float = 31.0*24.0*60.0*60.0;
+
+pub struct Sample
+{
+	pub owner: ~str,
+	pub name: ~str,			// sample set name
+	pub value: float,
+	pub kind: SampleKind,
+	pub time: float,
+	pub capacity: uint,		// number of samples to retain
+	pub entity: ~str,			// subject of the entity the sample is for (may be empty)
+	pub limit: float,			// max value for the sample, e.g. interface speed (NaN if unknown)
+	pub units: ~str,			// e.g. "kbps" (may be empty)
+}
+
+// Points for a sample set, e.g. the points added since the last update.
+pub struct Series
+{
+	name: ~str,				// sample set name
+	units: ~str,
+	points: Snapshot<float>,	// sample sets are zero filled so values are 0.0 if the sample set wasn't sampled at that time
+}
+
+// Statistics are computed over the non-NaN samples which were added (the
+// zeros used to fill missing samples are skipped) and are NaN if there are none.
 pub struct Detail
 {
 	sample_name: ~str,
 	min: float,
 	mean: float,
 	max: float,
+	median: float,
+	p95: float,			// 95th percentile
+	stddev: float,
+	last: float,			// most recent non-NaN sample
+	count: uint,			// number of non-NaN samples
 	units: ~str,
+	anomaly: bool,		// true if the latest samples deviate from the normal behavior of the sample set
 }
 
-pub fn manage_samples(port: oldcomm::Port<Msg>)
+/// Dir is where samples are persisted. Thresholds and anomalies are used to open
+/// and close alerts on the entities samples are associated with.
+pub fn manage_samples(port: oldcomm::Port<Msg>, dir: Path, state_chan: Chan<model::Msg>, thresholds: ~[Threshold], anomalies: ~[AnomalySpec])
 {
-	let sample_sets = HashMap();		// sample name => (owner, RingBuffer)
+	let sample_sets = HashMap();		// owner => SampleSet
+	let owners = HashMap();			// sample name => owner
 	let registered = HashMap();		// key => (owner, Chan<[Detail]>)
-	let mut num_adds = 0;			// this is for a hack in build_sparkline
+	let series = HashMap();			// key => (sample set names, Chan<(bool, [Series])>)
+	let units = HashMap();			// sample name => units
+	let counters = Counters();		// used to convert counters into rates
+	let thresholds = Thresholds(thresholds);
+	let anomalies = Anomalies(anomalies);
+	let charts = HashMap();			// chart name => Chart
+	let rendered = HashMap();		// chart cache key => RenderedChart
+	let rendering = HashMap();		// chart cache key => clients waiting for the render to finish
+	let last_adds = HashMap();		// sample name => num_adds when the sample set was last added to
+	let mut num_adds = 0;
+	let mut num_uses = 0;			// used to find the least recently used chart
+	
+	let log = SampleLog(&dir);
+	let count = do log.load |owner, name, value, time, capacity|
+	{
+		add_sample(sample_sets, owners, owner, name, value, time, capacity);
+	};
+	info!("loaded %? samples from %s", count, dir.to_str());
 	
 	loop
 	{
 		match oldcomm::recv(port)
 		{
-			AddSample(copy owner, copy name, value, capacity) =>
+			AddSample(ref sample) =>
 			{
-				let name = @name;
-				if !sample_sets.contains_key(name)
+				let value = counters.get_value(sample.name, sample.value, sample.kind, sample.time, sample.limit);
+				add_sample(sample_sets, owners, sample.owner, sample.name, value, sample.time, sample.capacity);
+				log.add(sample.owner, sample.name, value, sample.time, sample.capacity);
+				num_adds += 1;
+				last_adds.insert(@copy sample.name, num_adds);
+				
+				if sample.units.is_not_empty()
 				{
-					sample_sets.insert(name, (@owner, @RingBuffer(capacity)));
+					units.insert(@copy sample.name, @copy sample.units);
 				}
+				send_point(sample_sets, owners, series, units, sample.name, sample.time);
 				
-				let buffer = sample_sets[name].second();
-				buffer.push(value);
-				num_adds += 1;
+				let (opened, closed) = thresholds.check(sample.name, sample.entity, value, sample.limit, sample.time);
+				if opened.is_not_empty() || closed.is_not_empty()
+				{
+					update_alerts(state_chan, opened, closed);
+				}
+				
+				let (opened, closed) = anomalies.check(sample.name, sample.entity, value, sample.time);
+				if opened.is_not_empty() || closed.is_not_empty()
+				{
+					update_alerts(state_chan, opened, closed);
+				}
 			}
 			GetSampleSet(copy name, ch) =>
 			{
-				let buffer = sample_sets[@name].second();
-				ch.send((copy *buffer, num_adds));
+				let owner = owners[@name];
+				ch.send((sample_sets[owner].since(name, 0.0), num_adds));
 			}
-			GetSampleSets(ref names, ch) =>
+			GetSampleSets(ref names, range, kind, ch) =>
 			{
-				let buffers = do names.map |n| {copy *sample_sets[@n.to_owned()].second()};
-				ch.send(buffers);
+				// Sample sets with different owners have different times so each is
+				// returned with its own times.
+				let reply = do names.map |name|
+				{
+					match owners.find(@copy *name)
+					{
+						option::Some(owner) =>
+						{
+							let (times, buffers) = sample_sets[owner].get_range(~[copy *name], range, kind);
+							option::Some((times, buffers[0]))
+						}
+						option::None =>
+						{
+							option::None
+						}
+					}
+				};
+				ch.send(reply);
 			}
 			RegisterMsg(copy key, copy owner, channel) =>
 			{
 				let added = registered.insert(@key, (@copy owner, channel));
 				assert added;
-				send_update(sample_sets, owner, channel);
+				send_update(sample_sets, units, &anomalies, owner, channel);
+			}
+			RegisterSeries(copy key, copy names, channel) =>
+			{
+				let added = series.insert(@key, (@copy names, channel));
+				assert added;
+				let points = do names.map |name| {get_series(sample_sets, owners, units, *name, 0.0)};
+				channel.send((true, points));
 			}
 			DeregisterMsg(copy key) =>
 			{
-				registered.remove(@key);
+				registered.remove(@copy key);
+				series.remove(@key);
+			}
+			RegisterChart(ref chart) =>
+			{
+				charts.insert(@copy chart.name, @copy *chart);
+			}
+			GetChart(copy name, copy key, range, ch) =>
+			{
+				match charts.find(@name)
+				{
+					option::Some(chart) =>
+					{
+						// Charts are only re-rendered when one of their own sample sets changes
+						// and only one client at a time renders a given chart.
+						let generation = get_chart_generation(last_adds, chart.sample_sets);
+						num_uses += 1;
+						match rendered.find(@copy key)
+						{
+							option::Some(r) if r.generation == generation =>
+							{
+								r.used = num_uses;
+								ch.send(CachedChart);
+							}
+							_ =>
+							{
+								match rendering.find(@copy key)
+								{
+									option::Some(waiters) =>
+									{
+										waiters.chans.push(ch);
+									}
+									option::None =>
+									{
+										rendering.insert(@copy key, @Waiters {chans: ~[]});
+										let range = if range > 0.0 {range} else {chart.range};
+										let (times, buffers) = get_sample_sets(sample_sets, owners, chart.sample_sets, range, tiers::Average);
+										ch.send(RenderChart(copy *chart, times, buffers, generation));
+									}
+								}
+							}
+						}
+					}
+					option::None =>
+					{
+						ch.send(UnknownChart);
+					}
+				}
+			}
+			ChartRendered(copy key, copy path, generation, copy err) =>
+			{
+				let reply = match err
+				{
+					option::None =>
+					{
+						rendered.insert(@copy key, @RenderedChart {generation: generation, path: path, used: num_uses});
+						evict_charts(rendered, max_cached_charts);
+						CachedChart
+					}
+					option::Some(copy e) =>
+					{
+						FailedChart(e)
+					}
+				};
+				match rendering.find(@copy key)
+				{
+					option::Some(waiters) =>
+					{
+						for waiters.chans.each |chan| {chan.send(copy reply);}
+						rendering.remove(@key);
+					}
+					option::None => {}
+				}
 			}
 			SyncMsg =>
 			{
 				for registered.each_value |value|
 				{
-					send_update(sample_sets, value.first().to_owned(), value.second());
+					send_update(sample_sets, units, &anomalies, value.first().to_owned(), value.second());
 				}
 			}
+			FlushMsg(ch) =>
+			{
+				log.flush();
+				ch.send(true);
+			}
 			ExitMsg =>
 			{
+				log.flush();
 				break;
 			}
 		}
@@ -89,228 +278,296 @@ pub fn manage_samples(port: oldcomm::Port<Msg>)
 
 pub struct Chart
 {
-	path: ~str,					// path to the generated png file
+	name: ~str,					// used in the chart's URL
 	sample_sets: ~[~str],		// name each sample set was saved under
 	legends: ~[~str],			// name to use in the legend for each sample set
-	interval: float,				// in seconds
+	range: float,				// time span to chart in seconds (0.0 to chart the raw samples)
 	title: ~str,					// main title
 	y_label: ~str,				// x label is assumed to be Time
 }
 
-// Creates a chart with color-coded lines for each sample set. Id is used to 
-// uniquely identify the charts (across tasks). Note that multiple charts are
-// normally created at once to amortize the cost of starting up Rscript. 
-//
-// It would be more efficient to create these charts client-side. It might also
-// be more flexible (easier to allow users to customize their appearence).
-// However we'd have to use some javascript library to create the charts
-// instead of R and it's very hard for any tool to compete with R's chart
-// capabilities.
-pub fn create_charts(id: ~str, charts: &[Chart], samples_chan: Chan<Msg>)
+/// Returns a schedule which compacts the sample log once a day (removing
+/// segments older than sample_retention).
+pub fn compact_schedule(dir: &Path) -> Schedule
 {
-	let mut script = ~"";
-	
-	// Assemble a mondo R script,
-	let port = Port();
-	let chan = Chan(&port);
-	for charts.each |chart|
+	let dir = copy *dir;
+	let make: fn~ () -> Job = |move dir|
 	{
-		assert chart.sample_sets.is_not_empty();
-		
-		samples_chan.send(samples::GetSampleSets(copy chart.sample_sets, chan));
-		let samples = port.recv();
-		append_r_script(chart, samples, &mut script);
-	}
-	
-	// and execute it.
-	let action: JobFn =
-		|move script, move id|
-		{
-			let path = GenericPath::from_str(fmt!("/tmp/gnos-%s.R", id));
-			match io::file_writer(&path, ~[io::Create, io::Truncate])
-			{
-				result::Ok(writer) =>
-				{
-					writer.write_str(script);
-					run_script(&path)
-				}
-				result::Err(ref err) =>
-				{
-					option::Some(fmt!("Failed to create %s: %s", path.to_str(), *err))
-				}
-			}
-		};
-	let cleanup: ExitFn = || {};
-	run(Job {action: action, policy: IgnoreFailures}, ~[cleanup]);
+		let dir = copy dir;
+		let action: JobFn = |move dir| {sample_log::compact(&dir, sample_retention)};
+		Job {name: ~"compact samples", action: action, policy: IgnoreFailures, timeout: option::None}
+	};
+	Schedule {name: ~"compact samples", when: At(cron::parse_cron("15 3 * * *").get()), jitter: 600.0, make: make}
 }
 
-// The poll interval can vary by quite a bit so using seconds for the x axis
-// will often be hard to interpret. So we use this function to choose a time
-// unit that should be suitable for the times in use.
-priv fn get_time_interval(interval: float, num_samples: uint) -> (float, ~str)
+// ---- Internal Types --------------------------------------------------------
+priv struct RenderedChart
 {
-	let max_time = interval*(num_samples as float - 1.0);
-	let x = from_units(max_time, Second).normalize_time();
-	(interval*(x.value/max_time), x.units.to_str())
+	generation: uint,
+	path: Path,
+	mut used: uint,				// value of num_uses when the chart was last requested
 }
 
-// We generate the R script instead of using mustache because the mustache
-// version winds up being all templates anyway.
-//
-// Files wind up looking like this:
-// library(RColorBrewer)
-// png('/Users/jessejones/Source/gnos/html/generated/10.101.0.2-in-interfaces.png', 600, 400)
-// 
-// samples1 = c(11.7057, 11.6448, 11.6452, 11.6578, 11.6615, 11.6394, 11.6578, 11.6732, 11.6452, 11.6461, 11.6732, 11.6278, 11.6636, 11.6615, 11.6452)
-// samples2 = c(1.6499, 0.8344, 0.8792, 1.6216, 0.796, 0.8236, 1.6200, 0.8168, 0.8608, 1.5848, 0.7968, 0.8228, 1.6208, 0.8328, 0.824)
-// samples3 = c(0.9099, 0.4196, 0.4656, 0.9403, 0.4196, 0.4654, 0.9403, 0.42, 0.4656, 0.9114, 0.42, 0.4649, 0.9408, 0.4196, 0.4656)
-// samples4 = c(1.6145, 0.8511, 0.8072, 1.6752, 0.796, 0.8236, 1.6032, 0.8152, 0.824, 1.6048, 0.8136, 0.8060, 1.6376, 0.8144, 0.824)
-// samples5 = c(0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0)
-// times = -c(14*0.3333, 13*0.3333, 12*0.3333, 11*0.3333, 10*0.3333, 9*0.3333, 8*0.3333, 7*0.3333, 6*0.3333, 5*0.3333, 4*0.3333, 3*0.3333, 2*0.3333, 1*0.3333, 0*0.3333)
-// 
-// max_samples1 = max(samples1)
-// max_samples2 = max(samples2)
-// max_samples3 = max(samples3)
-// max_samples4 = max(samples4)
-// max_samples5 = max(samples5)
-// max_samples = max(c(max_samples1, max_samples2, max_samples3, max_samples4, max_samples5))
-// 
-// colors = brewer.pal(5, 'Set1')
-// plot(samples1 ~ times, type = 'l', lwd = 2, col = colors[1], ylim = c(0, max_samples), xlab = 'Time (min)', ylab = 'Bandwidth (kb/s)', main = '10.101.0.2 In Bandwidth')
-// points(samples2 ~ times, type = 'l', lwd = 2, col = colors[2])
-// points(samples3 ~ times, type = 'l', lwd = 2, col = colors[3])
-// points(samples4 ~ times, type = 'l', lwd = 2, col = colors[4])
-// points(samples5 ~ times, type = 'l', lwd = 2, col = colors[5])
-// 
-// legend('topleft', c('eth0', 'eth1', 'eth2', 'eth3', 'lo'), fill = c(colors[1], colors[2], colors[3], colors[4], colors[5]))
-// grid()
-// 
-// dev.off()
-priv fn append_r_script(chart: &Chart, samples: &[RingBuffer], script: &mut ~str)
+priv struct Waiters
 {
-	let num_lines = samples.len();
-	let num_samples = samples[0].len();
-	
-	// The built in palettes don't do a very good job of picking colors that are both
-	// pleasing to the eye and visually distinct. There are a number of packages
-	// that provide better palettes. RColorBrewer seems to be one of the better
-	// ones and has a really nifty online palette picker at http://colorbrewer2.org
-	// Manual is at http://svitsrv25.epfl.ch/R-doc/library/RColorBrewer/html/ColorBrewer.html
-	if script.len() == 0
-	{
-		*script += "library(RColorBrewer)\n";
-	}
-	else
+	mut chans: ~[Chan<ChartReply>],
+}
+
+// ---- Internal Functions ----------------------------------------------------
+priv fn add_sample(sample_sets: HashMap<@~str, @SampleSet>, owners: HashMap<@~str, @~str>, owner: &str, name: &str, value: float, time: float, capacity: uint)
+{
+	let owner = @owner.to_owned();
+	if !sample_sets.contains_key(owner)
 	{
-		*script += "\n####################################################\n";
+		// Missing samples are zero filled so that the charts line up.
+		sample_sets.insert(owner, @SampleSet(capacity, 0.0, get_tiers()));
 	}
-	*script += fmt!("png('%s', 800, 500)\n\n", chart.path);
 	
-	for samples.eachi |i, buffer|
-	{
-		// TODO: Currently samples are only used for interface stats. Unfortunately
-		// tap interfaces are managed by applications and may come and go (for
-		// example after a reboot the normal interfaces can come up before the
-		// tap interfaces).
-		//
-		// A good fix for this seems painful. Perhaps the way to go is to have a SampleSet
-		// abstraction in rust that manages multiple samples as a unit. When a new sample
-		// appears it can be prepended with zeros. When samples are added if one is
-		// missing it can be given a zero value.
-		if buffer.len() == num_samples
+	let set = sample_sets[owner];
+	set.add(name, value, time);
+	owners.insert(@name.to_owned(), owner);
+}
+
+priv fn update_alerts(state_chan: Chan<model::Msg>, opened: ~[model::Alert], closed: ~[(~str, ~str)])
+{
+	oldcomm::send(state_chan, model::UpdateMsg(~"primary",
+		|store, _data, move opened, move closed|
 		{
-			let values = do iter::map_to_vec(buffer) |x| {x.to_str()};
-			*script += fmt!("samples%? = c(%s)\n", i+1, str::connect(values, ", "));
-		}
-		else
+			for opened.each |alert| {model::open_alert(store, alert);}
+			for closed.each |entry| {model::close_alert(store, entry.first(), entry.second());}
+			true
+		}, ~""));
+}
+
+// Sends the new point to the clients which registered for the sample set.
+priv fn send_point(sample_sets: HashMap<@~str, @SampleSet>, owners: HashMap<@~str, @~str>, series: HashMap<@~str, (@~[~str], Chan<(bool, ~[Series])>)>, units: HashMap<@~str, @~str>, name: &str, time: float)
+{
+	for series.each_value |entry|
+	{
+		let (names, channel) = entry;
+		if names.contains(&name.to_owned())
 		{
-			// This sucks but the chart will recover once the ring buffers fill up.
-			error!("For %s number of %s samples doesn't match %s", chart.path, chart.sample_sets[i], chart.sample_sets[0]);
+			channel.send((false, ~[get_series(sample_sets, owners, units, name, time)]));
 		}
 	}
-	
-	let (interval, x_units) = get_time_interval(chart.interval, num_samples);
-	let times = do vec::from_fn(num_samples) |i|
+}
+
+// Returns the raw samples for the named sample set with times >= time.
+priv fn get_series(sample_sets: HashMap<@~str, @SampleSet>, owners: HashMap<@~str, @~str>, units: HashMap<@~str, @~str>, name: &str, time: float) -> Series
+{
+	let points = match owners.find(@name.to_owned())
 	{
-		fmt!("%?*%?", num_samples - i - 1, interval)
+		option::Some(owner) => sample_sets[owner].since(name, time),
+		option::None => Snapshot(),
 	};
-	*script += fmt!("times = -c(%s)\n\n", str::connect(times, ", "));
-	
-	for uint::range(0, num_lines) |i|
+	Series {name: name.to_owned(), units: get_units(units, name), points: points}
+}
+
+priv fn get_units(units: HashMap<@~str, @~str>, name: &str) -> ~str
+{
+	match units.find(@name.to_owned())
 	{
-		*script += fmt!("max_samples%? = max(samples%?)\n", i+1, i+1);
+		option::Some(u) => copy *u,
+		option::None => ~"",
 	}
-	let max_samples = do vec::from_fn(num_lines) |i| {fmt!("max_samples%?", i+1)};
-	*script += fmt!("max_samples = max(c(%s))\n\n", str::connect(max_samples, ", "));
+}
 
-	// TODO: assumes units are kbps	
-	*script += fmt!("colors = brewer.pal(%?, 'Set1')\n", num_lines);
-	*script += fmt!("plot(samples1 ~ times, type = 'l', lwd = 2, col = colors[1], ylim = c(0, max_samples), xlab = 'Time (%s)', ylab = '%s (%s)', main = '%s')\n", x_units, chart.y_label, "kbps", chart.title);
-	for uint::iterate(1, num_lines) |i|
-	{
-		// Note that R vector indexing is 1-based.
-		*script += fmt!("points(samples%? ~ times, type = 'l', lwd = 2, col = colors[%?])\n", i+1, i+1);
-	};
-	*script += "\n";
-	
-	let legends = do chart.legends.map |n| {fmt!("'%s'", *n)};
-	let colors = do vec::from_fn(num_lines) |i| {fmt!("colors[%?]", i + 1)};
-	*script += fmt!("legend('topleft', c(%s), fill = c(%s))\n", str::connect(legends, ", "), str::connect(colors, ", "));
-	*script += "grid()\n\n";
-	
-	*script += "dev.off()\n";
+// Raw samples are retained for about an hour (see samples_capacity). After
+// that samples are consolidated into one minute steps for a day and then into
+// fifteen minute steps for a month.
+priv fn get_tiers() -> ~[TierSpec]
+{
+	~[
+		TierSpec {step: 60.0, capacity: 24*60},
+		TierSpec {step: 15.0*60.0, capacity: 31*24*4},
+	]
 }
 
-priv fn run_script(path: &Path) -> option::Option<~str>
+// All of the names should belong to the same owner (which will be the case
+// for charts) so that the buffers line up with the times. If none of the names
+// are known then empty buffers are returned.
+priv fn get_sample_sets(sample_sets: HashMap<@~str, @SampleSet>, owners: HashMap<@~str, @~str>, names: &[~str], range: float, kind: Consolidation) -> (RingBuffer<float>, ~[RingBuffer<float>])
 {
-	fn get_output(label: &str, reader: io::Reader) -> ~str
+	match vec::find(names, |name| {owners.contains_key(@copy *name)})
 	{
-		let text = str::from_bytes(reader.read_whole_stream());
-		if text.is_not_empty() {fmt!("%s:\n%s\n", label, text)} else {~""}
+		option::Some(ref first) =>
+		{
+			let owner = owners[@copy *first];
+			for names.each |name|
+			{
+				match owners.find(@copy *name)
+				{
+					option::Some(o) if o != owner =>
+					{
+						error!("%s is owned by %s but %s is owned by %s", *name, *o, *first, *owner);
+					}
+					_ => {}
+				}
+			}
+			
+			let set = sample_sets[owner];
+			set.get_range(names, range, kind)
+		}
+		option::None =>
+		{
+			(RingBuffer(0), do names.map |_name| {RingBuffer(0)})
+		}
 	}
-	
-	let program = core::run::start_program("Rscript", [path.to_str()]);
-	let result = program.finish();
-	if result != 0
+}
+
+// Charts have to be re-rendered when any of their sample sets change.
+priv fn get_chart_generation(last_adds: HashMap<@~str, uint>, names: &[~str]) -> uint
+{
+	do names.foldl(0) |generation, name|
 	{
-		let mut err = fmt!("Rscript %s returned %?\n", path.to_str(), result);
-		err += get_output("stdout", program.output());
-		err += get_output("stderr", program.err());
-		option::Some(err)
+		uint::max(*generation, last_adds.find(@copy *name).get_default(0))
 	}
-	else
+}
+
+// Deletes the least recently used charts until there are at most max_charts.
+priv fn evict_charts(rendered: HashMap<@~str, @RenderedChart>, max_charts: uint)
+{
+	while rendered.size() > max_charts
 	{
-		option::None
+		let mut oldest = option::None;
+		for rendered.each |key, chart|
+		{
+			match oldest
+			{
+				option::Some((_, used)) if used <= chart.used => {}
+				_ => oldest = option::Some((key, chart.used)),
+			}
+		}
+		
+		let key = oldest.get().first();
+		let chart = rendered[key];
+		debug!("evicting chart %s", chart.path.to_str());
+		os::remove_file(&chart.path);
+		rendered.remove(key);
 	}
 }
 
-priv fn send_update(sample_sets: HashMap<@~str, (@~str, @RingBuffer)>, owner: &str, channel: Chan<~[Detail]>)
+priv fn send_update(sample_sets: HashMap<@~str, @SampleSet>, units: HashMap<@~str, @~str>, anomalies: &Anomalies, owner: &str, channel: Chan<~[Detail]>)
 {
 	let mut details = ~[];
 	
-	for sample_sets.each |sample_name, value|
+	match sample_sets.find(@owner.to_owned())
 	{
-		if str::eq_slice(*value.first(), owner)
+		option::Some(set) =>
+		{
+			for set.each |sample_name, stats|
+			{
+				vec::push(&mut details, get_detail(sample_name, stats, get_units(units, sample_name), anomalies.is_anomalous(sample_name)));
+			}
+		}
+		option::None =>
 		{
-			vec::push(&mut details, get_detail(*sample_name, value.second()));
 		}
 	}
 	
 	channel.send(details);
 }
 
-priv fn get_detail(sample_name: &str, buffer: &RingBuffer) -> Detail
+priv fn get_detail(sample_name: &str, stats: &WindowStats, units: ~str, anomaly: bool) -> Detail
+{
+	Detail
+	{
+		sample_name: sample_name.to_owned(),
+		min: stats.min(),
+		mean: stats.mean(),
+		max: stats.max(),
+		median: stats.percentile(50.0),
+		p95: stats.percentile(95.0),
+		stddev: stats.stddev(),
+		last: stats.last(),
+		count: stats.count(),
+		units: units,
+		anomaly: anomaly,
+	}
+}
+
+#[test]
+fn test_get_detail()
 {
-	let mut min = float::infinity;
-	let mut mean = 0.0;
-	let mut max = 0.0;
+	let stats = WindowStats();
+	for [-4.0, 3.0, float::NaN, -1.0, 2.0, -5.0].each |x| {stats.add(*x);}
 	
-	for buffer.each |x|
+	let detail = get_detail("eth0", &stats, ~"kbps", false);
+	assert detail.count == 5;
+	assert detail.min == -5.0;
+	assert detail.max == 3.0;		// used to be 0.0 for negative data
+	assert detail.mean == -1.0;
+	assert detail.median == -1.0;
+	assert detail.last == -5.0;
+	assert detail.units == ~"kbps";
+	assert float::abs(detail.stddev - float::sqrt(10.0)) < 0.001;
+	assert float::abs(detail.p95 - 2.8) < 0.001;
+	
+	let detail = get_detail("eth1", &WindowStats(), ~"", false);
+	assert detail.count == 0;
+	assert float::is_NaN(detail.max);
+}
+
+#[test]
+fn test_charts()
+{
+	let last_adds = HashMap();
+	last_adds.insert(@~"eth0", 3);
+	last_adds.insert(@~"eth1", 7);
+	assert get_chart_generation(last_adds, ~[~"eth0"]) == 3;
+	assert get_chart_generation(last_adds, ~[~"eth0", ~"eth1", ~"eth2"]) == 7;
+	assert get_chart_generation(last_adds, ~[~"eth2"]) == 0;
+	
+	let dir = std::tempfile::mkdtemp(&os::tmpdir(), "charts").get();
+	let rendered = HashMap();
+	for [(~"a", 3), (~"b", 1), (~"c", 2)].each |entry|
 	{
-		if *x < min {min = *x}
-		if *x > max {max = *x}
-		mean += *x;
+		let (key, used) = copy *entry;
+		let path = dir.push(copy key);
+		assert chart::write_file(&path, str::to_bytes(key), &dir.push("scratch")).is_none();
+		rendered.insert(@key, @RenderedChart {generation: 1, path: path, used: used});
 	}
-	mean /= buffer.len() as float;
-	Detail {sample_name: sample_name.to_owned(), min: min, mean: mean, max: max, units: ~"kbps"}	// TODO: use better units
+	
+	evict_charts(rendered, 2);
+	assert rendered.size() == 2;
+	assert !rendered.contains_key(@~"b");
+	assert !os::path_exists(&dir.push("b"));
+	assert os::path_exists(&dir.push("a"));
+	
+	evict_charts(rendered, 0);
+	assert rendered.size() == 0;
+	assert !os::path_exists(&dir.push("a"));
+	os::remove_dir(&dir.push("scratch"));
+	os::remove_dir(&dir);
+}
+
+#[test]
+fn test_owners()
+{
+	// Sources which poll at different times shouldn't pad each other's series.
+	let sample_sets = HashMap();
+	let owners = HashMap();
+	for uint::range(0, 5) |i|
+	{
+		let time = 20.0*(i as float);
+		add_sample(sample_sets, owners, "snmp rtr", "rtr-eth0", 1.0, time, 10);
+		add_sample(sample_sets, owners, "netflow 10.0.0.2", "rtr-flows", 2.0, time + 7.0, 10);
+	}
+	assert sample_sets.size() == 2;
+	assert *owners[@~"rtr-eth0"] == ~"snmp rtr";
+	assert *owners[@~"rtr-flows"] == ~"netflow 10.0.0.2";
+	
+	let set = sample_sets[@~"snmp rtr"];
+	assert set.len() == 5;
+	assert set.get_times()[1] == 20.0;
+	for set.get("rtr-eth0").each |value| {assert *value == 1.0;}
+	
+	let set = sample_sets[@~"netflow 10.0.0.2"];
+	assert set.len() == 5;
+	assert set.get_times()[1] == 27.0;
+	for set.get("rtr-flows").each |value| {assert *value == 2.0;}
+	
+	let (times, buffers) = get_sample_sets(sample_sets, owners, ~[~"rtr-flows"], 0.0, tiers::Average);
+	assert times.len() == 5;
+	assert buffers[0][4] == 2.0;
 }
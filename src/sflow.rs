@@ -0,0 +1,744 @@
+/// Collects sFlow v5 datagrams from the devices in the network json file
+/// (typically switches which don't support NetFlow).
+///
+/// The collector is enabled by adding an sflow-port (typically 6343) to the
+/// network json file. Datagrams are only decoded if they were sent by a device
+/// and the agent address within them must be the sender's address unless the
+/// sender is in the optional sflow-proxies list of ips. Datagrams are then
+/// attributed to devices using the agent address. Every poll the collector:
+/// * adds in and out kbps samples for each port using the generic interface
+/// counter samples,
+/// * publishes sFlow details for the agent with a table of the port counters
+/// and tables of the per-VLAN and per-port traffic estimated from the flow
+/// samples (frame lengths scaled by the sampling rate).
+///
+/// VLANs are taken from the extended switch records or, if those aren't
+/// exported, from the 802.1Q tags in the sampled ethernet headers.
+use std::json::{Json};
+use std::map::*;
+use modeler::{ModelerContext};
+use options::{Device};
+use task_runner::{ExitFn};
+
+/// Generic interface counters (only the fields gnos uses are retained).
+pub struct CounterSample
+{
+	pub index: uint,			// ifIndex
+	pub speed: float,			// bps
+	pub status: uint,			// bit 0 is admin up, bit 1 is oper up
+	pub in_octets: float,
+	pub out_octets: float,
+	pub in_errors: float,
+	pub out_errors: float,
+}
+
+pub struct FlowSample
+{
+	pub input: uint,			// ifIndex (0 if unknown)
+	pub output: uint,			// ifIndex (0 if unknown, discarded, or sent to multiple ports)
+	pub vlan: uint,				// 0 if untagged
+	pub frame_length: uint,		// bytes
+	pub sampling_rate: uint,	// one packet out of sampling_rate was sampled
+}
+
+pub struct Datagram
+{
+	pub agent: ~str,			// ip address
+	pub counters: ~[CounterSample],
+	pub flows: ~[FlowSample],
+}
+
+/// Decodes an sFlow v5 datagram. Samples and records gnos doesn't use are
+/// skipped.
+pub fn decode_datagram(data: &[u8]) -> result::Result<Datagram, ~str>
+{
+	let reader = Reader {data: vec::from_slice(data), offset: 0, end: data.len(), error: ~""};
+	let version = reader.u32();
+	if version != 5 && reader.error.is_empty()
+	{
+		return result::Err(fmt!("sFlow version %? is not supported", version));
+	}
+	
+	let agent = match reader.u32()
+		{
+			1 => reader.ipv4(),
+			2 => reader.ipv6(),
+			kind => {reader.fail(fmt!("bad agent address type %?", kind)); ~""}
+		};
+	reader.skip(12);						// sub-agent id, sequence number, uptime
+	
+	let mut datagram = Datagram {agent: agent, counters: ~[], flows: ~[]};
+	let count = reader.u32();
+	for uint::range(0, count) |_i|
+	{
+		if reader.error.is_not_empty()
+		{
+			break;
+		}
+		
+		let format = reader.u32();
+		let body = reader.sub(reader.u32());
+		match format
+		{
+			1 => decode_flow_sample(&body, false, &mut datagram),
+			2 => decode_counter_sample(&body, false, &mut datagram),
+			3 => decode_flow_sample(&body, true, &mut datagram),
+			4 => decode_counter_sample(&body, true, &mut datagram),
+			_ => {}
+		}
+		if body.error.is_not_empty()
+		{
+			reader.fail(copy body.error);
+		}
+	}
+	
+	if reader.error.is_empty()
+	{
+		result::Ok(datagram)
+	}
+	else
+	{
+		result::Err(fmt!("bad sFlow datagram: %s", reader.error))
+	}
+}
+
+/// Spawns a task to collect datagrams on options.sflow_port. Returns a
+/// function which stops the collector.
+pub fn start_sflow_collector(ctx: &ModelerContext) -> ExitFn
+{
+	let ctx = copy *ctx;
+	let stop_chan = do utils::spawn_moded_listener(task::SingleThreaded) |stop, move ctx|
+	{
+		match snmp::UdpSocket(ctx.options.sflow_port)
+		{
+			result::Ok(socket) =>
+			{
+				info!("listening for sFlow on port %?", ctx.options.sflow_port);
+				let agents: HashMap<~str, @Agent> = HashMap();
+				let interval = if ctx.options.poll_rate > 0 {ctx.options.poll_rate as float} else {1.0};
+				let mut last_publish = utils::imprecise_time_s();
+				let mut publishes = 0;
+				while !stop.peek()
+				{
+					match socket.recv_from(poll_interval)
+					{
+						option::Some((ref data, ref ip, _port)) =>
+						{
+							add_datagram(&ctx, agents, *data, *ip);
+						}
+						option::None => {}
+					}
+					
+					let now = utils::imprecise_time_s();
+					if now - last_publish >= interval
+					{
+						publishes += 1;
+						for agents.each_value |agent|
+						{
+							let update = agent.get_update(now - last_publish, publishes);
+							handlers::put_json::put_update(&ctx.options, ctx.state_chan, ctx.samples_chan, ctx.render_queue, ~"sflow " + agent.device.managed_ip, update);
+						}
+						last_publish = now;
+					}
+				}
+				socket.close();
+			}
+			result::Err(ref err) =>
+			{
+				let alert = model::Alert {target: ~"gnos:container", id: ~"sflow collector", level: ~"error", mesg: fmt!("Couldn't listen for sFlow: %s.", *err), resolution: ~"Use a different sflow-port or run gnos with permission to bind to the port."};
+				oldcomm::send(ctx.state_chan, model::UpdateMsg(~"primary", |store, _err| {model::open_alert(store, &alert)}, ~""));
+			}
+		}
+	};
+	|| {oldcomm::send(stop_chan, ())}
+}
+
+// ---- Internal Types --------------------------------------------------------
+// XDR reader. Errors are sticky: once one happens reads return zeros.
+priv struct Reader
+{
+	data: ~[u8],
+	mut offset: uint,
+	end: uint,
+	mut error: ~str,
+}
+
+priv impl Reader
+{
+	fn fail(mesg: ~str)
+	{
+		if self.error.is_empty()
+		{
+			self.error = mesg;
+		}
+	}
+	
+	fn has(count: uint) -> bool
+	{
+		if self.error.is_empty() && self.offset + count <= self.end
+		{
+			true
+		}
+		else
+		{
+			self.fail(fmt!("expected %? more bytes at %?", count, self.offset));
+			false
+		}
+	}
+	
+	fn u32() -> uint
+	{
+		let mut value = 0;
+		if self.has(4)
+		{
+			for uint::range(0, 4) |i|
+			{
+				value = (value << 8) | self.data[self.offset + i] as uint;
+			}
+			self.offset += 4;
+		}
+		value
+	}
+	
+	fn u64() -> float
+	{
+		let high = self.u32() as float;
+		let low = self.u32() as float;
+		high*4294967296.0 + low
+	}
+	
+	fn skip(count: uint)
+	{
+		if self.has(count)
+		{
+			self.offset += count;
+		}
+	}
+	
+	fn ipv4() -> ~str
+	{
+		if self.has(4)
+		{
+			let d = &self.data;
+			let i = self.offset;
+			self.offset += 4;
+			fmt!("%?.%?.%?.%?", d[i] as uint, d[i + 1] as uint, d[i + 2] as uint, d[i + 3] as uint)
+		}
+		else
+		{
+			~""
+		}
+	}
+	
+	fn ipv6() -> ~str
+	{
+		let groups = do vec::from_fn(4) |_i| {let word = self.u32(); fmt!("%x:%x", word >> 16, word & 0xFFFF)};
+		str::connect(groups, ":")
+	}
+	
+	// Returns a reader for the next count bytes (padded to a multiple of four)
+	// and skips over them.
+	fn sub(count: uint) -> Reader
+	{
+		let padded = (count + 3) & !3;
+		if self.has(padded)
+		{
+			let reader = Reader {data: vec::slice(self.data, self.offset, self.offset + count), offset: 0, end: count, error: ~""};
+			self.offset += padded;
+			reader
+		}
+		else
+		{
+			Reader {data: ~[], offset: 0, end: 0, error: copy self.error}
+		}
+	}
+}
+
+// State for an agent since the last publish.
+priv struct Agent
+{
+	device: Device,
+	rates: counters::Counters,
+	ports: HashMap<uint, Port>,				// ifIndex => Port
+	mut vlans: HashMap<uint, (float, float)>,	// vlan => (estimated bytes, estimated packets)
+	mut flows: HashMap<uint, (float, float)>,	// ifIndex => (estimated in bytes, estimated out bytes)
+}
+
+priv struct Port
+{
+	counters: CounterSample,
+	in_kbps: float,							// NaN until there are two counter samples
+	out_kbps: float,
+}
+
+priv impl Agent
+{
+	fn add_datagram(datagram: &Datagram)
+	{
+		let time = utils::imprecise_time_s();
+		for datagram.counters.each |sample|
+		{
+			let ip = &self.device.managed_ip;
+			let limit = if sample.speed > 0.0 {sample.speed/1000.0} else {float::NaN};
+			let in_kbps = self.rates.get_value(fmt!("%s-port%?-in_octets", *ip, sample.index), sample.in_octets, counters::Counter64(8.0/1000.0), time, limit);
+			let out_kbps = self.rates.get_value(fmt!("%s-port%?-out_octets", *ip, sample.index), sample.out_octets, counters::Counter64(8.0/1000.0), time, limit);
+			self.ports.insert(sample.index, Port {counters: copy *sample, in_kbps: in_kbps, out_kbps: out_kbps});
+		}
+		
+		for datagram.flows.each |sample|
+		{
+			let bytes = (sample.frame_length*sample.sampling_rate) as float;
+			let (vlan_bytes, vlan_packets) = self.vlans.find(sample.vlan).get_default((0.0, 0.0));
+			self.vlans.insert(sample.vlan, (vlan_bytes + bytes, vlan_packets + sample.sampling_rate as float));
+			
+			if sample.input != 0
+			{
+				let (in_bytes, out_bytes) = self.flows.find(sample.input).get_default((0.0, 0.0));
+				self.flows.insert(sample.input, (in_bytes + bytes, out_bytes));
+			}
+			if sample.output != 0
+			{
+				let (in_bytes, out_bytes) = self.flows.find(sample.output).get_default((0.0, 0.0));
+				self.flows.insert(sample.output, (in_bytes, out_bytes + bytes));
+			}
+		}
+	}
+	
+	fn get_update(elapsed: float, publishes: uint) -> Json
+	{
+		let ip = copy self.device.managed_ip;
+		let target = ~"entities:" + ip;
+		let to_kbps = |bytes: float| {8.0*bytes/1000.0/elapsed};
+		
+		let mut samples = ~[];
+		let mut port_rows = ~[];
+		for sorted_keys(self.ports).each |index|
+		{
+			let port = self.ports.get(*index);
+			let mut row = ~[fmt!("%?", *index), speed_to_str(port.counters.speed), status_to_str(port.counters.status)];
+			for [(~"in", port.in_kbps), (~"out", port.out_kbps)].each |entry|
+			{
+				let (direction, kbps) = copy *entry;
+				let name = fmt!("%s-port%?-%s_kbps", ip, *index, direction);
+				if !float::is_NaN(kbps)
+				{
+					let mut sample = ~[
+						(~"name", std::json::String(copy name)),
+						(~"value", std::json::Number(kbps)),
+						(~"units", std::json::String(~"kbps")),
+						(~"entity-id", std::json::String(copy target)),
+					];
+					if port.counters.speed > 0.0
+					{
+						sample.push((~"limit", std::json::Number(port.counters.speed/1000.0)));
+					}
+					samples.push(utils::to_object(sample));
+				}
+				row.push(if float::is_NaN(kbps) {~""} else {fmt!("%.1f", kbps)});
+				row.push(if publishes >= 2 {fmt!("<img src = '/generated/%s.png#%?' alt = '%s'>", name, publishes, name)} else {~""});
+			}
+			row.push(fmt!("%?", port.counters.in_errors as uint));
+			row.push(fmt!("%?", port.counters.out_errors as uint));
+			port_rows.push(row);
+		}
+		
+		let vlan_rows = do sorted_keys(self.vlans).map |vlan|
+			{
+				let (bytes, packets) = self.vlans.get(*vlan);
+				~[if *vlan == 0 {~"untagged"} else {fmt!("%?", *vlan)}, fmt!("%?", packets as uint), fmt!("%?", bytes as uint), fmt!("%.1f", to_kbps(bytes))]
+			};
+		let flow_rows = do sorted_keys(self.flows).map |index|
+			{
+				let (in_bytes, out_bytes) = self.flows.get(*index);
+				~[fmt!("%?", *index), fmt!("%.1f", to_kbps(in_bytes)), fmt!("%.1f", to_kbps(out_bytes))]
+			};
+		self.vlans = HashMap();
+		self.flows = HashMap();
+		
+		let mut details = ~[];
+		if port_rows.is_not_empty()
+		{
+			details.push(std::json::String(~"**Port Counters**"));
+			details.push(utils::to_object(~[
+				(~"style", std::json::String(~"html")),
+				(~"header", utils::strs_to_json(~[~"Port", ~"Speed", ~"Status", ~"In (kbps)", ~"", ~"Out (kbps)", ~"", ~"In Errors", ~"Out Errors"])),
+				(~"rows", utils::rows_to_json(port_rows)),
+			]));
+		}
+		if vlan_rows.is_not_empty()
+		{
+			details.push(std::json::String(~"**VLAN Traffic**"));
+			details.push(utils::to_object(~[
+				(~"style", std::json::String(~"plain")),
+				(~"header", utils::strs_to_json(~[~"VLAN", ~"Packets", ~"Bytes", ~"Rate (kbps)"])),
+				(~"rows", utils::rows_to_json(vlan_rows)),
+			]));
+		}
+		if flow_rows.is_not_empty()
+		{
+			details.push(std::json::String(~"**Port Traffic**"));
+			details.push(utils::to_object(~[
+				(~"style", std::json::String(~"plain")),
+				(~"header", utils::strs_to_json(~[~"Port", ~"In (kbps)", ~"Out (kbps)"])),
+				(~"rows", utils::rows_to_json(flow_rows)),
+			]));
+		}
+		if vlan_rows.is_not_empty() || flow_rows.is_not_empty()
+		{
+			details.push(std::json::String(~"*VLAN and port traffic are estimated from the flow samples received during the last poll.*"));
+		}
+		
+		let mut update = ~[
+			(~"modeler", std::json::String(~"sflow " + ip)),
+			(~"samples", std::json::List(samples)),
+		];
+		if details.is_not_empty()
+		{
+			update.push((~"details", std::json::List(~[utils::to_object(~[
+				(~"entity-id", std::json::String(copy target)),
+				(~"label", std::json::String(~"sFlow")),
+				(~"details", std::json::String(std::json::List(details).to_str())),
+				(~"open", std::json::String(~"no")),
+				(~"sort-key", std::json::String(~"gamma")),
+				(~"id", std::json::String(~"sflow")),
+			])])));
+		}
+		utils::to_object(update)
+	}
+}
+
+// How often (in ms) the collector checks to see if it should stop.
+priv const poll_interval: uint = 1000;
+
+// ---- Internal Functions ----------------------------------------------------
+// Datagrams are checked before they are decoded (and the agent address after)
+// so that spoofed agent addresses can't be used to add rates to other devices.
+priv fn add_datagram(ctx: &ModelerContext, agents: HashMap<~str, @Agent>, data: &[u8], ip: &str)
+{
+	if !is_known_source(ctx.options.devices, ctx.options.sflow_proxies, ip)
+	{
+		debug!("ignoring sFlow from unknown device %s", ip);
+		return;
+	}
+	
+	match decode_datagram(data)
+	{
+		result::Ok(ref datagram) if !is_trusted_agent(ctx.options.sflow_proxies, ip, datagram.agent) =>
+		{
+			info!("ignoring sFlow from %s for agent %s", ip, datagram.agent);
+		}
+		result::Ok(ref datagram) =>
+		{
+			match get_agent(ctx, agents, datagram.agent)
+			{
+				option::Some(agent) => agent.add_datagram(datagram),
+				option::None => debug!("ignoring sFlow from unknown device %s", datagram.agent),
+			}
+		}
+		result::Err(ref err) =>
+		{
+			info!("%s from %s", *err, ip);
+		}
+	}
+}
+
+priv fn is_known_source(devices: &[Device], proxies: &[~str], ip: &str) -> bool
+{
+	proxies.contains(&ip.to_owned()) || devices.any(|d| {d.managed_ip == ip.to_owned()})
+}
+
+priv fn is_trusted_agent(proxies: &[~str], ip: &str, agent: &str) -> bool
+{
+	str::eq_slice(agent, ip) || proxies.contains(&ip.to_owned())
+}
+
+priv fn get_agent(ctx: &ModelerContext, agents: HashMap<~str, @Agent>, ip: &str) -> option::Option<@Agent>
+{
+	match agents.find(ip.to_owned())
+	{
+		option::Some(agent) => option::Some(agent),
+		option::None =>
+		{
+			do ctx.options.devices.find(|d| {d.managed_ip == ip.to_owned()}).map |device|
+			{
+				let agent = @Agent {device: copy *device, rates: counters::Counters(), ports: HashMap(), vlans: HashMap(), flows: HashMap()};
+				agents.insert(ip.to_owned(), agent);
+				agent
+			}
+		}
+	}
+}
+
+// Compact samples identify the source with (type << 24) | index and use
+// (format << 30) | value for the input and output ports. Expanded samples use
+// separate words for each.
+priv fn decode_flow_sample(reader: &Reader, expanded: bool, datagram: &mut Datagram)
+{
+	reader.skip(if expanded {12} else {8});		// sequence number and source id
+	let sampling_rate = reader.u32();
+	reader.skip(8);								// sample pool and drops
+	let (input, output) =
+		if expanded
+		{
+			let input = if reader.u32() == 0 {reader.u32()} else {reader.u32(); 0};
+			let output = if reader.u32() == 0 {reader.u32()} else {reader.u32(); 0};
+			(input, output)
+		}
+		else
+		{
+			let input = reader.u32();
+			let output = reader.u32();
+			(if input >> 30 == 0 {input} else {0}, if output >> 30 == 0 {output} else {0})
+		};
+	
+	let mut sample = FlowSample {input: to_port(input), output: to_port(output), vlan: 0, frame_length: 0, sampling_rate: sampling_rate};
+	let mut tagged_vlan = 0;
+	let count = reader.u32();
+	for uint::range(0, count) |_i|
+	{
+		let format = reader.u32();
+		let record = reader.sub(reader.u32());
+		match format
+		{
+			1 =>
+			{
+				// Raw packet header
+				let protocol = record.u32();
+				sample.frame_length = record.u32();
+				record.skip(4);							// stripped
+				let header = record.sub(record.u32());
+				if protocol == 1 && header.end >= 16 && header.data[12] == 0x81 && header.data[13] == 0x00
+				{
+					tagged_vlan = ((header.data[14] as uint) << 8 | header.data[15] as uint) & 0x0FFF;
+				}
+			}
+			1001 =>
+			{
+				// Extended switch
+				sample.vlan = record.u32();
+			}
+			_ => {}
+		}
+		if record.error.is_not_empty()
+		{
+			reader.fail(copy record.error);
+			break;
+		}
+	}
+	if sample.vlan == 0
+	{
+		sample.vlan = tagged_vlan;
+	}
+	
+	if reader.error.is_empty() && sampling_rate > 0
+	{
+		datagram.flows.push(sample);
+	}
+}
+
+priv fn decode_counter_sample(reader: &Reader, expanded: bool, datagram: &mut Datagram)
+{
+	reader.skip(if expanded {12} else {8});		// sequence number and source id
+	let count = reader.u32();
+	for uint::range(0, count) |_i|
+	{
+		let format = reader.u32();
+		let record = reader.sub(reader.u32());
+		if format == 1
+		{
+			// Generic interface counters
+			let index = record.u32();
+			record.skip(4);							// ifType
+			let speed = record.u64();
+			record.skip(4);							// ifDirection
+			let status = record.u32();
+			let in_octets = record.u64();
+			record.skip(16);						// unicast, multicast, broadcast packets and discards
+			let in_errors = record.u32() as float;
+			record.skip(4);							// unknown protos
+			let out_octets = record.u64();
+			record.skip(16);
+			let out_errors = record.u32() as float;
+			if record.error.is_empty()
+			{
+				datagram.counters.push(CounterSample {index: index, speed: speed, status: status, in_octets: in_octets, out_octets: out_octets, in_errors: in_errors, out_errors: out_errors});
+			}
+		}
+		if record.error.is_not_empty()
+		{
+			reader.fail(copy record.error);
+			break;
+		}
+	}
+}
+
+// 0x3FFFFFFF is the internal (i.e. the switch itself) port.
+priv pure fn to_port(value: uint) -> uint
+{
+	if value == 0x3FFFFFFF {0} else {value}
+}
+
+priv fn sorted_keys<V: Copy>(map: HashMap<uint, V>) -> ~[uint]
+{
+	let mut keys = ~[];
+	for map.each_key |key|
+	{
+		keys.push(key);
+	}
+	std::sort::merge_sort(keys, |a, b| {*a <= *b})
+}
+
+priv fn speed_to_str(bps: float) -> ~str
+{
+	if bps >= 1.0e9
+	{
+		fmt!("%.0f Gbps", bps/1.0e9)
+	}
+	else if bps > 0.0
+	{
+		fmt!("%.0f Mbps", bps/1.0e6)
+	}
+	else
+	{
+		~""
+	}
+}
+
+priv fn status_to_str(status: uint) -> ~str
+{
+	match status & 3
+	{
+		3 => ~"up",
+		1 => ~"<strong>down</strong>",
+		_ => ~"admin down",
+	}
+}
+
+#[test]
+fn test_counter_sample()
+{
+	let mut data = ~[];
+	utils::push_u32(&mut data, 5);				// version
+	utils::push_u32(&mut data, 1);				// agent address type
+	vec::push_all(&mut data, ~[10, 0, 0, 7]);
+	utils::push_u32(&mut data, 0);				// sub-agent id
+	utils::push_u32(&mut data, 1);				// sequence
+	utils::push_u32(&mut data, 1000);			// uptime
+	utils::push_u32(&mut data, 1);				// samples
+	
+	utils::push_u32(&mut data, 2);				// counter sample
+	utils::push_u32(&mut data, 12 + 8 + 88);
+	utils::push_u32(&mut data, 1);				// sequence
+	utils::push_u32(&mut data, 3);				// source id
+	utils::push_u32(&mut data, 1);				// records
+	utils::push_u32(&mut data, 1);				// generic interface counters
+	utils::push_u32(&mut data, 88);
+	utils::push_u32(&mut data, 3);				// ifIndex
+	utils::push_u32(&mut data, 6);				// ifType
+	utils::push_u64(&mut data, 1000000000);	// ifSpeed
+	utils::push_u32(&mut data, 1);				// ifDirection
+	utils::push_u32(&mut data, 3);				// ifStatus
+	utils::push_u64(&mut data, 0x100000000);	// ifInOctets
+	for 4.times {utils::push_u32(&mut data, 0)};
+	utils::push_u32(&mut data, 2);				// ifInErrors
+	utils::push_u32(&mut data, 0);
+	utils::push_u64(&mut data, 5000);			// ifOutOctets
+	for 4.times {utils::push_u32(&mut data, 0)};
+	utils::push_u32(&mut data, 1);				// ifOutErrors
+	utils::push_u32(&mut data, 0);				// ifPromiscuousMode
+	
+	let datagram = decode_datagram(data).get();
+	assert datagram.agent == ~"10.0.0.7";
+	assert datagram.flows.is_empty();
+	assert datagram.counters.len() == 1;
+	assert datagram.counters[0].index == 3;
+	assert datagram.counters[0].speed == 1.0e9;
+	assert datagram.counters[0].status == 3;
+	assert datagram.counters[0].in_octets == 4294967296.0;
+	assert datagram.counters[0].out_octets == 5000.0;
+	assert datagram.counters[0].in_errors == 2.0;
+	assert datagram.counters[0].out_errors == 1.0;
+	
+	assert decode_datagram(vec::slice(data, 0, data.len() - 4)).is_err();
+}
+
+#[test]
+fn test_flow_sample()
+{
+	let mut data = ~[];
+	utils::push_u32(&mut data, 5);				// version
+	utils::push_u32(&mut data, 1);				// agent address type
+	vec::push_all(&mut data, ~[10, 0, 0, 7]);
+	utils::push_u32(&mut data, 0);				// sub-agent id
+	utils::push_u32(&mut data, 1);				// sequence
+	utils::push_u32(&mut data, 1000);			// uptime
+	utils::push_u32(&mut data, 2);				// samples
+	
+	// Flow sample with a tagged raw header.
+	utils::push_u32(&mut data, 1);
+	utils::push_u32(&mut data, 32 + 8 + 16 + 20);
+	utils::push_u32(&mut data, 1);				// sequence
+	utils::push_u32(&mut data, 5);				// source id
+	utils::push_u32(&mut data, 100);			// sampling rate
+	utils::push_u32(&mut data, 1000);			// sample pool
+	utils::push_u32(&mut data, 0);				// drops
+	utils::push_u32(&mut data, 5);				// input
+	utils::push_u32(&mut data, 0x80000002);	// output (multiple ports)
+	utils::push_u32(&mut data, 1);				// records
+	utils::push_u32(&mut data, 1);				// raw packet header
+	utils::push_u32(&mut data, 16 + 20);
+	utils::push_u32(&mut data, 1);				// ethernet
+	utils::push_u32(&mut data, 1500);			// frame length
+	utils::push_u32(&mut data, 4);				// stripped
+	utils::push_u32(&mut data, 18);			// header length
+	vec::push_all(&mut data, ~[0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 0x81, 0x00, 0x20, 0x0A, 0x08, 0x00, 0, 0]);
+	
+	// Expanded flow sample with an extended switch record.
+	utils::push_u32(&mut data, 3);
+	utils::push_u32(&mut data, 44 + 8 + 16);
+	utils::push_u32(&mut data, 2);				// sequence
+	utils::push_u32(&mut data, 0);				// source id type
+	utils::push_u32(&mut data, 5);				// source id index
+	utils::push_u32(&mut data, 10);			// sampling rate
+	utils::push_u32(&mut data, 1000);			// sample pool
+	utils::push_u32(&mut data, 0);				// drops
+	utils::push_u32(&mut data, 0);				// input format
+	utils::push_u32(&mut data, 5);				// input
+	utils::push_u32(&mut data, 0);				// output format
+	utils::push_u32(&mut data, 7);				// output
+	utils::push_u32(&mut data, 1);				// records
+	utils::push_u32(&mut data, 1001);			// extended switch
+	utils::push_u32(&mut data, 16);
+	utils::push_u32(&mut data, 20);			// src vlan
+	utils::push_u32(&mut data, 0);
+	utils::push_u32(&mut data, 20);			// dst vlan
+	utils::push_u32(&mut data, 0);
+	
+	let datagram = decode_datagram(data).get();
+	assert datagram.counters.is_empty();
+	assert datagram.flows.len() == 2;
+	assert datagram.flows[0].input == 5;
+	assert datagram.flows[0].output == 0;
+	assert datagram.flows[0].vlan == 10;
+	assert datagram.flows[0].frame_length == 1500;
+	assert datagram.flows[0].sampling_rate == 100;
+	assert datagram.flows[1].input == 5;
+	assert datagram.flows[1].output == 7;
+	assert datagram.flows[1].vlan == 20;
+	assert datagram.flows[1].sampling_rate == 10;
+}
+
+#[test]
+fn test_sources()
+{
+	let devices = ~[Device {name: ~"switch", managed_ip: ~"10.0.0.7", modeler: ~"snmp", modeler_type: options::Builtin, snmp_security: snmp::Community(~"public"), snmp_port: 161}];
+	let proxies = ~[~"10.0.0.100"];
+	assert is_known_source(devices, proxies, "10.0.0.7");
+	assert is_known_source(devices, proxies, "10.0.0.100");
+	assert !is_known_source(devices, proxies, "10.0.0.8");
+	
+	// only proxies can send datagrams for other agents
+	assert is_trusted_agent(proxies, "10.0.0.7", "10.0.0.7");
+	assert !is_trusted_agent(proxies, "10.0.0.7", "10.0.0.9");
+	assert is_trusted_agent(proxies, "10.0.0.100", "10.0.0.7");
+}
@@ -0,0 +1,1378 @@
+/// Minimal SNMPv2c and SNMPv3 support used by builtin modelers: BER encoding
+/// and decoding of messages, a UDP socket, and get and walk requests.
+///
+/// SNMPv3 uses the user-based security model with noAuthNoPriv or authNoPriv
+/// (see usm.rs). Privacy (encryption) is not supported.
+
+/// Decoded variable values.
+pub enum Value
+{
+	Integer(i64),
+	OctetString(~[u8]),
+	Null,
+	ObjectId(~[uint]),
+	IpAddress(~[u8]),
+	Counter32(u64),
+	Gauge32(u64),
+	TimeTicks(u64),							// hundredths of a second
+	Opaque(~[u8]),
+	Counter64(u64),
+	NoSuchObject,
+	NoSuchInstance,
+	EndOfMibView,
+}
+
+pub struct VarBind
+{
+	pub oid: ~[uint],
+	pub value: Value,
+}
+
+pub struct Pdu
+{
+	pub kind: u8,							// e.g. get_bulk_request
+	pub request_id: i64,
+	pub error_status: i64,					// non-repeaters for GetBulk
+	pub error_index: i64,					// max-repetitions for GetBulk
+	pub bindings: ~[VarBind],
+}
+
+pub struct Message
+{
+	pub version: i64,						// 0 for v1 and 1 for v2c
+	pub community: ~str,
+	pub pdu: Pdu,
+}
+
+/// SNMPv3 message using the user-based security model (RFCs 3412 and 3414).
+pub struct V3Message
+{
+	pub msg_id: i64,
+	pub flags: u8,							// auth_flag and/or reportable_flag
+	pub engine_id: ~[u8],					// of the authoritative engine (the agent for requests)
+	pub engine_boots: i64,
+	pub engine_time: i64,					// seconds since the engine last booted
+	pub user: ~str,
+	pub context_engine_id: ~[u8],
+	pub context_name: ~str,
+	pub pdu: Pdu,
+}
+
+/// How requests are authenticated.
+pub enum Security
+{
+	Community(~str),						// SNMPv2c
+	Usm(UsmUser),							// SNMPv3
+}
+
+pub enum AuthProtocol
+{
+	NoAuth,									// noAuthNoPriv
+	AuthMd5,								// authNoPriv using HMAC-MD5-96
+	AuthSha,								// authNoPriv using HMAC-SHA-96
+}
+
+pub struct UsmUser
+{
+	pub name: ~str,
+	pub auth: AuthProtocol,
+	pub password: ~str,						// empty for NoAuth
+}
+
+pub const version_1: i64 = 0;
+pub const version_2c: i64 = 1;
+pub const version_3: i64 = 3;
+
+pub const auth_flag: u8 = 0x01;
+pub const priv_flag: u8 = 0x02;
+pub const reportable_flag: u8 = 0x04;
+
+pub const get_request: u8 = 0xA0;
+pub const get_next_request: u8 = 0xA1;
+pub const response: u8 = 0xA2;
+pub const trap_v1: u8 = 0xA4;
+pub const get_bulk_request: u8 = 0xA5;
+pub const inform_request: u8 = 0xA6;
+pub const trap_v2: u8 = 0xA7;
+pub const report: u8 = 0xA8;
+
+/// Converts dotted text like "1.3.6.1.2.1.1.3.0" into an oid.
+pub pure fn parse_oid(text: &str) -> ~[uint]
+{
+	do str::split_char(text, '.').filter_map |part| {uint::from_str(*part)}
+}
+
+pub pure fn oid_to_str(oid: &[uint]) -> ~str
+{
+	str::connect(oid.map(|n| {n.to_str()}), ".")
+}
+
+/// Returns true if oid is within the subtree rooted at root.
+pub pure fn oid_starts_with(oid: &[uint], root: &[uint]) -> bool
+{
+	oid.len() >= root.len() && vec::all2(vec::view(oid, 0, root.len()), root, |a, b| {*a == *b})
+}
+
+pub pure fn oid_less(lhs: &[uint], rhs: &[uint]) -> bool
+{
+	for uint::range(0, uint::min(lhs.len(), rhs.len())) |i|
+	{
+		if lhs[i] != rhs[i]
+		{
+			return lhs[i] < rhs[i];
+		}
+	}
+	lhs.len() < rhs.len()
+}
+
+pub impl Value
+{
+	/// Returns NaN for values which are not numbers.
+	pure fn to_float() -> float
+	{
+		match self
+		{
+			Integer(value) => value as float,
+			Counter32(value) | Gauge32(value) | TimeTicks(value) | Counter64(value) => value as float,
+			_ => float::NaN,
+		}
+	}
+}
+
+pub impl Value : ToStr
+{
+	pure fn to_str() -> ~str
+	{
+		match self
+		{
+			Integer(value) => i64::str(value),
+			OctetString(ref bytes) | Opaque(ref bytes) => utils::bytes_to_str(*bytes),
+			Null => ~"null",
+			ObjectId(ref oid) => oid_to_str(*oid),
+			IpAddress(ref bytes) => str::connect(bytes.map(|b| {b.to_str()}), "."),
+			Counter32(value) | Gauge32(value) | TimeTicks(value) | Counter64(value) => u64::str(value),
+			NoSuchObject => ~"noSuchObject",
+			NoSuchInstance => ~"noSuchInstance",
+			EndOfMibView => ~"endOfMibView",
+		}
+	}
+}
+
+pub fn encode_message(message: &Message) -> ~[u8]
+{
+	let body = encode_signed(integer, message.version) + encode_tlv(octet_string, str::to_bytes(message.community)) + encode_pdu(&message.pdu);
+	encode_tlv(sequence, body)
+}
+
+/// Note that v1 traps are converted into the v2 form (see RFC 3584): the
+/// bindings start with sysUpTime.0, snmpTrapOID.0, snmpTrapAddress.0, and
+/// snmpTrapEnterprise.0.
+pub fn decode_message(data: &[u8]) -> result::Result<Message, ~str>
+{
+	let decoder = Decoder {data: vec::from_slice(data), pos: 0, err: ~""};
+	decoder.expect(sequence, "message");
+	let version = decoder.integer();
+	let community = utils::bytes_to_str(decoder.octet_string());
+	let pdu = decoder.pdu();
+	
+	if decoder.ok()
+	{
+		result::Ok(Message {version: version, community: community, pdu: pdu})
+	}
+	else
+	{
+		result::Err(copy decoder.err)
+	}
+}
+
+/// If the auth_flag is set then the message is authenticated using key (see
+/// usm::get_localized_key).
+pub fn encode_v3_message(message: &V3Message, auth: AuthProtocol, key: &[u8]) -> ~[u8]
+{
+	let authenticated = message.flags & auth_flag != 0;
+	let user = encode_tlv(octet_string, message.engine_id) + encode_signed(integer, message.engine_boots) + encode_signed(integer, message.engine_time) + encode_tlv(octet_string, str::to_bytes(message.user));
+	let mac = if authenticated {vec::from_elem(usm::mac_len, 0u8)} else {~[]};
+	let security = encode_tlv(sequence, user + encode_tlv(octet_string, mac) + encode_tlv(octet_string, ~[]));
+	
+	let global = encode_tlv(sequence, encode_signed(integer, message.msg_id) + encode_signed(integer, max_datagram as i64) + encode_tlv(octet_string, ~[message.flags]) + encode_signed(integer, usm_security_model));
+	let scoped = encode_tlv(sequence, encode_tlv(octet_string, message.context_engine_id) + encode_tlv(octet_string, str::to_bytes(message.context_name)) + encode_pdu(&message.pdu));
+	let prefix = encode_signed(integer, version_3) + global + encode_header(octet_string, security.len());
+	let body = prefix + security + scoped;
+	let mut data = encode_tlv(sequence, body);
+	
+	if authenticated
+	{
+		// The MAC is computed with the authentication parameters zeroed and then
+		// written into them.
+		let security_header = security.len() - (user.len() + 2 + usm::mac_len + 2);
+		let offset = (data.len() - body.len()) + prefix.len() + security_header + user.len() + 2;
+		let mac = usm::get_mac(auth, key, data);
+		for uint::range(0, usm::mac_len) |i|
+		{
+			data[offset + i] = mac[i];
+		}
+	}
+	data
+}
+
+/// Returns the message and the offset of its authentication parameters (see
+/// verify_mac). Encrypted messages are not supported.
+pub fn decode_v3_message(data: &[u8]) -> result::Result<(V3Message, uint), ~str>
+{
+	let decoder = Decoder {data: vec::from_slice(data), pos: 0, err: ~""};
+	decoder.expect(sequence, "message");
+	let version = decoder.integer();
+	if decoder.ok() && version != version_3
+	{
+		return result::Err(fmt!("expected SNMPv3 but found version %?", version));
+	}
+	
+	decoder.expect(sequence, "global data");
+	let msg_id = decoder.integer();
+	decoder.integer();						// max size
+	let flags = decoder.octet_string();
+	let model = decoder.integer();
+	if decoder.ok() && (flags.len() != 1 || model != usm_security_model)
+	{
+		return result::Err(~"message does not use the user-based security model");
+	}
+	if decoder.ok() && flags[0] & priv_flag != 0
+	{
+		return result::Err(~"encrypted messages are not supported");
+	}
+	
+	decoder.expect(octet_string, "security parameters");
+	decoder.expect(sequence, "usm parameters");
+	let engine_id = decoder.octet_string();
+	let engine_boots = decoder.integer();
+	let engine_time = decoder.integer();
+	let user = utils::bytes_to_str(decoder.octet_string());
+	let end = decoder.expect(octet_string, "authentication parameters");
+	let offset = decoder.pos;
+	let mac = decoder.contents(end);
+	decoder.octet_string();					// privacy parameters
+	if decoder.ok() && flags[0] & auth_flag != 0 && mac.len() != usm::mac_len
+	{
+		return result::Err(fmt!("expected %? bytes of authentication parameters but found %?", usm::mac_len, mac.len()));
+	}
+	
+	decoder.expect(sequence, "scoped pdu");
+	let context_engine_id = decoder.octet_string();
+	let context_name = utils::bytes_to_str(decoder.octet_string());
+	let pdu = decoder.pdu();
+	
+	if decoder.ok()
+	{
+		result::Ok((V3Message {msg_id: msg_id, flags: flags[0], engine_id: engine_id, engine_boots: engine_boots, engine_time: engine_time, user: user, context_engine_id: context_engine_id, context_name: context_name, pdu: pdu}, offset))
+	}
+	else
+	{
+		result::Err(copy decoder.err)
+	}
+}
+
+/// Returns true if the MAC at offset within the encoded message data matches
+/// the MAC computed using key.
+pub fn verify_mac(data: &[u8], offset: uint, auth: AuthProtocol, key: &[u8]) -> bool
+{
+	if offset + usm::mac_len > data.len()
+	{
+		return false;
+	}
+	
+	let mut zeroed = vec::from_slice(data);
+	for uint::range(offset, offset + usm::mac_len) |i|
+	{
+		zeroed[i] = 0;
+	}
+	usm::get_mac(auth, key, zeroed) == vec::slice(data, offset, offset + usm::mac_len)
+}
+
+/// Blocking IPv4 UDP socket. Note that this should be used from a task running
+/// in its own thread (e.g. a task_runner job).
+pub struct UdpSocket
+{
+	priv fd: libc::c_int,
+}
+
+/// Binds to port on all interfaces (zero uses an ephemeral port).
+pub fn UdpSocket(port: u16) -> result::Result<UdpSocket, ~str>
+{
+	unsafe
+	{
+		let fd = udp::socket(af_inet, sock_dgram, 0);
+		if fd < 0
+		{
+			return result::Err(~"failed to create a UDP socket");
+		}
+		
+		let addr = make_addr(&[0, 0, 0, 0], port);
+		if udp::bind(fd, ptr::addr_of(&addr), sys::size_of::<SockAddrIn>() as libc::c_uint) < 0
+		{
+			libc::close(fd);
+			return result::Err(fmt!("failed to bind to UDP port %?", port));
+		}
+		result::Ok(UdpSocket {fd: fd})
+	}
+}
+
+pub impl UdpSocket
+{
+	fn send_to(ip: &str, port: u16, data: &[u8]) -> option::Option<~str>
+	{
+		match parse_ip(ip)
+		{
+			option::Some(ref bytes) =>
+			{
+				let addr = make_addr(*bytes, port);
+				let sent = do vec::as_imm_buf(data) |buffer, len|
+				{
+					unsafe {udp::sendto(self.fd, buffer, len as libc::size_t, 0, ptr::addr_of(&addr), sys::size_of::<SockAddrIn>() as libc::c_uint)}
+				};
+				if sent as uint == data.len() {option::None} else {option::Some(fmt!("failed to send to %s:%?", ip, port))}
+			}
+			option::None =>
+			{
+				option::Some(fmt!("'%s' is not an IPv4 address", ip))
+			}
+		}
+	}
+	
+	/// Returns the datagram along with the address and port it came from or
+	/// None if nothing arrived within timeout milliseconds.
+	fn recv_from(timeout: uint) -> option::Option<(~[u8], ~str, u16)>
+	{
+		let mut fds = PollFd {fd: self.fd, events: pollin, revents: 0};
+		if unsafe {udp::poll(ptr::to_mut_unsafe_ptr(&mut fds), 1, timeout as libc::c_int)} <= 0
+		{
+			return option::None;
+		}
+		
+		let buffer = vec::from_elem(max_datagram, 0u8);
+		let mut addr = make_addr(&[0, 0, 0, 0], 0);
+		let mut addr_len = sys::size_of::<SockAddrIn>() as libc::c_uint;
+		let count = do vec::as_imm_buf(buffer) |p, len|
+		{
+			unsafe {udp::recvfrom(self.fd, p as *mut u8, len as libc::size_t, 0, ptr::to_mut_unsafe_ptr(&mut addr), ptr::to_mut_unsafe_ptr(&mut addr_len))}
+		};
+		if count < 0
+		{
+			return option::None;
+		}
+		
+		let ip = fmt!("%?.%?.%?.%?", addr.sin_addr[0], addr.sin_addr[1], addr.sin_addr[2], addr.sin_addr[3]);
+		let port = (addr.sin_port[0] as u16 << 8) | addr.sin_port[1] as u16;
+		option::Some((vec::slice(buffer, 0, count as uint), ip, port))
+	}
+	
+	fn close()
+	{
+		unsafe {libc::close(self.fd);}
+	}
+}
+
+/// Used to make requests of an agent.
+pub struct Session
+{
+	priv socket: UdpSocket,
+	priv ip: ~str,
+	priv port: u16,
+	priv security: Security,
+	priv timeout: uint,						// ms
+	priv retries: uint,
+	priv mut request_id: i64,				// also used for SNMPv3 message ids
+	priv mut engine: option::Option<Engine>,	// set once the SNMPv3 agent's engine has been discovered
+}
+
+pub fn Session(ip: &str, port: u16, security: &Security, timeout: uint, retries: uint) -> result::Result<Session, ~str>
+{
+	do UdpSocket(0).map |socket|
+	{
+		Session {socket: *socket, ip: ip.to_owned(), port: port, security: copy *security, timeout: timeout, retries: retries, request_id: 0, engine: option::None}
+	}
+}
+
+pub impl Session
+{
+	/// Returns the values for the oids (missing objects have NoSuchObject or
+	/// NoSuchInstance values).
+	fn get(oids: &[~[uint]]) -> result::Result<~[VarBind], ~str>
+	{
+		do self.request(get_request, oids, 0, 0).map |pdu| {copy pdu.bindings}
+	}
+	
+	/// Returns all the variables within the subtree rooted at root.
+	fn walk(root: &[uint]) -> result::Result<~[VarBind], ~str>
+	{
+		let mut bindings = ~[];
+		let mut next = vec::from_slice(root);
+		let mut done = false;
+		while !done
+		{
+			match self.request(get_bulk_request, ~[copy next], 0, max_repetitions)
+			{
+				result::Ok(ref pdu) =>
+				{
+					done = pdu.bindings.is_empty();
+					for pdu.bindings.each |binding|
+					{
+						if done {break}
+						done = match binding.value
+						{
+							EndOfMibView | NoSuchObject | NoSuchInstance => true,
+							_ => !oid_starts_with(binding.oid, root) || !oid_less(next, binding.oid),		// the latter guards against broken agents
+						};
+						if !done
+						{
+							next = copy binding.oid;
+							bindings.push(copy *binding);
+						}
+					}
+				}
+				result::Err(ref err) =>
+				{
+					return result::Err(copy *err);
+				}
+			}
+		}
+		result::Ok(bindings)
+	}
+	
+	fn close()
+	{
+		self.socket.close();
+	}
+}
+
+priv impl Session
+{
+	fn request(kind: u8, oids: &[~[uint]], non_repeaters: i64, repetitions: i64) -> result::Result<Pdu, ~str>
+	{
+		self.request_id += 1;
+		let bindings = oids.map(|oid| {VarBind {oid: copy *oid, value: Null}});
+		let pdu = Pdu {kind: kind, request_id: self.request_id, error_status: non_repeaters, error_index: repetitions, bindings: bindings};
+		let reply = match self.security
+		{
+			Community(ref community) => self.request_v2c(*community, pdu),
+			Usm(ref user) => self.request_v3(user, pdu),
+		};
+		
+		match reply
+		{
+			result::Ok(ref pdu) if pdu.error_status != 0 => return result::Err(fmt!("%s returned error status %? for request %?", self.ip, pdu.error_status, pdu.error_index)),
+			_ => {}
+		}
+		reply
+	}
+	
+	fn request_v2c(community: &str, pdu: Pdu) -> result::Result<Pdu, ~str>
+	{
+		let request_id = pdu.request_id;
+		let data = encode_message(&Message {version: version_2c, community: community.to_owned(), pdu: pdu});
+		do self.exchange(data) |reply|
+		{
+			match decode_message(reply)
+			{
+				result::Ok(ref reply) if reply.pdu.request_id == request_id => option::Some(result::Ok(copy reply.pdu)),
+				result::Ok(_) => option::None,
+				result::Err(ref err) => option::Some(result::Err(copy *err)),
+			}
+		}
+	}
+	
+	fn request_v3(user: &UsmUser, pdu: Pdu) -> result::Result<Pdu, ~str>
+	{
+		// Discover the agent's engine id, boots, and time (see RFC 3414 section 4).
+		if self.engine.is_none()
+		{
+			let probe = V3Message {msg_id: self.request_id, flags: reportable_flag, engine_id: ~[], engine_boots: 0, engine_time: 0, user: ~"", context_engine_id: ~[], context_name: ~"", pdu: Pdu {kind: get_request, request_id: self.request_id, error_status: 0, error_index: 0, bindings: ~[]}};
+			match self.exchange_v3(&probe, NoAuth, ~[])
+			{
+				result::Ok(ref reply) if reply.engine_id.is_not_empty() => self.update_engine(user, reply),
+				result::Ok(_) => return result::Err(fmt!("%s did not report its engine id", self.ip)),
+				result::Err(ref err) => return result::Err(copy *err),
+			}
+		}
+		
+		// If our idea of the agent's time is off then the agent replies with a
+		// notInTimeWindow report containing its time so we try again.
+		let flags = match user.auth {NoAuth => reportable_flag, _ => auth_flag | reportable_flag};
+		let mut attempts = 0;
+		loop
+		{
+			self.request_id += 1;
+			attempts += 1;
+			
+			let engine = self.engine.get();
+			let time = engine.time + (utils::imprecise_time_s() - engine.synced) as i64;
+			let message = V3Message {msg_id: self.request_id, flags: flags, engine_id: copy engine.id, engine_boots: engine.boots, engine_time: time, user: copy user.name, context_engine_id: copy engine.id, context_name: ~"", pdu: copy pdu};
+			match self.exchange_v3(&message, user.auth, engine.key)
+			{
+				result::Ok(ref reply) if reply.pdu.kind == report =>
+				{
+					let err = get_report_error(&reply.pdu);
+					if attempts > 1 || err != ~"not in time window"
+					{
+						return result::Err(fmt!("%s rejected the request: %s", self.ip, err));
+					}
+					self.update_engine(user, reply);
+				}
+				result::Ok(ref reply) =>
+				{
+					return result::Ok(copy reply.pdu);
+				}
+				result::Err(ref err) =>
+				{
+					return result::Err(copy *err);
+				}
+			}
+		}
+	}
+	
+	// Returns the reply to message. Replies must be authenticated if message was.
+	fn exchange_v3(message: &V3Message, auth: AuthProtocol, key: &[u8]) -> result::Result<V3Message, ~str>
+	{
+		let msg_id = message.msg_id;
+		let authenticated = message.flags & auth_flag != 0;
+		let data = encode_v3_message(message, auth, key);
+		do self.exchange(data) |reply|
+		{
+			match decode_v3_message(reply)
+			{
+				result::Ok((ref message, _)) if message.msg_id != msg_id =>
+				{
+					option::None
+				}
+				result::Ok((ref message, offset)) =>
+				{
+					if message.flags & auth_flag != 0 && (!authenticated || !verify_mac(reply, offset, auth, key))
+					{
+						option::Some(result::Err(~"authentication failed"))
+					}
+					else if authenticated && message.flags & auth_flag == 0 && message.pdu.kind != report
+					{
+						option::Some(result::Err(~"reply was not authenticated"))
+					}
+					else
+					{
+						option::Some(result::Ok(copy *message))
+					}
+				}
+				result::Err(ref err) =>
+				{
+					option::Some(result::Err(copy *err))
+				}
+			}
+		}
+	}
+	
+	// Records the engine's boots and time (and localizes the user's key if the
+	// engine is new).
+	fn update_engine(user: &UsmUser, message: &V3Message)
+	{
+		let key = match self.engine
+		{
+			option::Some(ref engine) if engine.id == message.engine_id => copy engine.key,
+			_ => usm::get_localized_key(user.auth, user.password, message.engine_id),
+		};
+		self.engine = option::Some(Engine {id: copy message.engine_id, boots: message.engine_boots, time: message.engine_time, synced: utils::imprecise_time_s(), key: key});
+	}
+	
+	// Sends data until handle returns something for a reply (or we run out of
+	// retries). Handle returns None for replies to earlier requests that timed out.
+	fn exchange<T: Copy>(data: &[u8], handle: fn (&[u8]) -> option::Option<result::Result<T, ~str>>) -> result::Result<T, ~str>
+	{
+		for (self.retries + 1).times
+		{
+			match self.socket.send_to(self.ip, self.port, data)
+			{
+				option::Some(ref err) => return result::Err(copy *err),
+				option::None => {}
+			}
+			
+			loop
+			{
+				match self.socket.recv_from(self.timeout)
+				{
+					option::Some((ref reply, _, _)) =>
+					{
+						match handle(*reply)
+						{
+							option::Some(result::Ok(move value)) => return result::Ok(value),
+							option::Some(result::Err(ref err)) => return result::Err(fmt!("bad reply from %s: %s", self.ip, *err)),
+							option::None => {}
+						}
+					}
+					option::None =>
+					{
+						break;
+					}
+				}
+			}
+		}
+		result::Err(fmt!("%s did not respond", self.ip))
+	}
+}
+
+// ---- Internal Types --------------------------------------------------------
+// What we know about an SNMPv3 agent.
+priv struct Engine
+{
+	id: ~[u8],
+	boots: i64,
+	time: i64,
+	synced: float,							// local time at which time was received
+	key: ~[u8],								// the user's localized key (empty for NoAuth)
+}
+
+priv const usm_security_model: i64 = 3;
+
+priv const integer: u8 = 0x02;
+priv const octet_string: u8 = 0x04;
+priv const null: u8 = 0x05;
+priv const object_id: u8 = 0x06;
+priv const sequence: u8 = 0x30;
+priv const ip_address: u8 = 0x40;
+priv const counter32: u8 = 0x41;
+priv const gauge32: u8 = 0x42;
+priv const time_ticks: u8 = 0x43;
+priv const opaque: u8 = 0x44;
+priv const counter64: u8 = 0x46;
+priv const no_such_object: u8 = 0x80;
+priv const no_such_instance: u8 = 0x81;
+priv const end_of_mib_view: u8 = 0x82;
+
+priv const max_repetitions: i64 = 20;
+priv const max_datagram: uint = 65536;
+
+priv const af_inet: libc::c_int = 2;
+priv const sock_dgram: libc::c_int = 2;
+priv const pollin: i16 = 1;
+
+// Port and address are in network byte order.
+priv struct SockAddrIn
+{
+	sin_family: u16,
+	sin_port: [u8 * 2],
+	sin_addr: [u8 * 4],
+	sin_zero: [u8 * 8],
+}
+
+priv struct PollFd
+{
+	fd: libc::c_int,
+	events: i16,
+	revents: i16,
+}
+
+#[nolink]
+extern mod udp
+{
+	fn socket(domain: libc::c_int, kind: libc::c_int, protocol: libc::c_int) -> libc::c_int;
+	fn bind(fd: libc::c_int, addr: *SockAddrIn, addr_len: libc::c_uint) -> libc::c_int;
+	fn sendto(fd: libc::c_int, buffer: *u8, len: libc::size_t, flags: libc::c_int, addr: *SockAddrIn, addr_len: libc::c_uint) -> libc::ssize_t;
+	fn recvfrom(fd: libc::c_int, buffer: *mut u8, len: libc::size_t, flags: libc::c_int, addr: *mut SockAddrIn, addr_len: *mut libc::c_uint) -> libc::ssize_t;
+	fn poll(fds: *mut PollFd, count: libc::c_ulong, timeout: libc::c_int) -> libc::c_int;
+}
+
+// Errors are sticky: once an error happens the remaining reads return defaults.
+priv struct Decoder
+{
+	data: ~[u8],
+	mut pos: uint,
+	mut err: ~str,
+}
+
+priv impl Decoder
+{
+	fn ok() -> bool
+	{
+		self.err.is_empty()
+	}
+	
+	fn fail_with(mesg: ~str)
+	{
+		if self.ok()
+		{
+			self.err = mesg;
+		}
+	}
+	
+	// Returns the tag and the end of the contents.
+	fn header() -> (u8, uint)
+	{
+		if !self.ok() || self.pos + 2 > self.data.len()
+		{
+			self.fail_with(~"message was truncated");
+			return (0, self.pos);
+		}
+		
+		let tag = self.data[self.pos];
+		let mut len = self.data[self.pos + 1] as uint;
+		self.pos += 2;
+		if len & 0x80 != 0
+		{
+			let count = len & 0x7F;
+			if count == 0 || count > 4 || self.pos + count > self.data.len()
+			{
+				self.fail_with(fmt!("bad length at offset %?", self.pos - 1));
+				return (tag, self.pos);
+			}
+			
+			len = 0;
+			for count.times
+			{
+				len = (len << 8) | self.data[self.pos] as uint;
+				self.pos += 1;
+			}
+		}
+		
+		if self.pos + len > self.data.len()
+		{
+			self.fail_with(~"message was truncated");
+			return (tag, self.pos);
+		}
+		(tag, self.pos + len)
+	}
+	
+	fn expect(tag: u8, what: &str) -> uint
+	{
+		let (actual, end) = self.header();
+		if self.ok() && actual != tag
+		{
+			self.fail_with(fmt!("expected %s (0x%X) but found 0x%X", what, tag as uint, actual as uint));
+		}
+		end
+	}
+	
+	fn contents(end: uint) -> ~[u8]
+	{
+		if self.ok()
+		{
+			let bytes = vec::slice(self.data, self.pos, end);
+			self.pos = end;
+			bytes
+		}
+		else
+		{
+			~[]
+		}
+	}
+	
+	fn integer() -> i64
+	{
+		let end = self.expect(integer, "integer");
+		decode_signed(self.contents(end))
+	}
+	
+	fn octet_string() -> ~[u8]
+	{
+		let end = self.expect(octet_string, "octet string");
+		self.contents(end)
+	}
+	
+	fn oid() -> ~[uint]
+	{
+		let end = self.expect(object_id, "object id");
+		decode_oid(self.contents(end))
+	}
+	
+	fn pdu() -> Pdu
+	{
+		let (kind, _) = self.header();
+		let mut bindings = ~[];
+		let (request_id, error_status, error_index) =
+			if kind == trap_v1
+			{
+				let enterprise = self.oid();
+				let agent = self.value();
+				let generic = self.integer();
+				let specific = self.integer();
+				let timestamp = self.value();
+				
+				let trap = if generic == 6 {copy enterprise + ~[0, specific as uint]} else {parse_oid("1.3.6.1.6.3.1.1.5") + ~[generic as uint + 1]};
+				bindings.push(VarBind {oid: parse_oid("1.3.6.1.2.1.1.3.0"), value: timestamp});
+				bindings.push(VarBind {oid: parse_oid("1.3.6.1.6.3.1.1.4.1.0"), value: ObjectId(trap)});
+				bindings.push(VarBind {oid: parse_oid("1.3.6.1.6.3.18.1.3.0"), value: agent});
+				bindings.push(VarBind {oid: parse_oid("1.3.6.1.6.3.1.1.4.3.0"), value: ObjectId(enterprise)});
+				(0, 0, 0)
+			}
+			else
+			{
+				let request_id = self.integer();
+				let error_status = self.integer();
+				(request_id, error_status, self.integer())
+			};
+		
+		let end = self.expect(sequence, "bindings");
+		while self.ok() && self.pos < end
+		{
+			self.expect(sequence, "binding");
+			let oid = self.oid();
+			let value = self.value();
+			bindings.push(VarBind {oid: oid, value: value});
+		}
+		Pdu {kind: kind, request_id: request_id, error_status: error_status, error_index: error_index, bindings: bindings}
+	}
+	
+	fn value() -> Value
+	{
+		let (tag, end) = self.header();
+		let bytes = self.contents(end);
+		if !self.ok()
+		{
+			return Null;
+		}
+		
+		match tag
+		{
+			0x02 => Integer(decode_signed(bytes)),
+			0x04 => OctetString(bytes),
+			0x05 => Null,
+			0x06 => ObjectId(decode_oid(bytes)),
+			0x40 => IpAddress(bytes),
+			0x41 => Counter32(decode_unsigned(bytes)),
+			0x42 => Gauge32(decode_unsigned(bytes)),
+			0x43 => TimeTicks(decode_unsigned(bytes)),
+			0x44 => Opaque(bytes),
+			0x46 => Counter64(decode_unsigned(bytes)),
+			0x80 => NoSuchObject,
+			0x81 => NoSuchInstance,
+			0x82 => EndOfMibView,
+			_ =>
+			{
+				self.fail_with(fmt!("unknown value type 0x%X", tag as uint));
+				Null
+			}
+		}
+	}
+}
+
+// ---- Internal Functions ----------------------------------------------------
+priv fn encode_pdu(pdu: &Pdu) -> ~[u8]
+{
+	let mut bindings = ~[];
+	for pdu.bindings.each |binding|
+	{
+		bindings.push_all(encode_tlv(sequence, encode_oid(binding.oid) + encode_value(&binding.value)));
+	}
+	
+	let contents = encode_signed(integer, pdu.request_id) + encode_signed(integer, pdu.error_status) + encode_signed(integer, pdu.error_index) + encode_tlv(sequence, bindings);
+	encode_tlv(pdu.kind, contents)
+}
+
+// Reports use the usmStats counters to say why a request was rejected.
+priv fn get_report_error(pdu: &Pdu) -> ~str
+{
+	let usm_stats = parse_oid("1.3.6.1.6.3.15.1.1");
+	match pdu.bindings.find(|b| {b.oid.len() > usm_stats.len() && oid_starts_with(b.oid, usm_stats)})
+	{
+		option::Some(ref binding) =>
+		{
+			match binding.oid[usm_stats.len()]
+			{
+				1 => ~"unsupported security level",
+				2 => ~"not in time window",
+				3 => ~"unknown user name",
+				4 => ~"unknown engine id",
+				5 => ~"wrong digest",
+				6 => ~"decryption error",
+				_ => oid_to_str(binding.oid),
+			}
+		}
+		option::None =>
+		{
+			if pdu.bindings.is_not_empty() {oid_to_str(pdu.bindings[0].oid)} else {~"empty report"}
+		}
+	}
+}
+
+priv fn encode_value(value: &Value) -> ~[u8]
+{
+	match *value
+	{
+		Integer(value) => encode_signed(integer, value),
+		OctetString(ref bytes) => encode_tlv(octet_string, *bytes),
+		Null => encode_tlv(null, ~[]),
+		ObjectId(ref oid) => encode_oid(*oid),
+		IpAddress(ref bytes) => encode_tlv(ip_address, *bytes),
+		Counter32(value) => encode_unsigned(counter32, value),
+		Gauge32(value) => encode_unsigned(gauge32, value),
+		TimeTicks(value) => encode_unsigned(time_ticks, value),
+		Opaque(ref bytes) => encode_tlv(opaque, *bytes),
+		Counter64(value) => encode_unsigned(counter64, value),
+		NoSuchObject => encode_tlv(no_such_object, ~[]),
+		NoSuchInstance => encode_tlv(no_such_instance, ~[]),
+		EndOfMibView => encode_tlv(end_of_mib_view, ~[]),
+	}
+}
+
+priv fn encode_tlv(tag: u8, contents: &[u8]) -> ~[u8]
+{
+	let mut result = encode_header(tag, contents.len());
+	result.push_all(contents);
+	result
+}
+
+priv fn encode_header(tag: u8, len: uint) -> ~[u8]
+{
+	let mut result = ~[tag];
+	if len < 0x80
+	{
+		result.push(len as u8);
+	}
+	else
+	{
+		let mut bytes = ~[];
+		let mut n = len;
+		while n > 0
+		{
+			bytes.push((n & 0xFF) as u8);
+			n >>= 8;
+		}
+		result.push(0x80 | bytes.len() as u8);
+		result.push_all(vec::reversed(bytes));
+	}
+	result
+}
+
+// Two's complement using the fewest bytes.
+priv fn encode_signed(tag: u8, value: i64) -> ~[u8]
+{
+	let mut bytes = ~[];
+	let mut n = value;
+	loop
+	{
+		let byte = (n & 0xFF) as u8;
+		bytes.push(byte);
+		n >>= 8;
+		if (n == 0 && byte & 0x80 == 0) || (n == -1 && byte & 0x80 != 0)
+		{
+			break;
+		}
+	}
+	encode_tlv(tag, vec::reversed(bytes))
+}
+
+priv fn encode_unsigned(tag: u8, value: u64) -> ~[u8]
+{
+	let mut bytes = ~[];
+	let mut n = value;
+	loop
+	{
+		bytes.push((n & 0xFF) as u8);
+		n >>= 8;
+		if n == 0
+		{
+			break;
+		}
+	}
+	if bytes.last() & 0x80 != 0
+	{
+		bytes.push(0);			// otherwise it would look negative
+	}
+	encode_tlv(tag, vec::reversed(bytes))
+}
+
+// The first two sub-ids are combined and sub-ids are base 128 with the high
+// bit set on all but the last byte.
+priv fn encode_oid(oid: &[uint]) -> ~[u8]
+{
+	let mut bytes = ~[];
+	if oid.len() >= 2
+	{
+		let subids = ~[40*oid[0] + oid[1]] + vec::slice(oid, 2, oid.len());
+		for subids.each |subid|
+		{
+			let mut chunk = ~[(*subid & 0x7F) as u8];
+			let mut n = *subid >> 7;
+			while n > 0
+			{
+				chunk.push(0x80 | (n & 0x7F) as u8);
+				n >>= 7;
+			}
+			bytes.push_all(vec::reversed(chunk));
+		}
+	}
+	encode_tlv(object_id, bytes)
+}
+
+priv fn decode_signed(bytes: &[u8]) -> i64
+{
+	let mut value: i64 = if bytes.is_not_empty() && bytes[0] & 0x80 != 0 {-1} else {0};
+	for bytes.each |byte|
+	{
+		value = (value << 8) | *byte as i64;
+	}
+	value
+}
+
+priv fn decode_unsigned(bytes: &[u8]) -> u64
+{
+	let mut value = 0u64;
+	for bytes.each |byte|
+	{
+		value = (value << 8) | *byte as u64;
+	}
+	value
+}
+
+priv fn decode_oid(bytes: &[u8]) -> ~[uint]
+{
+	let mut oid = ~[];
+	let mut subid = 0;
+	for bytes.each |byte|
+	{
+		subid = (subid << 7) | (*byte & 0x7F) as uint;
+		if *byte & 0x80 == 0
+		{
+			if oid.is_empty()
+			{
+				let first = uint::min(subid/40, 2);
+				oid.push(first);
+				oid.push(subid - 40*first);
+			}
+			else
+			{
+				oid.push(subid);
+			}
+			subid = 0;
+		}
+	}
+	oid
+}
+
+priv pure fn parse_ip(ip: &str) -> option::Option<~[u8]>
+{
+	let parts = str::split_char(ip, '.');
+	let bytes = parts.filter_map(|part| {uint::from_str(*part)});
+	if parts.len() == 4 && bytes.len() == 4 && bytes.all(|b| {*b < 256})
+	{
+		option::Some(bytes.map(|b| {*b as u8}))
+	}
+	else
+	{
+		option::None
+	}
+}
+
+priv pure fn make_addr(ip: &[u8], port: u16) -> SockAddrIn
+{
+	SockAddrIn {
+		sin_family: af_inet as u16,
+		sin_port: [(port >> 8) as u8, (port & 0xFF) as u8],
+		sin_addr: [ip[0], ip[1], ip[2], ip[3]],
+		sin_zero: [0, 0, 0, 0, 0, 0, 0, 0],
+	}
+}
+
+// Simple agent simulator: it answers requests using the bindings in table
+// until no requests arrive for a second.
+#[cfg(test)]
+pub fn spawn_agent(port: u16, security: Security, table: ~[VarBind])
+{
+	let ready = oldcomm::Port();
+	let ready_chan = oldcomm::Chan(&ready);
+	do task::spawn_sched(task::SingleThreaded) |move security, move table|
+	{
+		let table = std::sort::merge_sort(table, |a, b| {!oid_less(b.oid, a.oid)});
+		let key = match security {Usm(ref user) => usm::get_localized_key(user.auth, user.password, agent_engine_id), _ => ~[]};
+		let socket = result::unwrap(UdpSocket(port));
+		oldcomm::send(ready_chan, ());
+		loop
+		{
+			match socket.recv_from(1000)
+			{
+				option::Some((ref data, ref ip, from)) =>
+				{
+					let reply = match security
+					{
+						Community(ref community) => answer_v2c(*data, *community, table),
+						Usm(ref user) => answer_v3(*data, user, key, table),
+					};
+					if reply.is_not_empty()
+					{
+						socket.send_to(*ip, from, reply);
+					}
+				}
+				option::None =>
+				{
+					break;
+				}
+			}
+		}
+		socket.close();
+	}
+	oldcomm::recv(ready);
+}
+
+#[cfg(test)]
+priv const agent_engine_id: [u8 * 9] = [0x80, 0x00, 0x1F, 0x88, 0x80, 0x01, 0x02, 0x03, 0x04];
+
+// Requests with the wrong community are ignored.
+#[cfg(test)]
+priv fn answer_v2c(data: &[u8], community: &str, table: &[VarBind]) -> ~[u8]
+{
+	match decode_message(data)
+	{
+		result::Ok(ref request) if request.community == community.to_owned() =>
+		{
+			let reply = Message {version: request.version, community: community.to_owned(), pdu: Pdu {kind: response, request_id: request.pdu.request_id, error_status: 0, error_index: 0, bindings: answer(&request.pdu, table)}};
+			encode_message(&reply)
+		}
+		_ =>
+		{
+			~[]
+		}
+	}
+}
+
+// Bad requests get reports with the usmStats counter which says what was wrong.
+#[cfg(test)]
+priv fn answer_v3(data: &[u8], user: &UsmUser, key: &[u8], table: &[VarBind]) -> ~[u8]
+{
+	match decode_v3_message(data)
+	{
+		result::Ok((ref request, offset)) =>
+		{
+			let reply = |flags: u8, pdu: Pdu|
+			{
+				let message = V3Message {msg_id: request.msg_id, flags: flags, engine_id: vec::from_slice(agent_engine_id), engine_boots: 1, engine_time: 100, user: copy request.user, context_engine_id: vec::from_slice(agent_engine_id), context_name: ~"", pdu: pdu};
+				encode_v3_message(&message, user.auth, key)
+			};
+			let report = |counter: uint| {Pdu {kind: report, request_id: request.pdu.request_id, error_status: 0, error_index: 0, bindings: ~[VarBind {oid: parse_oid("1.3.6.1.6.3.15.1.1") + ~[counter, 0], value: Counter32(1)}]}};
+			let authenticated = request.flags & auth_flag != 0;
+			
+			if request.engine_id != vec::from_slice(agent_engine_id)
+			{
+				reply(0, report(4))
+			}
+			else if request.user != user.name
+			{
+				reply(0, report(3))
+			}
+			else if authenticated != match user.auth {NoAuth => false, _ => true}
+			{
+				reply(0, report(1))
+			}
+			else if authenticated && !verify_mac(data, offset, user.auth, key)
+			{
+				reply(0, report(5))
+			}
+			else
+			{
+				reply(request.flags & auth_flag, Pdu {kind: response, request_id: request.pdu.request_id, error_status: 0, error_index: 0, bindings: answer(&request.pdu, table)})
+			}
+		}
+		result::Err(_) =>
+		{
+			~[]
+		}
+	}
+}
+
+#[cfg(test)]
+priv fn answer(request: &Pdu, table: &[VarBind]) -> ~[VarBind]
+{
+	let mut result = ~[];
+	for request.bindings.eachi |i, binding|
+	{
+		if request.kind == get_request
+		{
+			match table.find(|b| {b.oid == binding.oid})
+			{
+				option::Some(found) => result.push(found),
+				option::None => result.push(VarBind {oid: copy binding.oid, value: NoSuchObject}),
+			}
+		}
+		else
+		{
+			let count = if request.kind == get_bulk_request && i as i64 >= request.error_status {request.error_index as uint} else {1};
+			let mut oid = copy binding.oid;
+			for count.times
+			{
+				match table.find(|b| {oid_less(oid, b.oid)})
+				{
+					option::Some(found) =>
+					{
+						oid = copy found.oid;
+						result.push(found);
+					}
+					option::None =>
+					{
+						result.push(VarBind {oid: copy oid, value: EndOfMibView});
+						break;
+					}
+				}
+			}
+		}
+	}
+	result
+}
+
+#[test]
+fn test_oids()
+{
+	assert parse_oid("1.3.6.1.2.1.1.3.0") == ~[1, 3, 6, 1, 2, 1, 1, 3, 0];
+	assert oid_to_str(~[1, 3, 6, 1]) == ~"1.3.6.1";
+	assert oid_starts_with(~[1, 3, 6, 1, 2], ~[1, 3, 6]);
+	assert !oid_starts_with(~[1, 3], ~[1, 3, 6]);
+	assert oid_less(~[1, 3, 6], ~[1, 3, 6, 1]);
+	assert oid_less(~[1, 3, 6, 1, 9], ~[1, 3, 6, 1, 10]);
+	assert !oid_less(~[1, 3, 7], ~[1, 3, 6, 1]);
+	
+	let oid = ~[1, 3, 6, 1, 4, 1, 2021, 300000, 0];
+	assert encode_oid(oid) == ~[0x06, 0x0B, 0x2B, 0x06, 0x01, 0x04, 0x01, 0x8F, 0x65, 0x92, 0xA7, 0x60, 0x00];
+	assert decode_oid(vec::slice(encode_oid(oid), 2, 13)) == oid;
+}
+
+#[test]
+fn test_integers()
+{
+	assert encode_signed(integer, 0) == ~[0x02, 0x01, 0x00];
+	assert encode_signed(integer, 127) == ~[0x02, 0x01, 0x7F];
+	assert encode_signed(integer, 128) == ~[0x02, 0x02, 0x00, 0x80];
+	assert encode_signed(integer, -1) == ~[0x02, 0x01, 0xFF];
+	assert encode_signed(integer, -129) == ~[0x02, 0x02, 0xFF, 0x7F];
+	assert encode_unsigned(counter32, 4294967295) == ~[0x41, 0x05, 0x00, 0xFF, 0xFF, 0xFF, 0xFF];
+	
+	for [0i64, 1, -1, 255, 256, -32768, 2147483647, -2147483648].each |n|
+	{
+		assert decode_signed(vec::slice(encode_signed(integer, *n), 2, encode_signed(integer, *n).len())) == *n;
+	}
+}
+
+#[test]
+fn test_messages()
+{
+	let bindings = ~[
+		VarBind {oid: parse_oid("1.3.6.1.2.1.1.1.0"), value: OctetString(str::to_bytes("Linux router"))},
+		VarBind {oid: parse_oid("1.3.6.1.2.1.1.3.0"), value: TimeTicks(397724214)},
+		VarBind {oid: parse_oid("1.3.6.1.2.1.4.20.1.1.10.0.4.2"), value: IpAddress(~[10, 0, 4, 2])},
+		VarBind {oid: parse_oid("1.3.6.1.2.1.2.2.1.10.1"), value: Counter32(9840)},
+		VarBind {oid: parse_oid("1.3.6.1.2.1.2.2.1.2.1"), value: OctetString(vec::from_elem(300, 'x' as u8))},
+		VarBind {oid: parse_oid("1.3.6.1.2.1.2.2.1.2.2"), value: EndOfMibView},
+	];
+	let message = Message {version: version_2c, community: ~"public", pdu: Pdu {kind: response, request_id: 1234, error_status: 0, error_index: 0, bindings: copy bindings}};
+	
+	let decoded = result::unwrap(decode_message(encode_message(&message)));
+	assert decoded.version == version_2c;
+	assert decoded.community == ~"public";
+	assert decoded.pdu.kind == response;
+	assert decoded.pdu.request_id == 1234;
+	assert decoded.pdu.bindings.len() == bindings.len();
+	assert decoded.pdu.bindings[0].value.to_str() == ~"Linux router";
+	assert decoded.pdu.bindings[1].value.to_float() == 397724214.0;
+	assert decoded.pdu.bindings[2].value.to_str() == ~"10.0.4.2";
+	assert decoded.pdu.bindings[4].value.to_str().len() == 300;
+	assert decoded.pdu.bindings[5].oid == parse_oid("1.3.6.1.2.1.2.2.1.2.2");
+	
+	let encoded = encode_message(&message);
+	assert decode_message(vec::slice(encoded, 0, encoded.len() - 1)).is_err();
+	assert decode_message(~[0x30, 0x03, 0x02, 0x01]).is_err();
+	assert decode_message(~[]).is_err();
+}
+
+#[test]
+fn test_v1_trap()
+{
+	// linkDown from 10.0.4.2 for ifIndex 2
+	let binding = encode_tlv(sequence, encode_oid(parse_oid("1.3.6.1.2.1.2.2.1.1.2")) + encode_signed(integer, 2));
+	let pdu = encode_oid(parse_oid("1.3.6.1.4.1.8072.3.2.10")) + encode_tlv(ip_address, ~[10, 0, 4, 2]) + encode_signed(integer, 2) + encode_signed(integer, 0) + encode_unsigned(time_ticks, 1234) + encode_tlv(sequence, binding);
+	let data = encode_tlv(sequence, encode_signed(integer, version_1) + encode_tlv(octet_string, str::to_bytes("public")) + encode_tlv(trap_v1, pdu));
+	
+	let message = result::unwrap(decode_message(data));
+	assert message.version == version_1;
+	assert message.pdu.kind == trap_v1;
+	assert message.pdu.bindings.len() == 5;
+	assert message.pdu.bindings[0].value.to_float() == 1234.0;
+	assert message.pdu.bindings[1].value.to_str() == ~"1.3.6.1.6.3.1.1.5.3";
+	assert message.pdu.bindings[2].value.to_str() == ~"10.0.4.2";
+	assert message.pdu.bindings[3].value.to_str() == ~"1.3.6.1.4.1.8072.3.2.10";
+	assert message.pdu.bindings[4].oid == parse_oid("1.3.6.1.2.1.2.2.1.1.2");
+}
+
+#[test]
+fn test_walk()
+{
+	let table = ~[
+		VarBind {oid: parse_oid("1.3.6.1.2.1.1.3.0"), value: TimeTicks(6000)},
+		VarBind {oid: parse_oid("1.3.6.1.2.1.2.2.1.2.1"), value: OctetString(str::to_bytes("lo"))},
+		VarBind {oid: parse_oid("1.3.6.1.2.1.2.2.1.2.2"), value: OctetString(str::to_bytes("eth0"))},
+		VarBind {oid: parse_oid("1.3.6.1.2.1.2.2.1.4.1"), value: Integer(16436)},
+	];
+	let port = 16161;
+	spawn_agent(port, Community(~"secret"), table);
+	
+	let session = result::unwrap(Session("127.0.0.1", port, &Community(~"secret"), 500, 1));
+	let bindings = result::unwrap(session.walk(parse_oid("1.3.6.1.2.1.2.2.1.2")));
+	assert bindings.len() == 2;
+	assert bindings[1].value.to_str() == ~"eth0";
+	
+	let bindings = result::unwrap(session.get(~[parse_oid("1.3.6.1.2.1.1.3.0"), parse_oid("1.3.6.1.2.1.1.4.0")]));
+	assert bindings[0].value.to_float() == 6000.0;
+	match bindings[1].value {NoSuchObject => (), _ => fail}
+	session.close();
+	
+	let session = result::unwrap(Session("127.0.0.1", port, &Community(~"public"), 100, 0));
+	assert session.walk(parse_oid("1.3.6.1.2.1")).is_err();		// wrong community so the agent ignores us
+	session.close();
+}
+
+#[test]
+fn test_v3_messages()
+{
+	let engine_id = ~[0x80u8, 0x00, 0x1F, 0x88, 0x80, 0x01];
+	let key = usm::get_localized_key(AuthSha, "maplesyrup", engine_id);
+	let pdu = Pdu {kind: get_request, request_id: 7, error_status: 0, error_index: 0, bindings: ~[VarBind {oid: parse_oid("1.3.6.1.2.1.1.3.0"), value: Null}]};
+	let message = V3Message {msg_id: 42, flags: auth_flag | reportable_flag, engine_id: copy engine_id, engine_boots: 3, engine_time: 1234, user: ~"gnos", context_engine_id: copy engine_id, context_name: ~"", pdu: pdu};
+	
+	let data = encode_v3_message(&message, AuthSha, key);
+	let (decoded, offset) = result::unwrap(decode_v3_message(data));
+	assert decoded.msg_id == 42;
+	assert decoded.flags == auth_flag | reportable_flag;
+	assert decoded.engine_id == engine_id;
+	assert decoded.engine_boots == 3;
+	assert decoded.engine_time == 1234;
+	assert decoded.user == ~"gnos";
+	assert decoded.pdu.request_id == 7;
+	assert decoded.pdu.bindings[0].oid == parse_oid("1.3.6.1.2.1.1.3.0");
+	
+	// the mac covers the whole message
+	assert verify_mac(data, offset, AuthSha, key);
+	assert !verify_mac(data, offset, AuthSha, usm::get_localized_key(AuthSha, "pancakes!", engine_id));
+	let mut tampered = copy data;
+	tampered[data.len() - 1] ^= 1;
+	assert !verify_mac(tampered, offset, AuthSha, key);
+	
+	assert decode_v3_message(encode_message(&Message {version: version_2c, community: ~"public", pdu: copy message.pdu})).is_err();
+}
+
+#[test]
+fn test_walk_v3()
+{
+	let table = ~[
+		VarBind {oid: parse_oid("1.3.6.1.2.1.1.3.0"), value: TimeTicks(6000)},
+		VarBind {oid: parse_oid("1.3.6.1.2.1.2.2.1.2.1"), value: OctetString(str::to_bytes("lo"))},
+		VarBind {oid: parse_oid("1.3.6.1.2.1.2.2.1.2.2"), value: OctetString(str::to_bytes("eth0"))},
+	];
+	let user = UsmUser {name: ~"gnos", auth: AuthMd5, password: ~"maplesyrup"};
+	let port = 16163;
+	spawn_agent(port, Usm(copy user), table);
+	
+	let session = result::unwrap(Session("127.0.0.1", port, &Usm(copy user), 500, 1));
+	let bindings = result::unwrap(session.walk(parse_oid("1.3.6.1.2.1.2.2.1.2")));
+	assert bindings.len() == 2;
+	assert bindings[1].value.to_str() == ~"eth0";
+	session.close();
+	
+	let session = result::unwrap(Session("127.0.0.1", port, &Usm(UsmUser {password: ~"pancakes!", ..user}), 500, 1));
+	let err = result::get_err(&session.get(~[parse_oid("1.3.6.1.2.1.1.3.0")]));
+	assert err.contains("wrong digest");
+	session.close();
+	
+	let session = result::unwrap(Session("127.0.0.1", port, &Usm(UsmUser {name: ~"mallory", auth: NoAuth, password: ~""}), 500, 1));
+	let err = result::get_err(&session.get(~[parse_oid("1.3.6.1.2.1.1.3.0")]));
+	assert err.contains("unknown user name");
+	session.close();
+}
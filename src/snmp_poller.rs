@@ -0,0 +1,714 @@
+/// Builtin modeler which polls a device using SNMPv2c or SNMPv3. It walks the MIB subsets
+/// net-modeler.py uses for all devices (uptime, system info, IP addresses,
+/// interfaces, and ipRouteTable) and produces the same entities, labels, gauges,
+/// details, samples, and alerts for the device.
+use std::json::{Json};
+use modeler::{Producer};
+use options::{Device};
+use snmp::*;
+
+pub struct SnmpPoller
+{
+	priv chan: oldcomm::Chan<PollMsg>,
+}
+
+/// Spawns a task which polls the device whenever an update is produced. The
+/// task retains the previous counters so that interface gauges can be computed.
+pub fn SnmpPoller(device: &Device) -> SnmpPoller
+{
+	let device = copy *device;
+	SnmpPoller {chan: do utils::spawn_moded_listener(task::SingleThreaded) |port, move device| {poll_device(port, &device)}}
+}
+
+pub impl SnmpPoller : Producer
+{
+	fn produce_update() -> result::Result<Json, ~str>
+	{
+		let port = oldcomm::Port();
+		let chan = oldcomm::Chan(&port);
+		oldcomm::send(self.chan, PollMsg(chan));
+		oldcomm::recv(port)
+	}
+}
+
+// ---- Internal Types --------------------------------------------------------
+priv enum PollMsg
+{
+	PollMsg(oldcomm::Chan<result::Result<Json, ~str>>),
+}
+
+priv struct Interface
+{
+	index: uint,
+	name: ~str,
+	status: ~str,					// "up", "down", "dormant", etc
+	admin_status: ~str,
+	mac_addr: ~str,					// "00:19:bb:5f:59:8a" or empty
+	speed: float,					// bps
+	mtu: float,						// bytes
+	in_octets: float,
+	out_octets: float,
+	last_changed: float,			// seconds since the device came up
+	mut ip: ~str,
+	mut net_mask: ~str,
+}
+
+priv struct Route
+{
+	dst_subnet: ~str,
+	dst_mask: ~str,
+	via_ip: ~str,
+	protocol: ~str,
+	metric: ~str,
+	ifindex: uint,
+}
+
+priv struct DeviceInfo
+{
+	uptime: float,					// seconds
+	system_info: ~str,				// markdown
+	interfaces: ~[Interface],
+	routes: ~[Route],
+}
+
+// Lists of json objects for the update.
+priv struct Update
+{
+	mut entities: ~[Json],
+	mut labels: ~[Json],
+	mut gauges: ~[Json],
+	mut details: ~[Json],
+	mut alerts: ~[Json],
+	mut samples: ~[Json],
+}
+
+// These are net-snmp's defaults (except that it uses 5 retries).
+priv const timeout: uint = 1000;
+priv const retries: uint = 3;
+
+// ---- Internal Functions ----------------------------------------------------
+priv fn poll_device(port: oldcomm::Port<PollMsg>, device: &Device)
+{
+	let rates = counters::Counters();
+	let mut polls = 0;
+	loop
+	{
+		match oldcomm::recv(port)
+		{
+			PollMsg(reply) =>
+			{
+				polls += 1;
+				oldcomm::send(reply, get_update(device, &rates, polls));
+			}
+		}
+	}
+}
+
+priv fn get_update(device: &Device, rates: &counters::Counters, polls: uint) -> result::Result<Json, ~str>
+{
+	let session = match Session(device.managed_ip, device.snmp_port, &device.snmp_security, timeout, retries)
+	{
+		result::Ok(move session) => session,
+		result::Err(move err) => return result::Err(err),
+	};
+	let info = query_device(&session);
+	session.close();
+	
+	let target = ~"entities:" + device.managed_ip;
+	let update = Update {entities: ~[], labels: ~[], gauges: ~[], details: ~[], alerts: ~[], samples: ~[]};
+	update.entities.push(utils::to_object(~[(~"id", std::json::String(copy device.managed_ip)), (~"label", std::json::String(copy device.name)), (~"style", std::json::String(~"font-weight:bolder"))]));
+	update.add_label(target, device.managed_ip, ~"a", 1, ~"font-size:x-small");
+	
+	match info
+	{
+		result::Ok(ref info) =>
+		{
+			update.close_alert(target, ~"device down");
+			add_device(&update, device, info, rates, polls);
+		}
+		result::Err(ref err) =>
+		{
+			error!("polling %s failed: %s", device.name, *err);
+			update.open_alert(target, ~"device down", ~"Device is down.", ~"Check the power cable, power it on if it is off, check the IP address, verify routing.", ~"error");
+		}
+	}
+	
+	result::Ok(utils::to_object(~[
+		(~"modeler", std::json::String(~"snmp " + device.name)),
+		(~"entities", std::json::List(copy update.entities)),
+		(~"labels", std::json::List(copy update.labels)),
+		(~"gauges", std::json::List(copy update.gauges)),
+		(~"details", std::json::List(copy update.details)),
+		(~"alerts", std::json::List(copy update.alerts)),
+		(~"samples", std::json::List(copy update.samples)),
+	]))
+}
+
+// Errors are only returned if the device didn't respond at all.
+priv fn query_device(session: &Session) -> result::Result<DeviceInfo, ~str>
+{
+	let oids = ~[
+		parse_oid("1.3.6.1.2.1.1.1.0"),			// sysDescr
+		parse_oid("1.3.6.1.2.1.1.3.0"),			// sysUpTime
+		parse_oid("1.3.6.1.2.1.1.4.0"),			// sysContact
+		parse_oid("1.3.6.1.2.1.1.6.0"),			// sysLocation
+		parse_oid("1.3.6.1.2.1.4.1.0"),			// ipForwarding
+	];
+	let system = match session.get(oids)
+	{
+		result::Ok(move bindings) => bindings,
+		result::Err(move err) => return result::Err(err),
+	};
+	if system.len() != oids.len()
+	{
+		return result::Err(fmt!("expected %? values but got %?", oids.len(), system.len()));
+	}
+	
+	let mut system_info = ~"";
+	for [0u, 2u].each |i|
+	{
+		if is_present(&system[*i].value)
+		{
+			system_info += fmt!("* %s\n", system[*i].value.to_str());
+		}
+	}
+	if is_present(&system[3].value)
+	{
+		system_info += fmt!("* location is %s\n", system[3].value.to_str());
+	}
+	system_info += if system[4].value.to_float() == 1.0 {~"* ip forwarding is on\n"} else {~"* ip forwarding is off\n"};
+	
+	let interfaces = get_interfaces(session);
+	let indexes = walk_column(session, "1.3.6.1.2.1.4.20.1.2");		// ipAdEntIfIndex
+	let masks = walk_column(session, "1.3.6.1.2.1.4.20.1.3");		// ipAdEntNetMask
+	for indexes.each |entry|
+	{
+		let (ip, index) = copy *entry;
+		for interfaces.each |interface|
+		{
+			if interface.index as float == index.to_float()
+			{
+				interface.ip = oid_to_str(ip);
+				interface.net_mask = find_value(masks, ip).map_default(~"", |v| {v.to_str()});
+			}
+		}
+	}
+	
+	result::Ok(DeviceInfo {
+		uptime: system[1].value.to_float()/100.0,
+		system_info: system_info,
+		interfaces: interfaces,
+		routes: get_routes(session),
+	})
+}
+
+priv fn get_interfaces(session: &Session) -> ~[Interface]
+{
+	let descs = walk_column(session, "1.3.6.1.2.1.2.2.1.2");		// ifDescr
+	let mtus = walk_column(session, "1.3.6.1.2.1.2.2.1.4");
+	let speeds = walk_column(session, "1.3.6.1.2.1.2.2.1.5");
+	let macs = walk_column(session, "1.3.6.1.2.1.2.2.1.6");
+	let admin_status = walk_column(session, "1.3.6.1.2.1.2.2.1.7");
+	let oper_status = walk_column(session, "1.3.6.1.2.1.2.2.1.8");
+	let last_changes = walk_column(session, "1.3.6.1.2.1.2.2.1.9");
+	let in_octets = walk_column(session, "1.3.6.1.2.1.2.2.1.10");
+	let out_octets = walk_column(session, "1.3.6.1.2.1.2.2.1.16");
+	
+	let get_float = |column: &[(~[uint], Value)], index: &[uint]| {find_value(column, index).map_default(float::NaN, |v| {v.to_float()})};
+	
+	// When devices are brought up and down multiple entries can land in the table
+	// so we'll add the ones that are enabled and then add any down ones that we
+	// missed.
+	let mut interfaces = ~[];
+	for [true, false].each |want_active|
+	{
+		for descs.each |entry|
+		{
+			let (index, desc) = copy *entry;
+			let name = str::replace(desc.to_str(), "/", "-");		// Ciscos use ifnames like FastEthernet0/0.8 which can cause problems with file paths and urls
+			let status = status_to_str(get_float(oper_status, index));
+			if is_active(status) == *want_active && !interfaces.any(|i: &Interface| {i.name == name})
+			{
+				interfaces.push(Interface {
+					index: if index.is_not_empty() {index[0]} else {0},
+					name: name,
+					status: status,
+					admin_status: status_to_str(get_float(admin_status, index)),
+					mac_addr: find_value(macs, index).map_default(~"", |v| {mac_to_str(v)}),
+					speed: get_float(speeds, index),
+					mtu: get_float(mtus, index),
+					in_octets: if *want_active {get_float(in_octets, index)} else {0.0},		// these will often be nonsense for inactive interfaces
+					out_octets: if *want_active {get_float(out_octets, index)} else {0.0},
+					last_changed: get_float(last_changes, index)/100.0,
+					ip: ~"",
+					net_mask: ~"",
+				});
+			}
+		}
+	}
+	interfaces
+}
+
+priv fn get_routes(session: &Session) -> ~[Route]
+{
+	let indexes = walk_column(session, "1.3.6.1.2.1.4.21.1.2");		// ipRouteIfIndex
+	let metrics = walk_column(session, "1.3.6.1.2.1.4.21.1.3");
+	let nexts = walk_column(session, "1.3.6.1.2.1.4.21.1.7");
+	let protocols = walk_column(session, "1.3.6.1.2.1.4.21.1.9");
+	let masks = walk_column(session, "1.3.6.1.2.1.4.21.1.11");
+	
+	do nexts.map |entry|
+	{
+		let (dest, next) = copy *entry;
+		Route {
+			dst_subnet: oid_to_str(dest),
+			dst_mask: find_value(masks, dest).map_default(~"", |v| {v.to_str()}),
+			via_ip: next.to_str(),
+			protocol: find_value(protocols, dest).map_default(~"", |v| {protocol_to_str(v.to_float())}),
+			metric: find_value(metrics, dest).map_default(~"", |v| {v.to_str()}),
+			ifindex: find_value(indexes, dest).map_default(0, |v| {v.to_float() as uint}),
+		}
+	}
+}
+
+priv fn add_device(update: &Update, device: &Device, info: &DeviceInfo, rates: &counters::Counters, polls: uint)
+{
+	let target = ~"entities:" + device.managed_ip;
+	if info.uptime > 0.0
+	{
+		update.add_label(target, ~"uptime: " + secs_to_str(info.uptime), ~"alpha", 2, ~"font-size:x-small");	// alpha so that uptime appears before most other labels
+		if info.uptime < 60.0
+		{
+			update.open_alert(target, ~"uptime", ~"Device rebooted.", ~"", ~"error");
+		}
+		else
+		{
+			update.close_alert(target, ~"uptime");
+		}
+	}
+	
+	if info.system_info.is_not_empty()
+	{
+		update.add_details(target, ~"System Info", ~[std::json::String(copy info.system_info)], ~"no", ~"beta", ~"system info");
+	}
+	if info.interfaces.is_not_empty()
+	{
+		add_interfaces_table(update, device, info, rates, polls);
+	}
+	if info.routes.is_not_empty()
+	{
+		add_routing_table(update, target, info);
+	}
+	
+	for info.interfaces.each |interface|
+	{
+		// Alert if the operational status doesn't match the admin status.
+		let key = fmt!("%s-oper-status", interface.name);
+		if interface.admin_status != interface.status && interface.status != ~"dormant"
+		{
+			update.open_alert(target, key, fmt!("Admin set %s to %s but it is %s.", interface.name, interface.admin_status, interface.status), ~"", ~"warning");
+		}
+		else
+		{
+			update.close_alert(target, key);
+		}
+		
+		if interface.last_changed > 0.0
+		{
+			let key = fmt!("%s-last-change", interface.name);
+			let delta = info.uptime - interface.last_changed;
+			if delta >= 0.0 && delta < 60.0
+			{
+				update.open_alert(target, key, fmt!("%s status recently changed to %s.", interface.name, interface.status), ~"", ~"warning");
+			}
+			else
+			{
+				update.close_alert(target, key);
+			}
+		}
+	}
+}
+
+priv fn add_interfaces_table(update: &Update, device: &Device, info: &DeviceInfo, rates: &counters::Counters, polls: uint)
+{
+	let target = ~"entities:" + device.managed_ip;
+	let time = utils::imprecise_time_s();
+	let mut rows = ~[];
+	for info.interfaces.each |interface|
+	{
+		let name = utils::escape_html(interface.name);
+		
+		// We always need to add samples so that they stay in sync with one another.
+		// The samples are converted into rates by the samples task.
+		let mut cells = ~[];
+		for [(~"in", interface.in_octets), (~"out", interface.out_octets)].each |entry|
+		{
+			let (direction, octets) = copy *entry;
+			let key = fmt!("%s-%s-%s_octets", device.managed_ip, name, direction);
+			let mut sample = ~[
+				(~"name", std::json::String(copy key)),
+				(~"value", std::json::Number(if float::is_NaN(octets) {0.0} else {octets})),
+				(~"units", std::json::String(~"kbps")),
+				(~"type", std::json::String(~"counter32")),
+				(~"scale", std::json::Number(8.0/1000.0)),
+				(~"entity-id", std::json::String(copy target)),
+			];
+			if interface.speed > 0.0
+			{
+				sample.push((~"limit", std::json::Number(interface.speed/1000.0)));
+			}
+			update.samples.push(utils::to_object(sample));
+			
+			// When dynamically adding html content browsers will not reload images that have
+			// been already loaded. To work around this we add a unique fragment identifier
+			// which the server will ignore.
+			cells.push(if polls >= 2 {fmt!("<img src = '/generated/%s.png#%?' alt = '%s'>", key, polls, key)} else {~""});
+		}
+		
+		let out_kbps = rates.get_value(fmt!("%s-%s-out_octets", device.managed_ip, name), interface.out_octets, counters::Counter32(8.0/1000.0), time, if interface.speed > 0.0 {interface.speed/1000.0} else {float::NaN});
+		if is_active(interface.status)
+		{
+			let mut ip = copy interface.ip;
+			if interface.net_mask.is_not_empty()
+			{
+				ip = fmt!("%s/%s", ip, mask_to_subnet(interface.net_mask));
+			}
+			if interface.ip == device.managed_ip
+			{
+				ip = fmt!("<strong>%s</strong>", ip);
+			}
+			
+			let speed =
+				if interface.speed > 0.0
+				{
+					if !float::is_NaN(out_kbps)
+					{
+						add_interface_gauge(update, target, name, out_kbps, interface.speed/1000.0);
+					}
+					fmt!("%.1f Mbps", interface.speed/1000000.0)
+				}
+				else
+				{
+					~""
+				};
+			let mtu = if float::is_NaN(interface.mtu) {~""} else {fmt!("%? B", interface.mtu as int)};
+			rows.push(~[copy name, ip, copy interface.mac_addr, speed, mtu, copy cells[0], copy cells[1]]);
+		}
+	}
+	
+	if rows.is_not_empty()
+	{
+		let detail = utils::to_object(~[
+			(~"style", std::json::String(~"html")),
+			(~"header", utils::strs_to_json(~[~"Name", ~"IP Address", ~"Mac Address", ~"Speed", ~"MTU", ~"In Octets (kbps)", ~"Out Octets (kbps)"])),
+			(~"rows", utils::rows_to_json(rows)),
+		]);
+		let footnote = ~"*The shaded area in the sparklines is the inter-quartile range: the range in which half the samples appear.*";
+		update.add_details(target, ~"Interfaces", ~[detail, std::json::String(footnote)], ~"yes", ~"alpha", ~"interfaces table");
+	}
+}
+
+priv fn add_interface_gauge(update: &Update, target: &str, name: &str, out_kbps: float, speed_kbps: float)
+{
+	let bandwidth = float::min(out_kbps/speed_kbps, 1.0);
+	let (level, style) =
+		if bandwidth >= 0.75
+		{
+			(1, ~"gauge-bar-color:salmon")
+		}
+		else if bandwidth >= 0.50
+		{
+			(1, ~"gauge-bar-color:darkorange")
+		}
+		else if bandwidth >= 0.25
+		{
+			(2, ~"gauge-bar-color:skyblue")
+		}
+		else if bandwidth >= 0.10
+		{
+			(4, ~"gauge-bar-color:limegreen")
+		}
+		else
+		{
+			(0, ~"")
+		};
+	if level > 0
+	{
+		update.add_gauge(target, fmt!("%s bandwidth", name), bandwidth, level, style, ~"z");
+	}
+}
+
+priv fn add_routing_table(update: &Update, target: &str, info: &DeviceInfo)
+{
+	let rows = do info.routes.map |route|
+	{
+		let out = match info.interfaces.find(|i| {i.index == route.ifindex})
+		{
+			option::Some(ref interface) => copy interface.name,
+			option::None => ~"",
+		};
+		let via = if route.via_ip != ~"0.0.0.0" {copy route.via_ip} else {~""};
+		~[fmt!("%s/%s", route.dst_subnet, mask_to_subnet(route.dst_mask)), via, out, copy route.protocol, copy route.metric]
+	};
+	let rows = std::sort::merge_sort(rows, |a, b| {a[0] <= b[0]});
+	
+	let detail = utils::to_object(~[
+		(~"style", std::json::String(~"plain")),
+		(~"header", utils::strs_to_json(~[~"Destination", ~"Via", ~"Out", ~"Protocol", ~"Cost"])),
+		(~"rows", utils::rows_to_json(rows)),
+	]);
+	update.add_details(target, ~"Routes", ~[detail], ~"no", ~"beta", ~"routing table");
+}
+
+priv impl Update
+{
+	fn add_label(target: &str, label: &str, key: &str, level: int, style: &str)
+	{
+		self.labels.push(utils::to_object(~[
+			(~"target-id", std::json::String(target.to_owned())),
+			(~"label", std::json::String(label.to_owned())),
+			(~"level", std::json::Number(level as float)),
+			(~"sort-key", std::json::String(fmt!("%?-%s", level, key))),
+			(~"style", std::json::String(style.to_owned())),
+		]));
+	}
+	
+	fn add_gauge(target: &str, label: &str, value: float, level: int, style: &str, sort_key: &str)
+	{
+		self.gauges.push(utils::to_object(~[
+			(~"entity-id", std::json::String(target.to_owned())),
+			(~"label", std::json::String(label.to_owned())),
+			(~"value", std::json::Number(value)),
+			(~"level", std::json::Number(level as float)),
+			(~"style", std::json::String(style.to_owned())),
+			(~"sort-key", std::json::String(sort_key.to_owned())),
+		]));
+	}
+	
+	// Like the scripts details are a json encoded list of markdown strings and
+	// table objects.
+	fn add_details(target: &str, label: &str, details: ~[Json], open: &str, sort_key: &str, key: &str)
+	{
+		self.details.push(utils::to_object(~[
+			(~"entity-id", std::json::String(target.to_owned())),
+			(~"label", std::json::String(label.to_owned())),
+			(~"details", std::json::String(std::json::List(details).to_str())),
+			(~"open", std::json::String(open.to_owned())),
+			(~"sort-key", std::json::String(sort_key.to_owned())),
+			(~"id", std::json::String(key.to_owned())),
+		]));
+	}
+	
+	fn open_alert(target: &str, key: &str, mesg: &str, resolution: &str, kind: &str)
+	{
+		self.alerts.push(utils::to_object(~[
+			(~"entity-id", std::json::String(target.to_owned())),
+			(~"key", std::json::String(key.to_owned())),
+			(~"mesg", std::json::String(mesg.to_owned())),
+			(~"resolution", std::json::String(resolution.to_owned())),
+			(~"kind", std::json::String(kind.to_owned())),
+		]));
+	}
+	
+	fn close_alert(target: &str, key: &str)
+	{
+		self.alerts.push(utils::to_object(~[
+			(~"entity-id", std::json::String(target.to_owned())),
+			(~"key", std::json::String(key.to_owned())),
+		]));
+	}
+}
+
+priv fn walk_column(session: &Session, column: &str) -> ~[(~[uint], Value)]
+{
+	let root = parse_oid(column);
+	match session.walk(root)
+	{
+		result::Ok(ref bindings) =>
+		{
+			do bindings.map |binding| {(vec::slice(binding.oid, root.len(), binding.oid.len()), copy binding.value)}
+		}
+		result::Err(ref err) =>
+		{
+			error!("walking %s failed: %s", column, *err);
+			~[]
+		}
+	}
+}
+
+priv fn find_value(column: &[(~[uint], Value)], index: &[uint]) -> option::Option<Value>
+{
+	let found = column.find(|entry| {entry.first() == vec::from_slice(index)});
+	found.map(|entry| {entry.second()})
+}
+
+priv pure fn is_present(value: &Value) -> bool
+{
+	match *value
+	{
+		NoSuchObject | NoSuchInstance | EndOfMibView | Null => false,
+		_ => true,
+	}
+}
+
+priv pure fn is_active(status: &str) -> bool
+{
+	status == "up" || status == "dormant"
+}
+
+// ifAdminStatus and ifOperStatus
+priv fn status_to_str(status: float) -> ~str
+{
+	match status as int
+	{
+		1 => ~"up",
+		2 => ~"down",
+		3 => ~"testing",
+		4 => ~"unknown",
+		5 => ~"dormant",
+		6 => ~"notPresent",
+		7 => ~"lowerLayerDown",
+		_ => ~"?",
+	}
+}
+
+// ipRouteProto
+priv fn protocol_to_str(protocol: float) -> ~str
+{
+	match protocol as int
+	{
+		1 => ~"other",
+		2 => ~"local",
+		3 => ~"netmgmt",
+		4 => ~"icmp",
+		5 => ~"egp",
+		6 => ~"ggp",
+		7 => ~"hello",
+		8 => ~"rip",
+		9 => ~"is-is",
+		10 => ~"es-is",
+		11 => ~"ciscoIgrp",
+		12 => ~"bbnSpfIgp",
+		13 => ~"ospf",
+		14 => ~"bgp",
+		_ => ~"?",
+	}
+}
+
+priv fn mac_to_str(value: &Value) -> ~str
+{
+	match *value
+	{
+		OctetString(ref bytes) => str::connect(bytes.map(|b| {fmt!("%02x", *b as uint)}), ":"),
+		_ => ~"",
+	}
+}
+
+// Returns "24" for "255.255.255.0" or the mask if it isn't contiguous.
+priv fn mask_to_subnet(mask: &str) -> ~str
+{
+	let bits = str::split_char(mask, '.').foldl(0u, |sum, part| {(sum << 8) + uint::from_str(*part).get_default(0)});
+	let mut count = 0;
+	while count < 32 && bits & (1 << (31 - count)) != 0
+	{
+		count += 1;
+	}
+	if count == 32 || (bits & ((1 << (32 - count)) - 1)) == 0 {count.to_str()} else {mask.to_owned()}
+}
+
+priv fn secs_to_str(secs: float) -> ~str
+{
+	let (value, units) =
+		if secs >= 365.25*86400.0
+		{
+			(fmt!("%.2f", secs/(365.25*86400.0)), ~"year")
+		}
+		else if secs >= 365.25*86400.0/12.0
+		{
+			(fmt!("%.2f", secs/(365.25*86400.0/12.0)), ~"month")
+		}
+		else if secs >= 86400.0
+		{
+			(fmt!("%.1f", secs/86400.0), ~"day")
+		}
+		else if secs >= 60.0*60.0
+		{
+			(fmt!("%.1f", secs/(60.0*60.0)), ~"hour")
+		}
+		else if secs >= 60.0
+		{
+			(fmt!("%.0f", secs/60.0), ~"minute")
+		}
+		else if secs >= 1.0
+		{
+			(fmt!("%.0f", secs), ~"second")
+		}
+		else
+		{
+			(fmt!("%.3f", 1000.0*secs), ~"msec")
+		};
+	if value == ~"1" {fmt!("%s %s", value, units)} else {fmt!("%s %ss", value, units)}
+}
+
+#[test]
+fn test_poll()
+{
+	let oid = |text: &str| {parse_oid(text)};
+	let table = ~[
+		VarBind {oid: oid("1.3.6.1.2.1.1.1.0"), value: OctetString(str::to_bytes("Linux RTR-4 2.6.39.4"))},
+		VarBind {oid: oid("1.3.6.1.2.1.1.3.0"), value: TimeTicks(3000)},
+		VarBind {oid: oid("1.3.6.1.2.1.1.4.0"), value: OctetString(str::to_bytes("support@blargh.com"))},
+		VarBind {oid: oid("1.3.6.1.2.1.1.6.0"), value: OctetString(str::to_bytes("closet"))},
+		VarBind {oid: oid("1.3.6.1.2.1.4.1.0"), value: Integer(1)},
+		
+		VarBind {oid: oid("1.3.6.1.2.1.2.2.1.2.1"), value: OctetString(str::to_bytes("lo"))},
+		VarBind {oid: oid("1.3.6.1.2.1.2.2.1.2.2"), value: OctetString(str::to_bytes("Fa0/1"))},
+		VarBind {oid: oid("1.3.6.1.2.1.2.2.1.4.1"), value: Integer(16436)},
+		VarBind {oid: oid("1.3.6.1.2.1.2.2.1.4.2"), value: Integer(1500)},
+		VarBind {oid: oid("1.3.6.1.2.1.2.2.1.5.2"), value: Gauge32(10000000)},
+		VarBind {oid: oid("1.3.6.1.2.1.2.2.1.6.2"), value: OctetString(~[0xc2, 0x25, 0xa1, 0xa0, 0x30, 0x9b])},
+		VarBind {oid: oid("1.3.6.1.2.1.2.2.1.7.1"), value: Integer(1)},
+		VarBind {oid: oid("1.3.6.1.2.1.2.2.1.7.2"), value: Integer(1)},
+		VarBind {oid: oid("1.3.6.1.2.1.2.2.1.8.1"), value: Integer(1)},
+		VarBind {oid: oid("1.3.6.1.2.1.2.2.1.8.2"), value: Integer(2)},
+		VarBind {oid: oid("1.3.6.1.2.1.2.2.1.10.1"), value: Counter32(9840)},
+		VarBind {oid: oid("1.3.6.1.2.1.2.2.1.16.1"), value: Counter32(9840)},
+		
+		VarBind {oid: oid("1.3.6.1.2.1.4.20.1.2.127.0.0.1"), value: Integer(1)},
+		VarBind {oid: oid("1.3.6.1.2.1.4.20.1.3.127.0.0.1"), value: IpAddress(~[255, 0, 0, 0])},
+		
+		VarBind {oid: oid("1.3.6.1.2.1.4.21.1.2.10.0.4.0"), value: Integer(2)},
+		VarBind {oid: oid("1.3.6.1.2.1.4.21.1.3.10.0.4.0"), value: Integer(0)},
+		VarBind {oid: oid("1.3.6.1.2.1.4.21.1.7.10.0.4.0"), value: IpAddress(~[0, 0, 0, 0])},
+		VarBind {oid: oid("1.3.6.1.2.1.4.21.1.9.10.0.4.0"), value: Integer(2)},
+		VarBind {oid: oid("1.3.6.1.2.1.4.21.1.11.10.0.4.0"), value: IpAddress(~[255, 255, 255, 0])},
+	];
+	let port = 16162;
+	spawn_agent(port, Community(~"public"), table);
+	
+	let device = Device {name: ~"RTR", managed_ip: ~"127.0.0.1", modeler: ~"snmp", modeler_type: options::Builtin, snmp_security: Community(~"public"), snmp_port: port};
+	let json = result::unwrap(get_update(&device, &counters::Counters(), 2)).to_str();
+	assert json.contains("\"modeler\":\"snmp RTR\"");
+	assert json.contains("uptime: 30 seconds");
+	assert json.contains("Device rebooted.");
+	assert json.contains("* location is closet");
+	assert json.contains("ip forwarding is on");
+	assert json.contains("127.0.0.1-lo-in_octets");
+	assert json.contains("127.0.0.1/8");
+	assert json.contains("10.0.4.0/24");
+	assert json.contains("Admin set Fa0-1 to up but it is down.");
+	assert !json.contains("Device is down.");
+	
+	let device = Device {snmp_port: port + 1, ..device};
+	let json = result::unwrap(get_update(&device, &counters::Counters(), 1)).to_str();
+	assert json.contains("Device is down.");
+}
+
+#[test]
+fn test_mask_to_subnet()
+{
+	assert mask_to_subnet("255.255.255.0") == ~"24";
+	assert mask_to_subnet("255.255.255.255") == ~"32";
+	assert mask_to_subnet("0.0.0.0") == ~"0";
+	assert mask_to_subnet("255.0.255.0") == ~"255.0.255.0";
+}
@@ -0,0 +1,80 @@
+/// Accumulates SVG elements used to render charts.
+use chart::{Color, Anchor, Start, Middle, End, Surface, char_height};
+
+pub struct Svg
+{
+	pub width: uint,
+	pub height: uint,
+	priv mut body: ~str,
+}
+
+pub fn Svg(width: uint, height: uint) -> Svg
+{
+	Svg {width: width, height: height, body: ~""}
+}
+
+pub impl Svg : ToStr
+{
+	pure fn to_str() -> ~str
+	{
+		let header = fmt!("<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"%?\" height=\"%?\" viewBox=\"0 0 %? %?\">\n", self.width, self.height, self.width, self.height);
+		header + self.body + "</svg>\n"
+	}
+}
+
+pub impl Svg : Surface
+{
+	fn fill_rect(x: int, y: int, width: int, height: int, color: Color)
+	{
+		self.body += fmt!("<rect x=\"%?\" y=\"%?\" width=\"%?\" height=\"%?\" fill=\"%s\"/>\n", x, y, width, height, color.to_str());
+	}
+	
+	fn line(x0: int, y0: int, x1: int, y1: int, color: Color, thickness: uint)
+	{
+		self.body += fmt!("<line x1=\"%?\" y1=\"%?\" x2=\"%?\" y2=\"%?\" stroke=\"%s\" stroke-width=\"%?\" stroke-linecap=\"round\"/>\n", x0, y0, x1, y1, color.to_str(), thickness);
+	}
+	
+	// Monospace at this size has about the same metrics as the canvas font.
+	fn text(x: int, y: int, text: &str, color: Color, anchor: Anchor)
+	{
+		let anchor = match anchor
+		{
+			Start => "start",
+			Middle => "middle",
+			End => "end",
+		};
+		self.body += fmt!("<text x=\"%?\" y=\"%?\" font-family=\"monospace\" font-size=\"10\" text-anchor=\"%s\" fill=\"%s\">%s</text>\n", x, y + char_height, anchor, color.to_str(), escape(text));
+	}
+}
+
+// ---- Internal Functions ----------------------------------------------------
+priv fn escape(text: &str) -> ~str
+{
+	let mut result = ~"";
+	for str::each_char(text) |ch|
+	{
+		match ch
+		{
+			'&' => result += "&amp;",
+			'<' => result += "&lt;",
+			'>' => result += "&gt;",
+			'"' => result += "&quot;",
+			_ => str::push_char(&mut result, ch),
+		}
+	}
+	result
+}
+
+#[test]
+fn test_svg()
+{
+	let svg = Svg(20, 10);
+	svg.line(0, 0, 10, 5, Color {r: 255, g: 0, b: 0}, 2);
+	svg.text(0, 0, "a<b", Color {r: 0, g: 0, b: 0}, Middle);
+	
+	let text = svg.to_str();
+	assert text.starts_with("<svg ");
+	assert text.contains("stroke=\"#ff0000\"");
+	assert text.contains(">a&lt;b</text>");
+	assert text.ends_with("</svg>\n");
+}
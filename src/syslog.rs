@@ -0,0 +1,766 @@
+/// Receives syslog messages (RFC 3164 and RFC 5424) from the devices in the
+/// network json file and keeps the most recent messages for each device.
+///
+/// The listener is enabled by adding a syslog-port to the network json file.
+/// Messages are received over UDP and, if syslog-tcp is true, over TCP using
+/// either octet counting or newline framing (RFC 6587). Messages are
+/// attributed to devices using the source address (tcp connections from
+/// unknown addresses are closed) and are published as a
+/// Syslog details table on the device's entity. The full log can be searched
+/// using /syslog/{ip} (see get_syslog).
+///
+/// Admins can open and close alerts using a syslog-rules list in the network
+/// json file, e.g.
+/// {"pattern": "(?i)OSPF.*neighbor.*down", "open": true, "alert": "ospf", "level": "error", "mesg": "{mesg}"}
+/// {"pattern": "(?i)OSPF.*neighbor.*full", "open": false, "alert": "ospf"}
+/// Patterns are matched against the message (see regex.rs for the syntax) and
+/// the first matching rule wins. Within alert, mesg, and resolution "{app}" is
+/// replaced with the name of the application that sent the message and
+/// "{mesg}" with the message.
+use std::json::{Json};
+use std::map::*;
+use std::net::ip;
+use std::net::tcp;
+use handlers::put_json::{SamplesChan};
+use options::{Options, Device};
+use regex::{Regex};
+use ring_buffer::{RingBuffer};
+use task_runner::{ExitFn, JobQueue};
+
+/// A message received from a device.
+pub struct Entry
+{
+	pub received: float,		// secs since the epoch
+	pub facility: uint,			// e.g. 3 for daemon
+	pub severity: uint,			// 0 (emerg) to 7 (debug)
+	pub timestamp: ~str,		// as sent by the device (may be empty)
+	pub host: ~str,				// as sent by the device (may be empty)
+	pub app: ~str,				// tag or app-name (may be empty)
+	pub mesg: ~str,
+}
+
+pub struct SyslogRule
+{
+	pub pattern: Regex,
+	pub open: bool,				// if false the alert is closed
+	pub alert: ~str,			// alert id
+	pub level: ~str,			// "error", "warning", or "info"
+	pub mesg: ~str,
+	pub resolution: ~str,
+}
+
+/// Holds the most recent messages from each device.
+pub struct SyslogLogs
+{
+	priv chan: oldcomm::Chan<LogMsg>,
+}
+
+pub fn SyslogLogs(options: &Options, state_chan: oldcomm::Chan<model::Msg>, samples_chan: SamplesChan, render_queue: JobQueue) -> SyslogLogs
+{
+	let options = copy *options;
+	let chan = do utils::spawn_moded_listener(task::ThreadPerCore) |port, move options| {manage_logs(port, &options, state_chan, samples_chan, render_queue)};
+	SyslogLogs {chan: chan}
+}
+
+pub impl SyslogLogs
+{
+	/// Records a message received from ip. Messages from unknown addresses are
+	/// dropped.
+	fn add(ip: &str, text: &str)
+	{
+		oldcomm::send(self.chan, EntryMsg(ip.to_owned(), text.to_owned()));
+	}
+	
+	/// Publishes details for the devices which have sent messages since the
+	/// last publish.
+	fn publish()
+	{
+		oldcomm::send(self.chan, PublishMsg);
+	}
+	
+	/// Returns up to limit messages from ip, newest first. If pattern is set
+	/// then only messages whose app or mesg matches are returned. Patterns which
+	/// take too long to match return an error.
+	fn search(ip: &str, pattern: option::Option<Regex>, limit: uint) -> result::Result<~[Entry], ~str>
+	{
+		let port = oldcomm::Port();
+		let chan = oldcomm::Chan(&port);
+		oldcomm::send(self.chan, SearchMsg(ip.to_owned(), pattern, limit, chan));
+		oldcomm::recv(port)
+	}
+}
+
+pub pure fn facility_to_str(facility: uint) -> ~str
+{
+	if facility < facility_names.len() {facility_names[facility].to_owned()} else {fmt!("%?", facility)}
+}
+
+pub pure fn severity_to_str(severity: uint) -> ~str
+{
+	if severity < severity_names.len() {severity_names[severity].to_owned()} else {fmt!("%?", severity)}
+}
+
+/// Parses RFC 5424 and RFC 3164 messages. Messages which don't follow either
+/// RFC are treated as user.notice messages with no header (as RFC 3164 says
+/// relays should).
+pub fn parse_syslog(text: &str, received: float) -> Entry
+{
+	let mut entry = Entry {received: received, facility: 1, severity: 5, timestamp: ~"", host: ~"", app: ~"", mesg: ~""};
+	let mut rest = str::trim_right(text);
+	
+	if rest.starts_with("<")
+	{
+		match str::find_char(rest, '>')
+		{
+			option::Some(i) if i >= 2 && i <= 4 =>
+			{
+				match uint::from_str(str::slice(rest, 1, i))
+				{
+					option::Some(priority) if priority < 192 =>
+					{
+						entry.facility = priority/8;
+						entry.severity = priority%8;
+						rest = str::slice(rest, i + 1, rest.len());
+					}
+					_ => {}
+				}
+			}
+			_ => {}
+		}
+	}
+	
+	if rest.starts_with("1 ")
+	{
+		parse_rfc5424(&mut entry, str::slice(rest, 2, rest.len()));
+	}
+	else
+	{
+		parse_rfc3164(&mut entry, rest);
+	}
+	entry
+}
+
+/// Spawns tasks to listen for syslog messages on options.syslog_port. Returns
+/// a function which stops the listeners.
+pub fn start_syslog_listener(options: &Options, logs: SyslogLogs, state_chan: oldcomm::Chan<model::Msg>) -> ExitFn
+{
+	let stop_udp = start_udp_listener(options.syslog_port, logs, state_chan);
+	if options.syslog_tcp
+	{
+		let ips = options.devices.map(|d| {copy d.managed_ip});
+		let stop_tcp = start_tcp_listener(options.syslog_port, ips, logs, state_chan);
+		|| {stop_udp(); stop_tcp()}
+	}
+	else
+	{
+		stop_udp
+	}
+}
+
+// ---- Internal Types --------------------------------------------------------
+priv enum LogMsg
+{
+	EntryMsg(~str, ~str),												// source ip + message
+	PublishMsg,
+	SearchMsg(~str, option::Option<Regex>, uint, oldcomm::Chan<result::Result<~[Entry], ~str>>),	// ip + pattern + limit + reply
+}
+
+priv const facility_names: [&str * 24] = ["kern", "user", "mail", "daemon", "auth", "syslog", "lpr", "news", "uucp", "cron", "authpriv", "ftp", "ntp", "security", "console", "cron2", "local0", "local1", "local2", "local3", "local4", "local5", "local6", "local7"];
+priv const severity_names: [&str * 8] = ["emerg", "alert", "crit", "err", "warning", "notice", "info", "debug"];
+priv const month_names: [&str * 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+
+// Number of messages retained for each device.
+priv const log_capacity: uint = 500;
+
+// Number of messages shown in the details table.
+priv const details_count: uint = 20;
+
+// How often (in ms) the udp listener checks to see if it should stop.
+priv const poll_interval: uint = 1000;
+
+// Tcp connections which send frames larger than this (in bytes) are dropped.
+priv const max_frame_len: uint = 65536;
+
+// Searches come from unauthenticated clients so matching each field of an
+// entry is limited to this many regex steps.
+priv const search_steps: uint = 10000;
+
+// ---- Internal Functions ----------------------------------------------------
+priv fn manage_logs(port: oldcomm::Port<LogMsg>, options: &Options, state_chan: oldcomm::Chan<model::Msg>, samples_chan: SamplesChan, render_queue: JobQueue)
+{
+	let logs: HashMap<~str, @RingBuffer<Entry>> = HashMap();
+	let mut changed = ~[];
+	loop
+	{
+		match oldcomm::recv(port)
+		{
+			EntryMsg(ip, text) =>
+			{
+				match options.devices.find(|d| {d.managed_ip == ip})
+				{
+					option::Some(ref device) =>
+					{
+						let entry = parse_syslog(text, utils::imprecise_time_s());
+						if !logs.contains_key(copy ip)
+						{
+							logs.insert(copy ip, @RingBuffer(log_capacity));
+						}
+						logs.get(copy ip).push(copy entry);
+						apply_rules(device, &entry, options.syslog_rules, state_chan);
+						
+						if !changed.contains(&ip)
+						{
+							changed.push(ip);
+						}
+					}
+					option::None =>
+					{
+						debug!("ignoring syslog message from unknown device %s", ip);
+					}
+				}
+			}
+			PublishMsg =>
+			{
+				for changed.each |ip|
+				{
+					let update = get_update(*ip, logs.get(copy *ip));
+					handlers::put_json::put_update(options, state_chan, samples_chan, render_queue, ~"syslog " + *ip, update);
+				}
+				changed = ~[];
+			}
+			SearchMsg(ip, pattern, limit, reply) =>
+			{
+				oldcomm::send(reply, search_log(logs.find(ip), &pattern, limit));
+			}
+		}
+	}
+}
+
+priv fn search_log(log: option::Option<@RingBuffer<Entry>>, pattern: &option::Option<Regex>, limit: uint) -> result::Result<~[Entry], ~str>
+{
+	let mut entries = ~[];
+	match log
+	{
+		option::Some(log) =>
+		{
+			let mut i = log.len();
+			while i > 0 && entries.len() < limit
+			{
+				i -= 1;
+				let entry = log[i];
+				let matched = match *pattern
+					{
+						option::Some(ref re) =>
+						{
+							match re.is_match_within(entry.app, search_steps)
+							{
+								option::Some(false) => re.is_match_within(entry.mesg, search_steps),
+								x => x,
+							}
+						}
+						option::None => option::Some(true),
+					};
+				match matched
+				{
+					option::Some(true) => entries.push(entry),
+					option::Some(false) => {}
+					option::None => return result::Err(~"search took too long to match"),
+				}
+			}
+		}
+		option::None => {}
+	}
+	result::Ok(entries)
+}
+
+priv fn apply_rules(device: &Device, entry: &Entry, rules: &[SyslogRule], state_chan: oldcomm::Chan<model::Msg>)
+{
+	match rules.find(|r| {r.pattern.is_match(entry.mesg)})
+	{
+		option::Some(ref rule) =>
+		{
+			let target = ~"entities:" + device.managed_ip;
+			let id = expand(rule.alert, entry);
+			info!("syslog message from %s %s alert %s", device.name, if rule.open {"opened"} else {"closed"}, id);
+			if rule.open
+			{
+				let alert = model::Alert {target: target, id: id, level: copy rule.level, mesg: expand(rule.mesg, entry), resolution: expand(rule.resolution, entry)};
+				oldcomm::send(state_chan, model::UpdateMsg(~"primary", |store, _err| {model::open_alert(store, &alert)}, ~""));
+			}
+			else
+			{
+				oldcomm::send(state_chan, model::UpdateMsg(~"primary", |store, _err, copy target, copy id| {model::close_alert(store, target, id)}, ~""));
+			}
+		}
+		option::None => {}
+	}
+}
+
+priv fn expand(text: &str, entry: &Entry) -> ~str
+{
+	str::replace(str::replace(text, "{app}", entry.app), "{mesg}", entry.mesg)
+}
+
+priv fn get_update(ip: &str, log: @RingBuffer<Entry>) -> Json
+{
+	let mut rows = ~[];
+	let mut i = log.len();
+	while i > 0 && rows.len() < details_count
+	{
+		i -= 1;
+		let entry = log[i];
+		let time = std::time::at(std::time::Timespec::new(entry.received as i64, 0)).strftime("%b %e %H:%M:%S");
+		let severity = if entry.severity <= 3 {fmt!("<strong>%s</strong>", severity_to_str(entry.severity))} else {severity_to_str(entry.severity)};
+		rows.push(std::json::List(~[std::json::String(time), std::json::String(severity), std::json::String(utils::escape_html(entry.app)), std::json::String(utils::escape_html(entry.mesg))]));
+	}
+	
+	let table = utils::to_object(~[
+		(~"style", std::json::String(~"html")),
+		(~"header", std::json::List(~[std::json::String(~"Received"), std::json::String(~"Severity"), std::json::String(~"App"), std::json::String(~"Message")])),
+		(~"rows", std::json::List(rows)),
+	]);
+	let footnote = fmt!("*The newest %? messages can be searched using [/syslog/%s](/syslog/%s?search=).*", log_capacity, ip, ip);
+	let details = utils::to_object(~[
+		(~"entity-id", std::json::String(~"entities:" + ip)),
+		(~"label", std::json::String(~"Syslog")),
+		(~"details", std::json::String(std::json::List(~[table, std::json::String(footnote)]).to_str())),
+		(~"open", std::json::String(~"no")),
+		(~"sort-key", std::json::String(~"gamma")),
+		(~"id", std::json::String(~"syslog")),
+	]);
+	
+	utils::to_object(~[
+		(~"modeler", std::json::String(~"syslog " + ip)),
+		(~"details", std::json::List(~[details])),
+	])
+}
+
+// <PRI>1 TIMESTAMP HOSTNAME APP-NAME PROCID MSGID STRUCTURED-DATA [MSG]
+priv fn parse_rfc5424(entry: &mut Entry, text: &str)
+{
+	let mut rest = text.to_owned();
+	let mut fields = ~[];
+	for 5.times
+	{
+		let (field, tail) = split_token(rest);
+		fields.push(if field == ~"-" {~""} else {field});
+		rest = tail;
+	}
+	entry.timestamp = copy fields[0];
+	entry.host = copy fields[1];
+	entry.app = copy fields[2];
+	
+	rest = skip_structured_data(rest);
+	if rest.starts_with("\uFEFF")
+	{
+		rest = str::slice(rest, 3, rest.len());
+	}
+	entry.mesg = rest;
+}
+
+// <PRI>Mmm dd hh:mm:ss HOSTNAME TAG[PID]: MSG
+priv fn parse_rfc3164(entry: &mut Entry, text: &str)
+{
+	// Note that offsets are in bytes so we only slice at the ascii characters we
+	// have checked for.
+	let mut rest = text.to_owned();
+	if rest.len() > 16 && rest[15] == ' ' as u8 && rest[9] == ':' as u8 && rest[12] == ':' as u8 && month_names.any(|m| {rest.starts_with(*m)})
+	{
+		entry.timestamp = str::slice(rest, 0, 15);
+		let (host, tail) = split_token(str::slice(rest, 16, rest.len()));
+		entry.host = host;
+		rest = tail;
+	}
+	
+	// The tag is alphanumeric (in practice it may include things like - and /)
+	// and is terminated by [ or :.
+	let (token, tail) = split_token(rest);
+	if token.len() > 1 && token[0] < 0x80 && char::is_alphabetic(token[0] as char) && token.ends_with(":")
+	{
+		let tag = str::slice(token, 0, token.len() - 1);
+		entry.app = match str::find_char(tag, '[') {option::Some(i) => str::slice(tag, 0, i), option::None => tag};
+		entry.mesg = tail;
+	}
+	else
+	{
+		entry.mesg = rest;
+	}
+}
+
+priv fn split_token(text: &str) -> (~str, ~str)
+{
+	match str::find_char(text, ' ')
+	{
+		option::Some(i) => (str::slice(text, 0, i), str::slice(text, i + 1, text.len())),
+		option::None => (text.to_owned(), ~""),
+	}
+}
+
+// Structured data is either - or one or more [id param="value"...] elements
+// where values may contain escaped quotes and brackets.
+priv fn skip_structured_data(text: &str) -> ~str
+{
+	let mut i = 0;
+	if text.starts_with("-")
+	{
+		i = 1;
+	}
+	else
+	{
+		let mut in_quotes = false;
+		while i < text.len()
+		{
+			let ch = text[i] as char;
+			if ch == '\\' && in_quotes
+			{
+				i += 1;
+			}
+			else if ch == '"'
+			{
+				in_quotes = !in_quotes;
+			}
+			else if ch == ']' && !in_quotes && (i + 1 == text.len() || text[i + 1] != '[' as u8)
+			{
+				i += 1;
+				break;
+			}
+			i += 1;
+		}
+	}
+	
+	if i < text.len() && text[i] == ' ' as u8
+	{
+		i += 1;
+	}
+	str::slice(text, uint::min(i, text.len()), text.len())
+}
+
+priv fn start_udp_listener(port: u16, logs: SyslogLogs, state_chan: oldcomm::Chan<model::Msg>) -> ExitFn
+{
+	let stop_chan = do utils::spawn_moded_listener(task::SingleThreaded) |stop|
+	{
+		match snmp::UdpSocket(port)
+		{
+			result::Ok(socket) =>
+			{
+				info!("listening for syslog messages on udp port %?", port);
+				let mut last_publish = utils::imprecise_time_s();
+				while !stop.peek()
+				{
+					match socket.recv_from(poll_interval)
+					{
+						option::Some((ref data, ref ip, _port)) => logs.add(*ip, utils::bytes_to_str(*data)),
+						option::None => {}
+					}
+					
+					let now = utils::imprecise_time_s();
+					if now - last_publish >= 1.0
+					{
+						logs.publish();
+						last_publish = now;
+					}
+				}
+				socket.close();
+			}
+			result::Err(ref err) =>
+			{
+				listener_failed(fmt!("Couldn't listen for syslog messages on udp port %?: %s.", port, *err), state_chan);
+			}
+		}
+	};
+	|| {oldcomm::send(stop_chan, ())}
+}
+
+// Note that messages received over tcp are published by the udp listener.
+priv fn start_tcp_listener(port: u16, ips: ~[~str], logs: SyslogLogs, state_chan: oldcomm::Chan<model::Msg>) -> ExitFn
+{
+	let kill_port = oldcomm::Port();
+	let kill_chan = oldcomm::Chan(&kill_port);
+	do task::spawn_sched(task::SingleThreaded) |move ips|
+	{
+		let result = tcp::listen(ip::v4::parse_addr("0.0.0.0"), port as uint, 32, std::uv::global_loop::get(),
+			|kill| {oldcomm::send(kill_chan, option::Some(kill))},
+			|connection, _kill, copy ips| {accept_connection(connection, copy ips, logs)});
+		match result
+		{
+			result::Ok(()) => {}
+			result::Err(ref err) =>
+			{
+				listener_failed(fmt!("Couldn't listen for syslog messages on tcp port %?: %?.", port, err), state_chan);
+				oldcomm::send(kill_chan, option::None);
+			}
+		}
+	}
+	
+	match oldcomm::recv(kill_port)
+	{
+		option::Some(kill) =>
+		{
+			info!("listening for syslog messages on tcp port %?", port);
+			|| {oldcomm::send(kill, option::None)}
+		}
+		option::None =>
+		{
+			|| {}
+		}
+	}
+}
+
+// The connection has to be accepted before the new connection callback returns
+// but reading has to happen in a different task.
+priv fn accept_connection(connection: tcp::TcpNewConnection, ips: ~[~str], logs: SyslogLogs)
+{
+	let port = oldcomm::Port();
+	let chan = oldcomm::Chan(&port);
+	do task::spawn_sched(task::SingleThreaded) |move connection, move ips|
+	{
+		match tcp::accept(connection)
+		{
+			result::Ok(ref socket) =>
+			{
+				oldcomm::send(chan, ());
+				read_connection(socket, ips, logs);
+			}
+			result::Err(ref err) =>
+			{
+				oldcomm::send(chan, ());
+				info!("failed to accept syslog connection: %s", err.err_msg);
+			}
+		}
+	}
+	oldcomm::recv(port);
+}
+
+// The socket is closed when the accepting task exits.
+priv fn read_connection(socket: &tcp::TcpSocket, ips: &[~str], logs: SyslogLogs)
+{
+	let ip = ip::format_addr(&socket.get_peer_addr());
+	if !ips.contains(&ip)
+	{
+		info!("closing syslog connection from unknown device %s", ip);
+		return;
+	}
+	
+	let mut buffer = ~[];
+	loop
+	{
+		match tcp::read(socket, 0)
+		{
+			result::Ok(ref data) =>
+			{
+				vec::push_all(&mut buffer, *data);
+				match split_frames(&mut buffer)
+				{
+					result::Ok(ref frames) =>
+					{
+						for frames.each |frame|
+						{
+							logs.add(ip, *frame);
+						}
+					}
+					result::Err(ref err) =>
+					{
+						info!("dropping syslog connection from %s: %s", ip, *err);
+						break;
+					}
+				}
+			}
+			result::Err(_) =>
+			{
+				break;			// normally EOF
+			}
+		}
+	}
+}
+
+// Removes complete frames from buffer. Frames are either octet counted
+// ("LEN MSG") or terminated by a newline or NUL. Returns an error if a frame is
+// larger than max_frame_len.
+priv fn split_frames(buffer: &mut ~[u8]) -> result::Result<~[~str], ~str>
+{
+	let mut frames = ~[];
+	loop
+	{
+		let digits = vec::position(*buffer, |b| {!char::is_digit(*b as char)}).get_default(buffer.len());
+		if digits > 0 && digits < buffer.len() && buffer[digits] == ' ' as u8
+		{
+			let count = match uint::from_str(str::from_bytes(vec::slice(*buffer, 0, digits)))
+				{
+					option::Some(n) if n <= max_frame_len => n,
+					_ => return result::Err(fmt!("octet count is larger than %? bytes", max_frame_len)),
+				};
+			let end = digits + 1 + count;
+			if end > buffer.len()
+			{
+				break;
+			}
+			frames.push(utils::bytes_to_str(vec::view(*buffer, digits + 1, end)));
+			*buffer = vec::slice(*buffer, end, buffer.len());
+		}
+		else if digits == buffer.len() && digits < 10
+		{
+			break;				// may be the start of an octet count
+		}
+		else
+		{
+			match vec::position(*buffer, |b| {*b == '\n' as u8 || *b == 0})
+			{
+				option::Some(i) =>
+				{
+					let frame = str::trim_right(utils::bytes_to_str(vec::view(*buffer, 0, i)));
+					if frame.is_not_empty()
+					{
+						frames.push(frame);
+					}
+					*buffer = vec::slice(*buffer, i + 1, buffer.len());
+				}
+				option::None if buffer.len() > max_frame_len =>
+				{
+					return result::Err(fmt!("frame is larger than %? bytes", max_frame_len));
+				}
+				option::None =>
+				{
+					break;
+				}
+			}
+		}
+	}
+	result::Ok(frames)
+}
+
+priv fn listener_failed(mesg: ~str, state_chan: oldcomm::Chan<model::Msg>)
+{
+	error!("%s", mesg);
+	let alert = model::Alert {target: ~"gnos:container", id: ~"syslog listener", level: ~"error", mesg: mesg, resolution: ~"Use a different syslog-port or run gnos with permission to bind to the port."};
+	oldcomm::send(state_chan, model::UpdateMsg(~"primary", |store, _err| {model::open_alert(store, &alert)}, ~""));
+}
+
+#[test]
+fn test_rfc3164()
+{
+	let entry = parse_syslog("<34>Oct 11 22:14:15 mymachine su: 'su root' failed for lonvick on /dev/pts/8\n", 0.0);
+	assert entry.facility == 4;
+	assert entry.severity == 2;
+	assert entry.timestamp == ~"Oct 11 22:14:15";
+	assert entry.host == ~"mymachine";
+	assert entry.app == ~"su";
+	assert entry.mesg == ~"'su root' failed for lonvick on /dev/pts/8";
+	
+	let entry = parse_syslog("<29>Mar  1 09:02:03 rtr-4 ospfd[1234]: OSPF neighbor 10.0.0.2 down", 0.0);
+	assert facility_to_str(entry.facility) == ~"daemon";
+	assert severity_to_str(entry.severity) == ~"notice";
+	assert entry.timestamp == ~"Mar  1 09:02:03";
+	assert entry.host == ~"rtr-4";
+	assert entry.app == ~"ospfd";
+	assert entry.mesg == ~"OSPF neighbor 10.0.0.2 down";
+	
+	// Cisco style (no valid header)
+	let entry = parse_syslog("<189>123: *Mar  1 00:01:02.345: %LINK-3-UPDOWN: Interface Fa0/1, changed state to down", 0.0);
+	assert entry.facility == 23;
+	assert entry.severity == 5;
+	assert entry.timestamp == ~"";
+	assert entry.app == ~"";
+	assert entry.mesg == ~"123: *Mar  1 00:01:02.345: %LINK-3-UPDOWN: Interface Fa0/1, changed state to down";
+	
+	let entry = parse_syslog("no priority", 0.0);
+	assert entry.facility == 1;
+	assert entry.severity == 5;
+	assert entry.mesg == ~"no priority";
+}
+
+#[test]
+fn test_rfc5424()
+{
+	let entry = parse_syslog("<165>1 2003-10-11T22:14:15.003Z mymachine.example.com evntslog - ID47 [exampleSDID@32473 iut=\"3\" eventSource=\"App\\]\"][examplePriority@32473 class=\"high\"] An application event", 0.0);
+	assert entry.facility == 20;
+	assert entry.severity == 5;
+	assert entry.timestamp == ~"2003-10-11T22:14:15.003Z";
+	assert entry.host == ~"mymachine.example.com";
+	assert entry.app == ~"evntslog";
+	assert entry.mesg == ~"An application event";
+	
+	let entry = parse_syslog("<34>1 2003-10-11T22:14:15.003Z - su - ID47 - 'su root' failed", 0.0);
+	assert entry.host == ~"";
+	assert entry.app == ~"su";
+	assert entry.mesg == ~"'su root' failed";
+	
+	let entry = parse_syslog("<34>1 2003-10-11T22:14:15.003Z host su - - -", 0.0);
+	assert entry.mesg == ~"";
+}
+
+#[test]
+fn test_split_frames()
+{
+	let mut buffer = str::to_bytes("5 hello11 hello world\nfirst line\nsecond");
+	let frames = split_frames(&mut buffer).get();
+	assert frames == ~[~"hello", ~"hello world", ~"first line"];
+	assert buffer == str::to_bytes("second");
+	
+	let mut buffer = str::to_bytes("12");
+	assert split_frames(&mut buffer).get().is_empty();
+	assert buffer.len() == 2;
+	
+	let mut buffer = str::to_bytes("123: cisco message\n");
+	assert split_frames(&mut buffer).get() == ~[~"123: cisco message"];
+	assert buffer.is_empty();
+	
+	// frames larger than max_frame_len are errors
+	let mut buffer = str::to_bytes("65537 too long");
+	assert split_frames(&mut buffer).is_err();
+	
+	let mut buffer = str::to_bytes("99999999999999999999999 too long");
+	assert split_frames(&mut buffer).is_err();
+	
+	let mut buffer = vec::from_elem(max_frame_len + 1, 'x' as u8);
+	assert split_frames(&mut buffer).is_err();
+	
+	let mut buffer = vec::from_elem(max_frame_len, 'x' as u8);
+	assert split_frames(&mut buffer).get().is_empty();
+}
+
+#[test]
+fn test_non_ascii()
+{
+	// Ü and ß are two bytes each so the colons and space are where a timestamp's
+	// would be but byte 3 is within ß
+	let entry = parse_syslog("<34>Üß 1 22:14:15 mymachine su: failed", 0.0);
+	assert entry.timestamp == ~"";
+	assert entry.app == ~"";
+	assert entry.mesg == ~"Üß 1 22:14:15 mymachine su: failed";
+	
+	let entry = parse_syslog("<34>Oct 11 22:14:15 mäschine sü: 'sü root' fällt", 0.0);
+	assert entry.timestamp == ~"Oct 11 22:14:15";
+	assert entry.host == ~"mäschine";
+	assert entry.app == ~"sü";
+	assert entry.mesg == ~"'sü root' fällt";
+	
+	let entry = parse_syslog("<34>Öct 11 22:14:15 host", 0.0);
+	assert entry.mesg == ~"Öct 11 22:14:15 host";
+	
+	let entry = parse_syslog("<34>ünknown: message", 0.0);
+	assert entry.app == ~"";
+	assert entry.mesg == ~"ünknown: message";
+}
+
+#[test]
+fn test_search_log()
+{
+	let log = @RingBuffer(log_capacity);
+	log.push(parse_syslog("<29>Mar  1 09:02:03 rtr-4 ospfd[1234]: OSPF neighbor 10.0.0.2 down", 0.0));
+	log.push(parse_syslog("<29>Mar  1 09:02:04 rtr-4 bgpd: peer up", 0.0));
+	log.push(parse_syslog("<29>Mar  1 09:02:05 rtr-4 ospfd: aaaaaaaaaaaaaaaaaaaaaaaaaaaaac", 0.0));
+	
+	let entries = search_log(option::Some(log), &option::None, 100).get();
+	assert entries.len() == 3;
+	assert entries[0].mesg == ~"aaaaaaaaaaaaaaaaaaaaaaaaaaaaac";
+	
+	let entries = search_log(option::Some(log), &option::Some(Regex("bgp").get()), 100).get();
+	assert entries.len() == 1;
+	assert entries[0].app == ~"bgpd";
+	
+	assert search_log(option::Some(log), &option::None, 1).get().len() == 1;
+	assert search_log(option::None, &option::None, 100).get().is_empty();
+	
+	// patterns which backtrack too much are errors
+	assert search_log(option::Some(log), &option::Some(Regex("^(a*)*b$").get()), 100).is_err();
+}
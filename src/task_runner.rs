@@ -1,15 +1,36 @@
+use std::map::{HashMap};
+
 /// Actions to take after a job finishes.
 ///
 /// * IgnoreFailures - do nothing.
 /// * NotifyOnFailure - call a function with the error.
 /// * NotifyOnExit - call a function with the error or option::none.
 /// * ShutdownOnFailure - call cleanup actions and then call exit.
+/// * RestartWithBackoff - re-run the job after failures (see RestartPolicy).
 pub enum FailurePolicy
 {
 	IgnoreFailures,
 	NotifyOnFailure(fn~ (~str)),
 	NotifyOnExit(fn~ (option::Option<~str>)),
 	ShutdownOnFailure,
+	RestartWithBackoff(RestartPolicy),
+}
+
+/// Jobs which fail are re-run after a delay which starts at one second and
+/// doubles with each restart within window (up to about a minute). If the job
+/// fails after max_restarts restarts within window seconds then we give up on
+/// it. Notify is called after each failure.
+pub struct RestartPolicy
+{
+	pub max_restarts: uint,
+	pub window: float,
+	pub notify: fn~ (&RestartEvent),
+}
+
+pub enum RestartEvent
+{
+	Restarting(uint, ~str),		// number of restarts (including this one) + error
+	GaveUp(uint, ~str),			// number of restarts + error
 }
 
 /// A pointer to a function to call when the server shuts down.
@@ -20,47 +41,172 @@ pub type ExitFn = fn~ () -> ();
 /// Returns a message on errors.
 pub type JobFn = fn~ () -> option::Option<~str>;
 
+/// If timeout is set and the action runs for longer than timeout seconds then
+/// the child processes started by the action (see utils::run_command) are
+/// killed and the action fails with a "timed out" error. Note that actions
+/// which block without starting a process cannot be interrupted.
 pub struct Job
 {
+	pub name: ~str,							// used in error messages
 	pub action: JobFn,
 	pub policy: FailurePolicy,
+	pub timeout: option::Option<float>,
 }
 
-/// Run the job within the current task.
-pub fn run_blocking(job: Job, cleanup: ~[ExitFn])
+/// Returned when a job is started in the background. Cancelling a job kills
+/// any child processes it is running and keeps it from being restarted. The
+/// job's policy is given a "cancelled" error (but ShutdownOnFailure jobs will
+/// not shutdown the server).
+pub struct JobHandle
 {
-	do_run(&job, cleanup);
+	priv watchdog: Watchdog,
 }
 
-/// Run the job within a task.
-pub fn run(job: Job, cleanup: ~[ExitFn])
+pub impl JobHandle
 {
-	// These guys can block for arbitrary amounts of time so they need their own thread.
-	do task::spawn_sched(task::SingleThreaded)
+	fn cancel()
+	{
+		self.watchdog.cancel();
+	}
+	
+	/// Like cancel except that the job's processes are sent SIGTERM and are only
+	/// killed if they haven't exited after grace seconds. This doesn't block:
+	/// use JobRegistry::wait_for_stopped to wait for the processes to go away
+	/// (so that many jobs can be stopped at once).
+	fn stop(grace: float)
 	{
-		do_run(&job, cleanup);
+		oldcomm::send(self.watchdog.chan, StopMsg(self.watchdog.id, grace));
 	}
 }
 
-/// Run the jobs within a task: one after another.
-pub fn sequence(jobs: ~[Job], cleanup: ~[ExitFn])
+/// Run the job within the current task.
+pub fn run_blocking(registry: JobRegistry, job: Job, cleanup: ~[ExitFn])
+{
+	let watchdog = registry.watch();
+	do_run(registry, &job, cleanup, watchdog);
+	watchdog.unwatch();
+	registry.returned(job, rerun_watched(registry, watchdog));
+}
+
+/// Run the job within a task.
+pub fn run(registry: JobRegistry, job: Job, cleanup: ~[ExitFn]) -> JobHandle
+{
+	let watchdog = registry.watch();
+	run_watched(registry, job, cleanup, watchdog);
+	JobHandle {watchdog: watchdog}
+}
+
+/// Run the jobs within a task: one after another. Cancelling the handle
+/// cancels the current job and any jobs which have not yet run.
+pub fn sequence(registry: JobRegistry, jobs: ~[Job], cleanup: ~[ExitFn]) -> JobHandle
 {
-	do task::spawn_sched(task::SingleThreaded)
+	let watchdog = registry.watch();
+	do task::spawn_sched(task::SingleThreaded) |move jobs|
 	{
 		for jobs.each |job|
 		{
-			do_run(job, cleanup);
+			do_run(registry, job, cleanup, watchdog);
 		}
+		watchdog.unwatch();
+		do vec::consume(jobs) |_i, job| {registry.returned(job, rerun_watched(registry, watchdog))};
+	}
+	JobHandle {watchdog: watchdog}
+}
+
+/// Should be called when a job action starts a process so that the process
+/// can be killed if the job times out or is cancelled.
+pub fn child_started(pid: libc::pid_t)
+{
+	match get_watchdog()
+	{
+		option::Some(watchdog) => oldcomm::send(watchdog.chan, ChildStartedMsg(watchdog.id, pid)),
+		option::None => {}		// not running within a job
+	}
+}
+
+pub fn child_exited(pid: libc::pid_t)
+{
+	match get_watchdog()
+	{
+		option::Some(watchdog) => oldcomm::send(watchdog.chan, ChildExitedMsg(watchdog.id, pid)),
+		option::None => {}
+	}
+}
+
+/// Killing ssh doesn't kill the processes it started on the remote machine so
+/// job actions which use ssh should call this before running a remote command
+/// (see utils::run_remote_command). The remote shell should write its pid into
+/// pid_file (sshd makes the remote shell a process group leader so when the job
+/// times out or is cancelled the process group is killed using ssh).
+pub fn remote_started(user: &str, host: &str, pid_file: &str)
+{
+	match get_watchdog()
+	{
+		option::Some(watchdog) => oldcomm::send(watchdog.chan, RemoteStartedMsg(watchdog.id, Remote {user: user.to_owned(), host: host.to_owned(), pid_file: pid_file.to_owned()})),
+		option::None => {}
+	}
+}
+
+pub fn remote_exited(pid_file: &str)
+{
+	match get_watchdog()
+	{
+		option::Some(watchdog) => oldcomm::send(watchdog.chan, RemoteExitedMsg(watchdog.id, pid_file.to_owned())),
+		option::None => {}
+	}
+}
+
+// Runs the job within its own task using a watchdog from JobRegistry::watch
+// (or Watchdog::rewatch).
+priv fn run_watched(registry: JobRegistry, job: Job, cleanup: ~[ExitFn], watchdog: Watchdog)
+{
+	// These guys can block for arbitrary amounts of time so they need their own thread.
+	do task::spawn_sched(task::SingleThreaded) |move job, move cleanup|
+	{
+		do_run(registry, &job, cleanup, watchdog);
+		watchdog.unwatch();
+		registry.returned(job, rerun_watched(registry, watchdog));
+	}
+}
+
+// Restarted jobs keep their watchdog so that the original JobHandle can still
+// cancel them.
+priv fn rerun_watched(registry: JobRegistry, watchdog: Watchdog) -> Rerun
+{
+	|move job|
+	{
+		watchdog.rewatch();
+		run_watched(registry, job, ~[], watchdog);
+	}
+}
+
+priv fn rerun_queued(queue: JobQueue, key: ~str) -> Rerun
+{
+	|move key, move job| {queue.enqueue(copy key, job)}
+}
+
+priv fn rerun_scheduled(chan: oldcomm::Chan<ScheduleMsg>, name: ~str, generation: uint) -> Rerun
+{
+	|move name, move job| {oldcomm::send(chan, RerunScheduledMsg(copy name, generation, job))}
+}
+
+// Restarting a ShutdownOnFailure job could take down the server.
+priv pure fn can_restart(job: &Job) -> bool
+{
+	match job.policy
+	{
+		ShutdownOnFailure => false,
+		_ => true,
 	}
 }
 
-priv fn do_run(job: &Job, cleanup: &[ExitFn])
+priv fn do_run(registry: JobRegistry, job: &Job, cleanup: &[ExitFn], watchdog: Watchdog)
 {
 	match job.policy
 	{
 		IgnoreFailures =>
 		{
-			let err = (job.action)();
+			let (_, err) = run_action(registry, job, watchdog);
 			if err.is_some()
 			{
 				let errors = err.get().split_char('\n');
@@ -69,7 +215,7 @@ priv fn do_run(job: &Job, cleanup: &[ExitFn])
 		}
 		NotifyOnFailure(ref notify) =>
 		{
-			let err = (job.action)();
+			let (_, err) = run_action(registry, job, watchdog);
 			if err.is_some()
 			{
 				(*notify)(err.get());
@@ -77,18 +223,1569 @@ priv fn do_run(job: &Job, cleanup: &[ExitFn])
 		}
 		NotifyOnExit(ref notify) =>
 		{
-			(*notify)((job.action)())
+			let (_, err) = run_action(registry, job, watchdog);
+			(*notify)(err)
 		}
 		ShutdownOnFailure =>
 		{
-			let err = (job.action)();
+			let (outcome, err) = run_action(registry, job, watchdog);
 			if err.is_some()
 			{
 				error!("%s", err.get());
-				for cleanup.each |f| {(*f)()};
-				libc::exit(3);
+				if !outcome.is_cancelled()
+				{
+					for cleanup.each |f| {(*f)()};
+					libc::exit(3);
+				}
 			}
 		}
+		RestartWithBackoff(ref policy) =>
+		{
+			run_with_restarts(registry, job, policy, watchdog);
+		}
+	}
+}
+
+/// Runs jobs in the background with at most max_running jobs running at once.
+/// Jobs are keyed: jobs with the same key never run at the same time and if a
+/// job is queued while an older job with the same key is still waiting to run
+/// then the older job is dropped. This is useful for jobs like rendering charts
+/// where only the newest result matters.
+///
+/// Note that ShutdownOnFailure jobs exit without calling any cleanup functions.
+/// Jobs queued after shutdown is called are ignored.
+pub struct JobQueue
+{
+	priv chan: oldcomm::Chan<QueueMsg>,
+	priv registry: JobRegistry,
+}
+
+pub fn JobQueue(registry: JobRegistry, max_running: uint) -> JobQueue
+{
+	assert max_running > 0;
+	let chan = do utils::spawn_moded_listener(task::ThreadPerCore) |port| {manage_queue(port, registry, max_running)};
+	JobQueue {chan: chan, registry: registry}
+}
+
+pub impl JobQueue
+{
+	fn enqueue(key: ~str, job: Job)
+	{
+		self.registry.set_state(job.name, JobQueued, option::None);
+		oldcomm::send(self.chan, EnqueueMsg(key, job));
+	}
+	
+	/// Drops the jobs which are waiting to run and waits up to grace seconds for
+	/// the running jobs to finish.
+	fn shutdown(grace: float)
+	{
+		let port = oldcomm::Port();
+		let chan = oldcomm::Chan(&port);
+		oldcomm::send(self.chan, StopQueueMsg(chan));
+		if std::timer::recv_timeout(uv::global_loop::get(), (1000.0*grace) as uint, port).is_none()
+		{
+			info!("queued jobs did not finish after %?s", grace);
+		}
+	}
+}
+
+/// When a scheduled job should run.
+///
+/// * Every - run the job every n seconds (the first run is n seconds after the
+/// job is scheduled).
+/// * At - run the job at the local times matching a cron expression.
+pub enum When
+{
+	Every(float),
+	At(cron::Cron),
+}
+
+/// A job which is run repeatedly. Make is called to create the job each time
+/// it is due. If jitter is positive then a random delay of up to jitter seconds
+/// is added to each run (which keeps jobs scheduled for the same time from all
+/// running at once). If the job is still running when it is next due then that
+/// run is skipped.
+pub struct Schedule
+{
+	pub name: ~str,							// schedules with the same name replace each other
+	pub when: When,
+	pub jitter: float,
+	pub make: fn~ () -> Job,
+}
+
+/// Returned by Scheduler::list.
+pub struct ScheduleInfo
+{
+	pub name: ~str,
+	pub when: ~str,							// e.g. "every 60s" or "cron 0 * * * *"
+	pub next_run: float,					// NaN if paused or the schedule will never run again
+	pub last_run: float,					// NaN if the job has not run
+	pub runs: uint,
+	pub skipped: uint,						// number of times the job was due while it was still running
+	pub running: bool,
+	pub paused: bool,
+}
+
+/// Runs jobs at fixed intervals or at times matching cron expressions. Each
+/// run starts in its own task so jobs may block (e.g. call sleep or run a
+/// process).
+pub struct Scheduler
+{
+	priv chan: oldcomm::Chan<ScheduleMsg>,
+}
+
+pub fn Scheduler(registry: JobRegistry) -> Scheduler
+{
+	let chan = do utils::spawn_moded_listener(task::ThreadPerCore) |port| {manage_schedules(port, registry)};
+	Scheduler {chan: chan}
+}
+
+pub impl Scheduler
+{
+	fn add(schedule: Schedule)
+	{
+		oldcomm::send(self.chan, AddScheduleMsg(schedule));
+	}
+	
+	/// Paused schedules are not run until they are resumed. Pausing does not
+	/// affect a run which is already in progress.
+	fn pause(name: &str)
+	{
+		oldcomm::send(self.chan, PauseScheduleMsg(name.to_owned(), true));
+	}
+	
+	fn resume(name: &str)
+	{
+		oldcomm::send(self.chan, PauseScheduleMsg(name.to_owned(), false));
+	}
+	
+	fn list() -> ~[ScheduleInfo]
+	{
+		let port = oldcomm::Port();
+		let chan = oldcomm::Chan(&port);
+		oldcomm::send(self.chan, ListSchedulesMsg(chan));
+		oldcomm::recv(port)
+	}
+}
+
+/// What a job is doing (see JobRegistry).
+pub enum JobState
+{
+	JobQueued,								// waiting to run in a JobQueue
+	JobRunning,
+	JobRestarting,							// failed and waiting to be restarted (see RestartWithBackoff)
+	JobSucceeded,
+	JobFailed,
+	JobTimedOut,
+	JobCancelled,
+	JobGaveUp,								// failed too often to be restarted
+}
+
+pub impl JobState : ToStr
+{
+	pure fn to_str() -> ~str
+	{
+		match self
+		{
+			JobQueued => ~"queued",
+			JobRunning => ~"running",
+			JobRestarting => ~"restarting",
+			JobSucceeded => ~"succeeded",
+			JobFailed => ~"failed",
+			JobTimedOut => ~"timed out",
+			JobCancelled => ~"cancelled",
+			JobGaveUp => ~"gave up",
+		}
 	}
 }
 
+/// Returned by JobRegistry::list.
+pub struct JobInfo
+{
+	pub name: ~str,
+	pub state: JobState,
+	pub started: float,						// NaN if the job has not started
+	pub finished: float,					// NaN if the job has not finished (or is running again)
+	pub runs: uint,							// number of times the job has been started (including restarts)
+	pub error: ~str,						// error from the last failed run (usually stderr) or empty
+	pub restartable: bool,					// queued jobs cannot be restarted
+}
+
+/// Records what each job is doing. Jobs are recorded by name so jobs which are
+/// run over and over (e.g. chart renders) share one record.
+pub struct JobRegistry
+{
+	priv chan: oldcomm::Chan<RegistryMsg>,
+	priv watchdog: oldcomm::Chan<WatchMsg>,		// one task watches all of the jobs
+}
+
+pub fn JobRegistry() -> JobRegistry
+{
+	let watchdog = do utils::spawn_moded_listener(task::SingleThreaded) |port| {manage_watchdog(port)};
+	let chan = do utils::spawn_moded_listener(task::ThreadPerCore) |port| {manage_registry(port, max_retained_jobs)};
+	JobRegistry {chan: chan, watchdog: watchdog}
+}
+
+pub impl JobRegistry
+{
+	fn list() -> ~[JobInfo]
+	{
+		let port = oldcomm::Port();
+		let chan = oldcomm::Chan(&port);
+		oldcomm::send(self.chan, ListJobsMsg(chan));
+		oldcomm::recv(port)
+	}
+	
+	/// Like JobHandle::cancel.
+	fn cancel(name: &str)
+	{
+		oldcomm::send(self.chan, CancelJobMsg(name.to_owned()));
+	}
+	
+	/// Runs the job again. If the job is running then it is cancelled and run
+	/// again once it finishes. Jobs are restarted the way they were originally
+	/// run: queued jobs are queued again, scheduled jobs count as a run of their
+	/// schedule, and other jobs can still be cancelled using their original
+	/// JobHandle. Note that restarted jobs do not call cleanup functions and that
+	/// ShutdownOnFailure jobs cannot be restarted.
+	fn restart(name: &str)
+	{
+		oldcomm::send(self.chan, RestartJobMsg(name.to_owned()));
+	}
+	
+	/// Chan is sent all of the jobs whenever a job changes state.
+	fn register(key: ~str, chan: oldcomm::Chan<~[JobInfo]>)
+	{
+		oldcomm::send(self.chan, RegisterJobsMsg(key, chan));
+	}
+	
+	fn deregister(key: ~str)
+	{
+		oldcomm::send(self.chan, DeregisterJobsMsg(key));
+	}
+	
+	/// Blocks until the processes of every job being stopped (see JobHandle::stop)
+	/// have exited or been killed (or until timeout seconds have passed).
+	fn wait_for_stopped(timeout: float)
+	{
+		let port = oldcomm::Port();
+		let chan = oldcomm::Chan(&port);
+		oldcomm::send(self.watchdog, WaitForStoppedMsg(chan));
+		if std::timer::recv_timeout(uv::global_loop::get(), (1000.0*timeout) as uint, port).is_none()
+		{
+			info!("stopped jobs did not exit after %?s", timeout);
+		}
+	}
+}
+
+// ---- Internal Types --------------------------------------------------------
+// Messages to the watchdog are for the job (or sequence of jobs) with the id
+// returned by WatchMsg.
+priv enum WatchMsg
+{
+	WatchMsg(oldcomm::Chan<uint>),						// channel which receives the new id
+	RewatchMsg(uint),									// id of a job which is being run again
+	StartedMsg(uint, option::Option<float>, oldcomm::Chan<bool>),	// id + timeout + channel which receives false if the job has been cancelled
+	ChildStartedMsg(uint, libc::pid_t),
+	ChildExitedMsg(uint, libc::pid_t),
+	RemoteStartedMsg(uint, Remote),
+	RemoteExitedMsg(uint, ~str),						// id + pid file
+	FinishedMsg(uint, oldcomm::Chan<Outcome>),
+	CancelMsg(uint),
+	StopMsg(uint, float),								// id + grace period
+	WaitForStoppedMsg(oldcomm::Chan<()>),				// channel which is notified once no jobs are stopping
+	UnwatchMsg(uint),
+}
+
+priv impl Watched
+{
+	fn kill(signal: libc::c_int)
+	{
+		for self.children.each |pid| {kill_tree(*pid, signal);}
+		for self.remotes.each |remote| {kill_remote(remote, signal);}
+	}
+}
+
+// Handle to the watchdog for one job (or sequence of jobs).
+priv struct Watchdog
+{
+	chan: oldcomm::Chan<WatchMsg>,
+	id: uint,
+}
+
+priv impl Watchdog
+{
+	fn cancel()
+	{
+		oldcomm::send(self.chan, CancelMsg(self.id));
+	}
+	
+	fn rewatch()
+	{
+		oldcomm::send(self.chan, RewatchMsg(self.id));
+	}
+	
+	fn unwatch()
+	{
+		oldcomm::send(self.chan, UnwatchMsg(self.id));
+	}
+}
+
+// A process group started on another machine using ssh.
+priv struct Remote
+{
+	user: ~str,
+	host: ~str,
+	pid_file: ~str,		// contains the id of the process group
+}
+
+// What the watchdog knows about a job.
+priv struct Watched
+{
+	mut children: ~[libc::pid_t],						// processes started by the running action
+	mut remotes: ~[Remote],
+	mut deadline: float,								// NaN if there is no running action with a timeout
+	mut outcome: Outcome,
+	mut cancelled: bool,
+	mut kill_time: float,								// when to kill the processes of a job which is stopping (NaN if it isn't stopping)
+	mut users: uint,									// number of tasks running jobs under this watchdog
+}
+
+priv enum Outcome
+{
+	Completed,
+	TimedOut,
+	Cancelled,
+}
+
+priv impl Outcome
+{
+	pure fn is_completed() -> bool
+	{
+		match self {Completed => true, _ => false}
+	}
+	
+	pure fn is_cancelled() -> bool
+	{
+		match self {Cancelled => true, _ => false}
+	}
+}
+
+priv enum QueueMsg
+{
+	EnqueueMsg(~str, Job),			// key + job
+	StopQueueMsg(oldcomm::Chan<()>),	// channel which is notified once the running jobs have finished
+}
+
+priv struct Pending
+{
+	key: ~str,
+	job: Job,
+}
+
+priv enum ScheduleMsg
+{
+	AddScheduleMsg(Schedule),
+	PauseScheduleMsg(~str, bool),			// name + paused
+	ListSchedulesMsg(oldcomm::Chan<~[ScheduleInfo]>),
+	ScheduledJobFinishedMsg(~str, uint),		// name + generation of the run
+	RerunScheduledMsg(~str, uint, Job),		// name + generation of the run + job which was restarted using the registry
+}
+
+priv struct Scheduled
+{
+	schedule: Schedule,
+	mut due: float,							// when the job is next due (without jitter)
+	mut next_run: float,					// when the job will next run (with jitter)
+	mut last_run: float,
+	mut runs: uint,
+	mut skipped: uint,
+	mut running: bool,
+	mut paused: bool,
+	mut generation: uint,					// incremented each time the job is run (so messages about older runs can be ignored)
+}
+
+priv enum RegistryMsg
+{
+	StartedJobMsg(~str, Watchdog, bool),					// name + the job's watchdog + true if the job can be restarted
+	JobStateMsg(~str, JobState, option::Option<~str>),		// name + state + error
+	ReturnJobMsg(Job, Rerun),								// sent once the job's task is done with the job
+	CancelJobMsg(~str),
+	RestartJobMsg(~str),
+	ListJobsMsg(oldcomm::Chan<~[JobInfo]>),
+	RegisterJobsMsg(~str, oldcomm::Chan<~[JobInfo]>),		// key + channel
+	DeregisterJobsMsg(~str),
+}
+
+priv impl JobRegistry
+{
+	fn started(job: &Job, watchdog: Watchdog)
+	{
+		oldcomm::send(self.chan, StartedJobMsg(copy job.name, watchdog, can_restart(job)));
+	}
+	
+	fn watch() -> Watchdog
+	{
+		let port = oldcomm::Port();
+		let chan = oldcomm::Chan(&port);
+		oldcomm::send(self.watchdog, WatchMsg(chan));
+		Watchdog {chan: self.watchdog, id: oldcomm::recv(port)}
+	}
+	
+	fn set_state(name: &str, state: JobState, err: option::Option<~str>)
+	{
+		oldcomm::send(self.chan, JobStateMsg(name.to_owned(), state, err));
+	}
+	
+	fn returned(job: Job, rerun: Rerun)
+	{
+		oldcomm::send(self.chan, ReturnJobMsg(job, rerun));
+	}
+}
+
+// Runs a job again in the same way that it was originally run.
+priv type Rerun = fn~ (Job);
+
+priv struct Returned
+{
+	job: Job,
+	rerun: Rerun,
+}
+
+priv struct Record
+{
+	name: ~str,
+	mut state: JobState,
+	mut started: float,
+	mut finished: float,
+	mut runs: uint,
+	mut error: ~str,
+	mut watchdog: option::Option<Watchdog>,					// set while the job's task has the job
+	mut job: option::Option<Returned>,						// set after the job's task is done with the job
+	mut restart: bool,									// true if the job should be run again once it is returned
+	mut restartable: bool,								// false for ShutdownOnFailure jobs
+}
+
+// ---- Internal Functions ----------------------------------------------------
+
+// Max number of seconds to wait before restarting a job.
+priv const max_backoff: uint = 64;
+
+// Max number of finished jobs the registry keeps around so that they can be
+// listed and restarted (older records are dropped along with their closures
+// which can be large).
+priv const max_retained_jobs: uint = 100;
+
+priv const sigterm: libc::c_int = 15;
+priv const sigkill: libc::c_int = 9;
+
+// Runs the job's action under the watchdog. If the action timed out or was
+// cancelled then the error says so.
+priv fn run_action(registry: JobRegistry, job: &Job, watchdog: Watchdog) -> (Outcome, option::Option<~str>)
+{
+	let port = oldcomm::Port();
+	let chan = oldcomm::Chan(&port);
+	oldcomm::send(watchdog.chan, StartedMsg(watchdog.id, job.timeout, chan));
+	if !oldcomm::recv(port)
+	{
+		let err = fmt!("%s was cancelled", job.name);
+		registry.set_state(job.name, JobCancelled, option::Some(copy err));
+		return (Cancelled, option::Some(err));
+	}
+	
+	registry.started(job, watchdog);
+	set_watchdog(watchdog);
+	let err = (job.action)();
+	
+	let port = oldcomm::Port();
+	let chan = oldcomm::Chan(&port);
+	oldcomm::send(watchdog.chan, FinishedMsg(watchdog.id, chan));
+	let result = match oldcomm::recv(port)
+	{
+		Completed => (Completed, err),
+		TimedOut => (TimedOut, option::Some(fmt!("%s timed out after %?s", job.name, job.timeout.get()))),
+		Cancelled => (Cancelled, option::Some(fmt!("%s was cancelled", job.name))),
+	};
+	
+	let state = match result
+	{
+		(Completed, option::None) => JobSucceeded,
+		(Completed, option::Some(_)) => JobFailed,
+		(TimedOut, _) => JobTimedOut,
+		(Cancelled, _) => JobCancelled,
+	};
+	registry.set_state(job.name, state, copy result.second());
+	result
+}
+
+priv fn run_with_restarts(registry: JobRegistry, job: &Job, policy: &RestartPolicy, watchdog: Watchdog)
+{
+	let mut restarts = ~[];		// times at which the job was restarted
+	let mut count = 0;
+	loop
+	{
+		match run_action(registry, job, watchdog)
+		{
+			(Cancelled, _) =>
+			{
+				info!("%s was cancelled", job.name);
+				break;
+			}
+			(_, option::Some(err)) =>
+			{
+				let now = utils::imprecise_time_s();
+				restarts = do restarts.filtered |time| {now - *time < policy.window};
+				if restarts.len() >= policy.max_restarts
+				{
+					registry.set_state(job.name, JobGaveUp, option::Some(copy err));
+					(policy.notify)(&GaveUp(count, err));
+					break;
+				}
+				
+				count += 1;
+				registry.set_state(job.name, JobRestarting, option::Some(copy err));
+				(policy.notify)(&Restarting(count, err));
+				
+				let delay = get_backoff(restarts.len());
+				info!("restarting job in %?s", delay);
+				libc::funcs::posix88::unistd::sleep(delay as core::libc::types::os::arch::c95::c_uint);
+				restarts.push(utils::imprecise_time_s());
+			}
+			(_, option::None) =>
+			{
+				break;
+			}
+		}
+	}
+}
+
+// Returns the number of seconds to wait before restarting a job which has
+// already been restarted restarts times within the policy's window.
+priv pure fn get_backoff(restarts: uint) -> uint
+{
+	if restarts < 7 {uint::min(1u << restarts, max_backoff)} else {max_backoff}
+}
+
+// The watchdog kills the processes jobs started if they time out or are cancelled.
+priv fn manage_watchdog(port: oldcomm::Port<WatchMsg>)
+{
+	let watched: HashMap<uint, @Watched> = HashMap();
+	let mut waiters = ~[];								// channels waiting for stopped jobs to exit
+	let mut next_id = 1;
+	loop
+	{
+		// Wait for a message or until the next deadline.
+		let mut wake = float::NaN;
+		for watched.each_value |w|
+		{
+			let time = if float::is_NaN(w.deadline) || w.kill_time < w.deadline {w.kill_time} else {w.deadline};
+			if float::is_NaN(wake) || time < wake {wake = time}		// false for NaN
+		}
+		let mesg =
+			if float::is_NaN(wake)
+			{
+				option::Some(oldcomm::recv(port))
+			}
+			else
+			{
+				let remaining = wake - utils::imprecise_time_s();
+				if remaining > 0.0 {std::timer::recv_timeout(uv::global_loop::get(), (1000.0*remaining) as uint + 1, port)} else {option::None}
+			};
+		
+		match mesg
+		{
+			option::None =>
+			{
+				let now = utils::imprecise_time_s();
+				for watched.each_value |w|
+				{
+					if w.kill_time <= now
+					{
+						info!("processes did not exit after SIGTERM");
+						w.kill(sigkill);
+						w.kill_time = float::NaN;
+					}
+					if w.deadline <= now
+					{
+						w.outcome = TimedOut;
+						w.deadline = float::NaN;
+						w.kill(sigkill);
+					}
+				}
+			}
+			option::Some(WatchMsg(reply)) =>
+			{
+				watched.insert(next_id, @Watched {children: ~[], remotes: ~[], deadline: float::NaN, outcome: Completed, cancelled: false, kill_time: float::NaN, users: 1});
+				oldcomm::send(reply, next_id);
+				next_id += 1;
+			}
+			option::Some(RewatchMsg(id)) =>
+			{
+				match watched.find(id)
+				{
+					option::Some(w) => w.users += 1,
+					option::None => {watched.insert(id, @Watched {children: ~[], remotes: ~[], deadline: float::NaN, outcome: Completed, cancelled: false, kill_time: float::NaN, users: 1});}
+				}
+			}
+			option::Some(StartedMsg(id, timeout, reply)) =>
+			{
+				let w = watched[id];
+				w.outcome = if w.cancelled {Cancelled} else {Completed};
+				w.deadline = match timeout
+				{
+					option::Some(secs) if !w.cancelled => utils::imprecise_time_s() + secs,
+					_ => float::NaN,
+				};
+				oldcomm::send(reply, !w.cancelled);
+			}
+			option::Some(ChildStartedMsg(id, pid)) =>
+			{
+				let w = watched[id];
+				if w.outcome.is_completed()
+				{
+					w.children.push(pid);
+				}
+				else
+				{
+					kill_tree(pid, sigkill);
+				}
+			}
+			option::Some(ChildExitedMsg(id, pid)) =>
+			{
+				let w = watched[id];
+				match w.children.position_elem(&pid)
+				{
+					option::Some(i) => {w.children.remove(i);}
+					option::None => {}
+				}
+				if w.children.is_empty() && w.remotes.is_empty()
+				{
+					w.kill_time = float::NaN;
+				}
+			}
+			option::Some(RemoteStartedMsg(id, move remote)) =>
+			{
+				let w = watched[id];
+				if w.outcome.is_completed()
+				{
+					w.remotes.push(remote);
+				}
+				else
+				{
+					kill_remote(&remote, sigkill);
+				}
+			}
+			option::Some(RemoteExitedMsg(id, ref pid_file)) =>
+			{
+				let w = watched[id];
+				match w.remotes.position(|r| {r.pid_file == *pid_file})
+				{
+					option::Some(i) => {w.remotes.remove(i);}
+					option::None => {}
+				}
+				if w.children.is_empty() && w.remotes.is_empty()
+				{
+					w.kill_time = float::NaN;
+				}
+			}
+			option::Some(FinishedMsg(id, reply)) =>
+			{
+				let w = watched[id];
+				oldcomm::send(reply, w.outcome);
+				w.deadline = float::NaN;
+			}
+			option::Some(CancelMsg(id)) =>
+			{
+				match watched.find(id)
+				{
+					option::Some(w) =>
+					{
+						w.cancelled = true;
+						w.outcome = Cancelled;
+						w.deadline = float::NaN;
+						w.kill(sigkill);
+					}
+					option::None => {}		// the job has already finished
+				}
+			}
+			option::Some(StopMsg(id, grace)) =>
+			{
+				match watched.find(id)
+				{
+					option::Some(w) =>
+					{
+						w.cancelled = true;
+						w.outcome = Cancelled;
+						w.deadline = float::NaN;
+						if w.children.is_not_empty() || w.remotes.is_not_empty()
+						{
+							w.kill(sigterm);
+							w.kill_time = utils::imprecise_time_s() + grace;
+						}
+					}
+					option::None => {}		// the job has already finished
+				}
+			}
+			option::Some(WaitForStoppedMsg(reply)) =>
+			{
+				waiters.push(reply);
+			}
+			option::Some(UnwatchMsg(id)) =>
+			{
+				match watched.find(id)
+				{
+					option::Some(w) if w.users > 1 =>
+					{
+						w.users -= 1;
+					}
+					option::Some(_) =>
+					{
+						watched.remove(id);
+					}
+					option::None => {}
+				}
+			}
+		}
+		
+		if waiters.is_not_empty()
+		{
+			let mut stopping = false;
+			for watched.each_value |w| {if !float::is_NaN(w.kill_time) {stopping = true}}
+			if !stopping
+			{
+				for waiters.each |reply| {oldcomm::send(*reply, ())}
+				waiters = ~[];
+			}
+		}
+	}
+}
+
+// Signals the process and all of its descendants.
+priv fn kill_tree(pid: libc::pid_t, signal: libc::c_int)
+{
+	let result = core::run::program_output("pgrep", ~[~"-P", fmt!("%?", pid)]);
+	info!("sending signal %? to process %?", signal, pid);
+	libc::funcs::posix88::signal::kill(pid, signal);
+	
+	for str::split_char(result.out, '\n').each |line|
+	{
+		match int::from_str(str::trim(*line))
+		{
+			option::Some(child) => kill_tree(child as libc::pid_t, signal),
+			option::None => {}
+		}
+	}
+}
+
+// Kills the remote process group (the remote shell removes the pid file if
+// the command exits normally). This uses its own task so that the watchdog
+// isn't blocked by ssh.
+priv fn kill_remote(remote: &Remote, signal: libc::c_int)
+{
+	info!("sending signal %? to %s on %s", signal, remote.pid_file, remote.host);
+	let target = fmt!("%s@%s", remote.user, remote.host);
+	let command = fmt!("kill -%? -- -$(cat %s) ; rm -f %s", signal, remote.pid_file, remote.pid_file);
+	do task::spawn_sched(task::SingleThreaded) |move target, move command|
+	{
+		core::run::program_output("ssh", ~[copy target, copy command]);
+	}
+}
+
+// Job actions run within their own task so we use task local data to find
+// the watchdog for the current job.
+priv fn watchdog_key(_watchdog: @Watchdog)
+{
+}
+
+priv fn set_watchdog(watchdog: Watchdog)
+{
+	unsafe {task::local_data::local_data_set(watchdog_key, @watchdog);}
+}
+
+priv fn get_watchdog() -> option::Option<Watchdog>
+{
+	unsafe {task::local_data::local_data_get(watchdog_key).map(|watchdog| {**watchdog})}
+}
+
+priv fn manage_queue(port: oldcomm::Port<QueueMsg>, registry: JobRegistry, max_running: uint)
+{
+	let queue = JobQueue {chan: oldcomm::Chan(&port), registry: registry};		// used to restart jobs
+	let finished_port = oldcomm::Port();		// receives the keys of jobs that have finished
+	let finished_chan = oldcomm::Chan(&finished_port);
+	
+	let mut pending = ~[];
+	let mut running = ~[];
+	let mut stopping = option::None;		// set once we've been told to stop
+	loop
+	{
+		match oldcomm::select2(port, finished_port)
+		{
+			either::Left(EnqueueMsg(_, ref job)) if stopping.is_some() =>
+			{
+				registry.set_state(job.name, JobCancelled, option::Some(fmt!("%s was queued after shutdown", job.name)));
+			}
+			either::Left(EnqueueMsg(move key, move job)) =>
+			{
+				match pending.position(|p: &Pending| {p.key == key})
+				{
+					option::Some(i) =>
+					{
+						debug!("dropping queued %s job", key);
+						pending[i] = Pending {key: key, job: job};
+					}
+					option::None =>
+					{
+						pending.push(Pending {key: key, job: job});
+					}
+				}
+			}
+			either::Left(StopQueueMsg(reply)) =>
+			{
+				for pending.each |p|
+				{
+					registry.set_state(p.job.name, JobCancelled, option::Some(fmt!("%s was dropped at shutdown", p.job.name)));
+				}
+				pending = ~[];
+				stopping = option::Some(reply);
+			}
+			either::Right(ref key) =>
+			{
+				match running.position_elem(key)
+				{
+					option::Some(i) => {running.remove(i);}
+					option::None => {}
+				}
+			}
+		}
+		
+		match stopping
+		{
+			option::Some(reply) if running.is_empty() =>
+			{
+				oldcomm::send(reply, ());
+				break;
+			}
+			option::Some(_) => {}
+			option::None => start_jobs(queue, registry, &mut pending, &mut running, max_running, finished_chan),
+		}
+	}
+}
+
+// Starts pending jobs (in the order in which they were queued) until we hit max_running.
+priv fn start_jobs(queue: JobQueue, registry: JobRegistry, pending: &mut ~[Pending], running: &mut ~[~str], max_running: uint, finished_chan: oldcomm::Chan<~str>)
+{
+	let mut i = 0;
+	while i < pending.len() && running.len() < max_running
+	{
+		if running.contains(&pending[i].key)
+		{
+			i += 1;
+		}
+		else
+		{
+			let Pending {key: key, job: job} = pending.remove(i);
+			running.push(copy key);
+			let rerun = rerun_queued(queue, copy key);
+			do spawn_job(registry, job, rerun) |move key| {oldcomm::send(finished_chan, copy key)};
+		}
+	}
+}
+
+// Runs the job within its own task and calls finished when it is done.
+priv fn spawn_job(registry: JobRegistry, job: Job, rerun: Rerun, finished: fn~ ())
+{
+	do task::spawn_sched(task::SingleThreaded) |move job, move rerun, move finished|
+	{
+		let name = copy job.name;
+		let watchdog = registry.watch();
+		let result = do task::try |move job| {do_run(registry, &job, ~[], watchdog); job};
+		watchdog.unwatch();
+		match result
+		{
+			result::Ok(move job) =>
+			{
+				registry.returned(job, rerun);
+			}
+			result::Err(_) =>
+			{
+				error!("%s job failed", name);
+				registry.set_state(name, JobFailed, option::Some(fmt!("%s job failed", name)));
+			}
+		}
+		finished();
+	}
+}
+
+priv fn manage_schedules(port: oldcomm::Port<ScheduleMsg>, registry: JobRegistry)
+{
+	let chan = oldcomm::Chan(&port);			// used to find out when jobs finish
+	let mut scheduled: ~[Scheduled] = ~[];
+	loop
+	{
+		// Wait for a message or until the next job is due.
+		let next_run = do scheduled.foldl(float::NaN) |next, s| {if !s.paused && (float::is_NaN(*next) || s.next_run < *next) {s.next_run} else {*next}};
+		let mesg =
+			if float::is_NaN(next_run)
+			{
+				option::Some(oldcomm::recv(port))
+			}
+			else
+			{
+				let remaining = next_run - utils::imprecise_time_s();
+				if remaining > 0.0 {std::timer::recv_timeout(uv::global_loop::get(), (1000.0*remaining) as uint + 1, port)} else {option::None}
+			};
+		
+		match mesg
+		{
+			option::None =>
+			{
+				run_due(scheduled, registry, chan);
+			}
+			option::Some(AddScheduleMsg(move schedule)) =>
+			{
+				// If an older version of the schedule is running we don't want to start
+				// the new version until it finishes.
+				let (running, generation) = match scheduled.position(|s: &Scheduled| {s.schedule.name == schedule.name})
+				{
+					option::Some(i) =>
+					{
+						let old = scheduled.remove(i);
+						(old.running, old.generation)
+					}
+					option::None => (false, 0),
+				};
+				let due = get_next_run(&schedule, utils::imprecise_time_s());
+				let next_run = add_jitter(&schedule, due);
+				info!("scheduled %s (%s)", schedule.name, when_to_str(&schedule.when));
+				scheduled.push(Scheduled {schedule: schedule, due: due, next_run: next_run, last_run: float::NaN, runs: 0, skipped: 0, running: running, paused: false, generation: generation});
+			}
+			option::Some(PauseScheduleMsg(ref name, paused)) =>
+			{
+				for scheduled.each |s|
+				{
+					if s.schedule.name == *name && s.paused != paused
+					{
+						info!("%s %s", if paused {~"paused"} else {~"resumed"}, *name);
+						s.paused = paused;
+						if !paused
+						{
+							s.due = get_next_run(&s.schedule, utils::imprecise_time_s());
+							s.next_run = add_jitter(&s.schedule, s.due);
+						}
+					}
+				}
+			}
+			option::Some(ListSchedulesMsg(reply)) =>
+			{
+				oldcomm::send(reply, do scheduled.map |s| {get_schedule_info(s)});
+			}
+			option::Some(ScheduledJobFinishedMsg(ref name, generation)) =>
+			{
+				for scheduled.each |s|
+				{
+					if s.schedule.name == *name && s.generation == generation
+					{
+						s.running = false;
+					}
+				}
+			}
+			option::Some(RerunScheduledMsg(move name, generation, move job)) =>
+			{
+				// The job is returned to the registry before the finished message is
+				// sent so the run being restarted may still look like it is running.
+				// But if the job has run since then the restart is stale.
+				match scheduled.position(|s: &Scheduled| {s.schedule.name == name})
+				{
+					option::Some(i) if scheduled[i].generation != generation =>
+					{
+						info!("not restarting %s: it has run since", name);
+					}
+					option::Some(i) =>
+					{
+						start_scheduled(&scheduled[i], registry, chan, job, utils::imprecise_time_s());
+					}
+					option::None =>
+					{
+						info!("not restarting %s: it is no longer scheduled", name);
+					}
+				}
+			}
+		}
+	}
+}
+
+// Starts the jobs which are due (unless they are still running from last time).
+priv fn run_due(scheduled: &[Scheduled], registry: JobRegistry, chan: oldcomm::Chan<ScheduleMsg>)
+{
+	let now = utils::imprecise_time_s();
+	for scheduled.each |s|
+	{
+		if !s.paused && s.next_run <= now
+		{
+			if s.running
+			{
+				info!("skipping %s: it is still running", s.schedule.name);
+				s.skipped += 1;
+			}
+			else
+			{
+				start_scheduled(s, registry, chan, (s.schedule.make)(), now);
+			}
+			// Jitter only delays runs: it doesn't move the schedule itself.
+			s.due = get_next_run(&s.schedule, float::max(s.due, now));
+			s.next_run = add_jitter(&s.schedule, s.due);
+		}
+	}
+}
+
+priv fn start_scheduled(s: &Scheduled, registry: JobRegistry, chan: oldcomm::Chan<ScheduleMsg>, job: Job, now: float)
+{
+	s.running = true;
+	s.last_run = now;
+	s.runs += 1;
+	s.generation += 1;
+	
+	let name = copy s.schedule.name;
+	let generation = s.generation;
+	let rerun = rerun_scheduled(chan, copy name, generation);
+	do spawn_job(registry, job, rerun) |move name| {oldcomm::send(chan, ScheduledJobFinishedMsg(copy name, generation))};
+}
+
+priv fn get_next_run(schedule: &Schedule, after: float) -> float
+{
+	let time = match schedule.when
+	{
+		Every(secs) => after + secs,
+		At(ref cron) => cron.next_after(after).get_default(float::NaN),
+	};
+	if float::is_NaN(time)
+	{
+		error!("%s will never run (%s)", schedule.name, when_to_str(&schedule.when));
+	}
+	time
+}
+
+priv fn add_jitter(schedule: &Schedule, due: float) -> float
+{
+	if schedule.jitter > 0.0
+	{
+		due + schedule.jitter*rand::Rng().gen_float()
+	}
+	else
+	{
+		due
+	}
+}
+
+priv fn get_schedule_info(s: &Scheduled) -> ScheduleInfo
+{
+	ScheduleInfo
+	{
+		name: copy s.schedule.name,
+		when: when_to_str(&s.schedule.when),
+		next_run: if s.paused {float::NaN} else {s.next_run},
+		last_run: s.last_run,
+		runs: s.runs,
+		skipped: s.skipped,
+		running: s.running,
+		paused: s.paused,
+	}
+}
+
+priv fn when_to_str(when: &When) -> ~str
+{
+	match *when
+	{
+		Every(secs) => fmt!("every %?s", secs),
+		At(ref cron) => fmt!("cron %s", cron.to_str()),
+	}
+}
+
+priv fn manage_registry(port: oldcomm::Port<RegistryMsg>, max_jobs: uint)
+{
+	let listeners = HashMap();								// key => Chan<~[JobInfo]>
+	let mut records: ~[Record] = ~[];
+	loop
+	{
+		let changed = match oldcomm::recv(port)
+		{
+			StartedJobMsg(ref name, watchdog, restartable) =>
+			{
+				let i = get_record(&mut records, *name);
+				records[i].state = JobRunning;
+				records[i].started = utils::imprecise_time_s();
+				records[i].finished = float::NaN;
+				records[i].runs += 1;
+				records[i].watchdog = option::Some(watchdog);
+				records[i].restartable = restartable;
+				true
+			}
+			JobStateMsg(ref name, state, ref err) =>
+			{
+				let i = get_record(&mut records, *name);
+				match (records[i].state, state)
+				{
+					(JobRunning, JobQueued) =>
+					{
+						// A new version of the job was queued while the old version is running.
+						false
+					}
+					_ =>
+					{
+						records[i].state = state;
+						match state
+						{
+							JobQueued | JobRunning => {}
+							_ => records[i].finished = utils::imprecise_time_s(),
+						}
+						if err.is_some()
+						{
+							records[i].error = err.get();
+						}
+						true
+					}
+				}
+			}
+			ReturnJobMsg(move job, move rerun) =>
+			{
+				match records.position(|r: &Record| {r.name == job.name})
+				{
+					option::Some(i) =>
+					{
+						records[i].watchdog = option::None;
+						if records[i].restart
+						{
+							info!("restarting %s", job.name);
+							records[i].restart = false;
+							rerun(job);
+						}
+						else
+						{
+							records[i].job = option::Some(Returned {job: job, rerun: rerun});
+							drop_old_jobs(&mut records, max_jobs);
+						}
+						true
+					}
+					option::None =>
+					{
+						// The record was already dropped so there's no way to restart the job.
+						false
+					}
+				}
+			}
+			CancelJobMsg(ref name) =>
+			{
+				match records.position(|r: &Record| {r.name == *name})
+				{
+					option::Some(i) if records[i].watchdog.is_some() =>
+					{
+						info!("cancelling %s", *name);
+						records[i].watchdog.get().cancel();
+					}
+					_ =>
+					{
+						info!("can't cancel %s: it isn't running", *name);
+					}
+				}
+				false
+			}
+			RestartJobMsg(ref name) =>
+			{
+				match records.position(|r: &Record| {r.name == *name})
+				{
+					option::Some(i) if !records[i].restartable =>
+					{
+						info!("can't restart %s: it shuts down the server on failure", *name);
+					}
+					option::Some(i) if records[i].job.is_some() =>
+					{
+						info!("restarting %s", *name);
+						let mut returned = option::None;
+						returned <-> records[i].job;
+						let Returned {job: job, rerun: rerun} = option::unwrap(returned);
+						rerun(job);
+					}
+					option::Some(i) if records[i].watchdog.is_some() =>
+					{
+						// The job is returned once it has been cancelled and then we'll run it again.
+						records[i].restart = true;
+						records[i].watchdog.get().cancel();
+					}
+					_ =>
+					{
+						info!("can't restart %s", *name);
+					}
+				}
+				false
+			}
+			ListJobsMsg(reply) =>
+			{
+				oldcomm::send(reply, do records.map |r| {get_job_info(r)});
+				false
+			}
+			RegisterJobsMsg(ref key, chan) =>
+			{
+				oldcomm::send(chan, do records.map |r| {get_job_info(r)});
+				listeners.insert(@copy *key, chan);
+				false
+			}
+			DeregisterJobsMsg(ref key) =>
+			{
+				listeners.remove(@copy *key);
+				false
+			}
+		};
+		
+		if changed && listeners.size() > 0
+		{
+			let jobs = do records.map |r| {get_job_info(r)};
+			for listeners.each_value |chan| {oldcomm::send(chan, copy jobs);}
+		}
+	}
+}
+
+// Returns the index of the named record (adding one if it is missing).
+priv fn get_record(records: &mut ~[Record], name: &str) -> uint
+{
+	match records.position(|r: &Record| {r.name == name.to_owned()})
+	{
+		option::Some(i) => i,
+		option::None =>
+		{
+			records.push(Record {name: name.to_owned(), state: JobQueued, started: float::NaN, finished: float::NaN, runs: 0, error: ~"", watchdog: option::None, job: option::None, restart: false, restartable: true});
+			records.len() - 1
+		}
+	}
+}
+
+// Drops the records of the jobs which finished longest ago until there are at
+// most max_jobs finished jobs. Lots of jobs have unique names (e.g. tool runs)
+// so without this the registry would grow forever.
+priv fn drop_old_jobs(records: &mut ~[Record], max_jobs: uint)
+{
+	let mut count = 0;
+	for records.each |r| {if is_finished(r) {count += 1}}
+	
+	while count > max_jobs
+	{
+		let mut oldest = 0;
+		let mut oldest_time = float::NaN;
+		for records.eachi |i, r|
+		{
+			if is_finished(r) && (float::is_NaN(oldest_time) || r.finished < oldest_time)
+			{
+				oldest = i;
+				oldest_time = r.finished;
+			}
+		}
+		
+		debug!("dropping %s job", records[oldest].name);
+		vec::remove(records, oldest);
+		count -= 1;
+	}
+}
+
+// Jobs which are finished are no longer referenced by their task (so dropping
+// them cannot lose track of a running or queued job).
+priv pure fn is_finished(r: &Record) -> bool
+{
+	match r.state
+	{
+		JobQueued | JobRunning | JobRestarting => false,
+		_ => r.watchdog.is_none(),
+	}
+}
+
+priv fn get_job_info(r: &Record) -> JobInfo
+{
+	JobInfo
+	{
+		name: copy r.name,
+		state: r.state,
+		started: r.started,
+		finished: r.finished,
+		runs: r.runs,
+		error: copy r.error,
+		restartable: r.restartable && (r.job.is_some() || r.watchdog.is_some()),
+	}
+}
+
+#[test]
+fn test_backoff()
+{
+	assert get_backoff(0) == 1;
+	assert get_backoff(1) == 2;
+	assert get_backoff(5) == 32;
+	assert get_backoff(6) == max_backoff;
+	assert get_backoff(100) == max_backoff;
+}
+
+#[test]
+fn test_restarts()
+{
+	let runs_port = oldcomm::Port();
+	let runs_chan = oldcomm::Chan(&runs_port);
+	let events_port = oldcomm::Port();
+	let events_chan = oldcomm::Chan(&events_port);
+	
+	let action: JobFn = || {oldcomm::send(runs_chan, ()); option::Some(~"oops")};
+	let notify: fn~ (&RestartEvent) = |event| {oldcomm::send(events_chan, copy *event)};
+	let policy = RestartPolicy {max_restarts: 1, window: 60.0, notify: notify};
+	
+	// the job is restarted once (after a one second delay) and then we give up
+	let registry = JobRegistry();
+	let start = utils::imprecise_time_s();
+	run_blocking(registry, Job {name: ~"flaky", action: action, policy: RestartWithBackoff(policy), timeout: option::None}, ~[]);
+	assert utils::imprecise_time_s() - start >= 1.0;
+	
+	match oldcomm::recv(events_port)
+	{
+		Restarting(1, ref err) => assert *err == ~"oops",
+		_ => fail ~"expected a restart",
+	}
+	match oldcomm::recv(events_port)
+	{
+		GaveUp(1, ref err) => assert *err == ~"oops",
+		_ => fail ~"expected to give up",
+	}
+	assert !events_port.peek();
+	
+	oldcomm::recv(runs_port);
+	oldcomm::recv(runs_port);
+	assert !runs_port.peek();
+	
+	let jobs = registry.list();
+	assert jobs.len() == 1;
+	assert jobs[0].runs == 2;
+	assert jobs[0].state.to_str() == ~"gave up";
+}
+
+#[test]
+fn test_restarts_succeed()
+{
+	let events_port = oldcomm::Port();
+	let events_chan = oldcomm::Chan(&events_port);
+	let notify: fn~ (&RestartEvent) = |event| {oldcomm::send(events_chan, copy *event)};
+	let policy = RestartPolicy {max_restarts: 0, window: 60.0, notify: notify};
+	
+	// jobs which succeed are not restarted
+	let registry = JobRegistry();
+	run_blocking(registry, Job {name: ~"solid", action: || {option::None}, policy: RestartWithBackoff(policy), timeout: option::None}, ~[]);
+	assert !events_port.peek();
+	
+	let jobs = registry.list();
+	assert jobs[0].runs == 1;
+	assert jobs[0].state.to_str() == ~"succeeded";
+}
+
+#[test]
+fn test_timeout()
+{
+	let registry = JobRegistry();
+	let action: JobFn = || {utils::run_command_with_output(~"sleep", ~[~"10"], |_line| {})};
+	let start = utils::imprecise_time_s();
+	run_blocking(registry, Job {name: ~"sleepy", action: action, policy: IgnoreFailures, timeout: option::Some(0.5)}, ~[]);
+	assert utils::imprecise_time_s() - start < 5.0;
+	
+	let jobs = registry.list();
+	assert jobs[0].state.to_str() == ~"timed out";
+	assert jobs[0].error == ~"sleepy timed out after 0.5s";
+}
+
+#[test]
+fn test_cancel()
+{
+	let port = oldcomm::Port();
+	let chan = oldcomm::Chan(&port);
+	let notify: fn~ (option::Option<~str>) = |err| {oldcomm::send(chan, err)};
+	let survivor_port = oldcomm::Port();
+	let survivor_chan = oldcomm::Chan(&survivor_port);
+	let survivor_notify: fn~ (option::Option<~str>) = |err| {oldcomm::send(survivor_chan, err)};
+	
+	// Jobs share the watchdog so cancelling one job kills its process but not
+	// the processes of a job running at the same time. (Remote process groups
+	// are killed using ssh so they aren't tested here.)
+	let registry = JobRegistry();
+	let action: JobFn = || {utils::run_command_with_output(~"sleep", ~[~"10"], |_line| {})};
+	let handle = run(registry, Job {name: ~"doomed", action: action, policy: NotifyOnExit(notify), timeout: option::None}, ~[]);
+	let action: JobFn = || {utils::run_command_with_output(~"sleep", ~[~"1"], |_line| {})};
+	run(registry, Job {name: ~"survivor", action: action, policy: NotifyOnExit(survivor_notify), timeout: option::Some(5.0)}, ~[]);
+	while registry.list().len() < 2 || registry.list().any(|j| {j.state.to_str() != ~"running"})
+	{
+		std::timer::sleep(uv::global_loop::get(), 10);
+	}
+	
+	let start = utils::imprecise_time_s();
+	handle.cancel();
+	assert oldcomm::recv(port) == option::Some(~"doomed was cancelled");
+	assert utils::imprecise_time_s() - start < 5.0;
+	assert oldcomm::recv(survivor_port) == option::None;
+	
+	let jobs = registry.list();
+	let doomed = jobs.find(|j| {j.name == ~"doomed"}).get();
+	let survivor = jobs.find(|j| {j.name == ~"survivor"}).get();
+	assert doomed.state.to_str() == ~"cancelled";
+	assert survivor.state.to_str() == ~"succeeded";
+}
+
+#[test]
+fn test_restart_uses_handle()
+{
+	fn wait_for_runs(registry: JobRegistry, runs: uint)
+	{
+		while registry.list().is_empty() || registry.list()[0].runs < runs || registry.list()[0].state.to_str() != ~"running"
+		{
+			std::timer::sleep(uv::global_loop::get(), 10);
+		}
+	}
+	
+	let port = oldcomm::Port();
+	let chan = oldcomm::Chan(&port);
+	let notify: fn~ (option::Option<~str>) = |err| {oldcomm::send(chan, err)};
+	
+	let registry = JobRegistry();
+	let action: JobFn = || {utils::run_command_with_output(~"sleep", ~[~"10"], |_line| {})};
+	let handle = run(registry, Job {name: ~"phoenix", action: action, policy: NotifyOnExit(notify), timeout: option::None}, ~[]);
+	wait_for_runs(registry, 1);
+	handle.cancel();
+	assert oldcomm::recv(port) == option::Some(~"phoenix was cancelled");
+	assert registry.list()[0].restartable;
+	
+	// the restarted job can still be cancelled using the original handle
+	registry.restart(~"phoenix");
+	wait_for_runs(registry, 2);
+	handle.cancel();
+	assert oldcomm::recv(port) == option::Some(~"phoenix was cancelled");
+}
+
+#[test]
+fn test_restart_queued()
+{
+	fn wait_for_state(registry: JobRegistry, name: &str, state: &str)
+	{
+		while registry.list().find(|j| {j.name == name.to_owned()}).get().state.to_str() != state.to_owned()
+		{
+			std::timer::sleep(uv::global_loop::get(), 10);
+		}
+	}
+	
+	let registry = JobRegistry();
+	let queue = JobQueue(registry, 1);
+	let port = oldcomm::Port();
+	let chan = oldcomm::Chan(&port);
+	let notify: fn~ (option::Option<~str>) = |err| {oldcomm::send(chan, err)};
+	queue.enqueue(~"key", Job {name: ~"queued", action: || {option::None}, policy: NotifyOnExit(notify), timeout: option::None});
+	assert oldcomm::recv(port).is_none();
+	
+	let action: JobFn = || {utils::run_command_with_output(~"sleep", ~[~"10"], |_line| {})};
+	queue.enqueue(~"blocker", Job {name: ~"blocker", action: action, policy: IgnoreFailures, timeout: option::None});
+	wait_for_state(registry, "blocker", "running");
+	
+	// the restarted job goes back into the queue so it has to wait for the blocker
+	registry.restart(~"queued");
+	wait_for_state(registry, "queued", "queued");
+	registry.cancel(~"blocker");
+	assert oldcomm::recv(port).is_none();
+	
+	let jobs = registry.list();
+	assert jobs.find(|j| {j.name == ~"queued"}).get().runs == 2;
+	assert jobs.find(|j| {j.name == ~"blocker"}).get().state.to_str() == ~"cancelled";
+}
+
+#[test]
+fn test_restart_shutdown_job()
+{
+	let registry = JobRegistry();
+	run_blocking(registry, Job {name: ~"critical", action: || {option::None}, policy: ShutdownOnFailure, timeout: option::None}, ~[]);
+	assert !registry.list()[0].restartable;
+	
+	registry.restart(~"critical");
+	let jobs = registry.list();
+	assert jobs[0].runs == 1;
+	assert jobs[0].state.to_str() == ~"succeeded";
+}
+
+#[test]
+fn test_job_retention()
+{
+	let watchdog = do utils::spawn_moded_listener(task::SingleThreaded) |port| {manage_watchdog(port)};
+	let chan = do utils::spawn_moded_listener(task::ThreadPerCore) |port| {manage_registry(port, 2)};
+	let registry = JobRegistry {chan: chan, watchdog: watchdog};
+	
+	for [~"a", ~"b", ~"c"].each |name|
+	{
+		run_blocking(registry, Job {name: copy *name, action: || {option::None}, policy: IgnoreFailures, timeout: option::None}, ~[]);
+	}
+	
+	// the job which finished first is dropped
+	let jobs = registry.list();
+	assert jobs.map(|j| {copy j.name}) == ~[~"b", ~"c"];
+	assert jobs.all(|j| {j.restartable});
+}
+
+#[test]
+fn test_stale_rerun()
+{
+	fn tick(chan: oldcomm::Chan<()>) -> Job
+	{
+		let action: JobFn = || {oldcomm::send(chan, ()); option::None};
+		Job {name: ~"tick", action: action, policy: IgnoreFailures, timeout: option::None}
+	}
+	
+	fn wait_for_finish(scheduler: Scheduler)
+	{
+		while scheduler.list()[0].running
+		{
+			std::timer::sleep(uv::global_loop::get(), 10);
+		}
+	}
+	
+	let port = oldcomm::Port();
+	let chan = oldcomm::Chan(&port);
+	let scheduler = Scheduler(JobRegistry());
+	let make: fn~ () -> Job = || {tick(chan)};
+	scheduler.add(Schedule {name: ~"tick", when: Every(3600.0), jitter: 0.0, make: make});
+	
+	// restarting the latest run works
+	oldcomm::send(scheduler.chan, RerunScheduledMsg(~"tick", 0, tick(chan)));
+	oldcomm::recv(port);
+	wait_for_finish(scheduler);
+	assert scheduler.list()[0].runs == 1;
+	
+	// but restarts of older runs are ignored
+	oldcomm::send(scheduler.chan, RerunScheduledMsg(~"tick", 0, tick(chan)));
+	assert scheduler.list()[0].runs == 1;
+	
+	oldcomm::send(scheduler.chan, RerunScheduledMsg(~"tick", 1, tick(chan)));
+	oldcomm::recv(port);
+	wait_for_finish(scheduler);
+	assert scheduler.list()[0].runs == 2;
+}
+
+#[test]
+fn test_stop()
+{
+	let registry = JobRegistry();
+	let action: JobFn = || {utils::run_command_with_output(~"sh", ~[~"-c", ~"trap '' TERM; sleep 10"], |_line| {})};
+	let first = run(registry, Job {name: ~"stubborn 1", action: action, policy: IgnoreFailures, timeout: option::None}, ~[]);
+	let action: JobFn = || {utils::run_command_with_output(~"sh", ~[~"-c", ~"trap '' TERM; sleep 10"], |_line| {})};
+	let second = run(registry, Job {name: ~"stubborn 2", action: action, policy: IgnoreFailures, timeout: option::None}, ~[]);
+	while registry.list().len() < 2 || registry.list().any(|j| {j.state.to_str() != ~"running"})
+	{
+		std::timer::sleep(uv::global_loop::get(), 10);
+	}
+	std::timer::sleep(uv::global_loop::get(), 200);
+	
+	// both jobs are signalled before we wait so we only wait for one grace period
+	let start = utils::imprecise_time_s();
+	first.stop(1.0);
+	second.stop(1.0);
+	registry.wait_for_stopped(5.0);
+	assert utils::imprecise_time_s() - start < 1.9;
+	
+	while registry.list().any(|j| {j.state.to_str() == ~"running"})
+	{
+		std::timer::sleep(uv::global_loop::get(), 10);
+	}
+	assert registry.list().all(|j| {j.state.to_str() == ~"cancelled"});
+}
@@ -366,3 +366,37 @@ fn test_alerts()
 		~[@StringValue(~"fie", ~""), @BoolValue(true)],
 	]});
 }
+
+#[test]
+fn test_relation_overrides()
+{
+	let namespaces = ~[
+		Namespace {prefix: ~"devices", path: ~"http://network/"},
+		Namespace {prefix: ~"gnos", path: ~"http://www.gnos.org/2012/schema#"},
+	];
+	let store = Store(namespaces, &std::map::HashMap());
+	
+	// no relations yet but the override is still recorded
+	assert !set_relation_override(&store, "devices:a", "devices:b", "line-width", "4");
+	assert get_relation_overrides(&store, "devices:a", "devices:b") == ~[(~"line-width", ~"4")];
+	assert get_relation_overrides(&store, "devices:b", "devices:a") == ~[(~"line-width", ~"4")];
+	assert get_relation_overrides(&store, "devices:a", "devices:c").is_empty();
+	
+	// setting it again replaces the value
+	let relation = get_blank_name(&store, ~"relation");
+	store.add(copy relation, ~[
+		(~"gnos:left", @IriValue(~"devices:b")),
+		(~"gnos:right", @IriValue(~"devices:a")),
+		(~"gnos:style", @StringValue(~"line-type:directed line-width:3", ~"")),
+	]);
+	assert set_relation_override(&store, "devices:a", "devices:b", "line-width", "5");
+	assert get_relation_overrides(&store, "devices:a", "devices:b") == ~[(~"line-width", ~"5")];
+	match store.find_object(relation, ~"gnos:style")
+	{
+		option::Some(@StringValue(ref style, _)) => assert *style == ~"line-type:directed line-width:5",
+		ref x => fail fmt!("expected a style but found %?", x),
+	}
+	
+	assert merge_style("", "line-width", "2") == ~"line-width:2";
+	assert merge_style("line-width:3 line-color:red", "line-width", "2") == ~"line-color:red line-width:2";
+}
@@ -7,3 +7,38 @@ fn test_title_case()
 	assert title_case(~"Hmm") == ~"Hmm";
 	assert title_case(~"hmm") == ~"Hmm";
 }
+
+#[test]
+fn test_glob_match()
+{
+	assert glob_match(~"eth0", ~"eth0");
+	assert !glob_match(~"eth0", ~"eth01");
+	assert glob_match(~"*", ~"");
+	assert glob_match(~"*-out_octets", ~"10.0.0.1-eth0-out_octets");
+	assert !glob_match(~"*-out_octets", ~"10.0.0.1-eth0-in_octets");
+	assert glob_match(~"10.0.0.1-*-*_octets", ~"10.0.0.1-eth0-in_octets");
+	assert !glob_match(~"10.*", ~"110.0.0.1");
+	assert glob_match(~"*b*", ~"abc");
+	assert !glob_match(~"a*b", ~"ab c");
+	
+	// non-ascii names are matched by char
+	assert glob_match(~"caf\u00e9-*", ~"caf\u00e9-eth0");
+	assert !glob_match(~"caf\u00e9", ~"cafe");
+	assert glob_match(~"*\u00e9", ~"\u00e9\u00e9");
+	
+	// this used to take exponential time
+	assert !glob_match(~"*a*a*a*a*a*a*a*a*a*a*a*a*b", str::from_chars(vec::from_elem(200, 'a')));
+}
+
+#[test]
+fn test_wrap_remote_command()
+{
+	// the pid file is removed and the command's result code is preserved
+	let pid_file = fmt!("/tmp/gnos-test-%?.pid", os::getpid());
+	let err = run_command_with_output(~"sh", ~[~"-c", wrap_remote_command("test -s " + pid_file + "; exit 3", pid_file)], |_line| {});
+	assert err == option::Some(~"sh result code was 3");
+	assert !os::path_exists(&path::Path(pid_file));
+	
+	let err = run_command_with_output(~"sh", ~[~"-c", wrap_remote_command("test -s " + pid_file, pid_file)], |_line| {});
+	assert err.is_none();
+}
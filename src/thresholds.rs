@@ -0,0 +1,244 @@
+/// Declarative thresholds on sample sets which open and close alerts.
+///
+/// Thresholds are configured in the network json file, e.g.
+/// {"samples": "*-out_octets", "above": 80, "percent": true, "polls": 3}
+/// will open an alert if an interface's out bandwidth is above 80% of its
+/// speed for three polls in a row. To avoid chatter alerts aren't closed
+/// until the value falls below the clear value (which defaults to 90% of the
+/// threshold) for the same number of polls.
+///
+/// Thresholds may instead use a window, e.g. "window": 300 compares the mean
+/// of the last five minutes of samples against the threshold. Windows already
+/// smooth the samples so they can't be combined with polls.
+///
+/// Each threshold has its own alert so, for example, a sample set may have
+/// both a warning and an error threshold.
+use std::map::{HashMap};
+
+pub enum Comparison
+{
+	Above,
+	Below,
+}
+
+pub struct Threshold
+{
+	pub samples: ~str,				// glob pattern matched against sample set names
+	pub comparison: Comparison,
+	pub value: float,
+	pub clear: float,					// value at which the alert closes
+	pub percent: bool,				// if true value and clear are percentages of the sample's limit
+	pub polls: uint,					// number of consecutive samples which must cross value (or clear)
+	pub window: float,				// if non-zero the mean of the samples within window seconds is used (and polls must be 1)
+	pub level: ~str,					// "error", "warning", or "info"
+	pub mesg: ~str,					// prefix for the alert message
+	pub resolution: ~str,
+}
+
+pub struct Thresholds
+{
+	priv thresholds: ~[Threshold],
+	priv states: HashMap<@~str, @State>,		// "threshold index sample name" => state
+}
+
+pub fn Thresholds(thresholds: ~[Threshold]) -> Thresholds
+{
+	Thresholds {thresholds: thresholds, states: HashMap()}
+}
+
+pub impl Thresholds
+{
+	/// Should be called with each new sample. Returns alerts which should be
+	/// opened and (target, id) pairs for alerts which should be closed. Target
+	/// is the subject of the entity the sample is for.
+	fn check(name: &str, target: &str, value: float, limit: float, time: float) -> (~[model::Alert], ~[(~str, ~str)])
+	{
+		let mut opened = ~[];
+		let mut closed = ~[];
+		if float::is_NaN(value) || target.is_empty()
+		{
+			return (opened, closed);
+		}
+		
+		for self.thresholds.eachi |i, threshold|
+		{
+			// Note that percent thresholds are skipped if we don't know the interface speed (or whatever).
+			let scale = if threshold.percent {limit/100.0} else {1.0};
+			if utils::glob_match(threshold.samples, name) && !float::is_NaN(scale)
+			{
+				let key = @fmt!("%? %s", i, name);
+				let state = match self.states.find(key)
+				{
+					option::Some(s) => s,
+					option::None =>
+					{
+						let s = @State {active: false, count: 0, history: ~[]};
+						self.states.insert(key, s);
+						s
+					}
+				};
+				
+				let metric = state.get_metric(threshold.window, value, time);
+				let id = fmt!("%s threshold %?", name, i);
+				match state.update(threshold, metric, scale)
+				{
+					option::Some(true) =>
+					{
+						opened.push(model::Alert
+						{
+							target: target.to_owned(),
+							id: id,
+							mesg: fmt!("%s%s is %.1f (threshold is %.1f).", threshold.mesg, name, metric, threshold.value*scale),
+							resolution: copy threshold.resolution,
+							level: copy threshold.level,
+						});
+					}
+					option::Some(false) =>
+					{
+						closed.push((target.to_owned(), id));
+					}
+					option::None =>
+					{
+					}
+				}
+			}
+		}
+		
+		(opened, closed)
+	}
+}
+
+// ---- Internal Types --------------------------------------------------------
+priv struct State
+{
+	mut active: bool,						// true if the alert is open
+	mut count: uint,						// number of consecutive samples which have crossed the value (or clear value if active)
+	mut history: ~[(float, float)],		// (time, value) within the window
+}
+
+priv impl State
+{
+	fn get_metric(window: float, value: float, time: float) -> float
+	{
+		if window > 0.0
+		{
+			self.history.push((time, value));
+			self.history = do self.history.filter |entry| {entry.first() > time - window};
+			
+			let sum = do self.history.foldl(0.0) |total, entry| {*total + entry.second()};
+			sum/(self.history.len() as float)
+		}
+		else
+		{
+			value
+		}
+	}
+	
+	// Returns Some(true) if the alert should be opened, Some(false) if it should
+	// be closed, and None if nothing has changed.
+	fn update(threshold: &Threshold, metric: float, scale: float) -> option::Option<bool>
+	{
+		let crossed = if self.active
+			{
+				// Hysteresis: once open the alert stays open until the clear value is crossed.
+				match threshold.comparison
+				{
+					Above => metric < threshold.clear*scale,
+					Below => metric > threshold.clear*scale,
+				}
+			}
+			else
+			{
+				match threshold.comparison
+				{
+					Above => metric > threshold.value*scale,
+					Below => metric < threshold.value*scale,
+				}
+			};
+		
+		if crossed
+		{
+			self.count += 1;
+			if self.count >= threshold.polls
+			{
+				self.active = !self.active;
+				self.count = 0;
+				return option::Some(self.active);
+			}
+		}
+		else
+		{
+			self.count = 0;
+		}
+		option::None
+	}
+}
+
+#[test]
+fn test_polls_and_hysteresis()
+{
+	let threshold = Threshold {samples: ~"*-out_octets", comparison: Above, value: 80.0, clear: 70.0, percent: true, polls: 2, window: 0.0, level: ~"warning", mesg: ~"", resolution: ~""};
+	let thresholds = Thresholds(~[threshold]);
+	
+	// doesn't match
+	let (opened, _closed) = thresholds.check("eth0-in_octets", "entities:a", 1000.0, 100.0, 1.0);
+	assert opened.is_empty();
+	
+	// needs two polls
+	let (opened, _closed) = thresholds.check("eth0-out_octets", "entities:a", 90.0, 100.0, 1.0);
+	assert opened.is_empty();
+	let (opened, _closed) = thresholds.check("eth0-out_octets", "entities:a", 95.0, 100.0, 2.0);
+	assert opened.len() == 1;
+	assert opened[0].target == ~"entities:a";
+	assert opened[0].id == ~"eth0-out_octets threshold 0";
+	
+	// stays open between clear and value
+	let (opened, closed) = thresholds.check("eth0-out_octets", "entities:a", 75.0, 100.0, 3.0);
+	let (opened2, closed2) = thresholds.check("eth0-out_octets", "entities:a", 75.0, 100.0, 4.0);
+	assert opened.is_empty() && closed.is_empty() && opened2.is_empty() && closed2.is_empty();
+	
+	// closes after two polls below clear
+	let (_opened, closed) = thresholds.check("eth0-out_octets", "entities:a", 10.0, 100.0, 5.0);
+	assert closed.is_empty();
+	let (_opened, closed) = thresholds.check("eth0-out_octets", "entities:a", 10.0, 100.0, 6.0);
+	assert closed == ~[(~"entities:a", ~"eth0-out_octets threshold 0")];
+	
+	// percent thresholds need a limit
+	let (opened, _closed) = thresholds.check("eth1-out_octets", "entities:a", 1000.0, float::NaN, 7.0);
+	let (opened2, _closed) = thresholds.check("eth1-out_octets", "entities:a", 1000.0, float::NaN, 8.0);
+	assert opened.is_empty() && opened2.is_empty();
+}
+
+#[test]
+fn test_window()
+{
+	let threshold = Threshold {samples: ~"cpu", comparison: Below, value: 10.0, clear: 20.0, percent: false, polls: 1, window: 30.0, level: ~"info", mesg: ~"", resolution: ~""};
+	let thresholds = Thresholds(~[threshold]);
+	
+	let (opened, _closed) = thresholds.check("cpu", "entities:a", 15.0, float::NaN, 10.0);
+	assert opened.is_empty();
+	let (opened, _closed) = thresholds.check("cpu", "entities:a", 0.0, float::NaN, 20.0);
+	assert opened.len() == 1;		// mean is 7.5
+	
+	let (_opened, closed) = thresholds.check("cpu", "entities:a", 30.0, float::NaN, 30.0);
+	assert closed.is_empty();		// mean is 15
+	let (_opened, closed) = thresholds.check("cpu", "entities:a", 40.0, float::NaN, 45.0);
+	assert closed.len() == 1;		// mean is 23.3 (the first sample fell out of the window)
+}
+
+#[test]
+fn test_multiple_thresholds()
+{
+	let warning = Threshold {samples: ~"cpu", comparison: Above, value: 80.0, clear: 70.0, percent: false, polls: 1, window: 0.0, level: ~"warning", mesg: ~"", resolution: ~""};
+	let error = Threshold {samples: ~"cpu", comparison: Above, value: 95.0, clear: 90.0, percent: false, polls: 1, window: 0.0, level: ~"error", mesg: ~"", resolution: ~""};
+	let thresholds = Thresholds(~[warning, error]);
+	
+	let (opened, _closed) = thresholds.check("cpu", "entities:a", 99.0, float::NaN, 1.0);
+	assert opened.len() == 2;
+	assert opened[0].id != opened[1].id;
+	
+	// only the error alert closes
+	let (opened, closed) = thresholds.check("cpu", "entities:a", 85.0, float::NaN, 2.0);
+	assert opened.is_empty();
+	assert closed == ~[(~"entities:a", ~"cpu threshold 1")];
+}
@@ -0,0 +1,263 @@
+/// Consolidated (RRD-style) copies of the samples within a SampleSet.
+///
+/// Raw samples are only retained for a short time (about an hour). To allow
+/// charts to cover longer time spans each SampleSet also maintains tiers which
+/// consolidate rows into fixed size steps (e.g. one minute or fifteen minutes)
+/// and retain the min, mean, and max of each step.
+use std::map::{HashMap};
+use RingBuffer = ring_buffer::RingBuffer;
+
+/// Describes a tier: step is in seconds and capacity is the number of
+/// consolidated rows to retain (so the tier spans step*capacity seconds).
+pub struct TierSpec
+{
+	pub step: float,
+	pub capacity: uint,
+}
+
+/// Which of the consolidated values to use.
+pub enum Consolidation
+{
+	Minimum,
+	Average,
+	Maximum,
+}
+
+pub struct Tier
+{
+	pub spec: TierSpec,
+	priv times: @RingBuffer<float>,							// start time of each consolidated step
+	priv series: HashMap<@~str, @Consolidated>,			// sample name => consolidated values
+	priv pending: HashMap<@~str, @Accumulator>,			// sample name => values within the current step
+	priv mut step_start: float,							// start of the step being accumulated (NaN if none)
+	priv fill: float,
+}
+
+pub fn Tier(spec: TierSpec, fill: float) -> Tier
+{
+	let capacity = spec.capacity;
+	Tier {spec: spec, times: @RingBuffer(capacity), series: HashMap(), pending: HashMap(), step_start: float::NaN, fill: fill}
+}
+
+pub impl Tier
+{
+	pure fn len() -> uint
+	{
+		self.times.len()
+	}
+	
+	/// Returns the number of seconds covered by the tier once it fills up.
+	pure fn span() -> float
+	{
+		self.spec.step*(self.spec.capacity as float)
+	}
+	
+	/// Adds a completed row from a SampleSet. Rows must be added in time order.
+	fn add(time: float, values: &[(@~str, float)])
+	{
+		let start = float::floor(time/self.spec.step)*self.spec.step;
+		if float::is_NaN(self.step_start)
+		{
+			self.step_start = start;
+		}
+		else if start > self.step_start
+		{
+			self.flush();
+			
+			// Steps without any rows are filled so that the consolidated rows stay
+			// evenly spaced (only the newest capacity steps are retained anyway).
+			let skipped = uint::min(float::round((start - self.step_start)/self.spec.step) as uint - 1, self.spec.capacity);
+			for uint::range(0, skipped) |i|
+			{
+				self.push_fill(start - ((skipped - i) as float)*self.spec.step);
+			}
+			self.step_start = start;
+		}
+		
+		for values.each |entry|
+		{
+			let (name, value) = *entry;
+			if !float::is_NaN(value)
+			{
+				match self.pending.find(name)
+				{
+					option::Some(acc) =>
+					{
+						acc.add(value);
+					}
+					option::None =>
+					{
+						let acc = @Accumulator {min: float::infinity, max: float::neg_infinity, sum: 0.0, count: 0};
+						acc.add(value);
+						self.pending.insert(name, acc);
+					}
+				}
+			}
+		}
+	}
+	
+	fn get_times() -> RingBuffer<float>
+	{
+		copy *self.times
+	}
+	
+	/// Returns a copy of the named series. If the series does not exist then
+	/// a series of fill values is returned.
+	fn get(name: &str, kind: Consolidation) -> RingBuffer<float>
+	{
+		match self.series.find(@name.to_owned())
+		{
+			option::Some(c) =>
+			{
+				match kind
+				{
+					Minimum => copy c.min,
+					Average => copy c.mean,
+					Maximum => copy c.max,
+				}
+			}
+			option::None =>
+			{
+				self.new_buffer()
+			}
+		}
+	}
+	
+	// Moves the accumulated values into the consolidated buffers. Series without
+	// any samples in the step get the fill value.
+	priv fn flush()
+	{
+		// New series are prepended with fill values so that they line up with the older series.
+		for self.pending.each_key |name|
+		{
+			if !self.series.contains_key(name)
+			{
+				self.series.insert(name, @Consolidated {min: self.new_buffer(), mean: self.new_buffer(), max: self.new_buffer()});
+			}
+		}
+		
+		self.times.push(self.step_start);
+		for self.series.each |name, c|
+		{
+			match self.pending.find(name)
+			{
+				option::Some(acc) if acc.count > 0 =>
+				{
+					c.push(acc.min, acc.sum/(acc.count as float), acc.max);
+				}
+				_ =>
+				{
+					c.push(self.fill, self.fill, self.fill);
+				}
+			}
+		}
+		
+		self.pending.clear();
+	}
+	
+	priv fn push_fill(time: float)
+	{
+		self.times.push(time);
+		for self.series.each_value |c|
+		{
+			c.push(self.fill, self.fill, self.fill);
+		}
+	}
+	
+	// Returns a buffer with a fill value for each consolidated row.
+	priv fn new_buffer() -> RingBuffer<float>
+	{
+		let buffer = RingBuffer(self.spec.capacity);
+		for self.times.len().times
+		{
+			buffer.push(self.fill);
+		}
+		buffer
+	}
+}
+
+// ---- Internal Types --------------------------------------------------------
+priv struct Consolidated
+{
+	min: RingBuffer<float>,
+	mean: RingBuffer<float>,
+	max: RingBuffer<float>,
+}
+
+priv impl Consolidated
+{
+	fn push(min: float, mean: float, max: float)
+	{
+		self.min.push(min);
+		self.mean.push(mean);
+		self.max.push(max);
+	}
+}
+
+priv struct Accumulator
+{
+	mut min: float,
+	mut max: float,
+	mut sum: float,
+	mut count: uint,
+}
+
+priv impl Accumulator
+{
+	fn add(value: float)
+	{
+		if value < self.min {self.min = value}
+		if value > self.max {self.max = value}
+		self.sum += value;
+		self.count += 1;
+	}
+}
+
+#[test]
+fn test_tier()
+{
+	let tier = Tier(TierSpec {step: 60.0, capacity: 2}, 0.0);
+	let eth0 = @~"eth0";
+	let eth1 = @~"eth1";
+	
+	// first step
+	tier.add(60.0, ~[(eth0, 1.0)]);
+	tier.add(80.0, ~[(eth0, 3.0)]);
+	tier.add(100.0, ~[(eth0, 5.0)]);
+	assert tier.len() == 0;
+	
+	// second step (eth1 shows up and eth0 has a gap)
+	tier.add(120.0, ~[(eth0, float::NaN), (eth1, 10.0)]);
+	assert tier.len() == 1;
+	assert tier.get_times()[0] == 60.0;
+	assert tier.get("eth0", Minimum)[0] == 1.0;
+	assert tier.get("eth0", Average)[0] == 3.0;
+	assert tier.get("eth0", Maximum)[0] == 5.0;
+	
+	// third step (skips the 180 step which is filled)
+	tier.add(250.0, ~[(eth0, 2.0), (eth1, 20.0)]);
+	assert tier.len() == 2;
+	assert tier.get_times()[0] == 120.0;
+	assert tier.get_times()[1] == 180.0;
+	assert tier.get("eth0", Average)[0] == 0.0;
+	assert tier.get("eth1", Average)[0] == 10.0;
+	assert tier.get("eth1", Average)[1] == 0.0;
+	
+	// fourth step (oldest step is dropped)
+	tier.add(300.0, ~[(eth0, 2.0), (eth1, 20.0)]);
+	let times = tier.get_times();
+	assert times.len() == 2;
+	assert times[0] == 180.0;
+	assert times[1] == 240.0;
+	assert tier.get("eth1", Maximum)[0] == 0.0;
+	assert tier.get("eth1", Maximum)[1] == 20.0;
+	assert tier.get("lo", Maximum)[1] == 0.0;
+	
+	// long gaps only fill the steps which are retained
+	tier.add(60000.0, ~[(eth0, 2.0)]);
+	let times = tier.get_times();
+	assert times.len() == 2;
+	assert times[0] == 59880.0;
+	assert times[1] == 59940.0;
+	assert tier.get("eth1", Maximum)[1] == 0.0;
+}
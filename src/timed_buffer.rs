@@ -0,0 +1,676 @@
+/// Fixed size buffer of timestamped entries: when it is at capacity pushes drop
+/// the oldest entry.
+///
+/// Entries are stored in fixed size segments. Once a segment fills up it is
+/// frozen and shared (using an ARC) with any snapshots taken of the buffer so
+/// readers in other tasks can be handed large buffers without copying them.
+/// Entries must be pushed in time order which allows the buffer to be queried
+/// by time using a binary search.
+use std::arc::{ARC};
+use RingBuffer = ring_buffer::RingBuffer;
+
+pub struct TimedBuffer<T: Copy Const Owned>
+{
+	priv mut segments: ~[ARC<~[(float, T)]>],	// full segments, oldest first
+	priv mut tail: ~[(float, T)],				// segment new entries land in (holds the newest entry if size > 0)
+	priv mut start: uint,						// index of the oldest entry within the first segment
+	priv capacity: uint,
+	priv mut size: uint,
+}
+
+pub fn TimedBuffer<T: Copy Const Owned>(capacity: uint) -> TimedBuffer<T>
+{
+	TimedBuffer {segments: ~[], tail: ~[], start: 0, capacity: capacity, size: 0}
+}
+
+pub impl<T: Copy Const Owned> TimedBuffer<T>
+{
+	pure fn len() -> uint
+	{
+		self.size
+	}
+	
+	pure fn is_empty() -> bool
+	{
+		self.size == 0
+	}
+	
+	pure fn is_not_empty() -> bool
+	{
+		self.size != 0
+	}
+	
+	/// Returns the entry at index (0 is the oldest entry).
+	pure fn get(index: uint) -> (float, T)
+	{
+		assert index < self.size;
+		get_entry(self.segments, self.tail, self.start + index)
+	}
+	
+	/// Returns the most recently pushed entry.
+	pure fn last() -> (float, T)
+	{
+		assert self.size > 0;
+		self.tail.last()
+	}
+	
+	/// Time should be no older than the last entry's time. Returns the entry
+	/// that was dropped to make room for the new entry.
+	fn push(time: float, value: T) -> option::Option<(float, T)>
+	{
+		assert self.capacity > 0;
+		assert self.size == 0 || time >= self.last().first();
+		
+		let mut dropped = option::None;
+		if self.size == self.capacity
+		{
+			dropped = option::Some(self.get(0));
+			if self.segments.is_empty()
+			{
+				self.tail.shift();
+			}
+			else
+			{
+				self.start += 1;
+				if self.start == segment_size
+				{
+					self.segments.shift();
+					self.start = 0;
+				}
+			}
+		}
+		else
+		{
+			self.size += 1;
+		}
+		
+		if self.tail.len() == segment_size
+		{
+			let mut full = ~[];
+			full <-> self.tail;
+			self.segments.push(ARC(full));
+		}
+		self.tail.push((time, value));
+		dropped
+	}
+	
+	/// Replaces the value of the most recently pushed entry and returns the old value.
+	fn set_last(value: T) -> T
+	{
+		assert self.size > 0;
+		
+		let index = self.tail.len() - 1;
+		let (time, old) = self.tail[index];
+		self.tail[index] = (time, value);
+		old
+	}
+	
+	/// Returns all of the entries.
+	fn snapshot() -> Snapshot<T>
+	{
+		self.slice(0, self.size)
+	}
+	
+	/// Returns the entries with times >= time.
+	fn since(time: float) -> Snapshot<T>
+	{
+		let first = self.lower_bound(time);
+		self.slice(first, self.size)
+	}
+	
+	/// Returns the entries with times within [from, to].
+	fn range(from: float, to: float) -> Snapshot<T>
+	{
+		let first = self.lower_bound(from);
+		let mut last = first;
+		while last < self.size && self.get(last).first() <= to
+		{
+			last += 1;
+		}
+		self.slice(first, last)
+	}
+	
+	// Full segments are shared with the snapshot, the tail is copied.
+	priv fn slice(first: uint, last: uint) -> Snapshot<T>
+	{
+		let first_entry = self.start + first;
+		let last_entry = self.start + last;
+		
+		let skip = uint::min(first_entry/segment_size, self.segments.len());
+		let end = uint::min((last_entry + segment_size - 1)/segment_size, self.segments.len());
+		let segments = do vec::from_fn(end - skip) |i| {std::arc::clone(&self.segments[skip + i])};
+		
+		let tail_start = self.segments.len()*segment_size;
+		let tail = if last_entry > tail_start {vec::slice(self.tail, 0, last_entry - tail_start)} else {~[]};
+		
+		Snapshot {segments: segments, tail: tail, start: first_entry - skip*segment_size, size: last - first}
+	}
+	
+	// Returns the index of the first entry with a time >= time.
+	priv fn lower_bound(time: float) -> uint
+	{
+		let mut low = 0;
+		let mut high = self.size;
+		while low < high
+		{
+			let middle = (low + high)/2;
+			if self.get(middle).first() < time
+			{
+				low = middle + 1;
+			}
+			else
+			{
+				high = middle;
+			}
+		}
+		low
+	}
+}
+
+/// Immutable view of some of the entries in a TimedBuffer. These can be sent to
+/// other tasks.
+pub struct Snapshot<T: Copy Const Owned>
+{
+	priv segments: ~[ARC<~[(float, T)]>],
+	priv tail: ~[(float, T)],
+	priv start: uint,
+	priv size: uint,
+}
+
+/// Returns a snapshot with no entries.
+pub fn Snapshot<T: Copy Const Owned>() -> Snapshot<T>
+{
+	Snapshot {segments: ~[], tail: ~[], start: 0, size: 0}
+}
+
+pub impl<T: Copy Const Owned> Snapshot<T>
+{
+	pure fn len() -> uint
+	{
+		self.size
+	}
+	
+	pure fn is_empty() -> bool
+	{
+		self.size == 0
+	}
+	
+	pure fn is_not_empty() -> bool
+	{
+		self.size != 0
+	}
+	
+	/// Returns the entry at index (0 is the oldest entry).
+	pure fn get(index: uint) -> (float, T)
+	{
+		assert index < self.size;
+		get_entry(self.segments, self.tail, self.start + index)
+	}
+	
+	/// Returns a copy of the times.
+	fn get_times() -> RingBuffer<float>
+	{
+		let buffer = RingBuffer(self.size);
+		for uint::range(0, self.size) |i| {buffer.push(self.get(i).first());}
+		buffer
+	}
+	
+	/// Returns a copy of the values.
+	fn get_values() -> RingBuffer<T>
+	{
+		let buffer = RingBuffer(self.size);
+		for uint::range(0, self.size) |i| {buffer.push(self.get(i).second());}
+		buffer
+	}
+}
+
+pub impl<T: Copy Const Owned> Snapshot<T> : BaseIter<(float, T)>
+{
+	pure fn each(&self, blk: fn(v: &(float, T)) -> bool)
+	{
+		let mut i = 0;
+		while i < self.size
+		{
+			if !blk(&self.get(i))
+			{
+				break;
+			}
+			i += 1;
+		}
+	}
+	
+	pure fn size_hint(&self) -> option::Option<uint>
+	{
+		option::Some(self.size)
+	}
+}
+
+/// Statistics for the non-NaN values within a window of samples. Values are
+/// added as they enter the window and removed as they leave it so that the
+/// statistics don't have to be recomputed from scratch. The values are kept in
+/// a treap (a binary tree ordered by value which is kept balanced by randomly
+/// assigned priorities) so adding, removing, and finding percentiles are all
+/// O(log n).
+pub struct WindowStats
+{
+	priv mut nodes: ~[Node],			// nodes[0] is the empty tree
+	priv mut root: uint,
+	priv mut free: ~[uint],			// indexes of nodes which can be re-used
+	priv mut seed: uint,				// used to generate priorities
+	priv mut mean: float,			// these are updated using Welford's method (summing squares loses
+	priv mut m2: float,				// too much precision with large values like byte counters)
+	priv mut last: float,			// most recently added value
+}
+
+pub fn WindowStats() -> WindowStats
+{
+	WindowStats {nodes: ~[empty_node], root: 0, free: ~[], seed: 1, mean: 0.0, m2: 0.0, last: float::NaN}
+}
+
+pub impl WindowStats
+{
+	/// Returns the number of non-NaN values in the window.
+	pure fn count() -> uint
+	{
+		self.nodes[self.root].size
+	}
+	
+	// These are NaN if the window has no values.
+	pure fn min() -> float
+	{
+		if self.count() == 0 {float::NaN} else {self.nth(0)}
+	}
+	
+	pure fn max() -> float
+	{
+		if self.count() == 0 {float::NaN} else {self.nth(self.count() - 1)}
+	}
+	
+	pure fn mean() -> float
+	{
+		if self.count() == 0 {float::NaN} else {self.mean}
+	}
+	
+	pure fn last() -> float
+	{
+		if self.count() == 0 {float::NaN} else {self.last}
+	}
+	
+	pure fn stddev() -> float
+	{
+		if self.count() == 0
+		{
+			float::NaN
+		}
+		else
+		{
+			let variance = self.m2/(self.count() as float);
+			if variance > 0.0 {float::sqrt(variance)} else {0.0}	// rounding can make the variance slightly negative
+		}
+	}
+	
+	/// Uses the same interpolation as utils::get_percentile.
+	pure fn percentile(percent: float) -> float
+	{
+		let count = self.count();
+		if count == 0
+		{
+			float::NaN
+		}
+		else
+		{
+			let rank = (percent/100.0)*((count - 1) as float);
+			let lower = float::floor(rank) as uint;
+			let upper = uint::min(lower + 1, count - 1);
+			let value = self.nth(lower);
+			value + (rank - (lower as float))*(self.nth(upper) - value)
+		}
+	}
+	
+	fn add(value: float)
+	{
+		if !float::is_NaN(value)
+		{
+			let index = self.new_node(value);
+			self.root = self.insert(self.root, index);
+			let delta = value - self.mean;
+			self.mean += delta/(self.count() as float);
+			self.m2 += delta*(value - self.mean);
+			self.last = value;
+		}
+	}
+	
+	fn remove(value: float)
+	{
+		if !float::is_NaN(value)
+		{
+			let (root, removed) = self.remove_value(self.root, value);
+			self.root = root;
+			if removed
+			{
+				if root == 0
+				{
+					// Don't let rounding errors accumulate forever.
+					self.mean = 0.0;
+					self.m2 = 0.0;
+					self.nodes = ~[empty_node];
+					self.free = ~[];
+				}
+				else
+				{
+					let old_mean = self.mean;
+					self.mean -= (value - old_mean)/(self.count() as float);
+					self.m2 -= (value - old_mean)*(value - self.mean);
+				}
+			}
+		}
+	}
+}
+
+// ---- Internal Types --------------------------------------------------------
+priv struct Node
+{
+	value: float,
+	priority: uint,		// parents have higher priorities than their children
+	size: uint,			// number of nodes in this subtree
+	left: uint,			// index of the subtree with values <= value (0 if none)
+	right: uint,			// index of the subtree with values >= value (0 if none)
+}
+
+priv const empty_node: Node = Node {value: 0.0, priority: 0, size: 0, left: 0, right: 0};
+
+priv impl WindowStats
+{
+	// Returns the index-th smallest value.
+	pure fn nth(index: uint) -> float
+	{
+		let mut tree = self.root;
+		let mut index = index;
+		loop
+		{
+			let node = self.nodes[tree];
+			let left = self.nodes[node.left].size;
+			if index < left
+			{
+				tree = node.left;
+			}
+			else if index == left
+			{
+				return node.value;
+			}
+			else
+			{
+				index -= left + 1;
+				tree = node.right;
+			}
+		}
+	}
+	
+	fn new_node(value: float) -> uint
+	{
+		// This is the LCG from the C standard: plenty good enough for balancing a tree.
+		self.seed = (self.seed*1103515245 + 12345) % 2147483648;
+		let node = Node {value: value, priority: self.seed, size: 1, left: 0, right: 0};
+		if self.free.is_empty()
+		{
+			self.nodes.push(node);
+			self.nodes.len() - 1
+		}
+		else
+		{
+			let index = self.free.pop();
+			self.nodes[index] = node;
+			index
+		}
+	}
+	
+	fn update_size(tree: uint)
+	{
+		let node = self.nodes[tree];
+		self.nodes[tree] = Node {size: 1 + self.nodes[node.left].size + self.nodes[node.right].size, ..node};
+	}
+	
+	// Inserts the index node into tree and returns the new root of tree.
+	fn insert(tree: uint, index: uint) -> uint
+	{
+		if tree == 0
+		{
+			return index;
+		}
+		
+		let node = self.nodes[tree];
+		if self.nodes[index].value < node.value
+		{
+			let left = self.insert(node.left, index);
+			self.nodes[tree] = Node {left: left, size: node.size + 1, ..node};
+			if self.nodes[left].priority > node.priority {self.rotate_right(tree)} else {tree}
+		}
+		else
+		{
+			let right = self.insert(node.right, index);
+			self.nodes[tree] = Node {right: right, size: node.size + 1, ..node};
+			if self.nodes[right].priority > node.priority {self.rotate_left(tree)} else {tree}
+		}
+	}
+	
+	// Removes one node with value from tree and returns the new root of tree
+	// along with whether a node was removed.
+	fn remove_value(tree: uint, value: float) -> (uint, bool)
+	{
+		if tree == 0
+		{
+			return (0, false);
+		}
+		
+		let node = self.nodes[tree];
+		if value == node.value
+		{
+			self.free.push(tree);
+			(self.merge(node.left, node.right), true)
+		}
+		else if value < node.value
+		{
+			let (left, removed) = self.remove_value(node.left, value);
+			self.nodes[tree] = Node {left: left, size: if removed {node.size - 1} else {node.size}, ..node};
+			(tree, removed)
+		}
+		else
+		{
+			let (right, removed) = self.remove_value(node.right, value);
+			self.nodes[tree] = Node {right: right, size: if removed {node.size - 1} else {node.size}, ..node};
+			(tree, removed)
+		}
+	}
+	
+	// All of the values in lhs should be <= the values in rhs.
+	fn merge(lhs: uint, rhs: uint) -> uint
+	{
+		if lhs == 0
+		{
+			return rhs;
+		}
+		if rhs == 0
+		{
+			return lhs;
+		}
+		
+		let (x, y) = (self.nodes[lhs], self.nodes[rhs]);
+		if x.priority > y.priority
+		{
+			let right = self.merge(x.right, rhs);
+			self.nodes[lhs] = Node {right: right, size: x.size + y.size, ..x};
+			lhs
+		}
+		else
+		{
+			let left = self.merge(lhs, y.left);
+			self.nodes[rhs] = Node {left: left, size: x.size + y.size, ..y};
+			rhs
+		}
+	}
+	
+	fn rotate_right(tree: uint) -> uint
+	{
+		let node = self.nodes[tree];
+		let pivot = self.nodes[node.left];
+		self.nodes[tree] = Node {left: pivot.right, ..node};
+		self.update_size(tree);
+		self.nodes[node.left] = Node {right: tree, ..pivot};
+		self.update_size(node.left);
+		node.left
+	}
+	
+	fn rotate_left(tree: uint) -> uint
+	{
+		let node = self.nodes[tree];
+		let pivot = self.nodes[node.right];
+		self.nodes[tree] = Node {right: pivot.left, ..node};
+		self.update_size(tree);
+		self.nodes[node.right] = Node {left: tree, ..pivot};
+		self.update_size(node.right);
+		node.right
+	}
+}
+
+// ---- Internal Functions ----------------------------------------------------
+
+// Number of entries in each shared segment.
+priv const segment_size: uint = 64;
+
+// Index is relative to the start of the first segment.
+priv pure fn get_entry<T: Copy Const Owned>(segments: &[ARC<~[(float, T)]>], tail: &[(float, T)], index: uint) -> (float, T)
+{
+	let segment = index/segment_size;
+	if segment < segments.len()
+	{
+		let entries = std::arc::get(&segments[segment]);
+		entries[index % segment_size]
+	}
+	else
+	{
+		tail[index - segments.len()*segment_size]
+	}
+}
+
+#[test]
+fn test_timed_buffer()
+{
+	let buffer = TimedBuffer(150);
+	assert buffer.is_empty();
+	
+	for uint::range(0, 200) |i|
+	{
+		let dropped = buffer.push(i as float, 2*i);
+		if i < 150 {assert dropped.is_none();} else {assert dropped == option::Some(((i - 150) as float, 2*(i - 150)));}
+	}
+	assert buffer.len() == 150;
+	assert buffer.get(0) == (50.0, 100);
+	assert buffer.get(149) == (199.0, 398);
+	assert buffer.last() == (199.0, 398);
+	
+	assert buffer.set_last(7) == 398;
+	assert buffer.last() == (199.0, 7);
+	
+	let snapshot = buffer.snapshot();
+	assert snapshot.len() == 150;
+	assert snapshot.get(0) == (50.0, 100);
+	assert snapshot.get(100) == (150.0, 300);
+	
+	// snapshots are not affected by later pushes
+	buffer.push(200.0, 0);
+	assert snapshot.get(0) == (50.0, 100);
+	assert snapshot.get(149) == (199.0, 7);
+	
+	let snapshot = buffer.since(190.5);
+	assert snapshot.len() == 10;
+	assert snapshot.get(0) == (191.0, 382);
+	assert snapshot.get_values()[9] == 0;
+	
+	let snapshot = buffer.range(60.0, 69.0);
+	assert snapshot.len() == 10;
+	assert snapshot.get_times()[0] == 60.0;
+	assert snapshot.get_times()[9] == 69.0;
+	
+	assert buffer.since(500.0).is_empty();
+	assert buffer.range(0.0, 10.0).is_empty();
+}
+
+#[test]
+fn test_window_stats()
+{
+	let stats = WindowStats();
+	assert stats.count() == 0;
+	assert float::is_NaN(stats.max());
+	
+	for [-4.0, 3.0, float::NaN, -1.0, 2.0, -5.0].each |x| {stats.add(*x);}
+	assert stats.count() == 5;
+	assert stats.min() == -5.0;
+	assert stats.max() == 3.0;
+	assert stats.mean() == -1.0;
+	assert stats.percentile(50.0) == -1.0;
+	assert stats.last() == -5.0;
+	assert float::abs(stats.stddev() - float::sqrt(10.0)) < 0.001;
+	
+	stats.remove(3.0);
+	stats.remove(float::NaN);
+	assert stats.count() == 4;
+	assert stats.max() == 2.0;
+	assert stats.mean() == -2.0;
+	
+	for [-4.0, -1.0, 2.0, -5.0].each |x| {stats.remove(*x);}
+	assert stats.count() == 0;
+	assert float::is_NaN(stats.mean());
+}
+
+#[test]
+fn test_window_stats_precision()
+{
+	// Byte counters are large but nearly constant (which is bad for sums of squares).
+	let stats = WindowStats();
+	for uint::range(0, 1000) |i|
+	{
+		stats.add(1.0e12 + ((i % 3) as float));
+		if i >= 100
+		{
+			stats.remove(1.0e12 + (((i - 100) % 3) as float));
+		}
+	}
+	assert stats.count() == 100;
+	assert float::abs(stats.stddev() - 0.8185) < 0.001;
+}
+
+#[test]
+fn test_window_stats_order()
+{
+	// Compare against a sorted vector using a window with lots of duplicates.
+	let stats = WindowStats();
+	let mut window = ~[];
+	let mut x = 7;
+	for uint::range(0, 2000) |i|
+	{
+		x = (x*31 + 11) % 101;
+		let value = (x % 50) as float;
+		stats.add(value);
+		window.push(value);
+		if i >= 500
+		{
+			stats.remove(window.shift());
+		}
+		
+		if i % 97 == 0
+		{
+			let sorted = std::sort::merge_sort(window, |a, b| {*a <= *b});
+			assert stats.count() == sorted.len();
+			assert stats.min() == sorted[0];
+			assert stats.max() == sorted.last();
+			for [0.0, 25.0, 50.0, 95.0, 100.0].each |p|
+			{
+				assert stats.percentile(*p) == utils::get_percentile(sorted, *p);
+			}
+		}
+	}
+	
+	// removing a value which isn't there does nothing
+	stats.remove(1000.0);
+	assert stats.count() == 500;
+}
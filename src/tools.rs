@@ -0,0 +1,497 @@
+/// Runs tools like ping, traceroute, and tcpdump against entities on behalf
+/// of admins (see handlers/post_tool.rs).
+///
+/// Tools are command line templates which are run on the network json client
+/// (using ssh unless the client is "local"). Templates may use:
+/// * {ip} which is replaced with the target's ip address,
+/// * {artifact} which is replaced with the path of a file the tool should
+/// write (only for tools with an artifact extension).
+///
+/// Additional tools (e.g. mping or mgen) can be added using the tools field in
+/// the network json file. Lines the tool writes to stdout or stderr are pushed
+/// to clients (see handlers/sse_tool.rs) and, if the tool succeeds, its
+/// artifact is copied into options.artifacts_dir so that admins can download
+/// it (see handlers/get_tool_artifact.rs). Artifacts have unguessable names and
+/// are deleted when their run is dropped.
+use oldcomm::{Chan};
+use std::json::{Json};
+use std::map::*;
+use Path = path::Path;
+use options::{Options, Device};
+use task_runner::{Job, JobFn, JobRegistry};
+
+pub struct Tool
+{
+	pub name: ~str,
+	pub command: ~str,			// template for the command line
+	pub description: ~str,		// html
+	pub artifact: ~str,			// extension of the file the tool writes to {artifact} (empty if the tool only writes to stdout)
+	pub timeout: float,			// seconds
+}
+
+pub fn default_tools() -> ~[Tool]
+{
+	let tool = |name: &str, command: &str, description: &str, artifact: &str, timeout: float|
+	{
+		Tool {name: name.to_owned(), command: command.to_owned(), description: description.to_owned(), artifact: artifact.to_owned(), timeout: timeout}
+	};
+	
+	~[
+		tool("ping", "ping -c 10 {ip}", "Sends ten ICMP echo requests. See <a href = 'http://linux.die.net/man/8/ping'>ping(8)</a>.", "", 30.0),
+		tool("traceroute", "traceroute -n -w 2 {ip}", "Lists the routers along the path to the entity. See <a href = 'http://linux.die.net/man/8/traceroute'>traceroute(8)</a>.", "", 120.0),
+		tool("tcpdump", "tcpdump -n -c 1000 -w {artifact} host {ip}", "Captures up to 1000 packets to or from the entity into a pcap file (which can be opened with wireshark). Note that tcpdump normally has to run as root on the client. See <a href = 'http://linux.die.net/man/8/tcpdump'>tcpdump(8)</a>.", "pcap", 120.0),
+	]
+}
+
+/// Returns the tools from the network json file followed by the default tools
+/// which they don't replace.
+pub fn get_tools(options: &Options) -> ~[Tool]
+{
+	let defaults = do default_tools().filter |t| {!options.tools.any(|u| {u.name == t.name})};
+	options.tools + defaults
+}
+
+pub fn expand_command(tool: &Tool, ip: &str, artifact: &str) -> ~str
+{
+	str::replace(str::replace(tool.command, "{ip}", ip), "{artifact}", artifact)
+}
+
+/// Returns the ip address of a target which must be an entity subject for a
+/// device (e.g. "entities:10.0.0.1" or "entities:rtr"). Other hosts are not
+/// allowed so that the tools can't be pointed at arbitrary machines.
+pub fn get_target_ip(devices: &[Device], target: &str) -> option::Option<~str>
+{
+	if !target.starts_with("entities:")
+	{
+		return option::None;
+	}
+	
+	let id = target.slice(9, target.len());
+	do devices.find(|d| {(d.name == id || d.managed_ip == id) && is_ip_address(d.managed_ip)}).map |device| {copy device.managed_ip}
+}
+
+/// Target ip addresses are substituted into commands so this is strict enough
+/// that they can't be used to inject shell code.
+pub pure fn is_ip_address(text: &str) -> bool
+{
+	text.is_not_empty() && text.len() <= 45 && str::all(text, |c| {char::is_digit_radix(c, 16) || c == '.' || c == ':'}) && (text.contains_char('.') || text.contains_char(':'))
+}
+
+/// Returns true if name could be the file name of an artifact (this keeps
+/// clients from escaping the artifacts directory).
+pub pure fn is_artifact_name(name: &str) -> bool
+{
+	name.is_not_empty() && !name.starts_with(".") && str::all(name, |c| {is_file_char(c) || c == '.'})
+}
+
+/// Handle to the task which records the output of tool runs.
+pub struct ToolRuns
+{
+	priv chan: Chan<RunMsg>,
+}
+
+/// Artifacts left over from a previous gnos are deleted.
+pub fn ToolRuns(artifacts_dir: &Path) -> ToolRuns
+{
+	for os::list_dir_path(artifacts_dir).each |path|
+	{
+		os::remove_file(*path);
+	}
+	
+	let chan = do utils::spawn_moded_listener(task::ThreadPerCore) |port| {manage_runs(port)};
+	ToolRuns {chan: chan}
+}
+
+pub impl ToolRuns
+{
+	/// Runs the tool against ip as a task_runner job (so it can be cancelled
+	/// using /jobs/cancel). Returns the run's id and the command line.
+	fn start(options: &Options, registry: JobRegistry, tool: &Tool, ip: &str) -> (uint, ~str)
+	{
+		let file = if tool.artifact.is_empty() {~""} else {fmt!("%s-%s.%s", to_file_name(tool.name), get_token(), to_file_name(tool.artifact))};
+		let local_path = if file.is_empty() {~""} else {options.artifacts_dir.push(copy file).to_str()};
+		
+		let port = oldcomm::Port();
+		let reply = Chan(&port);
+		oldcomm::send(self.chan, CreateMsg(copy tool.name, ip.to_owned(), copy local_path, reply));
+		let id = oldcomm::recv(port);
+		
+		let local = options.client_ip == ~"local";
+		let artifact = if file.is_empty() {~""} else if local {copy local_path} else {~"/tmp/gnos-" + file};
+		let command = expand_command(tool, ip, artifact);
+		
+		let runs = copy self;
+		let host = copy options.client_ip;
+		let action: JobFn = |copy command, move host, move artifact, copy local_path|
+		{
+			let on_line = |line: &str| {oldcomm::send(runs.chan, LineMsg(id, line.to_owned()))};
+			if local_path.is_not_empty()
+			{
+				let dir = Path(local_path).dir_path();
+				if !os::path_is_dir(&dir)
+				{
+					os::make_dir(&dir, 7*8*8 + 7*8 + 7);
+				}
+			}
+			
+			if local
+			{
+				utils::run_command_with_output(~"sh", ~[~"-c", copy command], on_line)
+			}
+			else
+			{
+				// The remote artifact is removed whether or not the tool succeeded (it may
+				// have written part of the file before failing or being cancelled).
+				let err = utils::run_remote_command_with_output(env!("GNOS_USER"), host, command, on_line);
+				if artifact.is_not_empty()
+				{
+					let err = if err.is_none() {utils::scp_remote_file(env!("GNOS_USER"), host, artifact, &Path(local_path))} else {err};
+					utils::run_remote_command(env!("GNOS_USER"), host, ~"rm -f " + artifact);
+					err
+				}
+				else
+				{
+					err
+				}
+			}
+		};
+		let notify: fn~ (option::Option<~str>) = |err, move local_path| {oldcomm::send(runs.chan, DoneMsg(id, err, copy local_path))};
+		
+		let name = fmt!("%s %s #%?", tool.name, ip, id);
+		task_runner::run(registry, Job {name: name, action: action, policy: task_runner::NotifyOnExit(notify), timeout: option::Some(tool.timeout)}, ~[]);
+		(id, command)
+	}
+	
+	/// Sends chan the json events recorded for the run and then each new event
+	/// as it happens. Events are objects of the form {"line": "64 bytes from..."}
+	/// or, when the tool exits, {"done": true, "error": null, "artifact": "/tool-artifacts/tcpdump-1f0c...pcap"}
+	/// (artifact is null if the tool has no artifact or failed). Returns false
+	/// if the run is unknown (runs are dropped after a while).
+	fn register(id: uint, key: ~str, chan: Chan<~str>) -> bool
+	{
+		let port = oldcomm::Port();
+		let reply = Chan(&port);
+		oldcomm::send(self.chan, RegisterMsg(id, key, chan, reply));
+		oldcomm::recv(port)
+	}
+	
+	fn deregister(id: uint, key: ~str)
+	{
+		oldcomm::send(self.chan, DeregisterMsg(id, key));
+	}
+}
+
+// ---- Internal Types --------------------------------------------------------
+priv enum RunMsg
+{
+	CreateMsg(~str, ~str, ~str, Chan<uint>),					// tool name + ip + artifact path (empty if none) + reply
+	LineMsg(uint, ~str),										// id + line
+	DoneMsg(uint, option::Option<~str>, ~str),					// id + error + artifact path
+	RegisterMsg(uint, ~str, Chan<~str>, Chan<bool>),			// id + key + listener + reply
+	DeregisterMsg(uint, ~str),									// id + key
+}
+
+priv struct Run
+{
+	artifact: ~str,								// local path (empty if the tool has no artifact)
+	mut events: ~[~str],						// json
+	mut dropped: uint,							// number of lines which weren't recorded
+	mut done: bool,
+	listeners: HashMap<~str, Chan<~str>>,
+}
+
+priv impl Run
+{
+	fn add_event(event: Json)
+	{
+		let event = event.to_str();
+		self.events.push(copy event);
+		for self.listeners.each_value |chan|
+		{
+			oldcomm::send(chan, copy event);
+		}
+	}
+}
+
+// Number of runs retained (older runs are dropped).
+priv const max_runs: uint = 50;
+
+// Number of lines recorded for each run.
+priv const max_lines: uint = 10000;
+
+// ---- Internal Functions ----------------------------------------------------
+priv fn manage_runs(port: oldcomm::Port<RunMsg>)
+{
+	let runs: HashMap<uint, @Run> = HashMap();
+	let mut next_id = 1;
+	loop
+	{
+		match oldcomm::recv(port)
+		{
+			CreateMsg(tool, ip, artifact, reply) =>
+			{
+				info!("running %s against %s (run %?)", tool, ip, next_id);
+				runs.insert(next_id, @Run {artifact: artifact, events: ~[], dropped: 0, done: false, listeners: HashMap()});
+				if next_id > max_runs
+				{
+					match runs.find(next_id - max_runs)
+					{
+						option::Some(run) => remove_artifact(run.artifact),
+						option::None => {}
+					}
+					runs.remove(next_id - max_runs);
+				}
+				oldcomm::send(reply, next_id);
+				next_id += 1;
+			}
+			LineMsg(id, line) =>
+			{
+				match runs.find(id)
+				{
+					option::Some(run) if run.events.len() < max_lines => run.add_event(utils::to_object(~[(~"line", std::json::String(line))])),
+					option::Some(run) => run.dropped += 1,
+					option::None => {}
+				}
+			}
+			DoneMsg(id, err, artifact) =>
+			{
+				match runs.find(id)
+				{
+					option::Some(run) =>
+					{
+						let name = Path(run.artifact).filename().get_default(~"");
+						let url = if name.is_not_empty() && err.is_none() {~"/tool-artifacts/" + name} else {~""};
+						if err.is_some()
+						{
+							remove_artifact(run.artifact);
+						}
+						if run.dropped > 0
+						{
+							run.add_event(utils::to_object(~[(~"line", std::json::String(fmt!("[%? lines were dropped]", run.dropped)))]));
+						}
+						run.add_event(utils::to_object(~[
+							(~"done", std::json::Boolean(true)),
+							(~"error", match err {option::Some(copy e) => std::json::String(e), option::None => std::json::Null}),
+							(~"artifact", if url.is_not_empty() {std::json::String(url)} else {std::json::Null}),
+						]));
+						run.done = true;
+					}
+					option::None =>
+					{
+						remove_artifact(artifact);		// the run was dropped before it finished
+					}
+				}
+			}
+			RegisterMsg(id, key, chan, reply) =>
+			{
+				match runs.find(id)
+				{
+					option::Some(run) =>
+					{
+						for run.events.each |event|
+						{
+							oldcomm::send(chan, copy *event);
+						}
+						if !run.done
+						{
+							run.listeners.insert(key, chan);
+						}
+						oldcomm::send(reply, true);
+					}
+					option::None =>
+					{
+						oldcomm::send(reply, false);
+					}
+				}
+			}
+			DeregisterMsg(id, key) =>
+			{
+				match runs.find(id)
+				{
+					option::Some(run) => {run.listeners.remove(key);}
+					option::None => {}
+				}
+			}
+		}
+	}
+}
+
+priv fn remove_artifact(path: &str)
+{
+	if path.is_not_empty()
+	{
+		os::remove_file(&Path(path));
+	}
+}
+
+priv pure fn is_file_char(c: char) -> bool
+{
+	(c >= 'a' && c <= 'z') || (c >= 'A' && c <= 'Z') || (c >= '0' && c <= '9') || c == '-' || c == '_'
+}
+
+priv pure fn to_file_name(text: &str) -> ~str
+{
+	str::map(text, |c| {if is_file_char(c) {c} else {'_'}})
+}
+
+// 128 random bits as hex.
+priv fn get_token() -> ~str
+{
+	let rng = rand::Rng();
+	str::concat(do vec::from_fn(4) |_i| {fmt!("%08x", rng.next() as uint)})
+}
+
+#[test]
+fn test_expand_command()
+{
+	let tools = default_tools();
+	let tcpdump = tools.find(|t| {t.name == ~"tcpdump"}).get();
+	assert expand_command(&tcpdump, "10.0.0.1", "/tmp/gnos-tcpdump-1.pcap") == ~"tcpdump -n -c 1000 -w /tmp/gnos-tcpdump-1.pcap host 10.0.0.1";
+	
+	let ping = tools.find(|t| {t.name == ~"ping"}).get();
+	assert expand_command(&ping, "fe80::1", "") == ~"ping -c 10 fe80::1";
+}
+
+#[test]
+fn test_is_ip_address()
+{
+	assert is_ip_address("10.0.0.1");
+	assert is_ip_address("fe80::1");
+	assert !is_ip_address("");
+	assert !is_ip_address("rtr-4");
+	assert !is_ip_address("10.0.0.1; rm -rf /");
+	assert !is_ip_address("$(reboot)");
+}
+
+#[test]
+fn test_get_target_ip()
+{
+	let devices = ~[Device {name: ~"rtr", managed_ip: ~"10.0.0.1", modeler: ~"snmp", modeler_type: options::Builtin, snmp_security: snmp::Community(~"public"), snmp_port: 161}];
+	assert get_target_ip(devices, "entities:10.0.0.1") == option::Some(~"10.0.0.1");
+	assert get_target_ip(devices, "entities:rtr") == option::Some(~"10.0.0.1");
+	assert get_target_ip(devices, "entities:foo") == option::None;
+	assert get_target_ip(devices, "entities:10.0.0.9") == option::None;
+	assert get_target_ip(devices, "entities:10.0.0.1; reboot") == option::None;
+	
+	// only entities are allowed
+	assert get_target_ip(devices, "rtr") == option::None;
+	assert get_target_ip(devices, "10.0.0.1") == option::None;
+	assert get_target_ip(devices, "10.0.0.9") == option::None;
+}
+
+#[cfg(test)]
+priv fn create_run(runs: &ToolRuns, artifact: &str) -> uint
+{
+	let port = oldcomm::Port();
+	oldcomm::send(runs.chan, CreateMsg(~"ping", ~"10.0.0.1", artifact.to_owned(), Chan(&port)));
+	oldcomm::recv(port)
+}
+
+// Registers with the run and returns the first count events.
+#[cfg(test)]
+priv fn get_events(runs: &ToolRuns, id: uint, count: uint) -> ~[~str]
+{
+	let port = oldcomm::Port();
+	assert runs.register(id, ~"test", Chan(&port));
+	let events = do vec::from_fn(count) |_i| {oldcomm::recv(port)};
+	runs.deregister(id, ~"test");
+	events
+}
+
+#[cfg(test)]
+priv fn write_artifact(path: &Path)
+{
+	match io::file_writer(path, ~[io::Create, io::Truncate])
+	{
+		result::Ok(writer) => writer.write_str("packets"),
+		result::Err(ref err) => fail copy *err,
+	}
+}
+
+#[test]
+fn test_event_replay()
+{
+	let dir = std::tempfile::mkdtemp(&os::tmpdir(), "gnos-replay").get();
+	let runs = ToolRuns(&dir);
+	let id = create_run(&runs, "");
+	oldcomm::send(runs.chan, LineMsg(id, ~"first"));
+	
+	// Lines sent before registering are replayed and later lines are pushed.
+	let port = oldcomm::Port();
+	assert runs.register(id, ~"a", Chan(&port));
+	assert oldcomm::recv(port) == ~"{\"line\":\"first\"}";
+	oldcomm::send(runs.chan, LineMsg(id, ~"second"));
+	assert oldcomm::recv(port) == ~"{\"line\":\"second\"}";
+	oldcomm::send(runs.chan, DoneMsg(id, option::None, ~""));
+	let done = oldcomm::recv(port);
+	assert done.contains("\"done\":true");
+	assert done.contains("\"artifact\":null");
+	
+	// Registering after the run finishes replays everything.
+	let events = get_events(&runs, id, 3);
+	assert events == ~[~"{\"line\":\"first\"}", ~"{\"line\":\"second\"}", done];
+	
+	let port = oldcomm::Port();
+	assert !runs.register(id + 1, ~"b", Chan(&port));
+}
+
+#[test]
+fn test_max_lines()
+{
+	let dir = std::tempfile::mkdtemp(&os::tmpdir(), "gnos-lines").get();
+	let runs = ToolRuns(&dir);
+	let id = create_run(&runs, "");
+	for uint::range(0, max_lines + 5) |i|
+	{
+		oldcomm::send(runs.chan, LineMsg(id, fmt!("line %?", i)));
+	}
+	oldcomm::send(runs.chan, DoneMsg(id, option::Some(~"timed out"), ~""));
+	
+	let events = get_events(&runs, id, max_lines + 2);
+	assert events[0] == ~"{\"line\":\"line 0\"}";
+	assert events[max_lines - 1] == fmt!("{\"line\":\"line %?\"}", max_lines - 1);
+	assert events[max_lines] == ~"{\"line\":\"[5 lines were dropped]\"}";
+	assert events[max_lines + 1].contains("\"done\":true");
+	assert events[max_lines + 1].contains("\"error\":\"timed out\"");
+}
+
+#[test]
+fn test_run_eviction()
+{
+	let dir = std::tempfile::mkdtemp(&os::tmpdir(), "gnos-tools").get();
+	write_artifact(&dir.push("stale.pcap"));
+	let runs = ToolRuns(&dir);
+	assert !os::path_exists(&dir.push("stale.pcap"));
+	
+	// Successful runs keep their artifact and failed runs delete it.
+	let first = dir.push("first.pcap");
+	let id1 = create_run(&runs, first.to_str());
+	write_artifact(&first);
+	oldcomm::send(runs.chan, DoneMsg(id1, option::None, first.to_str()));
+	assert get_events(&runs, id1, 1)[0].contains("\"artifact\":\"/tool-artifacts/first.pcap\"");
+	assert os::path_exists(&first);
+	
+	let second = dir.push("second.pcap");
+	let id2 = create_run(&runs, second.to_str());
+	write_artifact(&second);
+	oldcomm::send(runs.chan, DoneMsg(id2, option::Some(~"failed"), second.to_str()));
+	assert get_events(&runs, id2, 1)[0].contains("\"artifact\":null");
+	assert !os::path_exists(&second);
+	
+	// Evicted runs delete their artifact (including runs that finish after being evicted).
+	let third = dir.push("third.pcap");
+	let id3 = create_run(&runs, third.to_str());
+	for uint::range(0, max_runs) |_i|
+	{
+		create_run(&runs, "");
+	}
+	let port = oldcomm::Port();
+	assert !runs.register(id1, ~"a", Chan(&port));
+	assert !runs.register(id3, ~"a", Chan(&port));
+	assert !os::path_exists(&first);
+	
+	write_artifact(&third);
+	oldcomm::send(runs.chan, DoneMsg(id3, option::None, third.to_str()));
+	create_run(&runs, "");			// wait for the DoneMsg to be processed
+	assert !os::path_exists(&third);
+}
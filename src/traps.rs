@@ -0,0 +1,350 @@
+/// Listens for SNMPv1 and SNMPv2c traps (and informs) and uses them to open
+/// and close alerts on the device which sent them.
+///
+/// The listener is enabled by adding a trap-port to the network json file.
+/// Traps are mapped to devices using the ip they were sent from and are only
+/// accepted if their community matches the device's community (so only devices
+/// polled by the builtin snmp modeler with SNMPv2c can send traps). Proxies
+/// which forward traps for other devices (e.g. snmptrapd) can be listed in an
+/// optional trap-proxies list of ips: for traps sent by these snmpTrapAddress
+/// (or, for v1 traps, the agent address) is used instead. What a trap does is
+/// controlled by a table of rules. The default rules handle the generic traps,
+/// e.g. linkDown opens an alert and linkUp closes it. Admins can add rules
+/// using a traps list in the network json file, e.g.
+/// {"trap": "1.3.6.1.4.1.2021.251.1", "open": true, "alert": "shutdown", "mesg": "Agent is shutting down."}
+/// Rules from the network json file are checked before the default rules and
+/// the first matching rule wins. Within alert, mesg, and resolution "{index}" is
+/// replaced with the last sub-id of the first variable in the trap (e.g. the
+/// ifIndex for linkDown) and "{values}" with the values of the trap's variables.
+use options::{Options, Device};
+use snmp::*;
+use task_runner::{ExitFn};
+
+pub struct TrapRule
+{
+	pub trap: ~[uint],				// snmpTrapOID
+	pub open: bool,					// if false the alert is closed
+	pub alert: ~str,				// alert id
+	pub level: ~str,				// "error", "warning", or "info"
+	pub mesg: ~str,
+	pub resolution: ~str,
+}
+
+/// Returns the oid for a generic trap name (e.g. "linkDown") or for dotted
+/// text.
+pub fn parse_trap(name: &str) -> option::Option<~[uint]>
+{
+	match generic_traps.position(|n| {*n == name})
+	{
+		option::Some(i) => option::Some(parse_oid("1.3.6.1.6.3.1.1.5") + ~[i + 1]),
+		option::None =>
+		{
+			let oid = parse_oid(name);
+			if oid.len() >= 2 && oid_to_str(oid) == name.to_owned() {option::Some(oid)} else {option::None}
+		}
+	}
+}
+
+pub fn default_trap_rules() -> ~[TrapRule]
+{
+	let rule = |name: &str, open: bool, alert: &str, level: &str, mesg: &str|
+	{
+		TrapRule {trap: parse_trap(name).get(), open: open, alert: alert.to_owned(), level: level.to_owned(), mesg: mesg.to_owned(), resolution: ~""}
+	};
+	
+	~[
+		rule("coldStart", true, "uptime", "error", "Device rebooted."),		// the snmp modeler closes this once the device has been up for a while
+		rule("warmStart", true, "uptime", "error", "Device rebooted."),
+		rule("linkDown", true, "link {index}", "error", "Link {index} went down ({values})."),
+		rule("linkUp", false, "link {index}", "", ""),
+		rule("authenticationFailure", true, "authentication failure", "warning", "Received an SNMP request with the wrong community."),
+		rule("egpNeighborLoss", true, "egp neighbor loss", "warning", "An EGP peer went down ({values})."),
+	]
+}
+
+/// Spawns a task to listen for traps on options.trap_port. Returns a function
+/// which stops the listener.
+pub fn start_trap_listener(options: &Options, state_chan: oldcomm::Chan<model::Msg>) -> ExitFn
+{
+	let port = options.trap_port;
+	let devices = copy options.devices;
+	let proxies = copy options.trap_proxies;
+	let rules = options.trap_rules + default_trap_rules();
+	let stop_chan = do utils::spawn_moded_listener(task::SingleThreaded) |stop, move devices, move proxies, move rules|
+	{
+		match UdpSocket(port)
+		{
+			result::Ok(socket) =>
+			{
+				info!("listening for traps on port %?", port);
+				while !stop.peek()
+				{
+					match socket.recv_from(poll_interval)
+					{
+						option::Some((ref data, ref ip, from)) => handle_datagram(&socket, *data, *ip, from, devices, proxies, rules, state_chan),
+						option::None => {}
+					}
+				}
+				socket.close();
+			}
+			result::Err(ref err) =>
+			{
+				let alert = model::Alert {target: ~"gnos:container", id: ~"trap listener", level: ~"error", mesg: fmt!("Couldn't listen for traps: %s.", *err), resolution: ~"Use a different trap-port or run gnos with permission to bind to the port."};
+				oldcomm::send(state_chan, model::UpdateMsg(~"primary", |store, _err| {model::open_alert(store, &alert)}, ~""));
+			}
+		}
+	};
+	|| {oldcomm::send(stop_chan, ())}
+}
+
+// ---- Internal Functions ----------------------------------------------------
+// Names of the generic traps (their oids are snmpTraps.1 through snmpTraps.6).
+priv const generic_traps: [&str * 6] = ["coldStart", "warmStart", "linkDown", "linkUp", "authenticationFailure", "egpNeighborLoss"];
+
+// How often (in ms) the listener checks to see if it should stop.
+priv const poll_interval: uint = 1000;
+
+priv fn handle_datagram(socket: &UdpSocket, data: &[u8], ip: &str, port: u16, devices: &[Device], proxies: &[~str], rules: &[TrapRule], state_chan: oldcomm::Chan<model::Msg>)
+{
+	match decode_message(data)
+	{
+		result::Ok(ref message) if message.pdu.kind == trap_v1 || message.pdu.kind == trap_v2 || message.pdu.kind == inform_request =>
+		{
+			let source = get_source(message, ip, proxies);
+			match devices.find(|d| {d.managed_ip == source})
+			{
+				option::Some(ref device) if has_community(device, message.community) =>
+				{
+					if message.pdu.kind == inform_request
+					{
+						let reply = Message {pdu: Pdu {kind: response, ..copy message.pdu}, ..copy *message};
+						socket.send_to(ip, port, encode_message(&reply));
+					}
+					
+					match find_binding(message, "1.3.6.1.6.3.1.1.4.1.0")		// snmpTrapOID
+					{
+						option::Some(ObjectId(ref trap)) => apply_rules(device, *trap, message, rules, state_chan),
+						_ => info!("ignoring trap from %s without an snmpTrapOID", device.name),
+					}
+				}
+				option::Some(ref device) =>
+				{
+					info!("ignoring trap from %s with the wrong community", device.name);
+				}
+				option::None =>
+				{
+					info!("ignoring trap from unknown device %s", source);
+				}
+			}
+		}
+		result::Ok(ref message) =>
+		{
+			debug!("ignoring 0x%X pdu from %s", message.pdu.kind as uint, ip);
+		}
+		result::Err(ref err) =>
+		{
+			info!("bad trap from %s: %s", ip, *err);
+		}
+	}
+}
+
+// Anyone can put an address into a trap so snmpTrapAddress is only used for
+// traps forwarded by a proxy.
+priv fn get_source(message: &Message, ip: &str, proxies: &[~str]) -> ~str
+{
+	if proxies.contains(&ip.to_owned())
+	{
+		match find_binding(message, "1.3.6.1.6.3.18.1.3.0")		// snmpTrapAddress
+		{
+			option::Some(IpAddress(ref bytes)) if bytes.len() == 4 => return IpAddress(copy *bytes).to_str(),
+			_ => {}
+		}
+	}
+	ip.to_owned()
+}
+
+priv fn has_community(device: &Device, community: &str) -> bool
+{
+	match device.snmp_security
+	{
+		Community(ref c) => c.is_not_empty() && str::eq_slice(*c, community),
+		Usm(_) => false,
+	}
+}
+
+priv fn apply_rules(device: &Device, trap: &[uint], message: &Message, rules: &[TrapRule], state_chan: oldcomm::Chan<model::Msg>)
+{
+	match rules.find(|r| {r.trap == vec::from_slice(trap)})
+	{
+		option::Some(ref rule) =>
+		{
+			let target = ~"entities:" + device.managed_ip;
+			let id = expand(rule.alert, message);
+			info!("%s trap from %s %s alert %s", oid_to_str(trap), device.name, if rule.open {"opened"} else {"closed"}, id);
+			if rule.open
+			{
+				let alert = model::Alert {target: target, id: id, level: copy rule.level, mesg: expand(rule.mesg, message), resolution: expand(rule.resolution, message)};
+				oldcomm::send(state_chan, model::UpdateMsg(~"primary", |store, _err| {model::open_alert(store, &alert)}, ~""));
+			}
+			else
+			{
+				oldcomm::send(state_chan, model::UpdateMsg(~"primary", |store, _err, copy target, copy id| {model::close_alert(store, target, id)}, ~""));
+			}
+		}
+		option::None =>
+		{
+			info!("ignoring %s trap from %s (no rule matched)", oid_to_str(trap), device.name);
+		}
+	}
+}
+
+priv fn find_binding(message: &Message, oid: &str) -> option::Option<Value>
+{
+	let oid = parse_oid(oid);
+	message.pdu.bindings.find(|b| {b.oid == oid}).map(|b| {copy b.value})
+}
+
+// The trap's variables are the bindings after sysUpTime, snmpTrapOID, etc.
+priv fn get_variables(message: &Message) -> ~[VarBind]
+{
+	let skip = ~[parse_oid("1.3.6.1.2.1.1.3"), parse_oid("1.3.6.1.6.3.1.1.4"), parse_oid("1.3.6.1.6.3.18.1.3")];
+	do message.pdu.bindings.filtered |b| {!skip.any(|root| {oid_starts_with(b.oid, *root)})}
+}
+
+priv fn expand(text: &str, message: &Message) -> ~str
+{
+	let variables = get_variables(message);
+	let index = if variables.is_not_empty() && variables[0].oid.is_not_empty() {variables[0].oid.last().to_str()} else {~"?"};
+	let values = str::connect(variables.map(|b| {b.value.to_str()}), ", ");
+	str::replace(str::replace(text, "{index}", index), "{values}", values)
+}
+
+#[test]
+fn test_parse_trap()
+{
+	assert parse_trap("coldStart") == option::Some(parse_oid("1.3.6.1.6.3.1.1.5.1"));
+	assert parse_trap("linkUp") == option::Some(parse_oid("1.3.6.1.6.3.1.1.5.4"));
+	assert parse_trap("1.3.6.1.4.1.2021.251.1") == option::Some(parse_oid("1.3.6.1.4.1.2021.251.1"));
+	assert parse_trap("linkup").is_none();
+	assert parse_trap("1.3.x").is_none();
+}
+
+#[test]
+fn test_expand()
+{
+	let bindings = ~[
+		VarBind {oid: parse_oid("1.3.6.1.2.1.1.3.0"), value: TimeTicks(1234)},
+		VarBind {oid: parse_oid("1.3.6.1.6.3.1.1.4.1.0"), value: ObjectId(parse_oid("1.3.6.1.6.3.1.1.5.3"))},
+		VarBind {oid: parse_oid("1.3.6.1.2.1.2.2.1.1.7"), value: Integer(7)},
+		VarBind {oid: parse_oid("1.3.6.1.2.1.2.2.1.2.7"), value: OctetString(str::to_bytes("eth1"))},
+	];
+	let message = Message {version: version_2c, community: ~"public", pdu: Pdu {kind: trap_v2, request_id: 1, error_status: 0, error_index: 0, bindings: bindings}};
+	assert expand("link {index}", &message) == ~"link 7";
+	assert expand("Link {index} went down ({values}).", &message) == ~"Link 7 went down (7, eth1).";
+	
+	let rules = default_trap_rules();
+	let rule = rules.find(|r| {r.trap == parse_oid("1.3.6.1.6.3.1.1.5.4")}).get();
+	assert !rule.open && rule.alert == ~"link {index}";
+}
+
+#[cfg(test)]
+priv fn encode_trap(kind: u8, community: &str, trap: &str, index: i64, address: &str) -> ~[u8]
+{
+	let mut bindings = ~[
+		VarBind {oid: parse_oid("1.3.6.1.2.1.1.3.0"), value: TimeTicks(1234)},
+		VarBind {oid: parse_oid("1.3.6.1.6.3.1.1.4.1.0"), value: ObjectId(parse_trap(trap).get())},
+		VarBind {oid: parse_oid(fmt!("1.3.6.1.2.1.2.2.1.1.%d", index as int)), value: Integer(index)},
+	];
+	if address.is_not_empty()
+	{
+		bindings.push(VarBind {oid: parse_oid("1.3.6.1.6.3.18.1.3.0"), value: IpAddress(parse_oid(address).map(|n| {*n as u8}))});
+	}
+	let message = Message {version: version_2c, community: community.to_owned(), pdu: Pdu {kind: kind, request_id: 1, error_status: 0, error_index: 0, bindings: bindings}};
+	encode_message(&message)
+}
+
+#[cfg(test)]
+priv fn query_alerts(state_chan: oldcomm::Chan<model::Msg>, query: &str) -> ~str
+{
+	let port = oldcomm::Port();
+	let chan = oldcomm::Chan(&port);
+	oldcomm::send(state_chan, model::QueryMsg(~"primary", query.to_owned(), chan));
+	oldcomm::recv(port).to_str()
+}
+
+#[test]
+fn test_handle_datagram()
+{
+	let state_chan = do utils::spawn_moded_listener(task::ThreadPerCore) |port| {model::manage_state(port, "127.0.0.1", 8080)};
+	let socket = result::unwrap(UdpSocket(16164));
+	let manager = result::unwrap(UdpSocket(16165));
+	let devices = ~[
+		Device {name: ~"RTR", managed_ip: ~"127.0.0.1", modeler: ~"snmp", modeler_type: options::Builtin, snmp_security: Community(~"public"), snmp_port: 161},
+		Device {name: ~"PC", managed_ip: ~"10.0.0.2", modeler: ~"linux.py", modeler_type: options::Ssh, snmp_security: Community(~""), snmp_port: 161},
+	];
+	let proxies = ~[~"10.0.0.100"];
+	let rules = default_trap_rules();
+	let handle = |data: ~[u8], ip: &str| {handle_datagram(&socket, data, ip, 16165, devices, proxies, rules, state_chan)};
+	let open = "SELECT ?alert WHERE {?subject gnos:alert ?alert}";
+	
+	// traps from a device with its community open alerts
+	handle(encode_trap(trap_v2, "public", "linkDown", 1, ""), "127.0.0.1");
+	assert query_alerts(state_chan, open) == ~"[{\"alert\":\"link 1\"}]";
+	
+	// traps with the wrong community, from devices without a community, or from unknown devices are ignored
+	handle(encode_trap(trap_v2, "private", "linkDown", 2, ""), "127.0.0.1");
+	handle(encode_trap(trap_v2, "", "linkDown", 3, ""), "10.0.0.2");
+	handle(encode_trap(trap_v2, "public", "linkDown", 4, ""), "10.0.0.3");
+	
+	// snmpTrapAddress is ignored unless the trap came from a proxy
+	handle(encode_trap(trap_v2, "public", "linkDown", 5, "127.0.0.1"), "10.0.0.3");
+	handle(encode_trap(trap_v2, "public", "linkDown", 6, "127.0.0.1"), "10.0.0.100");
+	let alerts = query_alerts(state_chan, open);
+	assert alerts.contains("link 6");
+	assert !alerts.contains("link 2") && !alerts.contains("link 3") && !alerts.contains("link 4") && !alerts.contains("link 5");
+	
+	// informs are acknowledged
+	handle(encode_trap(inform_request, "public", "linkUp", 1, ""), "127.0.0.1");
+	match manager.recv_from(1000)
+	{
+		option::Some((ref data, _, _)) => assert result::unwrap(decode_message(*data)).pdu.kind == response,
+		option::None => fail ~"inform wasn't acknowledged",
+	}
+	
+	// but informs with the wrong community are not
+	handle(encode_trap(inform_request, "private", "linkUp", 1, ""), "127.0.0.1");
+	assert manager.recv_from(200).is_none();
+	
+	socket.close();
+	manager.close();
+	oldcomm::send(state_chan, model::ExitMsg);
+}
+
+#[test]
+fn test_apply_rules()
+{
+	let state_chan = do utils::spawn_moded_listener(task::ThreadPerCore) |port| {model::manage_state(port, "127.0.0.1", 8080)};
+	let device = Device {name: ~"RTR", managed_ip: ~"10.0.0.1", modeler: ~"snmp", modeler_type: options::Builtin, snmp_security: Community(~"public"), snmp_port: 161};
+	let custom = TrapRule {trap: parse_oid("1.3.6.1.4.1.2021.251.1"), open: true, alert: ~"shutdown", level: ~"warning", mesg: ~"Agent is shutting down.", resolution: ~""};
+	let rules = ~[custom] + default_trap_rules();
+	let apply = |trap: &str, index: i64|
+	{
+		let message = result::unwrap(decode_message(encode_trap(trap_v2, "public", trap, index, "")));
+		apply_rules(&device, parse_trap(trap).get(), &message, rules, state_chan);
+	};
+	
+	apply("linkDown", 7);
+	apply("1.3.6.1.4.1.2021.251.1", 0);
+	apply("1.3.6.1.4.1.2021.251.2", 0);		// no rule so ignored
+	let alerts = query_alerts(state_chan, "SELECT ?alert ?mesg WHERE {?subject gnos:alert ?alert . ?subject gnos:mesg ?mesg}");
+	assert alerts.contains("\"alert\":\"link 7\"");
+	assert alerts.contains("Link 7 went down (7).");
+	assert alerts.contains("\"alert\":\"shutdown\"");
+	assert !alerts.contains("251.2");
+	
+	// linkUp closes the linkDown alert
+	apply("linkUp", 7);
+	assert query_alerts(state_chan, "SELECT ?alert WHERE {?subject gnos:alert ?alert . ?subject gnos:end ?end}") == ~"[{\"alert\":\"link 7\"}]";
+	
+	oldcomm::send(state_chan, model::ExitMsg);
+}
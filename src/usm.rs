@@ -0,0 +1,170 @@
+/// The parts of the SNMPv3 user-based security model (RFC 3414) used by snmp.rs:
+/// turning passwords into localized keys and the HMAC-MD5-96 and HMAC-SHA-96
+/// authentication protocols. Privacy (encryption) is not supported.
+use snmp::{AuthProtocol, NoAuth, AuthMd5, AuthSha};
+
+/// Number of bytes in the authentication parameters of a message.
+pub const mac_len: uint = 12;
+
+/// Returns the key used with agents whose engine id is engine_id (see RFC 3414
+/// A.2). Empty for NoAuth.
+pub fn get_localized_key(auth: AuthProtocol, password: &str, engine_id: &[u8]) -> ~[u8]
+{
+	match auth
+	{
+		NoAuth => ~[],
+		_ =>
+		{
+			let key = password_to_key(auth, password);
+			digest(auth, key + engine_id + key)
+		}
+	}
+}
+
+/// Returns the first twelve bytes of the HMAC of data. Data should be the
+/// whole message with its authentication parameters set to zeros.
+pub fn get_mac(auth: AuthProtocol, key: &[u8], data: &[u8]) -> ~[u8]
+{
+	let mac = hmac(auth, key, data);
+	vec::slice(mac, 0, mac_len)
+}
+
+// ---- Internal Functions ----------------------------------------------------
+// Hashes a megabyte of the repeated password.
+priv fn password_to_key(auth: AuthProtocol, password: &str) -> ~[u8]
+{
+	let password = str::to_bytes(password);
+	assert password.is_not_empty();
+	
+	let buffer = do vec::from_fn(1048576) |i| {password[i % password.len()]};
+	digest(auth, buffer)
+}
+
+priv fn hmac(auth: AuthProtocol, key: &[u8], data: &[u8]) -> ~[u8]
+{
+	let key = if key.len() > 64 {digest(auth, key)} else {vec::from_slice(key)};
+	let pad = |x: u8| {do vec::from_fn(64) |i| {(if i < key.len() {key[i]} else {0}) ^ x}};
+	digest(auth, pad(0x5C) + digest(auth, pad(0x36) + data))
+}
+
+priv fn digest(auth: AuthProtocol, data: &[u8]) -> ~[u8]
+{
+	match auth
+	{
+		NoAuth => fail ~"NoAuth has no digest",
+		AuthMd5 => md5(data),
+		AuthSha =>
+		{
+			let hash = std::sha1::sha1();
+			hash.input(data);
+			hash.result()
+		}
+	}
+}
+
+// See RFC 1321.
+priv fn md5(data: &[u8]) -> ~[u8]
+{
+	let mut message = vec::from_slice(data);
+	let bits = 8*(data.len() as u64);
+	message.push(0x80);
+	while message.len() % 64 != 56
+	{
+		message.push(0);
+	}
+	for uint::range(0, 8) |i|
+	{
+		message.push((bits >> (8*i as u64)) as u8);
+	}
+	
+	let (mut h0, mut h1, mut h2, mut h3) = (0x67452301u32, 0xEFCDAB89u32, 0x98BADCFEu32, 0x10325476u32);
+	let mut offset = 0;
+	while offset < message.len()
+	{
+		let w = do vec::from_fn(16) |j| {get_u32_le(message, offset + 4*j)};
+		let (mut a, mut b, mut c, mut d) = (h0, h1, h2, h3);
+		for uint::range(0, 64) |i|
+		{
+			let (f, g) =
+				if i < 16 {((b & c) | (!b & d), i)}
+				else if i < 32 {((d & b) | (!d & c), (5*i + 1) % 16)}
+				else if i < 48 {(b ^ c ^ d, (3*i + 5) % 16)}
+				else {(c ^ (b | !d), (7*i) % 16)};
+			let temp = d;
+			d = c;
+			c = b;
+			b = b + rotate_left(a + f + md5_k[i] + w[g], md5_s[i]);
+			a = temp;
+		}
+		h0 += a;
+		h1 += b;
+		h2 += c;
+		h3 += d;
+		offset += 64;
+	}
+	
+	let mut result = ~[];
+	for [h0, h1, h2, h3].each |h|
+	{
+		for uint::range(0, 4) |i| {result.push((*h >> (8*i as u32)) as u8)}
+	}
+	result
+}
+
+priv pure fn get_u32_le(bytes: &[u8], offset: uint) -> u32
+{
+	(bytes[offset] as u32) | (bytes[offset + 1] as u32 << 8) | (bytes[offset + 2] as u32 << 16) | (bytes[offset + 3] as u32 << 24)
+}
+
+priv pure fn rotate_left(x: u32, n: u32) -> u32
+{
+	(x << n) | (x >> (32 - n))
+}
+
+priv const md5_s: [u32 * 64] = [
+	7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22, 7, 12, 17, 22,
+	5, 9, 14, 20, 5, 9, 14, 20, 5, 9, 14, 20, 5, 9, 14, 20,
+	4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23, 4, 11, 16, 23,
+	6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21, 6, 10, 15, 21,
+];
+
+// floor(abs(sin(i + 1))*2^32)
+priv const md5_k: [u32 * 64] = [
+	0xD76AA478, 0xE8C7B756, 0x242070DB, 0xC1BDCEEE, 0xF57C0FAF, 0x4787C62A, 0xA8304613, 0xFD469501,
+	0x698098D8, 0x8B44F7AF, 0xFFFF5BB1, 0x895CD7BE, 0x6B901122, 0xFD987193, 0xA679438E, 0x49B40821,
+	0xF61E2562, 0xC040B340, 0x265E5A51, 0xE9B6C7AA, 0xD62F105D, 0x02441453, 0xD8A1E681, 0xE7D3FBC8,
+	0x21E1CDE6, 0xC33707D6, 0xF4D50D87, 0x455A14ED, 0xA9E3E905, 0xFCEFA3F8, 0x676F02D9, 0x8D2A4C8A,
+	0xFFFA3942, 0x8771F681, 0x6D9D6122, 0xFDE5380C, 0xA4BEEA44, 0x4BDECFA9, 0xF6BB4B60, 0xBEBFBC70,
+	0x289B7EC6, 0xEAA127FA, 0xD4EF3085, 0x04881D05, 0xD9D4D039, 0xE6DB99E5, 0x1FA27CF8, 0xC4AC5665,
+	0xF4292244, 0x432AFF97, 0xAB9423A7, 0xFC93A039, 0x655B59C3, 0x8F0CCC92, 0xFFEFF47D, 0x85845DD1,
+	0x6FA87E4F, 0xFE2CE6E0, 0xA3014314, 0x4E0811A1, 0xF7537E82, 0xBD3AF235, 0x2AD7D2BB, 0xEB86D391,
+];
+
+#[cfg(test)]
+priv fn to_hex(bytes: &[u8]) -> ~str
+{
+	str::concat(bytes.map(|b| {fmt!("%02x", *b as uint)}))
+}
+
+#[test]
+fn test_digests()
+{
+	assert to_hex(md5(~[])) == ~"d41d8cd98f00b204e9800998ecf8427e";
+	assert to_hex(md5(str::to_bytes("abc"))) == ~"900150983cd24fb0d6963f7d28e17f72";
+	
+	// RFC 2104 and RFC 2202
+	assert to_hex(hmac(AuthMd5, vec::from_elem(16, 0x0Bu8), str::to_bytes("Hi There"))) == ~"9294727a3638bb1c13f48ef8158bfc9d";
+	assert to_hex(hmac(AuthSha, vec::from_elem(20, 0x0Bu8), str::to_bytes("Hi There"))) == ~"b617318655057264e28bc0b6fb378c8ef146be00";
+}
+
+#[test]
+fn test_localized_keys()
+{
+	// RFC 3414 A.3
+	let engine_id = ~[0u8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 2];
+	assert to_hex(password_to_key(AuthMd5, "maplesyrup")) == ~"9faf3283884e92834ebc9847d8edd963";
+	assert to_hex(get_localized_key(AuthMd5, "maplesyrup", engine_id)) == ~"526f5eed9fcce26f8964c2930787d82b";
+	assert to_hex(password_to_key(AuthSha, "maplesyrup")) == ~"9fb5cc0381497b3793528939ff788d5d79145211";
+	assert to_hex(get_localized_key(AuthSha, "maplesyrup", engine_id)) == ~"6695febc9288e36282235fc7151f128497b38f3f";
+	assert get_localized_key(NoAuth, "", engine_id).is_empty();
+}
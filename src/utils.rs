@@ -2,6 +2,7 @@ use std::map::{HashMap};
 use Path = path::Path;
 use std::getopts::*;
 use std::time::*;
+use io::ReaderUtil;
 
 // Like spawn_listener except that it supports custom modes. This allows code that blocks
 // within a foreign function to avoid blocking other tasks which may be on its thread.
@@ -49,6 +50,64 @@ pub fn remove_entry_if<K: cmp::Eq to_bytes::IterBytes hash::Hash Copy, V: Copy>(
 	}
 }
 
+/// Returns true if text matches pattern where '*' in pattern matches zero or more characters.
+pub pure fn glob_match(pattern: &str, text: &str) -> bool
+{
+	let pattern = str::chars(pattern);
+	let text = str::chars(text);
+	
+	// When a match fails after a star we only need to retry the most recent
+	// star (with it consuming one more char) so this is O(pattern*text).
+	let mut p = 0;
+	let mut t = 0;
+	let mut star = option::None;			// (index of the star in pattern, index in text the star matched up to)
+	while t < text.len()
+	{
+		if p < pattern.len() && pattern[p] == '*'
+		{
+			star = option::Some((p, t));
+			p += 1;
+		}
+		else if p < pattern.len() && pattern[p] == text[t]
+		{
+			p += 1;
+			t += 1;
+		}
+		else
+		{
+			match star
+			{
+				option::Some((sp, st)) =>
+				{
+					star = option::Some((sp, st + 1));
+					p = sp + 1;
+					t = st + 1;
+				}
+				option::None =>
+				{
+					return false;
+				}
+			}
+		}
+	}
+	
+	while p < pattern.len() && pattern[p] == '*'
+	{
+		p += 1;
+	}
+	p == pattern.len()
+}
+
+/// Returns the value below which percent of the values fall. Sorted must be
+/// non-empty. Uses linear interpolation between the closest ranks.
+pub pure fn get_percentile(sorted: &[float], percent: float) -> float
+{
+	let rank = (percent/100.0)*((sorted.len() - 1) as float);
+	let lower = float::floor(rank) as uint;
+	let upper = uint::min(lower + 1, sorted.len() - 1);
+	sorted[lower] + (rank - (lower as float))*(sorted[upper] - sorted[lower])
+}
+
 /// Returns an error if the files cannot be copied.
 pub fn scp_files(files: &[~Path], user: &str, host: &str) -> option::Option<~str>
 {
@@ -63,15 +122,129 @@ pub fn scp_files(files: &[~Path], user: &str, host: &str) -> option::Option<~str
 	run_command(~"scp", args)
 }
 
-/// Uses ssh to run a command remotely.
+/// Uses ssh to run a command remotely. If this is called from a job which times
+/// out or is cancelled then the remote processes are killed along with ssh
+/// (see task_runner::remote_started).
 ///
 /// Returns an error if the command returned a non-zero result code
 pub fn run_remote_command(user: &str, host: &str, command: &str) -> option::Option<~str>
 {
-	let args = ~[fmt!("%s@%s", user, host)] + ~[command.to_owned()];
+	let pid_file = get_pid_file();
+	let args = ~[fmt!("%s@%s", user, host), wrap_remote_command(command, pid_file)];
 	
-	info!("ssh %s \"%s\"", args.head(), str::connect(args.tail(), ~" "));
-	run_command(~"ssh", args)
+	info!("ssh %s \"%s\"", args.head(), command);
+	task_runner::remote_started(user, host, pid_file);
+	let err = run_command(~"ssh", args);
+	task_runner::remote_exited(pid_file);
+	err
+}
+
+/// Like run_remote_command except that lines written to stdout or stderr are
+/// passed to on_line as they are read.
+pub fn run_remote_command_with_output(user: &str, host: &str, command: &str, on_line: fn (&str)) -> option::Option<~str>
+{
+	let pid_file = get_pid_file();
+	let args = ~[fmt!("%s@%s", user, host), wrap_remote_command(command, pid_file)];
+	
+	info!("ssh %s \"%s\"", args.head(), command);
+	task_runner::remote_started(user, host, pid_file);
+	let err = run_command_with_output(~"ssh", args, on_line);
+	task_runner::remote_exited(pid_file);
+	err
+}
+
+/// Returns a command which records the pid of the remote shell in pid_file
+/// (this is also the id of the shell's process group), runs command, and
+/// then removes pid_file.
+pub fn wrap_remote_command(command: &str, pid_file: &str) -> ~str
+{
+	fmt!("echo $$ > %s; %s; status=$?; rm -f %s; exit $status", pid_file, command, pid_file)
+}
+
+/// Runs a command on this machine passing lines written to stdout or stderr
+/// to on_line as they are read.
+///
+/// Returns an error if the command returned a non-zero result code.
+pub fn run_command_with_output(tool: &str, args: &[~str], on_line: fn (&str)) -> option::Option<~str>
+{
+	let pipe = os::pipe();
+	let pid = core::run::spawn_process(tool, args, &option::None, &option::None, 0, pipe.out, pipe.out);
+	unsafe {libc::close(pipe.out);}
+	if pid == -1
+	{
+		unsafe {libc::close(pipe.in);}
+		return option::Some(fmt!("failed to start %s", tool));
+	}
+	
+	task_runner::child_started(pid);
+	let file = os::fdopen(pipe.in);
+	let reader = io::FILE_reader(file, false);
+	while !reader.eof()
+	{
+		let line = reader.read_line();
+		if line.is_not_empty() || !reader.eof()
+		{
+			on_line(line);
+		}
+	}
+	unsafe {libc::fclose(file);}
+	let code = core::run::waitpid(pid);
+	task_runner::child_exited(pid);
+	
+	if code == 0 {option::None} else {option::Some(fmt!("%s result code was %?", tool, code))}
+}
+
+/// Uses scp to copy a file from a remote machine.
+pub fn scp_remote_file(user: &str, host: &str, remote: &str, local: &Path) -> option::Option<~str>
+{
+	let args = ~[fmt!("%s@%s:%s", user, host, remote), local.to_str()];
+	
+	info!("scp %s", str::connect(args, ~" "));
+	run_command(~"scp", args)
+}
+
+/// Runs a command on this machine with dir as the working directory. Lines
+/// written to stdout are logged.
+///
+/// Returns an error if the command returned a non-zero result code.
+pub fn run_local_command(tool: &str, args: &[~str], dir: &Path) -> option::Option<~str>
+{
+	info!("%s %s (in %s)", tool, str::connect(args, ~" "), dir.to_str());
+	let out = os::pipe();
+	let err = os::pipe();
+	let pid = core::run::spawn_process(tool, args, &option::None, &option::Some(dir.to_str()), 0, out.out, err.out);
+	unsafe
+	{
+		libc::close(out.out);
+		libc::close(err.out);
+	}
+	
+	if pid != -1
+	{
+		// Stdout is read within its own thread so that the process can't block
+		// writing to it while we're waiting on stderr.
+		let name = if args.is_not_empty() {copy args[0]} else {tool.to_owned()};
+		let fd = out.in;
+		do task::spawn_sched(task::SingleThreaded) |move name|
+		{
+			let file = os::fdopen(fd);
+			let reader = io::FILE_reader(file, false);
+			while !reader.eof()
+			{
+				let line = reader.read_line();
+				if line.is_not_empty()
+				{
+					info!("%s: %s", name, line);
+				}
+			}
+			unsafe {libc::fclose(file);}
+		}
+	}
+	else
+	{
+		unsafe {libc::close(out.in);}
+	}
+	wait_for_command(tool, pid, err.in)
 }
 
 /// Returns paths to files in dir with an extension in extensions.
@@ -166,22 +339,123 @@ pub fn tm_to_delta_str(time: Tm) -> {elapsed: float, delta: ~str}
 	}
 }
 
+/// Builds a json object from (key, value) pairs.
+pub fn to_object(entries: ~[(~str, std::json::Json)]) -> std::json::Json
+{
+	let mut map = ~send_map::linear::LinearMap();
+	do vec::consume(entries) |_i, entry|
+	{
+		let (key, value) = entry;
+		map.insert(key, value);
+	}
+	std::json::Object(map)
+}
+
+pub fn strs_to_json(strs: &[~str]) -> std::json::Json
+{
+	std::json::List(strs.map(|s| {std::json::String(copy *s)}))
+}
+
+/// Converts the rows of a details table into json.
+pub fn rows_to_json(rows: &[~[~str]]) -> std::json::Json
+{
+	std::json::List(rows.map(|row| {strs_to_json(*row)}))
+}
+
+/// Converts bytes which are usually, but not always, utf-8 into a string.
+/// Invalid utf-8 is treated as latin-1.
+pub pure fn bytes_to_str(bytes: &[u8]) -> ~str
+{
+	if str::is_utf8(bytes)
+	{
+		str::from_bytes(bytes)
+	}
+	else
+	{
+		str::from_chars(bytes.map(|b| {*b as char}))
+	}
+}
+
+pub fn escape_html(text: &str) -> ~str
+{
+	str::replace(str::replace(str::replace(text, "&", "&amp;"), "<", "&lt;"), ">", "&gt;")
+}
+
+/// Returns a text/plain response, e.g. for errors.
+pub fn text_response(response: rwebserve::Response, status: ~str, mesg: ~str) -> rwebserve::Response
+{
+	let mut response = response;
+	response.status = status;
+	response.headers.insert(~"Content-Type", ~"text/plain");
+	rwebserve::Response {body: rwebserve::configuration::StringBody(@mesg), ..response}
+}
+
+/// Appends value in big endian (network) order.
+pub fn push_u32(bytes: &mut ~[u8], value: u32)
+{
+	for uint::range(0, 4) |i|
+	{
+		bytes.push((value >> (8*(3 - i) as u32)) as u8);
+	}
+}
+
+pub fn push_u64(bytes: &mut ~[u8], value: u64)
+{
+	push_u32(bytes, (value >> 32) as u32);
+	push_u32(bytes, value as u32);
+}
+
 // --------------------------------------------------------------------------------------
+priv fn get_pid_file() -> ~str
+{
+	fmt!("/tmp/gnos-%?-%?.pid", os::getpid(), rand::Rng().next())
+}
+
+// We don't use core::run::program_output because task_runner needs the pid so
+// that it can kill the process if the job times out. Stdout is discarded.
 priv fn run_command(tool: &str, args: &[~str]) -> option::Option<~str>
 {
-	match core::run::program_output(tool, args)
+	let null = do str::as_c_str("/dev/null") |path| {unsafe {libc::open(path, libc::O_WRONLY as libc::c_int, 0)}};
+	let pipe = os::pipe();
+	let pid = core::run::spawn_process(tool, args, &option::None, &option::None, 0, null, pipe.out);
+	unsafe
+	{
+		libc::close(null);
+		libc::close(pipe.out);
+	}
+	wait_for_command(tool, pid, pipe.in)
+}
+
+// Waits for the process to exit and returns an error if the process failed.
+// Err_fd is used to read the process's stderr.
+priv fn wait_for_command(tool: &str, pid: libc::pid_t, err_fd: libc::c_int) -> option::Option<~str>
+{
+	if pid == -1
+	{
+		unsafe {libc::close(err_fd);}
+		return option::Some(fmt!("failed to start %s", tool));
+	}
+	
+	task_runner::child_started(pid);
+	let file = os::fdopen(err_fd);
+	let err = io::FILE_reader(file, false).read_whole_stream();
+	unsafe {libc::fclose(file);}
+	let code = core::run::waitpid(pid);
+	task_runner::child_exited(pid);
+	
+	match code
 	{
-		{status: 0, _} =>
+		0 =>
 		{
 			option::None
 		}
-		{status: code, out: _, err: ~""} =>
+		_ if err.is_empty() =>
 		{
 			option::Some(fmt!("%s result code was %?", tool, code))
 		}
-		{status: code, out: _, err: ref err} =>
+		_ =>
 		{
-			option::Some(fmt!("%s result code was %? (%s)", tool, code, *err))
+			option::Some(fmt!("%s result code was %? (%s)", tool, code, str::from_bytes(err)))
 		}
 	}
 }